@@ -1,13 +1,12 @@
-use std::env;
+use std::{env, time::Duration};
 
 use askama::Template;
+use auth_middleware::{AuthClient, AuthenticatedUser, Verification};
 use axum::{
-    http::StatusCode,
     response::{Html, IntoResponse},
     routing::get,
     Json, Router,
 };
-use axum_extra::extract::CookieJar;
 use serde::Serialize;
 use tower_http::services::ServeDir;
 
@@ -16,7 +15,8 @@ async fn main() {
     let app = Router::new()
         .nest_service("/assets", ServeDir::new("assets"))
         .route("/", get(root))
-        .route("/protected", get(protected));
+        .route("/protected", get(protected))
+        .with_state(auth_client());
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:8000").await.unwrap();
 
@@ -46,39 +46,29 @@ async fn root() -> impl IntoResponse {
     Html(template.render().unwrap())
 }
 
-async fn protected(jar: CookieJar) -> impl IntoResponse {
-    let jwt_cookie = match jar.get("jwt") {
-        Some(cookie) => cookie,
-        None => {
-            return StatusCode::UNAUTHORIZED.into_response();
-        }
-    };
-
-    let api_client = reqwest::Client::builder().build().unwrap();
+// Requests without a valid jwt cookie or bearer token are refused with a 401 before getting here
+async fn protected(_user: AuthenticatedUser) -> impl IntoResponse {
+    Json(ProtectedRouteResponse {
+        img_url: "https://i.ibb.co/YP90j68/Light-Live-Bootcamp-Certificate.png".to_owned(),
+    })
+}
 
-    let verify_token_body = serde_json::json!({
-        "token": &jwt_cookie.value(),
-    });
+// AUTH_SERVICE_URL is where auth_service is reachable from this service, e.g. http://auth_service:3000 in Docker.
+// AUTH_TOKEN_VERIFICATION picks between asking its /verify-token (remote) and checking signatures locally (jwks).
+fn auth_client() -> AuthClient {
+    let url = env::var("AUTH_SERVICE_URL").unwrap_or("http://localhost:3000".to_owned());
 
-    let auth_hostname = env::var("AUTH_SERVICE_HOST_NAME").unwrap_or("0.0.0.0".to_owned());
-    let url = format!("http://{}:4000/verify-token", auth_hostname);
+    let verification = env::var("AUTH_TOKEN_VERIFICATION")
+        .map(|verification| verification.parse().expect("Invalid AUTH_TOKEN_VERIFICATION"))
+        .unwrap_or(Verification::Remote);
 
-    let response = match api_client.post(&url).json(&verify_token_body).send().await {
-        Ok(response) => response,
-        Err(_) => {
-            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-        }
-    };
+    let client = AuthClient::new(url, verification);
 
-    match response.status() {
-        reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::BAD_REQUEST => {
-            StatusCode::UNAUTHORIZED.into_response()
-        }
-        reqwest::StatusCode::OK => Json(ProtectedRouteResponse {
-            img_url: "https://i.ibb.co/YP90j68/Light-Live-Bootcamp-Certificate.png".to_owned(),
-        })
-        .into_response(),
-        _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    match env::var("AUTH_CACHE_TTL_SECONDS") {
+        Ok(seconds) => client.with_cache_ttl(Duration::from_secs(
+            seconds.parse().expect("AUTH_CACHE_TTL_SECONDS must be a number of seconds"),
+        )),
+        Err(_) => client,
     }
 }
 
@@ -0,0 +1,114 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH}
+};
+
+use crate::user::AuthenticatedUser;
+
+// Past this many tokens, expired entries are dropped before adding another
+const MAX_CACHED_TOKENS: usize = 10_000;
+
+// Tokens that were found valid, so repeated requests with them don't have to be checked again.
+// Only valid results are kept, and never past the token's own expiry.
+pub(crate) struct TokenCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (AuthenticatedUser, Instant)>>,
+}
+
+impl TokenCache {
+    pub(crate) fn new(ttl: Duration) -> Self {
+        Self { ttl, entries: Mutex::new(HashMap::new()) }
+    }
+
+    pub(crate) fn get(&self, token: &str, now: Instant) -> Option<AuthenticatedUser> {
+        let mut entries = self.entries.lock().ok()?;
+
+        match entries.get(token) {
+            Some((user, until)) if *until > now => Some(user.clone()),
+            Some(_) => {
+                entries.remove(token);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub(crate) fn insert(&self, token: &str, user: &AuthenticatedUser, now: Instant) {
+        if self.ttl.is_zero() {
+            return;
+        }
+
+        let until = now + self.ttl.min(time_to_expiry(user.expires_at));
+
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+
+        if entries.len() >= MAX_CACHED_TOKENS {
+            entries.retain(|_, (_, until)| *until > now);
+        }
+
+        // Still full of live tokens, so start over rather than grow without bound
+        if entries.len() >= MAX_CACHED_TOKENS {
+            entries.clear();
+        }
+
+        entries.insert(token.to_owned(), (user.clone(), until));
+    }
+}
+
+fn time_to_expiry(expires_at: u64) -> Duration {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    Duration::from_secs(expires_at.saturating_sub(now))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(expires_in: u64) -> AuthenticatedUser {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        AuthenticatedUser {
+            user_id: String::from("user"),
+            session_id: String::from("session"),
+            email: None,
+            roles: vec![],
+            permissions: vec![],
+            org_id: None,
+            org_role: None,
+            expires_at: now + expires_in,
+        }
+    }
+
+    #[test]
+    fn test_entries_last_for_the_ttl() {
+        let cache = TokenCache::new(Duration::from_secs(30));
+        let now = Instant::now();
+        let user = user(600);
+
+        cache.insert("token", &user, now);
+
+        assert_eq!(cache.get("token", now + Duration::from_secs(29)), Some(user));
+        assert_eq!(cache.get("token", now + Duration::from_secs(30)), None);
+        assert_eq!(cache.get("other", now), None);
+    }
+
+    #[test]
+    fn test_entries_never_outlive_the_token() {
+        let cache = TokenCache::new(Duration::from_secs(30));
+        let now = Instant::now();
+
+        cache.insert("token", &user(0), now);
+        assert_eq!(cache.get("token", now), None);
+
+        let disabled = TokenCache::new(Duration::ZERO);
+        disabled.insert("token", &user(600), now);
+        assert_eq!(disabled.get("token", now), None);
+    }
+}
@@ -0,0 +1,184 @@
+use std::{
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant}
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation
+};
+use reqwest::StatusCode;
+use tokio::sync::RwLock;
+
+use crate::{cache::TokenCache, error::AuthError, user::AuthenticatedUser};
+
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(30);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// An unknown key id refetches the key set, but no more often than this
+const JWKS_REFETCH_INTERVAL: Duration = Duration::from_secs(60);
+// Fetched keys are only trusted this long, so keys auth_service retired stop being accepted
+const JWKS_MAX_AGE: Duration = Duration::from_secs(3600);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Verification {
+    // Ask auth_service's /verify-token about every token that isn't cached. Logouts and revoked
+    // sessions are seen as soon as the cache lets go of the token.
+    #[default]
+    Remote,
+    // Check signatures against the keys at /.well-known/jwks.json, without a round trip per token.
+    // A token stays valid until it expires even if its session ends, and auth_service has to sign
+    // with an asymmetric key, since shared secrets aren't published.
+    Jwks,
+}
+
+impl FromStr for Verification {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "remote" => Ok(Self::Remote),
+            "jwks" => Ok(Self::Jwks),
+            other => Err(format!("Unknown token verification {}, expected remote or jwks", other)),
+        }
+    }
+}
+
+struct FetchedJwks {
+    keys: JwkSet,
+    fetched_at: Instant,
+}
+
+// Checks auth tokens against the auth_service at `base_url`. Clones share the cache and the fetched keys.
+#[derive(Clone)]
+pub struct AuthClient {
+    base_url: String,
+    verification: Verification,
+    http_client: reqwest::Client,
+    cache: Arc<TokenCache>,
+    jwks: Arc<RwLock<Option<FetchedJwks>>>,
+}
+
+impl AuthClient {
+    pub fn new(base_url: impl Into<String>, verification: Verification) -> Self {
+        let http_client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_owned(),
+            verification,
+            http_client,
+            cache: Arc::new(TokenCache::new(DEFAULT_CACHE_TTL)),
+            jwks: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    // How long a valid token is trusted without checking it again, zero turns caching off
+    pub fn with_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache = Arc::new(TokenCache::new(ttl));
+        self
+    }
+
+    pub async fn authenticate(&self, token: &str) -> Result<AuthenticatedUser, AuthError> {
+        let now = Instant::now();
+
+        if let Some(user) = self.cache.get(token, now) {
+            return Ok(user);
+        }
+
+        let user = match self.verification {
+            Verification::Remote => self.verify_remotely(token).await?,
+            Verification::Jwks => self.verify_locally(token).await?,
+        };
+
+        self.cache.insert(token, &user, now);
+
+        Ok(user)
+    }
+
+    async fn verify_remotely(&self, token: &str) -> Result<AuthenticatedUser, AuthError> {
+        let response = self.http_client
+            .post(format!("{}/verify-token", self.base_url))
+            .json(&serde_json::json!({ "token": token }))
+            .send()
+            .await
+            .map_err(|_| AuthError::Unavailable)?;
+
+        match response.status() {
+            StatusCode::OK => read_claims(token),
+            StatusCode::BAD_REQUEST | StatusCode::UNAUTHORIZED | StatusCode::UNPROCESSABLE_ENTITY => {
+                Err(AuthError::InvalidToken)
+            }
+            _ => Err(AuthError::Unavailable),
+        }
+    }
+
+    async fn verify_locally(&self, token: &str) -> Result<AuthenticatedUser, AuthError> {
+        let header = decode_header(token).map_err(|_| AuthError::InvalidToken)?;
+        let kid = header.kid.ok_or(AuthError::InvalidToken)?;
+        let jwk = self.find_key(&kid).await?;
+
+        // A published key is only good for its own algorithm, whatever the token claims
+        let algorithm = match jwk.common.key_algorithm {
+            Some(key_algorithm) => Algorithm::from_str(&key_algorithm.to_string()).map_err(|_| AuthError::InvalidToken)?,
+            None => header.alg,
+        };
+
+        let key = DecodingKey::from_jwk(&jwk).map_err(|_| AuthError::InvalidToken)?;
+
+        // Tokens with an audience are auth_service's emailed links, which the default validation refuses
+        decode::<AuthenticatedUser>(token, &key, &Validation::new(algorithm))
+            .map(|data| data.claims)
+            .map_err(|_| AuthError::InvalidToken)
+    }
+
+    async fn find_key(&self, kid: &str) -> Result<Jwk, AuthError> {
+        let now = Instant::now();
+
+        if let Some(fetched) = self.jwks.read().await.as_ref() {
+            let age = now.duration_since(fetched.fetched_at);
+
+            if age < JWKS_MAX_AGE {
+                if let Some(jwk) = fetched.keys.find(kid) {
+                    return Ok(jwk.clone());
+                }
+
+                // Made-up key ids shouldn't make every request hit auth_service
+                if age < JWKS_REFETCH_INTERVAL {
+                    return Err(AuthError::InvalidToken);
+                }
+            }
+        }
+
+        let keys = self.fetch_jwks().await?;
+        let jwk = keys.find(kid).cloned();
+
+        *self.jwks.write().await = Some(FetchedJwks { keys, fetched_at: now });
+
+        jwk.ok_or(AuthError::InvalidToken)
+    }
+
+    async fn fetch_jwks(&self) -> Result<JwkSet, AuthError> {
+        self.http_client
+            .get(format!("{}/.well-known/jwks.json", self.base_url))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|_| AuthError::Unavailable)?
+            .json::<JwkSet>()
+            .await
+            .map_err(|_| AuthError::Unavailable)
+    }
+}
+
+// Only for tokens auth_service just vouched for, the signature isn't checked here
+fn read_claims(token: &str) -> Result<AuthenticatedUser, AuthError> {
+    let payload = token.split('.').nth(1).ok_or(AuthError::InvalidToken)?;
+    let payload = URL_SAFE_NO_PAD.decode(payload).map_err(|_| AuthError::InvalidToken)?;
+
+    serde_json::from_slice(&payload).map_err(|_| AuthError::InvalidToken)
+}
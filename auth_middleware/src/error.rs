@@ -0,0 +1,39 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AuthError {
+    MissingToken,
+    InvalidToken,
+    // The token is valid, but lacks a role or permission the route requires
+    MissingPermission,
+    // auth_service couldn't be asked, so nothing can be said about the token
+    Unavailable,
+}
+
+// Same shape as auth_service's error bodies
+#[derive(Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        let (status, error_message) = match self {
+            AuthError::MissingToken => (StatusCode::UNAUTHORIZED, "Missing auth token"),
+            AuthError::InvalidToken => (StatusCode::UNAUTHORIZED, "Invalid auth token"),
+            AuthError::MissingPermission => (StatusCode::FORBIDDEN, "Missing permission"),
+            AuthError::Unavailable => (StatusCode::INTERNAL_SERVER_ERROR, "Unexpected error"),
+        };
+
+        let body = Json(ErrorResponse {
+            error: error_message.to_string(),
+        });
+
+        (status, body).into_response()
+    }
+}
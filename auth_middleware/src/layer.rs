@@ -0,0 +1,104 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll}
+};
+
+use axum::{
+    extract::Request,
+    response::{IntoResponse, Response}
+};
+use tower::{Layer, Service};
+
+use crate::{
+    client::AuthClient,
+    error::AuthError,
+    user::{auth_token, AuthenticatedUser}
+};
+
+// Refuses requests without a valid auth token before they reach the wrapped routes, which can
+// then take the `AuthenticatedUser` as an extractor or an `Extension`
+#[derive(Clone)]
+pub struct AuthLayer {
+    client: AuthClient,
+    required_roles: Vec<String>,
+    required_permissions: Vec<String>,
+}
+
+impl AuthLayer {
+    pub fn new(client: AuthClient) -> Self {
+        Self { client, required_roles: vec![], required_permissions: vec![] }
+    }
+
+    // Users without the role get a 403
+    pub fn require_role(mut self, role: impl Into<String>) -> Self {
+        self.required_roles.push(role.into());
+        self
+    }
+
+    pub fn require_permission(mut self, permission: impl Into<String>) -> Self {
+        self.required_permissions.push(permission.into());
+        self
+    }
+
+    async fn authorize(&self, token: Option<String>) -> Result<AuthenticatedUser, AuthError> {
+        let token = token.ok_or(AuthError::MissingToken)?;
+        let user = self.client.authenticate(&token).await?;
+
+        for role in &self.required_roles {
+            user.require_role(role)?;
+        }
+
+        for permission in &self.required_permissions {
+            user.require_permission(permission)?;
+        }
+
+        Ok(user)
+    }
+}
+
+impl<S> Layer<S> for AuthLayer {
+    type Service = AuthService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AuthService { inner, layer: self.clone() }
+    }
+}
+
+#[derive(Clone)]
+pub struct AuthService<S> {
+    inner: S,
+    layer: AuthLayer,
+}
+
+impl<S> Service<Request> for AuthService<S>
+where
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request) -> Self::Future {
+        // The service that was polled ready is the one to call, and a fresh clone takes its place
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+        let token = auth_token(request.headers());
+
+        Box::pin(async move {
+            match layer.authorize(token).await {
+                Ok(user) => {
+                    request.extensions_mut().insert(user);
+                    inner.call(request).await
+                }
+                Err(e) => Ok(e.into_response()),
+            }
+        })
+    }
+}
@@ -0,0 +1,14 @@
+// Authentication for services sitting behind auth_service. Requests carry the auth token in the `jwt`
+// cookie or an `Authorization: Bearer` header, and are checked either by asking auth_service's
+// /verify-token, or locally against the keys it publishes at /.well-known/jwks.json.
+
+mod cache;
+pub mod client;
+pub mod error;
+pub mod layer;
+pub mod user;
+
+pub use client::{AuthClient, Verification};
+pub use error::AuthError;
+pub use layer::{AuthLayer, AuthService};
+pub use user::AuthenticatedUser;
@@ -0,0 +1,116 @@
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts},
+    http::{header::AUTHORIZATION, request::Parts, HeaderMap}
+};
+use axum_extra::extract::CookieJar;
+use serde::Deserialize;
+
+use crate::{client::AuthClient, error::AuthError};
+
+pub const JWT_COOKIE_NAME: &str = "jwt";
+
+// The claims of a valid auth token, see auth_service's `Claims`
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct AuthenticatedUser {
+    // Stays the same when the user changes their email address
+    #[serde(rename = "sub")]
+    pub user_id: String,
+    #[serde(rename = "sid")]
+    pub session_id: String,
+    // Only issued when auth_service has JWT_EMAIL_CLAIM set
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub permissions: Vec<String>,
+    #[serde(default)]
+    pub org_id: Option<String>,
+    #[serde(default)]
+    pub org_role: Option<String>,
+    // Unix timestamp the token expires at
+    #[serde(rename = "exp")]
+    pub expires_at: u64,
+}
+
+impl AuthenticatedUser {
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.permissions.iter().any(|p| p == permission)
+    }
+
+    // For handlers to check on top of being logged in, e.g. `user.require_role("admin")?`
+    pub fn require_role(&self, role: &str) -> Result<(), AuthError> {
+        self.has_role(role).then_some(()).ok_or(AuthError::MissingPermission)
+    }
+
+    pub fn require_permission(&self, permission: &str) -> Result<(), AuthError> {
+        self.has_permission(permission).then_some(()).ok_or(AuthError::MissingPermission)
+    }
+}
+
+// Reuses the user an `AuthLayer` already authenticated, otherwise checks the request's token with the
+// `AuthClient` of the router state
+#[async_trait]
+impl<S> FromRequestParts<S> for AuthenticatedUser
+where
+    AuthClient: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(user) = parts.extensions.get::<AuthenticatedUser>() {
+            return Ok(user.clone());
+        }
+
+        let token = auth_token(&parts.headers).ok_or(AuthError::MissingToken)?;
+        let user = AuthClient::from_ref(state).authenticate(&token).await?;
+
+        parts.extensions.insert(user.clone());
+
+        Ok(user)
+    }
+}
+
+// The bearer token when there is one, like auth_service prefers it, otherwise the auth cookie
+pub fn auth_token(headers: &HeaderMap) -> Option<String> {
+    let bearer = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split_once(' '))
+        .filter(|(scheme, token)| scheme.eq_ignore_ascii_case("bearer") && !token.trim().is_empty())
+        .map(|(_, token)| token.trim().to_owned());
+
+    bearer.or_else(|| {
+        CookieJar::from_headers(headers)
+            .get(JWT_COOKIE_NAME)
+            .map(|cookie| cookie.value().to_owned())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{header::COOKIE, HeaderValue};
+
+    use super::*;
+
+    #[test]
+    fn test_auth_token_prefers_bearer_header() {
+        let mut headers = HeaderMap::new();
+        headers.insert(COOKIE, HeaderValue::from_static("jwt=from-cookie"));
+        assert_eq!(auth_token(&headers), Some(String::from("from-cookie")));
+
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Basic dXNlcjpwdw=="));
+        assert_eq!(auth_token(&headers), Some(String::from("from-cookie")));
+
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer from-header"));
+        assert_eq!(auth_token(&headers), Some(String::from("from-header")));
+
+        assert_eq!(auth_token(&HeaderMap::new()), None);
+    }
+}
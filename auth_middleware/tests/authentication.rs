@@ -0,0 +1,228 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use auth_middleware::{AuthClient, AuthError, AuthLayer, AuthenticatedUser, Verification};
+use axum::{
+    body::Body,
+    http::{header::{AUTHORIZATION, COOKIE}, Request, StatusCode},
+    routing::get,
+    Router
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use ring::{rand::SystemRandom, signature::{Ed25519KeyPair, KeyPair}};
+use tower::ServiceExt;
+use wiremock::{
+    matchers::{body_json, method, path},
+    Mock, MockServer, ResponseTemplate
+};
+
+const KID: &str = "ed25519";
+
+fn claims(extra: serde_json::Value) -> serde_json::Value {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+    let mut claims = serde_json::json!({
+        "sub": "3f0cd1e4-5d1a-4a43-9d43-8a1bbd5c3e59",
+        "sid": "6a3c9a56-0f44-4b1f-9d0e-2bb0e1b8f0a1",
+        "roles": ["admin"],
+        "exp": now + 600,
+        "iat": now as f64
+    });
+
+    for (key, value) in extra.as_object().unwrap() {
+        claims[key] = value.clone();
+    }
+
+    claims
+}
+
+// Like an HS256 token, but the remote tests never check signatures here anyway
+fn unsigned_token(claims: &serde_json::Value) -> String {
+    let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#);
+    let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+
+    format!("{}.{}.signature", header, payload)
+}
+
+struct SigningKey {
+    pkcs8: Vec<u8>,
+    public_key: Vec<u8>,
+}
+
+impl SigningKey {
+    fn generate() -> Self {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let public_key = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap().public_key().as_ref().to_vec();
+
+        Self { pkcs8: pkcs8.as_ref().to_vec(), public_key }
+    }
+
+    fn sign(&self, claims: &serde_json::Value) -> String {
+        let mut header = Header::new(Algorithm::EdDSA);
+        header.kid = Some(KID.to_owned());
+
+        encode(&header, claims, &EncodingKey::from_ed_der(&self.pkcs8)).unwrap()
+    }
+
+    fn jwks(&self) -> serde_json::Value {
+        serde_json::json!({
+            "keys": [{
+                "kty": "OKP",
+                "crv": "Ed25519",
+                "x": URL_SAFE_NO_PAD.encode(&self.public_key),
+                "kid": KID,
+                "alg": "EdDSA",
+                "use": "sig"
+            }]
+        })
+    }
+}
+
+async fn mock_verify_token(server: &MockServer, token: &str, status: u16, calls: u64) {
+    Mock::given(method("POST"))
+        .and(path("/verify-token"))
+        .and(body_json(serde_json::json!({ "token": token })))
+        .respond_with(ResponseTemplate::new(status))
+        .expect(calls)
+        .mount(server)
+        .await;
+}
+
+async fn mock_jwks(server: &MockServer, key: &SigningKey) {
+    Mock::given(method("GET"))
+        .and(path("/.well-known/jwks.json"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(key.jwks()))
+        .mount(server)
+        .await;
+}
+
+async fn whoami(user: AuthenticatedUser) -> String {
+    user.user_id
+}
+
+fn request(header: (axum::http::HeaderName, String)) -> Request<Body> {
+    Request::builder()
+        .uri("/whoami")
+        .header(header.0, header.1)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn should_verify_remotely_and_cache_valid_tokens() {
+    let server = MockServer::start().await;
+    let token = unsigned_token(&claims(serde_json::json!({})));
+
+    // The second check is answered from the cache
+    mock_verify_token(&server, &token, 200, 1).await;
+
+    let client = AuthClient::new(server.uri(), Verification::Remote);
+
+    for _ in 0..2 {
+        let user = client.authenticate(&token).await.expect("Token should be valid");
+        assert_eq!(user.user_id, "3f0cd1e4-5d1a-4a43-9d43-8a1bbd5c3e59");
+        assert!(user.has_role("admin"));
+    }
+}
+
+#[tokio::test]
+async fn should_refuse_tokens_auth_service_refuses() {
+    let server = MockServer::start().await;
+    let token = unsigned_token(&claims(serde_json::json!({})));
+
+    // Invalid tokens aren't cached, auth_service gets asked every time
+    mock_verify_token(&server, &token, 401, 2).await;
+
+    let client = AuthClient::new(server.uri(), Verification::Remote);
+
+    assert_eq!(client.authenticate(&token).await, Err(AuthError::InvalidToken));
+    assert_eq!(client.authenticate(&token).await, Err(AuthError::InvalidToken));
+}
+
+#[tokio::test]
+async fn should_report_unreachable_auth_service() {
+    let server = MockServer::start().await;
+    let token = unsigned_token(&claims(serde_json::json!({})));
+
+    mock_verify_token(&server, &token, 503, 1).await;
+
+    let client = AuthClient::new(server.uri(), Verification::Remote);
+
+    assert_eq!(client.authenticate(&token).await, Err(AuthError::Unavailable));
+}
+
+#[tokio::test]
+async fn should_verify_locally_with_jwks() {
+    let server = MockServer::start().await;
+    let key = SigningKey::generate();
+    mock_jwks(&server, &key).await;
+
+    let client = AuthClient::new(server.uri(), Verification::Jwks);
+
+    let token = key.sign(&claims(serde_json::json!({ "org_id": "acme" })));
+    let user = client.authenticate(&token).await.expect("Token should be valid");
+    assert_eq!(user.org_id.as_deref(), Some("acme"));
+
+    // Signed by a key that isn't published under that key id
+    let forged = SigningKey::generate().sign(&claims(serde_json::json!({})));
+    assert_eq!(client.authenticate(&forged).await, Err(AuthError::InvalidToken));
+
+    // Emailed links are signed with the same keys, but aren't auth tokens
+    let link = key.sign(&claims(serde_json::json!({ "aud": "password-reset" })));
+    assert_eq!(client.authenticate(&link).await, Err(AuthError::InvalidToken));
+
+    let expired = key.sign(&claims(serde_json::json!({ "exp": 1 })));
+    assert_eq!(client.authenticate(&expired).await, Err(AuthError::InvalidToken));
+}
+
+#[tokio::test]
+async fn should_extract_user_from_cookie_or_bearer_token() {
+    let server = MockServer::start().await;
+    let token = unsigned_token(&claims(serde_json::json!({})));
+    mock_verify_token(&server, &token, 200, 1).await;
+
+    let app = Router::new()
+        .route("/whoami", get(whoami))
+        .with_state(AuthClient::new(server.uri(), Verification::Remote));
+
+    let requests = [
+        request((COOKIE, format!("jwt={}", token))),
+        request((AUTHORIZATION, format!("Bearer {}", token))),
+    ];
+
+    for request in requests {
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    let response = app.oneshot(Request::get("/whoami").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn should_guard_routes_with_layer() {
+    let server = MockServer::start().await;
+    let admin = unsigned_token(&claims(serde_json::json!({})));
+    let member = unsigned_token(&claims(serde_json::json!({ "roles": [] })));
+    mock_verify_token(&server, &admin, 200, 1).await;
+    mock_verify_token(&server, &member, 200, 1).await;
+    mock_verify_token(&server, "not-a-jwt", 401, 1).await;
+
+    let client = AuthClient::new(server.uri(), Verification::Remote);
+
+    let app = Router::new()
+        .route("/whoami", get(whoami))
+        .route_layer(AuthLayer::new(client.clone()).require_role("admin"))
+        .with_state(client);
+
+    let test_cases = [
+        (admin.as_str(), StatusCode::OK),
+        (member.as_str(), StatusCode::FORBIDDEN),
+        ("not-a-jwt", StatusCode::UNAUTHORIZED),
+    ];
+
+    for (token, status) in test_cases {
+        let response = app.clone().oneshot(request((AUTHORIZATION, format!("Bearer {}", token)))).await.unwrap();
+        assert_eq!(response.status(), status, "Failed for token: {}", token);
+    }
+}
@@ -32,6 +32,19 @@ signupLoginLink.addEventListener("click", (e) => {
 
 // -----------------------------------------------------
 
+// Set by /authorize when it needs the user to log in first. Only paths of /authorize
+// are followed, so the parameter can't send users anywhere else.
+function returnAfterLogin() {
+    const returnTo = new URLSearchParams(window.location.search).get("returnTo");
+
+    if (returnTo !== null && returnTo.startsWith("/authorize?")) {
+        window.location.assign(returnTo);
+        return true;
+    }
+
+    return false;
+}
+
 const loginForm = document.getElementById("login-form");
 const loginButton = document.getElementById("login-form-submit");
 const loginErrAlter = document.getElementById("login-err-alert");
@@ -66,6 +79,9 @@ loginButton.addEventListener("click", (e) => {
             loginForm.email.value = "";
             loginForm.password.value = "";
             loginErrAlter.style.display = "none";
+            if (returnAfterLogin()) {
+                return;
+            }
             alert("You have successfully logged in.");
         } else {
             response.json().then(data => {
@@ -145,6 +161,9 @@ TwoFAButton.addEventListener("click", (e) => {
             TwoFAForm.email_code.value = "";
             TwoFAForm.login_attempt_id.value = "";
             TwoFAErrAlter.style.display = "none";
+            if (returnAfterLogin()) {
+                return;
+            }
             alert("You have successfully logged in.");
             loginSection.style.display = "block";
             twoFASection.style.display = "none";
@@ -0,0 +1,17 @@
+-- Add up migration script here
+-- Record which second factor each user has enrolled instead of a single email 2FA flag
+ALTER TABLE users
+   ADD COLUMN two_fa_method TEXT NOT NULL DEFAULT 'none'
+   CHECK (two_fa_method IN ('none', 'email', 'totp'));
+
+UPDATE users SET two_fa_method = 'email' WHERE requires_2fa;
+
+ALTER TABLE users DROP COLUMN requires_2fa;
+
+-- A pending secret is only promoted once the user proves their authenticator app produces valid codes
+CREATE TABLE IF NOT EXISTS totp_secrets(
+   email TEXT NOT NULL PRIMARY KEY REFERENCES users(email) ON DELETE CASCADE,
+   secret TEXT,
+   pending_secret TEXT,
+   last_used_step BIGINT
+);
@@ -0,0 +1,10 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS webauthn_credentials;
+
+UPDATE users SET two_fa_method = 'none' WHERE two_fa_method = 'passkey';
+
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_two_fa_method_check;
+
+ALTER TABLE users
+   ADD CONSTRAINT users_two_fa_method_check
+   CHECK (two_fa_method IN ('none', 'email', 'totp'));
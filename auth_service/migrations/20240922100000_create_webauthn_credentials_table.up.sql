@@ -0,0 +1,18 @@
+-- Add up migration script here
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_two_fa_method_check;
+
+ALTER TABLE users
+   ADD CONSTRAINT users_two_fa_method_check
+   CHECK (two_fa_method IN ('none', 'email', 'totp', 'passkey'));
+
+CREATE TABLE IF NOT EXISTS webauthn_credentials(
+   credential_id TEXT NOT NULL PRIMARY KEY,
+   email TEXT NOT NULL REFERENCES users(email) ON DELETE CASCADE,
+   user_handle TEXT NOT NULL,
+   public_key BYTEA NOT NULL,
+   sign_count BIGINT NOT NULL DEFAULT 0,
+   created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+   last_used_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS webauthn_credentials_email_idx ON webauthn_credentials(email);
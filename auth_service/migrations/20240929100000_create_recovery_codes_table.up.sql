@@ -0,0 +1,9 @@
+-- Add up migration script here
+-- Single-use codes that stand in for a second factor, only their Argon2 hashes are stored
+CREATE TABLE IF NOT EXISTS recovery_codes(
+   id BIGSERIAL PRIMARY KEY,
+   email TEXT NOT NULL REFERENCES users(email) ON DELETE CASCADE,
+   code_hash TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS recovery_codes_email_idx ON recovery_codes(email);
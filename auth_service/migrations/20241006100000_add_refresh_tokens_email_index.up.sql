@@ -0,0 +1 @@
+CREATE INDEX IF NOT EXISTS refresh_tokens_email_idx ON refresh_tokens(email);
@@ -0,0 +1,6 @@
+-- Add up migration script here
+-- Accounts that existed before verification was introduced are trusted, new ones start out unverified
+ALTER TABLE users ADD COLUMN email_verified BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE users ALTER COLUMN email_verified SET DEFAULT FALSE;
+
+ALTER TABLE users ADD COLUMN created_at BIGINT NOT NULL DEFAULT EXTRACT(EPOCH FROM NOW())::BIGINT;
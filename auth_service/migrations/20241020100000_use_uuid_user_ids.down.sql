@@ -0,0 +1,37 @@
+-- Add down migration script here
+ALTER TABLE refresh_tokens ADD COLUMN email TEXT;
+ALTER TABLE totp_secrets ADD COLUMN email TEXT;
+ALTER TABLE webauthn_credentials ADD COLUMN email TEXT;
+ALTER TABLE recovery_codes ADD COLUMN email TEXT;
+
+UPDATE refresh_tokens t SET email = u.email FROM users u WHERE u.id = t.user_id;
+UPDATE totp_secrets t SET email = u.email FROM users u WHERE u.id = t.user_id;
+UPDATE webauthn_credentials t SET email = u.email FROM users u WHERE u.id = t.user_id;
+UPDATE recovery_codes t SET email = u.email FROM users u WHERE u.id = t.user_id;
+
+ALTER TABLE refresh_tokens DROP COLUMN user_id;
+ALTER TABLE totp_secrets DROP COLUMN user_id;
+ALTER TABLE webauthn_credentials DROP COLUMN user_id;
+ALTER TABLE recovery_codes DROP COLUMN user_id;
+
+ALTER TABLE users DROP CONSTRAINT users_email_key;
+ALTER TABLE users DROP CONSTRAINT users_pkey;
+ALTER TABLE users ADD PRIMARY KEY (email);
+ALTER TABLE users DROP COLUMN id;
+
+ALTER TABLE refresh_tokens
+   ALTER COLUMN email SET NOT NULL,
+   ADD FOREIGN KEY (email) REFERENCES users(email) ON DELETE CASCADE;
+ALTER TABLE totp_secrets
+   ADD PRIMARY KEY (email),
+   ADD FOREIGN KEY (email) REFERENCES users(email) ON DELETE CASCADE;
+ALTER TABLE webauthn_credentials
+   ALTER COLUMN email SET NOT NULL,
+   ADD FOREIGN KEY (email) REFERENCES users(email) ON DELETE CASCADE;
+ALTER TABLE recovery_codes
+   ALTER COLUMN email SET NOT NULL,
+   ADD FOREIGN KEY (email) REFERENCES users(email) ON DELETE CASCADE;
+
+CREATE INDEX IF NOT EXISTS refresh_tokens_email_idx ON refresh_tokens(email);
+CREATE INDEX IF NOT EXISTS webauthn_credentials_email_idx ON webauthn_credentials(email);
+CREATE INDEX IF NOT EXISTS recovery_codes_email_idx ON recovery_codes(email);
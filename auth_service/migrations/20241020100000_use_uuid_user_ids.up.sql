@@ -0,0 +1,40 @@
+-- Add up migration script here
+-- Users get a surrogate key so the email can change, dependent tables follow the id instead of the address
+ALTER TABLE users ADD COLUMN id UUID NOT NULL DEFAULT gen_random_uuid();
+
+ALTER TABLE refresh_tokens ADD COLUMN user_id UUID;
+ALTER TABLE totp_secrets ADD COLUMN user_id UUID;
+ALTER TABLE webauthn_credentials ADD COLUMN user_id UUID;
+ALTER TABLE recovery_codes ADD COLUMN user_id UUID;
+
+UPDATE refresh_tokens t SET user_id = u.id FROM users u WHERE u.email = t.email;
+UPDATE totp_secrets t SET user_id = u.id FROM users u WHERE u.email = t.email;
+UPDATE webauthn_credentials t SET user_id = u.id FROM users u WHERE u.email = t.email;
+UPDATE recovery_codes t SET user_id = u.id FROM users u WHERE u.email = t.email;
+
+-- Dropping the email columns also drops their foreign keys, indexes and the totp_secrets primary key
+ALTER TABLE refresh_tokens DROP COLUMN email;
+ALTER TABLE totp_secrets DROP COLUMN email;
+ALTER TABLE webauthn_credentials DROP COLUMN email;
+ALTER TABLE recovery_codes DROP COLUMN email;
+
+ALTER TABLE users DROP CONSTRAINT users_pkey;
+ALTER TABLE users ADD PRIMARY KEY (id);
+ALTER TABLE users ADD CONSTRAINT users_email_key UNIQUE (email);
+
+ALTER TABLE refresh_tokens
+   ALTER COLUMN user_id SET NOT NULL,
+   ADD FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE;
+ALTER TABLE totp_secrets
+   ADD PRIMARY KEY (user_id),
+   ADD FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE;
+ALTER TABLE webauthn_credentials
+   ALTER COLUMN user_id SET NOT NULL,
+   ADD FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE;
+ALTER TABLE recovery_codes
+   ALTER COLUMN user_id SET NOT NULL,
+   ADD FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE;
+
+CREATE INDEX IF NOT EXISTS refresh_tokens_user_id_idx ON refresh_tokens(user_id);
+CREATE INDEX IF NOT EXISTS webauthn_credentials_user_id_idx ON webauthn_credentials(user_id);
+CREATE INDEX IF NOT EXISTS recovery_codes_user_id_idx ON recovery_codes(user_id);
@@ -0,0 +1,11 @@
+-- Add up migration script here
+-- One row per completed login, kept so users can review and export where their account was used
+CREATE TABLE IF NOT EXISTS login_events(
+   id BIGSERIAL PRIMARY KEY,
+   user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+   logged_in_at BIGINT NOT NULL,
+   ip TEXT,
+   user_agent TEXT
+);
+
+CREATE INDEX IF NOT EXISTS login_events_user_id_idx ON login_events(user_id);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS users_delete_after_idx;
+ALTER TABLE users DROP COLUMN delete_after;
@@ -0,0 +1,5 @@
+-- Add up migration script here
+-- Set when a user deletes their account with a grace period, the row is purged once it passes
+ALTER TABLE users ADD COLUMN delete_after BIGINT;
+
+CREATE INDEX IF NOT EXISTS users_delete_after_idx ON users(delete_after) WHERE delete_after IS NOT NULL;
@@ -0,0 +1,16 @@
+-- Add up migration script here
+-- Named sets of permissions, embedded in auth tokens so downstream services can authorize requests
+CREATE TABLE IF NOT EXISTS roles(
+   name TEXT PRIMARY KEY,
+   permissions TEXT[] NOT NULL DEFAULT '{}'
+);
+
+CREATE TABLE IF NOT EXISTS user_roles(
+   user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+   role TEXT NOT NULL REFERENCES roles(name) ON DELETE CASCADE,
+   PRIMARY KEY (user_id, role)
+);
+
+INSERT INTO roles (name, permissions)
+VALUES ('admin', '{users:read,users:write}')
+ON CONFLICT (name) DO NOTHING;
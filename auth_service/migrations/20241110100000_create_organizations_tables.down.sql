@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS active_organizations;
+DROP TABLE IF EXISTS organization_members;
+DROP TABLE IF EXISTS organizations;
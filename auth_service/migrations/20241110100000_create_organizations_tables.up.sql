@@ -0,0 +1,23 @@
+-- Add up migration script here
+-- Customer organizations sharing the deployment, users join them with a role of their own in each
+CREATE TABLE IF NOT EXISTS organizations(
+   id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+   name TEXT NOT NULL,
+   created_at BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS organization_members(
+   org_id UUID NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+   user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+   role TEXT NOT NULL,
+   PRIMARY KEY (org_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS organization_members_user_id_idx ON organization_members(user_id);
+
+-- The organization a user is acting in, which goes away with their membership
+CREATE TABLE IF NOT EXISTS active_organizations(
+   user_id UUID PRIMARY KEY,
+   org_id UUID NOT NULL,
+   FOREIGN KEY (org_id, user_id) REFERENCES organization_members(org_id, user_id) ON DELETE CASCADE
+);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS users_created_at_idx;
+ALTER TABLE users DROP COLUMN password_reset_required;
+ALTER TABLE users DROP COLUMN locked;
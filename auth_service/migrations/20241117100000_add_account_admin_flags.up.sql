@@ -0,0 +1,7 @@
+-- Add up migration script here
+-- Both are set through the admin API and checked whenever the user logs in
+ALTER TABLE users ADD COLUMN locked BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE users ADD COLUMN password_reset_required BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Admins page through users ordered by signup time
+CREATE INDEX IF NOT EXISTS users_created_at_idx ON users(created_at, email);
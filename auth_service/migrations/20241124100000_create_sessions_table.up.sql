@@ -0,0 +1,13 @@
+-- Add up migration script here
+-- One row per logged-in device. The id is the `sid` claim of its auth tokens and the family of its refresh tokens.
+CREATE TABLE IF NOT EXISTS sessions(
+   id UUID PRIMARY KEY,
+   user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+   device TEXT,
+   ip TEXT,
+   user_agent TEXT,
+   created_at BIGINT NOT NULL,
+   last_seen_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS sessions_user_id_idx ON sessions(user_id);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS sessions_oauth_client_id_idx;
+ALTER TABLE sessions DROP COLUMN IF EXISTS oauth_client_id;
+DROP TABLE IF EXISTS oauth_clients;
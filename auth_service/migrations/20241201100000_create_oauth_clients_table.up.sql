@@ -0,0 +1,14 @@
+-- Add up migration script here
+-- Third-party and SPA clients allowed to use /authorize and /token. Public clients have no secret.
+CREATE TABLE IF NOT EXISTS oauth_clients(
+   id UUID PRIMARY KEY,
+   name TEXT NOT NULL,
+   redirect_uris TEXT[] NOT NULL,
+   secret_hash TEXT,
+   created_at BIGINT NOT NULL
+);
+
+-- The client a session was started for through /token, whose refresh tokens only that client can use
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS oauth_client_id UUID REFERENCES oauth_clients(id) ON DELETE CASCADE;
+
+CREATE INDEX IF NOT EXISTS sessions_oauth_client_id_idx ON sessions(oauth_client_id);
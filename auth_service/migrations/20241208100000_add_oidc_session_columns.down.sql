@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE sessions DROP COLUMN IF EXISTS scopes;
+ALTER TABLE sessions DROP COLUMN IF EXISTS auth_methods;
+ALTER TABLE sessions DROP COLUMN IF EXISTS authenticated_at;
@@ -0,0 +1,11 @@
+-- Add up migration script here
+-- When and how the user authenticated, for the `auth_time` and `amr` claims of ID tokens. Sessions
+-- from before this migration were started by a login at their creation, by a method no longer known.
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS authenticated_at BIGINT;
+UPDATE sessions SET authenticated_at = created_at WHERE authenticated_at IS NULL;
+ALTER TABLE sessions ALTER COLUMN authenticated_at SET NOT NULL;
+
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS auth_methods TEXT[] NOT NULL DEFAULT '{}';
+
+-- The scopes the user authorized the OAuth client of the session for
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS scopes TEXT[] NOT NULL DEFAULT '{}';
@@ -1,7 +1,9 @@
-use std::error::Error;
+use std::{error::Error, net::SocketAddr};
 use axum::{
+    extract::{connect_info::IntoMakeServiceWithConnectInfo, ConnectInfo},
     http::Method,
-    routing::post,
+    middleware::{self, AddExtension},
+    routing::{delete, get, post},
     serve::Serve,
     Router
 };
@@ -12,17 +14,65 @@ use crate::services::tracing::{make_span_with_request_id, on_request, on_respons
 pub mod config;
 pub mod state;
 pub mod email_client;
+pub mod rate_limit;
 
 use state::AppState;
 use super::route_handlers::{
+    accept_invitation,
+    admin_delete_oauth_client,
+    admin_delete_user,
+    admin_force_password_reset,
+    admin_get_user,
+    admin_list_oauth_clients,
+    admin_list_users,
+    admin_lock_user,
+    admin_register_oauth_client,
+    admin_revoke_sessions,
+    admin_set_two_fa,
+    admin_unlock_user,
+    change_email,
+    change_email_cancel,
+    change_email_confirm,
+    change_password,
+    create_organization,
+    delete_account,
+    export_account,
+    get_recovery_codes,
+    invite_member,
+    jwks,
+    list_organizations,
+    list_sessions,
     login,
     logout,
+    oauth_authorize,
+    oauth_token,
+    oidc_end_session,
+    oidc_end_session_form,
+    openid_configuration,
+    password_reset_confirm,
+    password_reset_request,
+    refresh,
+    regenerate_recovery_codes,
+    resend_2fa,
+    resend_verification_email,
+    revoke_other_sessions,
+    revoke_session,
     signup,
+    switch_organization,
+    totp_confirm,
+    totp_enroll,
+    unlock_account,
+    userinfo,
     verify_2fa,
-    verify_token
+    verify_email,
+    verify_token,
+    webauthn_login_finish,
+    webauthn_login_start,
+    webauthn_register_finish,
+    webauthn_register_start
 };
 pub struct App {
-    server: Serve<Router, Router>,
+    server: Serve<IntoMakeServiceWithConnectInfo<Router, SocketAddr>, AddExtension<Router, ConnectInfo<SocketAddr>>>,
     pub address: String,
 }
 
@@ -36,7 +86,7 @@ impl App {
         ];
 
         let cors = CorsLayer::new()
-            .allow_methods([Method::GET, Method::POST])
+            .allow_methods([Method::GET, Method::POST, Method::DELETE])
             .allow_credentials(true)
             .allow_origin(allowed_origins);
 
@@ -45,8 +95,54 @@ impl App {
             .route(paths.signup, post(signup))
             .route(paths.login, post(login))
             .route(paths.verify_2fa, post(verify_2fa))
+            .route(paths.resend_2fa, post(resend_2fa))
             .route(paths.logout, post(logout))
+            .route(paths.refresh, post(refresh))
             .route(paths.verify_token, post(verify_token))
+            .route(paths.change_password, post(change_password))
+            .route(paths.account, delete(delete_account))
+            .route(paths.account_export, get(export_account))
+            .route(paths.change_email, post(change_email))
+            .route(paths.change_email_confirm, post(change_email_confirm))
+            .route(paths.change_email_cancel, post(change_email_cancel))
+            .route(paths.verify_email, post(verify_email))
+            .route(paths.verify_email_resend, post(resend_verification_email))
+            .route(paths.password_reset_request, post(password_reset_request))
+            .route(paths.password_reset_confirm, post(password_reset_confirm))
+            .route(paths.unlock_account, post(unlock_account))
+            .route(paths.sessions, get(list_sessions))
+            .route(paths.sessions_revoke, post(revoke_session))
+            .route(paths.sessions_revoke_others, post(revoke_other_sessions))
+            .route(paths.totp_enroll, post(totp_enroll))
+            .route(paths.totp_confirm, post(totp_confirm))
+            .route(paths.recovery_codes, get(get_recovery_codes))
+            .route(paths.recovery_codes_regenerate, post(regenerate_recovery_codes))
+            .route(paths.webauthn_register_start, post(webauthn_register_start))
+            .route(paths.webauthn_register_finish, post(webauthn_register_finish))
+            .route(paths.webauthn_login_start, post(webauthn_login_start))
+            .route(paths.webauthn_login_finish, post(webauthn_login_finish))
+            .route(paths.organizations, get(list_organizations).post(create_organization))
+            .route(paths.organization_invitations, post(invite_member))
+            .route(paths.organization_invitations_accept, post(accept_invitation))
+            .route(paths.organization_switch, post(switch_organization))
+            .route(paths.admin_users, get(admin_list_users).delete(admin_delete_user))
+            .route(paths.admin_user_details, get(admin_get_user))
+            .route(paths.admin_user_2fa, post(admin_set_two_fa))
+            .route(paths.admin_user_lock, post(admin_lock_user))
+            .route(paths.admin_user_unlock, post(admin_unlock_user))
+            .route(paths.admin_user_password_reset, post(admin_force_password_reset))
+            .route(paths.admin_user_revoke_sessions, post(admin_revoke_sessions))
+            .route(
+                paths.admin_oauth_clients,
+                get(admin_list_oauth_clients).post(admin_register_oauth_client).delete(admin_delete_oauth_client)
+            )
+            .route(paths.oauth_authorize, get(oauth_authorize))
+            .route(paths.oauth_token, post(oauth_token))
+            .route(paths.userinfo, get(userinfo).post(userinfo))
+            .route(paths.end_session, get(oidc_end_session).post(oidc_end_session_form))
+            .route(paths.jwks, get(jwks))
+            .route(paths.openid_configuration, get(openid_configuration))
+            .route_layer(middleware::from_fn_with_state(app_state.clone(), rate_limit::rate_limit))
             .with_state(app_state)
             .layer(cors)
             .layer(
@@ -58,7 +154,8 @@ impl App {
 
         let listener = tokio::net::TcpListener::bind(address).await?;
         let address = listener.local_addr()?.to_string();
-        let server = axum::serve(listener, router);
+        // Handlers record the client address alongside login attempts
+        let server = axum::serve(listener, router.into_make_service_with_connect_info::<SocketAddr>());
 
         Ok(App { server, address })
     }
@@ -7,9 +7,51 @@ impl AppConfig<'_> {
         let paths = Paths {
             login: "/login",
             logout: "/logout",
+            refresh: "/refresh",
             signup: "/signup",
             verify_2fa: "/verify-2fa",
-            verify_token: "/verify-token"
+            resend_2fa: "/resend-2fa",
+            verify_token: "/verify-token",
+            change_password: "/change-password",
+            account: "/account",
+            account_export: "/account/export",
+            change_email: "/change-email",
+            change_email_confirm: "/change-email/confirm",
+            change_email_cancel: "/change-email/cancel",
+            verify_email: "/verify-email",
+            verify_email_resend: "/verify-email/resend",
+            password_reset_request: "/password-reset/request",
+            password_reset_confirm: "/password-reset/confirm",
+            unlock_account: "/unlock-account",
+            sessions: "/sessions",
+            sessions_revoke: "/sessions/revoke",
+            sessions_revoke_others: "/sessions/revoke-others",
+            totp_enroll: "/2fa/totp/enroll",
+            totp_confirm: "/2fa/totp/confirm",
+            recovery_codes: "/2fa/recovery-codes",
+            recovery_codes_regenerate: "/2fa/recovery-codes/regenerate",
+            webauthn_register_start: "/webauthn/register/start",
+            webauthn_register_finish: "/webauthn/register/finish",
+            webauthn_login_start: "/webauthn/login/start",
+            webauthn_login_finish: "/webauthn/login/finish",
+            organizations: "/orgs",
+            organization_invitations: "/orgs/invitations",
+            organization_invitations_accept: "/orgs/invitations/accept",
+            organization_switch: "/orgs/switch",
+            admin_users: "/admin/users",
+            admin_user_details: "/admin/users/details",
+            admin_user_2fa: "/admin/users/2fa",
+            admin_user_lock: "/admin/users/lock",
+            admin_user_unlock: "/admin/users/unlock",
+            admin_user_password_reset: "/admin/users/password-reset",
+            admin_user_revoke_sessions: "/admin/users/revoke-sessions",
+            admin_oauth_clients: "/admin/oauth-clients",
+            oauth_authorize: "/authorize",
+            oauth_token: "/token",
+            userinfo: "/userinfo",
+            end_session: "/end-session",
+            jwks: "/.well-known/jwks.json",
+            openid_configuration: "/.well-known/openid-configuration"
         };
         
         Self {paths}
@@ -20,6 +62,48 @@ pub struct Paths<'a> {
     pub signup: &'a str,
     pub login: &'a str,
     pub logout: &'a str,
+    pub refresh: &'a str,
     pub verify_2fa: &'a str,
+    pub resend_2fa: &'a str,
     pub verify_token: &'a str,
+    pub change_password: &'a str,
+    pub account: &'a str,
+    pub account_export: &'a str,
+    pub change_email: &'a str,
+    pub change_email_confirm: &'a str,
+    pub change_email_cancel: &'a str,
+    pub verify_email: &'a str,
+    pub verify_email_resend: &'a str,
+    pub password_reset_request: &'a str,
+    pub password_reset_confirm: &'a str,
+    pub unlock_account: &'a str,
+    pub sessions: &'a str,
+    pub sessions_revoke: &'a str,
+    pub sessions_revoke_others: &'a str,
+    pub totp_enroll: &'a str,
+    pub totp_confirm: &'a str,
+    pub recovery_codes: &'a str,
+    pub recovery_codes_regenerate: &'a str,
+    pub webauthn_register_start: &'a str,
+    pub webauthn_register_finish: &'a str,
+    pub webauthn_login_start: &'a str,
+    pub webauthn_login_finish: &'a str,
+    pub organizations: &'a str,
+    pub organization_invitations: &'a str,
+    pub organization_invitations_accept: &'a str,
+    pub organization_switch: &'a str,
+    pub admin_users: &'a str,
+    pub admin_user_details: &'a str,
+    pub admin_user_2fa: &'a str,
+    pub admin_user_lock: &'a str,
+    pub admin_user_unlock: &'a str,
+    pub admin_user_password_reset: &'a str,
+    pub admin_user_revoke_sessions: &'a str,
+    pub admin_oauth_clients: &'a str,
+    pub oauth_authorize: &'a str,
+    pub oauth_token: &'a str,
+    pub userinfo: &'a str,
+    pub end_session: &'a str,
+    pub jwks: &'a str,
+    pub openid_configuration: &'a str,
 }
\ No newline at end of file
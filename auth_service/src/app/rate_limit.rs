@@ -0,0 +1,53 @@
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response}
+};
+use chrono::Utc;
+
+use crate::services::{
+    api::AuthApiError,
+    client_info::{client_ip, peer_ip},
+    constants::{RATE_LIMITS, TRUSTED_PROXY_DEPTH}
+};
+
+use super::state::AppState;
+
+// Limits how often each client IP can call a route, with the limits in RATE_LIMITS.
+// Added as a route layer, so only requests that matched a route count.
+pub async fn rate_limit(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let Some(path) = request.extensions().get::<MatchedPath>().map(|path| path.as_str().to_owned()) else {
+        return next.run(request).await;
+    };
+
+    let Some(limit) = RATE_LIMITS.for_route(&path) else {
+        return next.run(request).await;
+    };
+
+    let (parts, body) = request.into_parts();
+    let ip = client_ip(&parts.headers, peer_ip(&parts), *TRUSTED_PROXY_DEPTH);
+    let request = Request::from_parts(parts, body);
+
+    // Requests without an address share one bucket rather than skipping the limit
+    let client = ip.map_or_else(|| String::from("unknown"), |ip| ip.to_string());
+    let key = format!("{}:{}", path, client);
+
+    let result = state.rate_limit_store
+        .read()
+        .await
+        .hit(&key, limit, Utc::now().timestamp())
+        .await;
+
+    match result {
+        Ok(None) => next.run(request).await,
+        Ok(Some(retry_after)) => {
+            tracing::warn!("Rate limit of {} hit by {}", path, client);
+            AuthApiError::RateLimited(retry_after).into_response()
+        }
+        // The Redis store counts locally while Redis is down, so only a poisoned in-memory counter ends up here
+        Err(e) => {
+            tracing::error!("Failed to check rate limit: {:?}", e);
+            next.run(request).await
+        }
+    }
+}
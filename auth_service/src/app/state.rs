@@ -3,7 +3,27 @@ use std::sync::Arc;
 
 use tokio::sync::RwLock;
 
-use crate::{services::two_fa::TwoFaCodes, services::tokens::BannedTokenStoreType, user::store::UserStoreType};
+use crate::{
+    services::two_fa::TwoFaCodes, 
+    services::tokens::BannedTokenStoreType, 
+    services::refresh_tokens::RefreshTokenStoreType, 
+    services::sessions::SessionStoreType, 
+    services::keys::KeyringType, 
+    services::totp::TotpStoreType, 
+    services::recovery_codes::RecoveryCodeStoreType, 
+    services::passkeys::PasskeyStoreType, 
+    services::password_reset_tokens::PasswordResetTokenStoreType, 
+    services::email_changes::EmailChangeStoreType, 
+    services::login_history::LoginHistoryStoreType, 
+    services::organizations::OrganizationStoreType, 
+    services::invitations::InvitationStoreType, 
+    services::login_attempts::LoginAttemptStoreType, 
+    services::rate_limits::RateLimitStoreType, 
+    services::webauthn_challenges::WebauthnChallengeStoreType, 
+    services::oauth_clients::OAuthClientStoreType, 
+    services::authorization_codes::AuthorizationCodeStoreType, 
+    user::store::UserStoreType
+};
 
 use super::email_client::EmailClient;
 
@@ -14,6 +34,22 @@ pub type EmailClientType = Arc<dyn EmailClient + Send + Sync>;
 pub struct AppState {
     pub user_store: UserStoreType,
     pub banned_token_store: BannedTokenStoreType,
+    pub refresh_token_store: RefreshTokenStoreType,
+    pub session_store: SessionStoreType,
     pub two_fa_code: TwoFaCodeStoreType,
+    pub totp_store: TotpStoreType,
+    pub recovery_code_store: RecoveryCodeStoreType,
+    pub passkey_store: PasskeyStoreType,
+    pub webauthn_challenge_store: WebauthnChallengeStoreType,
+    pub password_reset_token_store: PasswordResetTokenStoreType,
+    pub email_change_store: EmailChangeStoreType,
+    pub login_history_store: LoginHistoryStoreType,
+    pub organization_store: OrganizationStoreType,
+    pub invitation_store: InvitationStoreType,
+    pub login_attempt_store: LoginAttemptStoreType,
+    pub rate_limit_store: RateLimitStoreType,
+    pub oauth_client_store: OAuthClientStoreType,
+    pub authorization_code_store: AuthorizationCodeStoreType,
+    pub keyring: KeyringType,
     pub email_client: EmailClientType
 }
\ No newline at end of file
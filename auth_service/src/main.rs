@@ -1,12 +1,13 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 use auth_service::{
-    app::{state::AppState, App}, get_postgres_pool, get_redis_client, services::{
-        constants::{prod, DATABASE_URL, POSTMARK_AUTH_TOKEN, REDIS_HOST_NAME}, postgres_user_store::PostgresUserStore, postmark_email_client::PostmarkEmailClient, redis_banned_token_store::RedisBannedTokenStore, redis_two_fa_code_store::RedisTwoFACodeStore, tokens::BannedTokens, tracing::init_tracing, two_fa::TwoFaCodeStore, MockEmailClient
-    }, user::Email
+    app::{state::{AppState, TwoFaCodeStoreType}, App}, get_postgres_pool, get_redis_client, services::{
+        constants::{prod, ACCOUNT_PURGE_INTERVAL_SECONDS, SHARED_SECRET_KEY_ID, DATABASE_URL, JWT_SECRET, JWT_SIGNING_ALGORITHM, JWT_SIGNING_KEY_PATH, POSTMARK_AUTH_TOKEN, REDIS_HOST_NAME}, keys::{Keyring, KeyringType, SigningKey}, postgres_refresh_token_store::PostgresRefreshTokenStore, postgres_totp_store::PostgresTotpStore, postgres_passkey_store::PostgresPasskeyStore, postgres_recovery_code_store::PostgresRecoveryCodeStore, postgres_login_history_store::PostgresLoginHistoryStore, redis_webauthn_challenge_store::RedisWebauthnChallengeStore, redis_password_reset_token_store::RedisPasswordResetTokenStore, redis_email_change_store::RedisEmailChangeStore, postgres_organization_store::PostgresOrganizationStore, postgres_session_store::PostgresSessionStore, redis_invitation_store::RedisInvitationStore, redis_login_attempt_store::RedisLoginAttemptStore, redis_rate_limit_store::RedisRateLimitStore, redis_authorization_code_store::RedisAuthorizationCodeStore, postgres_oauth_client_store::PostgresOAuthClientStore, postgres_user_store::PostgresUserStore, postmark_email_client::PostmarkEmailClient, redis_banned_token_store::RedisBannedTokenStore, redis_two_fa_code_store::RedisTwoFACodeStore, tracing::init_tracing
+    }, user::{store::UserStoreType, Email}
 };
+use chrono::Utc;
 use reqwest::Client;
 use sqlx::PgPool;
-use tokio::sync::RwLock;
+use tokio::{signal::unix::{signal, SignalKind}, sync::RwLock};
 
 #[tokio::main]
 async fn main() {
@@ -14,18 +15,53 @@ async fn main() {
     let email_client = Arc::new(configure_postmark_email_client()); 
     
     let pg_pool = configure_postgresql().await;
-    let store = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool)));
+    let store = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool.clone())));
+    let refresh_token_store = Arc::new(RwLock::new(PostgresRefreshTokenStore::new(pg_pool.clone())));
+    let totp_store = Arc::new(RwLock::new(PostgresTotpStore::new(pg_pool.clone())));
+    let recovery_code_store = Arc::new(RwLock::new(PostgresRecoveryCodeStore::new(pg_pool.clone())));
+    let passkey_store = Arc::new(RwLock::new(PostgresPasskeyStore::new(pg_pool.clone())));
+    let login_history_store = Arc::new(RwLock::new(PostgresLoginHistoryStore::new(pg_pool.clone())));
+    let organization_store = Arc::new(RwLock::new(PostgresOrganizationStore::new(pg_pool.clone())));
+    let session_store = Arc::new(RwLock::new(PostgresSessionStore::new(pg_pool.clone())));
+    let oauth_client_store = Arc::new(RwLock::new(PostgresOAuthClientStore::new(pg_pool)));
     let redis_connection = Arc::new(RwLock::new(configure_redis()));
 
     let banned_token_store = Arc::new(RwLock::new(RedisBannedTokenStore::new(
         redis_connection.clone(),
     )));
-    let two_fa_code = Arc::new(RwLock::new(RedisTwoFACodeStore::new(redis_connection)));
+    let two_fa_code = Arc::new(RwLock::new(RedisTwoFACodeStore::new(redis_connection.clone())));
+    let webauthn_challenge_store = Arc::new(RwLock::new(RedisWebauthnChallengeStore::new(redis_connection.clone())));
+    let password_reset_token_store = Arc::new(RwLock::new(RedisPasswordResetTokenStore::new(redis_connection.clone())));
+    let email_change_store = Arc::new(RwLock::new(RedisEmailChangeStore::new(redis_connection.clone())));
+    let invitation_store = Arc::new(RwLock::new(RedisInvitationStore::new(redis_connection.clone())));
+    let login_attempt_store = Arc::new(RwLock::new(RedisLoginAttemptStore::new(redis_connection.clone())));
+    let authorization_code_store = Arc::new(RwLock::new(RedisAuthorizationCodeStore::new(redis_connection)));
+    // Shared through Redis so the limits hold across every instance
+    let rate_limit_store = Arc::new(RwLock::new(RedisRateLimitStore::new(configure_async_redis().await)));
+    let keyring = Arc::new(RwLock::new(configure_keyring()));
+    tokio::spawn(rotate_keyring_on_hangup(keyring.clone()));
+    tokio::spawn(purge_deleted_accounts(store.clone(), two_fa_code.clone()));
     
     let app_state = AppState {
         user_store: store,
         banned_token_store,
+        refresh_token_store,
+        session_store,
         two_fa_code,
+        totp_store,
+        recovery_code_store,
+        passkey_store,
+        webauthn_challenge_store,
+        password_reset_token_store,
+        email_change_store,
+        login_history_store,
+        organization_store,
+        invitation_store,
+        login_attempt_store,
+        rate_limit_store,
+        oauth_client_store,
+        authorization_code_store,
+        keyring,
         email_client
     };
     
@@ -53,6 +89,83 @@ fn configure_redis() -> redis::Connection {
         .expect("Failed to get Redis connection")
 }
 
+async fn configure_async_redis() -> redis::aio::MultiplexedConnection {
+    get_redis_client(REDIS_HOST_NAME.to_owned())
+        .expect("Failed to get Redis client")
+        .get_multiplexed_tokio_connection()
+        .await
+        .expect("Failed to get Redis connection")
+}
+
+fn configure_keyring() -> Keyring {
+    let signing_key = match JWT_SIGNING_KEY_PATH.as_ref() {
+        Some(path) => SigningKey::from_pem_file(*JWT_SIGNING_ALGORITHM, path)
+            .expect("Failed to load JWT signing key"),
+        None => {
+            tracing::warn!("JWT_SIGNING_KEY_PATH is not set, signing tokens with the shared JWT_SECRET");
+            SigningKey::from_secret(SHARED_SECRET_KEY_ID, JWT_SECRET.as_bytes())
+        }
+    };
+
+    Keyring::new(signing_key)
+}
+
+// Swap in the key currently at JWT_SIGNING_KEY_PATH whenever the process receives SIGHUP.
+// Tokens signed with the previous key stay valid until they expire. Without a key file
+// there is nothing to reload, so rotation needs JWT_SIGNING_KEY_PATH.
+async fn rotate_keyring_on_hangup(keyring: KeyringType) {
+    let Some(path) = JWT_SIGNING_KEY_PATH.as_ref() else {
+        tracing::warn!("JWT_SIGNING_KEY_PATH is not set, the signing key can't be rotated with SIGHUP");
+        return;
+    };
+
+    let mut hangups = signal(SignalKind::hangup()).expect("Failed to listen for SIGHUP");
+
+    while hangups.recv().await.is_some() {
+        let signing_key = match SigningKey::from_pem_file(*JWT_SIGNING_ALGORITHM, path) {
+            Ok(signing_key) => signing_key,
+            Err(e) => {
+                tracing::error!("Failed to reload JWT signing key: {:?}", e);
+                continue;
+            }
+        };
+
+        let kid = signing_key.kid().to_owned();
+        match keyring.write().await.rotate(signing_key) {
+            Ok(()) => tracing::info!("Rotated JWT signing key, now signing with kid {}", kid),
+            Err(e) => tracing::warn!("JWT signing key was not rotated: {:?}", e),
+        }
+    }
+}
+
+// Hard delete the accounts whose deletion grace period has run out
+async fn purge_deleted_accounts(user_store: UserStoreType, two_fa_code: TwoFaCodeStoreType) {
+    let mut interval = tokio::time::interval(Duration::from_secs(ACCOUNT_PURGE_INTERVAL_SECONDS));
+
+    loop {
+        interval.tick().await;
+
+        let emails = match user_store.write().await.delete_scheduled_users(Utc::now().timestamp()).await {
+            Ok(emails) => emails,
+            Err(e) => {
+                tracing::error!("Failed to purge deleted accounts: {:?}", e);
+                continue;
+            }
+        };
+
+        // Logins started during the grace period may have left challenges behind
+        for email in &emails {
+            if let Err(e) = two_fa_code.write().await.remove_user_challenges(email).await {
+                tracing::error!("Failed to purge 2FA state of a deleted account: {:?}", e);
+            }
+        }
+
+        if !emails.is_empty() {
+            tracing::info!("Purged {} deleted accounts", emails.len());
+        }
+    }
+}
+
 fn configure_postmark_email_client() -> PostmarkEmailClient {
     let http_client = Client::builder()
         .timeout(prod::email_client::TIMEOUT)
@@ -1,11 +1,353 @@
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::{email_client::SendEmail, state::AppState},
+    services::{
+        api::AuthApiError,
+        auth::{
+            generate_access_token_cookie, generate_auth_cookie, generate_refresh_cookie, rotate_refresh_token,
+            validate_token, Grants, RefreshTokenError, TOKEN_TTL_SECONDS
+        },
+        auth_token::AuthToken,
+        client_info::ClientInfo,
+        login_history::LoginEvent,
+        oauth_clients::OAuthClientId,
+        oidc::AuthMethod,
+        refresh_tokens::{RefreshToken, RefreshTokenStoreError},
+        sessions::{Session, SessionId, SessionStoreError},
+        two_fa::TwoFaCode
+    },
+    user::{Email, Password, User, UserId}
+};
+
+mod change_email;
+mod account;
+mod admin;
+mod change_password;
+mod jwks;
 mod login;
+mod organizations;
 mod logout;
+mod oauth;
+mod oauth_clients;
+mod oidc;
+mod password_reset;
+mod recovery_codes;
+mod refresh;
+mod resend_2fa;
+mod sessions;
 mod signup;
+mod totp;
+mod unlock_account;
 mod verify_2fa;
+mod verify_email;
 mod verify_token;
+mod webauthn;
 
+pub use account::*;
+pub use admin::*;
+pub use change_email::*;
+pub use change_password::*;
+pub use jwks::*;
 pub use login::*;
+pub use organizations::*;
 pub use logout::*;
+pub use oauth::*;
+pub use oauth_clients::*;
+pub use oidc::*;
+pub use password_reset::*;
+pub use recovery_codes::*;
+pub use refresh::*;
+pub use resend_2fa::*;
+pub use sessions::*;
 pub use signup::*;
+pub use totp::*;
+pub use unlock_account::*;
 pub use verify_2fa::*;
+pub use verify_email::*;
 pub use verify_token::*;
+pub use webauthn::*;
+
+// Resolve the logged-in user from the JWT auth token, whose subject is the user id
+pub(crate) async fn authenticated_user(token: &AuthToken, state: &AppState) -> Result<User, AuthApiError> {
+    authenticated_session(token, state).await.map(|(user, _)| user)
+}
+
+// Like `authenticated_user`, along with the session the auth token was issued for. Access tokens of OAuth
+// clients and sessions they started are refused, clients only act for users within their scopes.
+pub(crate) async fn authenticated_session(token: &AuthToken, state: &AppState) -> Result<(User, SessionId), AuthApiError> {
+    let claims = validate_token(
+        token.as_ref(),
+        state.banned_token_store.clone(),
+        state.session_store.clone(),
+        state.keyring.clone()
+    )
+    .await
+    .map_err(|_| AuthApiError::InvalidToken)?;
+
+    let user_id = UserId::parse(claims.sub).map_err(|_| AuthApiError::InvalidToken)?;
+    let session = SessionId::parse(claims.sid).map_err(|_| AuthApiError::InvalidToken)?;
+
+    // The account may have been deleted since the token was issued
+    let user = state.user_store
+        .read()
+        .await
+        .get_user_by_id(&user_id)
+        .await
+        .map_err(|_| AuthApiError::InvalidToken)?;
+
+    Ok((user, session))
+}
+
+// The user's current roles and active organization, as embedded in the auth tokens minted for them
+pub(crate) async fn load_grants(user: &User, state: &AppState) -> Result<Grants, AuthApiError> {
+    let roles = state.user_store
+        .read()
+        .await
+        .get_roles(&user.email)
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?;
+
+    let membership = state.organization_store
+        .read()
+        .await
+        .get_active_membership(&user.id)
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?;
+
+    Ok(Grants { roles, membership })
+}
+
+// Adding or replacing a factor is as sensitive as changing the password, so it takes the current one
+// as well as an auth token, which alone may have been stolen
+pub(crate) async fn ensure_current_password(email: &Email, password: String, state: &AppState) -> Result<(), AuthApiError> {
+    let password_valid = match Password::parse(password) {
+        Ok(password) => state.user_store.read().await.validate_user(email, &password).await.is_ok(),
+        Err(_) => false,
+    };
+
+    if !password_valid {
+        return Err(AuthApiError::IncorrectCredentials);
+    }
+
+    Ok(())
+}
+
+// Accounts an admin locked or flagged for a password reset don't get new sessions
+pub(crate) fn ensure_can_log_in(user: &User) -> Result<(), AuthApiError> {
+    if user.locked {
+        return Err(AuthApiError::AccountLocked);
+    }
+
+    if user.password_reset_required {
+        return Err(AuthApiError::PasswordResetRequired);
+    }
+
+    Ok(())
+}
+
+// Mint an auth cookie for the session carrying the user's current grants
+pub(crate) async fn issue_auth_cookie(user: &User, session: &SessionId, state: &AppState) -> Result<Cookie<'static>, AuthApiError> {
+    ensure_can_log_in(user)?;
+
+    let grants = load_grants(user, state).await?;
+
+    generate_auth_cookie(user, session, &grants, state.keyring.clone())
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)
+}
+
+// Record a new session for a user who passed every factor, and mint its auth and refresh cookies
+pub(crate) async fn start_session(
+    user: &User,
+    methods: Vec<AuthMethod>,
+    client: &ClientInfo,
+    state: &AppState
+) -> Result<(Cookie<'static>, Cookie<'static>), AuthApiError> {
+    let now = Utc::now().timestamp();
+    let session = Session::new(user.id.clone(), client.clone(), now).authenticated_with(methods, now);
+
+    open_session(user, session, state).await
+}
+
+// Browser and first-party sessions get an auth cookie carrying the user's grants. Sessions an OAuth client
+// started get an access token for that client alone, limited to the scopes it was granted.
+pub(crate) async fn issue_session_token(user: &User, session: &Session, state: &AppState) -> Result<Cookie<'static>, AuthApiError> {
+    if session.oauth_client_id.is_none() {
+        return issue_auth_cookie(user, &session.id, state).await;
+    }
+
+    ensure_can_log_in(user)?;
+
+    generate_access_token_cookie(user, session, state.keyring.clone())
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)
+}
+
+// Record the session and mint its first auth and refresh cookies
+pub(crate) async fn open_session(
+    user: &User,
+    session: Session,
+    state: &AppState
+) -> Result<(Cookie<'static>, Cookie<'static>), AuthApiError> {
+    ensure_can_log_in(user)?;
+
+    state.session_store
+        .write()
+        .await
+        .add_session(session.clone())
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?;
+
+    let auth_cookie = issue_session_token(user, &session, state).await?;
+
+    let refresh_cookie = generate_refresh_cookie(&user.email, &session.id, state.refresh_token_store.clone())
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?;
+
+    Ok((auth_cookie, refresh_cookie))
+}
+
+// Exchange a refresh token for fresh auth and refresh cookies of its session. Sessions an OAuth client
+// started can only be refreshed by that client, and other sessions not by any OAuth client.
+pub(crate) async fn refresh_session(
+    token: &RefreshToken,
+    oauth_client_id: Option<&OAuthClientId>,
+    state: &AppState
+) -> Result<(User, Session, Cookie<'static>, Cookie<'static>), AuthApiError> {
+    let now = Utc::now().timestamp();
+
+    // Checked before rotating, so a token presented by the wrong client isn't used up
+    let record = match state.refresh_token_store.read().await.get_token(token).await {
+        Ok(record) => record,
+        Err(RefreshTokenStoreError::TokenNotFound) => return Err(AuthApiError::InvalidToken),
+        Err(_) => return Err(AuthApiError::UnexpectedError),
+    };
+
+    let session = SessionId::parse(record.family.as_ref().to_owned()).map_err(|_| AuthApiError::UnexpectedError)?;
+
+    let session = match state.session_store.read().await.get_session(&session, now).await {
+        Ok(session) => session,
+        Err(SessionStoreError::SessionNotFound) => return Err(AuthApiError::InvalidToken),
+        Err(SessionStoreError::UnexpectedError) => return Err(AuthApiError::UnexpectedError),
+    };
+
+    if session.oauth_client_id.as_ref() != oauth_client_id {
+        return Err(AuthApiError::InvalidToken);
+    }
+
+    let (email, _, refresh_cookie) = match rotate_refresh_token(token, state.refresh_token_store.clone()).await {
+        Ok(rotated) => rotated,
+        Err(RefreshTokenError::InvalidToken | RefreshTokenError::TokenReused) => return Err(AuthApiError::InvalidToken),
+        Err(RefreshTokenError::UnexpectedError) => return Err(AuthApiError::UnexpectedError),
+    };
+
+    // The account may have been deleted since the refresh token was issued
+    let user = state.user_store
+        .read()
+        .await
+        .get_user(&email)
+        .await
+        .map_err(|_| AuthApiError::InvalidToken)?;
+
+    // Auth tokens are short-lived, so refreshing is how an active session gets seen
+    match state.session_store.write().await.touch_session(&session.id, now).await {
+        Ok(()) => {}
+        Err(SessionStoreError::SessionNotFound) => return Err(AuthApiError::InvalidToken),
+        Err(SessionStoreError::UnexpectedError) => return Err(AuthApiError::UnexpectedError),
+    }
+
+    let auth_cookie = issue_session_token(&user, &session, state).await?;
+
+    Ok((user, session, auth_cookie, refresh_cookie))
+}
+
+// How the client wants the tokens minted for it: as cookies, or in the response body for clients without a cookie jar
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseMode {
+    #[default]
+    Cookie,
+    Token,
+}
+
+impl From<&AuthToken> for ResponseMode {
+    // Tokens are reissued the way the client sent the one it authenticated with
+    fn from(token: &AuthToken) -> Self {
+        if token.is_bearer() { Self::Token } else { Self::Cookie }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenResponse {
+    #[serde(rename = "accessToken")]
+    pub access_token: String,
+    #[serde(rename = "tokenType")]
+    pub token_type: String,
+    #[serde(rename = "expiresIn")]
+    pub expires_in: i64,
+    // Only handed out when the session gets a new one
+    #[serde(rename = "refreshToken", skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+}
+
+// Set the minted tokens as cookies, or return them for the response body when the client asked for that
+pub(crate) fn hand_out_tokens(
+    jar: CookieJar,
+    mode: ResponseMode,
+    auth_cookie: Cookie<'static>,
+    refresh_cookie: Option<Cookie<'static>>
+) -> (CookieJar, Option<TokenResponse>) {
+    match mode {
+        ResponseMode::Cookie => {
+            let jar = jar.add(auth_cookie);
+            let jar = match refresh_cookie {
+                Some(cookie) => jar.add(cookie),
+                None => jar,
+            };
+            (jar, None)
+        }
+        ResponseMode::Token => (jar, Some(TokenResponse {
+            access_token: auth_cookie.value().to_owned(),
+            token_type: String::from("Bearer"),
+            expires_in: TOKEN_TTL_SECONDS,
+            refresh_token: refresh_cookie.map(|cookie| cookie.value().to_owned()),
+        })),
+    }
+}
+
+pub(crate) async fn send_2fa_code(email: &Email, code: &TwoFaCode, state: &AppState) -> Result<(), AuthApiError> {
+    let email_detes = SendEmail {
+        recipient: email,
+        subject: "2FA Code",
+        content: code.as_ref()
+    };
+
+    state.email_client
+        .send_email(email_detes.recipient, email_detes.subject, email_detes.content)
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)
+}
+
+// Bookkeeping once a user has passed every factor. Logging back in is also how a user
+// changes their mind about deleting their account, so it cancels a pending deletion.
+pub(crate) async fn finish_login(email: &Email, client: ClientInfo, state: &AppState) {
+    let event = LoginEvent { logged_in_at: Utc::now().timestamp(), client };
+
+    if let Err(e) = state.login_history_store.write().await.record_login(email, event).await {
+        tracing::error!("Failed to record login: {:?}", e);
+    }
+
+    let pending_deletion = matches!(
+        state.user_store.read().await.get_user(email).await,
+        Ok(user) if user.delete_after.is_some()
+    );
+
+    if pending_deletion {
+        if let Err(e) = state.user_store.write().await.set_delete_after(email, None).await {
+            tracing::error!("Failed to cancel pending account deletion: {:?}", e);
+        }
+    }
+}
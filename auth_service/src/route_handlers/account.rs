@@ -0,0 +1,303 @@
+use axum::{
+    extract::State,
+    http::{header::CONTENT_DISPOSITION, StatusCode},
+    response::IntoResponse,
+    Json
+};
+use axum_extra::extract::{cookie, CookieJar};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::state::AppState,
+    services::{
+        api::AuthApiError,
+        auth::revoke_user_sessions,
+        auth_token::AuthToken,
+        constants::{ACCOUNT_DELETION_GRACE_SECONDS, JWT_COOKIE_NAME, REFRESH_COOKIE_NAME},
+        organizations::OrgRole,
+        totp::TotpStoreError
+    },
+    user::{Password, TwoFaMethod, User}
+};
+
+use super::authenticated_user;
+
+// Everything stored about a user, minus secrets like password hashes, TOTP seeds and passkey public keys
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccountExport {
+    pub user: ExportedUser,
+    pub roles: Vec<ExportedRole>,
+    pub organizations: Vec<ExportedMembership>,
+    pub sessions: Vec<ExportedSession>,
+    #[serde(rename = "loginHistory")]
+    pub login_history: Vec<ExportedLogin>,
+    pub factors: ExportedFactors,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedUser {
+    pub id: String,
+    pub email: String,
+    #[serde(rename = "emailVerified")]
+    pub email_verified: bool,
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+    #[serde(rename = "twoFaMethod")]
+    pub two_fa_method: TwoFaMethod,
+    #[serde(rename = "deleteAfter")]
+    pub delete_after: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedRole {
+    pub name: String,
+    pub permissions: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedMembership {
+    #[serde(rename = "orgId")]
+    pub org_id: String,
+    pub name: String,
+    pub role: OrgRole,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedSession {
+    pub id: String,
+    pub device: Option<String>,
+    pub ip: Option<String>,
+    #[serde(rename = "userAgent")]
+    pub user_agent: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+    #[serde(rename = "lastSeenAt")]
+    pub last_seen_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedLogin {
+    #[serde(rename = "loggedInAt")]
+    pub logged_in_at: i64,
+    pub ip: Option<String>,
+    #[serde(rename = "userAgent")]
+    pub user_agent: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedFactors {
+    pub totp: bool,
+    pub passkeys: Vec<ExportedPasskey>,
+    #[serde(rename = "recoveryCodesRemaining")]
+    pub recovery_codes_remaining: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedPasskey {
+    #[serde(rename = "credentialId")]
+    pub credential_id: String,
+    #[serde(rename = "signCount")]
+    pub sign_count: u32,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeleteAccountRequest {
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteAccountResponse {
+    pub message: String,
+    // Unix timestamp of the hard delete, absent when the account is already gone
+    #[serde(rename = "deleteAfter", default, skip_serializing_if = "Option::is_none")]
+    pub delete_after: Option<i64>,
+}
+
+#[tracing::instrument(name = "Export account", skip_all)]
+pub async fn export_account(
+    State(state): State<AppState>,
+    token: AuthToken
+) -> Result<impl IntoResponse, AuthApiError> {
+    let user = authenticated_user(&token, &state).await?;
+
+    let export = collect_account_export(user, &state).await?;
+
+    Ok((
+        StatusCode::OK,
+        [(CONTENT_DISPOSITION, "attachment; filename=\"account-export.json\"")],
+        Json(export)
+    ))
+}
+
+#[tracing::instrument(name = "Delete account", skip_all)]
+pub async fn delete_account(
+    State(state): State<AppState>,
+    token: AuthToken,
+    jar: CookieJar,
+    Json(request): Json<DeleteAccountRequest>
+) -> (CookieJar, Result<impl IntoResponse, AuthApiError>) {
+    let user = match authenticated_user(&token, &state).await {
+        Ok(user) => user,
+        Err(e) => return (jar, Err(e)),
+    };
+    let email = user.email.clone();
+
+    // A stolen session alone shouldn't be enough to wipe the account
+    let password_valid = match Password::parse(request.password) {
+        Ok(password) => state.user_store.read().await.validate_user(&email, &password).await.is_ok(),
+        Err(_) => false,
+    };
+
+    if !password_valid {
+        return (jar, Err(AuthApiError::IncorrectCredentials));
+    }
+
+    if revoke_user_sessions(&user, state.refresh_token_store.clone(), state.banned_token_store.clone(), state.session_store.clone()).await.is_err() {
+        return (jar, Err(AuthApiError::UnexpectedError));
+    }
+
+    if state.two_fa_code.write().await.remove_user_challenges(&email).await.is_err() {
+        return (jar, Err(AuthApiError::UnexpectedError));
+    }
+
+    let grace_seconds = *ACCOUNT_DELETION_GRACE_SECONDS;
+    let mut user_store = state.user_store.write().await;
+
+    let (status, response) = if grace_seconds > 0 {
+        let delete_after = Utc::now().timestamp() + grace_seconds;
+
+        if user_store.set_delete_after(&email, Some(delete_after)).await.is_err() {
+            return (jar, Err(AuthApiError::UnexpectedError));
+        }
+
+        (StatusCode::ACCEPTED, DeleteAccountResponse {
+            message: String::from("Account scheduled for deletion, log in again before then to keep it"),
+            delete_after: Some(delete_after),
+        })
+    } else {
+        // Every other table references the user, so removing the row removes everything else with it
+        if user_store.delete_user(&email).await.is_err() {
+            return (jar, Err(AuthApiError::UnexpectedError));
+        }
+
+        (StatusCode::OK, DeleteAccountResponse {
+            message: String::from("Account deleted"),
+            delete_after: None,
+        })
+    };
+
+    let jar = jar
+        .remove(cookie::Cookie::from(JWT_COOKIE_NAME))
+        .remove(cookie::Cookie::from(REFRESH_COOKIE_NAME));
+
+    (jar, Ok((status, Json(response))))
+}
+
+async fn collect_account_export(user: User, state: &AppState) -> Result<AccountExport, AuthApiError> {
+    let email = &user.email;
+
+    let roles = state.user_store
+        .read()
+        .await
+        .get_roles(email)
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?
+        .into_iter()
+        .map(|role| ExportedRole {
+            name: role.name,
+            permissions: role.permissions,
+        })
+        .collect();
+
+    let organizations = state.organization_store
+        .read()
+        .await
+        .get_memberships(&user.id)
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?
+        .into_iter()
+        .map(|membership| ExportedMembership {
+            org_id: membership.organization.id.as_ref().to_owned(),
+            name: membership.organization.name,
+            role: membership.role,
+        })
+        .collect();
+
+    let sessions = state.session_store
+        .read()
+        .await
+        .list_sessions(&user.id, Utc::now().timestamp())
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?
+        .into_iter()
+        .map(|session| ExportedSession {
+            id: session.id.as_ref().to_owned(),
+            device: session.device,
+            ip: session.client.ip,
+            user_agent: session.client.user_agent,
+            created_at: session.created_at,
+            last_seen_at: session.last_seen_at,
+        })
+        .collect();
+
+    let login_history = state.login_history_store
+        .read()
+        .await
+        .get_logins(email)
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?
+        .into_iter()
+        .map(|event| ExportedLogin {
+            logged_in_at: event.logged_in_at,
+            ip: event.client.ip,
+            user_agent: event.client.user_agent,
+        })
+        .collect();
+
+    let totp = match state.totp_store.read().await.get_secret(email).await {
+        Ok(_) => true,
+        Err(TotpStoreError::SecretNotFound) => false,
+        Err(_) => return Err(AuthApiError::UnexpectedError),
+    };
+
+    let passkeys = state.passkey_store
+        .read()
+        .await
+        .get_passkeys(email)
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?
+        .into_iter()
+        .map(|passkey| ExportedPasskey {
+            credential_id: passkey.credential_id,
+            sign_count: passkey.sign_count,
+        })
+        .collect();
+
+    let recovery_codes_remaining = state.recovery_code_store
+        .read()
+        .await
+        .count_codes(email)
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?;
+
+    Ok(AccountExport {
+        user: ExportedUser {
+            id: user.id.as_ref().to_owned(),
+            email: user.email.as_ref().to_owned(),
+            email_verified: user.email_verified,
+            created_at: user.created_at,
+            two_fa_method: user.two_fa_method,
+            delete_after: user.delete_after,
+        },
+        roles,
+        organizations,
+        sessions,
+        login_history,
+        factors: ExportedFactors {
+            totp,
+            passkeys,
+            recovery_codes_remaining,
+        },
+    })
+}
@@ -0,0 +1,400 @@
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json
+};
+use ring::constant_time;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::state::AppState,
+    services::{
+        api::AuthApiError,
+        auth::revoke_user_sessions,
+        auth_token::AuthToken,
+        constants::{ADMIN_API_KEY, ADMIN_API_KEY_HEADER, DEFAULT_ADMIN_PAGE_SIZE, MAX_ADMIN_PAGE_SIZE}
+    },
+    user::{roles::ADMIN_ROLE, store::UserStoreError, TwoFaMethod, User, UserId}
+};
+
+use super::{authenticated_user, send_password_reset_link};
+
+#[derive(Deserialize, Debug)]
+pub struct ListUsersQuery {
+    // Part of the email address, matched ignoring case
+    pub search: Option<String>,
+    // Starts at 1
+    pub page: Option<u64>,
+    #[serde(rename = "perPage")]
+    pub per_page: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AdminUserRequest {
+    #[serde(rename = "userId")]
+    pub user_id: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AdminTwoFaRequest {
+    #[serde(rename = "userId")]
+    pub user_id: String,
+    #[serde(rename = "requires2FA")]
+    pub requires_2fa: bool,
+}
+
+// What admins see of an account, never any secrets
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminUser {
+    pub id: String,
+    pub email: String,
+    #[serde(rename = "emailVerified")]
+    pub email_verified: bool,
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+    #[serde(rename = "requires2FA")]
+    pub requires_2fa: bool,
+    #[serde(rename = "twoFaMethod")]
+    pub two_fa_method: TwoFaMethod,
+    pub locked: bool,
+    #[serde(rename = "passwordResetRequired")]
+    pub password_reset_required: bool,
+    #[serde(rename = "deleteAfter")]
+    pub delete_after: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminUserDetails {
+    #[serde(flatten)]
+    pub user: AdminUser,
+    pub roles: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminUsersResponse {
+    pub users: Vec<AdminUser>,
+    pub page: u64,
+    #[serde(rename = "perPage")]
+    pub per_page: u64,
+    pub total: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminActionResponse {
+    pub message: String,
+}
+
+impl From<User> for AdminUser {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id.as_ref().to_owned(),
+            email: user.email.as_ref().to_owned(),
+            email_verified: user.email_verified,
+            created_at: user.created_at,
+            requires_2fa: user.requires_2fa(),
+            two_fa_method: user.two_fa_method,
+            locked: user.locked,
+            password_reset_required: user.password_reset_required,
+            delete_after: user.delete_after,
+        }
+    }
+}
+
+#[tracing::instrument(name = "Admin list users", skip_all, err(Debug))]
+pub async fn admin_list_users(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    token: Option<AuthToken>,
+    Query(query): Query<ListUsersQuery>
+) -> Result<impl IntoResponse, AuthApiError> {
+    authorize_admin(&headers, token.as_ref(), &state).await?;
+
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query.per_page.unwrap_or(DEFAULT_ADMIN_PAGE_SIZE).clamp(1, MAX_ADMIN_PAGE_SIZE);
+    let search = query.search.as_deref().map(str::trim).filter(|search| !search.is_empty());
+
+    let listing = state.user_store
+        .read()
+        .await
+        .list_users(search, (page - 1).saturating_mul(per_page), per_page)
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?;
+
+    Ok((StatusCode::OK, Json(AdminUsersResponse {
+        users: listing.users.into_iter().map(AdminUser::from).collect(),
+        page,
+        per_page,
+        total: listing.total,
+    })))
+}
+
+#[tracing::instrument(name = "Admin get user", skip_all, err(Debug))]
+pub async fn admin_get_user(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    token: Option<AuthToken>,
+    Query(query): Query<AdminUserRequest>
+) -> Result<impl IntoResponse, AuthApiError> {
+    authorize_admin(&headers, token.as_ref(), &state).await?;
+
+    let user = find_user(query.user_id, &state).await?;
+
+    let roles = state.user_store
+        .read()
+        .await
+        .get_roles(&user.email)
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?
+        .into_iter()
+        .map(|role| role.name)
+        .collect();
+
+    Ok((StatusCode::OK, Json(AdminUserDetails { user: user.into(), roles })))
+}
+
+// Turning 2FA on falls back to emailed codes, since an admin can't enroll an authenticator for
+// the user. Turning it off is how a user who lost their second factor gets back in.
+#[tracing::instrument(name = "Admin set user 2FA", skip_all, err(Debug))]
+pub async fn admin_set_two_fa(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    token: Option<AuthToken>,
+    Json(request): Json<AdminTwoFaRequest>
+) -> Result<impl IntoResponse, AuthApiError> {
+    authorize_admin(&headers, token.as_ref(), &state).await?;
+
+    let mut user = find_user(request.user_id, &state).await?;
+
+    let method = match (request.requires_2fa, user.two_fa_method) {
+        (true, TwoFaMethod::None) => TwoFaMethod::Email,
+        (true, method) => method,
+        (false, _) => TwoFaMethod::None,
+    };
+
+    if method != user.two_fa_method {
+        state.user_store
+            .write()
+            .await
+            .set_two_fa_method(&user.email, method)
+            .await
+            .map_err(map_user_store_error)?;
+
+        user.two_fa_method = method;
+    }
+
+    Ok((StatusCode::OK, Json(AdminUser::from(user))))
+}
+
+#[tracing::instrument(name = "Admin lock user", skip_all, err(Debug))]
+pub async fn admin_lock_user(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    token: Option<AuthToken>,
+    Json(request): Json<AdminUserRequest>
+) -> Result<impl IntoResponse, AuthApiError> {
+    authorize_admin(&headers, token.as_ref(), &state).await?;
+
+    let mut user = find_user(request.user_id, &state).await?;
+
+    state.user_store
+        .write()
+        .await
+        .set_locked(&user.email, true)
+        .await
+        .map_err(map_user_store_error)?;
+
+    // Locking only stops new logins, so the sessions and login attempts already underway go too
+    end_user_sessions(&user, &state).await?;
+
+    user.locked = true;
+
+    Ok((StatusCode::OK, Json(AdminUser::from(user))))
+}
+
+#[tracing::instrument(name = "Admin unlock user", skip_all, err(Debug))]
+pub async fn admin_unlock_user(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    token: Option<AuthToken>,
+    Json(request): Json<AdminUserRequest>
+) -> Result<impl IntoResponse, AuthApiError> {
+    authorize_admin(&headers, token.as_ref(), &state).await?;
+
+    let mut user = find_user(request.user_id, &state).await?;
+
+    state.user_store
+        .write()
+        .await
+        .set_locked(&user.email, false)
+        .await
+        .map_err(map_user_store_error)?;
+
+    // Also lifts any temporary lockout from failed logins
+    state.login_attempt_store
+        .write()
+        .await
+        .clear(&user.email)
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?;
+
+    user.locked = false;
+
+    Ok((StatusCode::OK, Json(AdminUser::from(user))))
+}
+
+// Unlike self-service deletion there is no grace period, the account is gone right away
+#[tracing::instrument(name = "Admin delete user", skip_all, err(Debug))]
+pub async fn admin_delete_user(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    token: Option<AuthToken>,
+    Json(request): Json<AdminUserRequest>
+) -> Result<impl IntoResponse, AuthApiError> {
+    authorize_admin(&headers, token.as_ref(), &state).await?;
+
+    let user = find_user(request.user_id, &state).await?;
+
+    end_user_sessions(&user, &state).await?;
+
+    state.user_store
+        .write()
+        .await
+        .delete_user(&user.email)
+        .await
+        .map_err(map_user_store_error)?;
+
+    Ok((StatusCode::OK, Json(AdminActionResponse {
+        message: String::from("User deleted"),
+    })))
+}
+
+// The user is logged out everywhere and can't log in again until they set a new password
+// through the emailed reset link
+#[tracing::instrument(name = "Admin force password reset", skip_all, err(Debug))]
+pub async fn admin_force_password_reset(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    token: Option<AuthToken>,
+    Json(request): Json<AdminUserRequest>
+) -> Result<impl IntoResponse, AuthApiError> {
+    authorize_admin(&headers, token.as_ref(), &state).await?;
+
+    let user = find_user(request.user_id, &state).await?;
+
+    state.user_store
+        .write()
+        .await
+        .set_password_reset_required(&user.email, true)
+        .await
+        .map_err(map_user_store_error)?;
+
+    end_user_sessions(&user, &state).await?;
+
+    send_password_reset_link(user.email, state).await;
+
+    Ok((StatusCode::ACCEPTED, Json(AdminActionResponse {
+        message: String::from("Password reset required, a reset link has been sent to the user"),
+    })))
+}
+
+#[tracing::instrument(name = "Admin revoke user sessions", skip_all, err(Debug))]
+pub async fn admin_revoke_sessions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    token: Option<AuthToken>,
+    Json(request): Json<AdminUserRequest>
+) -> Result<impl IntoResponse, AuthApiError> {
+    authorize_admin(&headers, token.as_ref(), &state).await?;
+
+    let user = find_user(request.user_id, &state).await?;
+
+    end_user_sessions(&user, &state).await?;
+
+    Ok((StatusCode::OK, Json(AdminActionResponse {
+        message: String::from("All sessions revoked"),
+    })))
+}
+
+// Operators either send the admin API key, or are logged in with the admin role. Roles are read
+// from the store rather than the token, so revoking the role takes effect immediately.
+pub(crate) async fn authorize_admin(headers: &HeaderMap, token: Option<&AuthToken>, state: &AppState) -> Result<(), AuthApiError> {
+    if let Some(provided) = headers.get(ADMIN_API_KEY_HEADER) {
+        if !api_key_matches(ADMIN_API_KEY.as_ref(), provided.as_bytes()) {
+            return Err(AuthApiError::InvalidToken);
+        }
+
+        return Ok(());
+    }
+
+    let user = authenticated_user(token.ok_or(AuthApiError::MissingToken)?, state).await?;
+
+    let is_admin = state.user_store
+        .read()
+        .await
+        .get_roles(&user.email)
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?
+        .iter()
+        .any(|role| role.name == ADMIN_ROLE);
+
+    if !is_admin {
+        return Err(AuthApiError::MissingPermission);
+    }
+
+    Ok(())
+}
+
+fn api_key_matches(expected: Option<&Secret<String>>, provided: &[u8]) -> bool {
+    expected.is_some_and(|expected| {
+        constant_time::verify_slices_are_equal(expected.expose_secret().as_bytes(), provided).is_ok()
+    })
+}
+
+async fn find_user(user_id: String, state: &AppState) -> Result<User, AuthApiError> {
+    let user_id = UserId::parse(user_id).map_err(|_| AuthApiError::InvalidCredentials)?;
+
+    state.user_store
+        .read()
+        .await
+        .get_user_by_id(&user_id)
+        .await
+        .map_err(map_user_store_error)
+}
+
+async fn end_user_sessions(user: &User, state: &AppState) -> Result<(), AuthApiError> {
+    revoke_user_sessions(user, state.refresh_token_store.clone(), state.banned_token_store.clone(), state.session_store.clone())
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?;
+
+    state.two_fa_code
+        .write()
+        .await
+        .remove_user_challenges(&user.email)
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)
+}
+
+fn map_user_store_error(e: UserStoreError) -> AuthApiError {
+    match e {
+        UserStoreError::UserNotFound => AuthApiError::UserNotFound,
+        _ => AuthApiError::UnexpectedError,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_key_matches() {
+        let key = Secret::new(String::from("s3cret-admin-key"));
+
+        assert!(api_key_matches(Some(&key), b"s3cret-admin-key"));
+        assert!(!api_key_matches(Some(&key), b"s3cret-admin-kez"));
+        assert!(!api_key_matches(Some(&key), b""));
+        // Without a configured key, no key is accepted
+        assert!(!api_key_matches(None, b""));
+    }
+}
@@ -0,0 +1,196 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::state::AppState,
+    services::{
+        api::AuthApiError,
+        auth::{generate_email_change_tokens, redeem_email_change_token, revoke_user_sessions, EmailChangeAction, LinkTokenError},
+        auth_token::AuthToken,
+        constants::{EMAIL_CHANGE_CANCEL_URL, EMAIL_CHANGE_CONFIRM_URL},
+        email_changes::EmailChange
+    },
+    user::{store::UserStoreError, Email, Password}
+};
+
+use super::authenticated_user;
+
+#[derive(Deserialize, Debug)]
+pub struct ChangeEmailRequest {
+    #[serde(rename = "newEmail")]
+    pub new_email: String,
+    pub password: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ChangeEmailTokenRequest {
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangeEmailResponse {
+    pub message: String,
+}
+
+#[tracing::instrument(name = "Change email", skip_all)]
+pub async fn change_email(
+    State(state): State<AppState>,
+    token: AuthToken,
+    Json(request): Json<ChangeEmailRequest>
+) -> Result<impl IntoResponse, AuthApiError> {
+    let email = authenticated_user(&token, &state).await?.email;
+
+    let new_email = match Email::parse(request.new_email) {
+        Ok(new_email) if new_email != email => new_email,
+        _ => return Err(AuthApiError::InvalidCredentials),
+    };
+
+    // A stolen session alone shouldn't be enough to take over the account
+    let user_store = state.user_store.read().await;
+
+    let password_valid = match Password::parse(request.password) {
+        Ok(password) => user_store.validate_user(&email, &password).await.is_ok(),
+        Err(_) => false,
+    };
+
+    if !password_valid {
+        return Err(AuthApiError::IncorrectCredentials);
+    }
+
+    if user_store.get_user(&new_email).await.is_ok() {
+        return Err(AuthApiError::UserAlreadyExists);
+    }
+
+    drop(user_store);
+
+    let change = EmailChange { email: email.clone(), new_email: new_email.clone() };
+
+    let (confirm_token, cancel_token) = match generate_email_change_tokens(
+        change,
+        state.email_change_store.clone(),
+        state.keyring.clone()
+    )
+    .await
+    {
+        Ok(tokens) => tokens,
+        Err(_) => return Err(AuthApiError::UnexpectedError),
+    };
+
+    let confirm_content = format!(
+        "Confirm this as the new email address of your account, the link expires in 24 hours: {}?token={}",
+        EMAIL_CHANGE_CONFIRM_URL.as_str(),
+        confirm_token
+    );
+
+    if state.email_client.send_email(&new_email, "Confirm your new email address", &confirm_content).await.is_err() {
+        return Err(AuthApiError::UnexpectedError);
+    }
+
+    let cancel_content = format!(
+        "Someone asked to move your account to {}. Nothing changes until the new address is confirmed. \
+        If this wasn't you, cancel the change and reset your password: {}?token={}",
+        new_email.as_ref(),
+        EMAIL_CHANGE_CANCEL_URL.as_str(),
+        cancel_token
+    );
+
+    if state.email_client.send_email(&email, "Your email address is being changed", &cancel_content).await.is_err() {
+        return Err(AuthApiError::UnexpectedError);
+    }
+
+    Ok((StatusCode::ACCEPTED, Json(ChangeEmailResponse {
+        message: String::from("A confirmation link has been sent to the new email address"),
+    })))
+}
+
+#[tracing::instrument(name = "Confirm email change", skip_all, err(Debug))]
+pub async fn change_email_confirm(
+    State(state): State<AppState>,
+    Json(request): Json<ChangeEmailTokenRequest>
+) -> Result<impl IntoResponse, AuthApiError> {
+    let change = redeem_email_change_token(
+        &request.token,
+        EmailChangeAction::Confirm,
+        state.email_change_store.clone(),
+        state.keyring.clone()
+    )
+    .await
+    .map_err(map_link_token_error)?;
+
+    // Someone may have signed up with the new address in the meantime
+    if state.user_store.read().await.get_user(&change.new_email).await.is_ok() {
+        return Err(AuthApiError::UserAlreadyExists);
+    }
+
+    let user = state.user_store
+        .read()
+        .await
+        .get_user(&change.email)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthApiError::InvalidToken,
+            _ => AuthApiError::UnexpectedError,
+        })?;
+
+    // Refresh tokens were issued to the old address, so sessions end before the account moves away from it
+    revoke_user_sessions(&user, state.refresh_token_store.clone(), state.banned_token_store.clone(), state.session_store.clone())
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?;
+
+    state
+        .user_store
+        .write()
+        .await
+        .update_email(&change.email, change.new_email)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthApiError::InvalidToken,
+            UserStoreError::UserAlreadyExists => AuthApiError::UserAlreadyExists,
+            _ => AuthApiError::UnexpectedError,
+        })?;
+
+    // Pending logins, resets and lockouts are keyed by the address, which anyone may now sign up with
+    forget_address(&change.email, &state).await?;
+
+    Ok((StatusCode::OK, Json(ChangeEmailResponse {
+        message: String::from("Email changed"),
+    })))
+}
+
+#[tracing::instrument(name = "Cancel email change", skip_all, err(Debug))]
+pub async fn change_email_cancel(
+    State(state): State<AppState>,
+    Json(request): Json<ChangeEmailTokenRequest>
+) -> Result<impl IntoResponse, AuthApiError> {
+    redeem_email_change_token(
+        &request.token,
+        EmailChangeAction::Cancel,
+        state.email_change_store.clone(),
+        state.keyring.clone()
+    )
+    .await
+    .map_err(map_link_token_error)?;
+
+    Ok((StatusCode::OK, Json(ChangeEmailResponse {
+        message: String::from("Email change cancelled"),
+    })))
+}
+
+async fn forget_address(email: &Email, state: &AppState) -> Result<(), AuthApiError> {
+    state.two_fa_code.write().await.remove_user_challenges(email).await.map_err(|_| AuthApiError::UnexpectedError)?;
+    state.login_attempt_store.write().await.clear(email).await.map_err(|_| AuthApiError::UnexpectedError)?;
+    state
+        .password_reset_token_store
+        .write()
+        .await
+        .remove_user_tokens(email)
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)
+}
+
+fn map_link_token_error(e: LinkTokenError) -> AuthApiError {
+    match e {
+        LinkTokenError::InvalidToken => AuthApiError::InvalidToken,
+        LinkTokenError::UnexpectedError => AuthApiError::UnexpectedError,
+    }
+}
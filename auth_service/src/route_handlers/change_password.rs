@@ -0,0 +1,99 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::state::AppState,
+    services::{api::AuthApiError, auth::restart_session, auth_token::AuthToken},
+    user::Password
+};
+
+use super::{authenticated_session, hand_out_tokens, load_grants, ResponseMode, TokenResponse};
+
+#[derive(Deserialize, Debug)]
+pub struct ChangePasswordRequest {
+    #[serde(rename = "currentPassword")]
+    pub current_password: String,
+    #[serde(rename = "newPassword")]
+    pub new_password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangePasswordResponse {
+    pub message: String,
+    // The session's fresh tokens, when the client authenticated with a bearer token
+    #[serde(flatten)]
+    pub tokens: Option<TokenResponse>,
+}
+
+#[tracing::instrument(name = "Change password", skip_all)]
+pub async fn change_password(
+    State(state): State<AppState>,
+    token: AuthToken,
+    jar: CookieJar,
+    Json(request): Json<ChangePasswordRequest>
+) -> (CookieJar, Result<impl IntoResponse, AuthApiError>) {
+    let (user, session) = match authenticated_session(&token, &state).await {
+        Ok(authenticated) => authenticated,
+        Err(e) => return (jar, Err(e)),
+    };
+    let email = user.email.clone();
+
+    let new_password = match Password::parse(request.new_password) {
+        Ok(password) => password,
+        Err(_) => return (jar, Err(AuthApiError::InvalidCredentials)),
+    };
+
+    let mut user_store = state.user_store.write().await;
+
+    let current_password_valid = match Password::parse(request.current_password) {
+        Ok(password) => user_store.validate_user(&email, &password).await.is_ok(),
+        Err(_) => false,
+    };
+
+    if !current_password_valid {
+        return (jar, Err(AuthApiError::IncorrectCredentials));
+    }
+
+    if user_store.update_password(&email, new_password).await.is_err() {
+        return (jar, Err(AuthApiError::UnexpectedError));
+    }
+
+    drop(user_store);
+
+    let grants = match load_grants(&user, &state).await {
+        Ok(grants) => grants,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    // Other devices have to log in with the new password, this one carries on with fresh tokens
+    let (auth_cookie, refresh_cookie) = match restart_session(
+        &user,
+        &session,
+        &grants,
+        state.refresh_token_store.clone(),
+        state.banned_token_store.clone(),
+        state.session_store.clone(),
+        state.keyring.clone()
+    )
+    .await
+    {
+        Ok(cookies) => cookies,
+        Err(_) => return (jar, Err(AuthApiError::UnexpectedError)),
+    };
+
+    // The password has changed either way, so a failed notification doesn't fail the request
+    let content = "The password of your account was just changed. \
+        If this wasn't you, reset your password right away and review your 2FA settings.";
+
+    if let Err(e) = state.email_client.send_email(&email, "Your password was changed", content).await {
+        tracing::error!("Failed to send password change notification: {:?}", e);
+    }
+
+    let (jar, tokens) = hand_out_tokens(jar, ResponseMode::from(&token), auth_cookie, Some(refresh_cookie));
+
+    (jar, Ok((StatusCode::OK, Json(ChangePasswordResponse {
+        message: String::from("Password changed"),
+        tokens,
+    }))))
+}
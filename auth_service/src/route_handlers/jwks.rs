@@ -0,0 +1,8 @@
+use axum::{extract::State, Json};
+use jsonwebtoken::jwk::JwkSet;
+
+use crate::app::state::AppState;
+
+pub async fn jwks(State(state): State<AppState>) -> Json<JwkSet> {
+    Json(state.keyring.read().await.jwks())
+}
@@ -5,22 +5,32 @@ use axum::{
     Json
 };
 use axum_extra::extract::CookieJar;
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    app::{email_client::SendEmail, state::AppState}, services::{api::AuthApiError, auth::generate_auth_cookie, two_fa::{LoginId, TwoFaCode}}, user::{Email, Password}
+    app::state::AppState, services::{api::AuthApiError, client_info::ClientInfo, constants::{LOGIN_LOCKOUT_POLICY, UNVERIFIED_LOGIN_POLICY}, oidc::AuthMethod, two_fa::{LoginId, TwoFaChallenge, TwoFaCode}}, user::{store::UserStoreError, Email, Password, TwoFaMethod, User}
+};
+
+use super::{
+    ensure_can_log_in, finish_login, hand_out_tokens, send_2fa_code, send_account_unlock_link, start_session,
+    ResponseMode, TokenResponse
 };
 
 #[derive(Deserialize)]
 pub struct LoginRequest {
     email: String,
     password: String,
+    // Only applies when no second factor is needed, /verify-2fa takes its own
+    #[serde(rename = "responseMode", default)]
+    response_mode: ResponseMode,
 }
 
 #[derive(Debug, Serialize)]
 #[serde(untagged)]
 pub enum LoginResponse {
     SingleFactorAuth,
+    Tokens(TokenResponse),
     TwoFactorAuth(TwoFactorAuthResponse)
 }
 
@@ -28,12 +38,15 @@ pub enum LoginResponse {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TwoFactorAuthResponse {
     pub message: String,
-    #[serde(rename = "loginId")]
+    #[serde(rename = "loginAttemptId")]
     pub login_id: String,
+    #[serde(rename = "twoFaMethod")]
+    pub two_fa_method: TwoFaMethod,
 }
 
 pub async fn login(
     State(state): State<AppState>, 
+    client: ClientInfo,
     jar: CookieJar, 
     Json(req): Json<LoginRequest>
 ) -> (CookieJar, Result<impl IntoResponse, AuthApiError>) {
@@ -46,10 +59,26 @@ pub async fn login(
         Err(_) => return (jar, Err(AuthApiError::InvalidCredentials))
     };
 
-    let store = &state.user_store.read().await;
-    
-    if store.validate_user(&user_email, &user_password).await.is_err() {
-        return (jar, Err(AuthApiError::IncorrectCredentials));
+    let now = Utc::now().timestamp();
+
+    // Checked before the password, so a locked account can't be used to test guesses
+    match state.login_attempt_store.read().await.get_locked_until(&user_email, now).await {
+        Ok(Some(locked_until)) => return (jar, Err(AuthApiError::AccountTemporarilyLocked(locked_until - now))),
+        Ok(None) => {}
+        Err(_) => return (jar, Err(AuthApiError::UnexpectedError)),
+    }
+
+    let store = state.user_store.read().await;
+
+    match store.validate_user(&user_email, &user_password).await {
+        Ok(()) => {}
+        // Unknown addresses are counted too, so the lockout doesn't tell which accounts exist
+        Err(e @ (UserStoreError::InvalidCredentials | UserStoreError::UserNotFound)) => {
+            drop(store);
+            let account_exists = e == UserStoreError::InvalidCredentials;
+            return (jar, Err(record_failed_login(&user_email, account_exists, now, &state).await));
+        }
+        Err(_) => return (jar, Err(AuthApiError::IncorrectCredentials)),
     }
     
     let user = match store.get_user(&user_email).await {
@@ -57,19 +86,55 @@ pub async fn login(
         Err(_) => return (jar, Err(AuthApiError::IncorrectCredentials)),
     };
 
-    let auth_cookie = match generate_auth_cookie(&user.email) {
-        Ok(cookie) => cookie,
-        Err(_) => return (jar, Err(AuthApiError::UnexpectedError)),
-    };
+    // Finishing the login may update the user, so the store can't stay locked
+    drop(store);
+
+    if let Err(e) = state.login_attempt_store.write().await.clear(&user_email).await {
+        tracing::error!("Failed to clear failed login attempts: {:?}", e);
+    }
+
+    // Checked after the password, so this doesn't reveal anything about accounts to strangers
+    if !UNVERIFIED_LOGIN_POLICY.allows_login(&user, now) {
+        return (jar, Err(AuthApiError::EmailNotVerified));
+    }
 
-    match user.requires_2fa {
-        true => handle_2fa(jar, &user.email, &state).await,
-        false => handle_no_2fa(&user.email, jar).await,
+    // Refused before any second factor is started, which would be pointless
+    if let Err(e) = ensure_can_log_in(&user) {
+        return (jar, Err(e));
+    }
+
+    match user.two_fa_method {
+        TwoFaMethod::Email => handle_2fa(jar, &user.email, client, &state).await,
+        TwoFaMethod::Totp | TwoFaMethod::Passkey => {
+            handle_authenticator_2fa(jar, &user.email, user.two_fa_method, client, &state).await
+        }
+        TwoFaMethod::None => handle_no_2fa(&user, jar, req.response_mode, client, &state).await,
     }
 }
 
 
-async fn handle_2fa(jar: CookieJar, email: &Email, state: &AppState) -> (
+// Counts the wrong password, and once that locks the account tells its owner how to get back in.
+// Nobody owns an unknown address, so it is locked all the same but no email goes out.
+async fn record_failed_login(email: &Email, account_exists: bool, now: i64, state: &AppState) -> AuthApiError {
+    let locked_until = match state.login_attempt_store
+        .write()
+        .await
+        .record_failure(email, &LOGIN_LOCKOUT_POLICY, now)
+        .await
+    {
+        Ok(Some(locked_until)) => locked_until,
+        Ok(None) => return AuthApiError::IncorrectCredentials,
+        Err(_) => return AuthApiError::UnexpectedError,
+    };
+
+    if account_exists {
+        tokio::spawn(send_account_unlock_link(email.clone(), locked_until, state.clone()));
+    }
+
+    AuthApiError::AccountTemporarilyLocked(locked_until - now)
+}
+
+async fn handle_2fa(jar: CookieJar, email: &Email, client: ClientInfo, state: &AppState) -> (
     CookieJar,
     Result<(StatusCode, Json<LoginResponse>), AuthApiError>,
 ) {
@@ -79,7 +144,7 @@ async fn handle_2fa(jar: CookieJar, email: &Email, state: &AppState) -> (
     let added_code = state.two_fa_code
         .write()
         .await
-        .add_code(email.clone(), login_id.clone(), two_fa_code.clone())
+        .add_challenge(login_id.clone(), TwoFaChallenge::new(email.clone(), two_fa_code.clone(), client))
         .await
         .is_ok();
 
@@ -87,38 +152,66 @@ async fn handle_2fa(jar: CookieJar, email: &Email, state: &AppState) -> (
         return (jar, Err(AuthApiError::UnexpectedError));
     }
 
-    let email_detes = SendEmail {
-        recipient: email,
-        subject: "2FA Code",
-        content: two_fa_code.as_ref()
-    };
+    if let Err(e) = send_2fa_code(email, &two_fa_code, state).await {
+        return (jar, Err(e));
+    }
+
+    let res = Json(LoginResponse::TwoFactorAuth(TwoFactorAuthResponse {
+        message: String::from("2FA required"),
+        login_id: login_id.as_ref().to_string(),
+        two_fa_method: TwoFaMethod::Email,
+    }));
+
+    (jar, Ok((StatusCode::PARTIAL_CONTENT, res)))
+}
 
-    let sent = state.email_client
-        .send_email(email_detes.recipient, email_detes.subject, email_detes.content)
+async fn handle_authenticator_2fa(
+    jar: CookieJar,
+    email: &Email,
+    method: TwoFaMethod,
+    client: ClientInfo,
+    state: &AppState
+) -> (
+    CookieJar,
+    Result<(StatusCode, Json<LoginResponse>), AuthApiError>,
+) {
+    let login_id = LoginId::default();
+
+    // The login attempt is tracked like an emailed one, but the stored code is never sent anywhere.
+    // It is completed by an authenticator app code at /verify-2fa, or a passkey at /webauthn/login/finish.
+    let added_code = state.two_fa_code
+        .write()
+        .await
+        .add_challenge(login_id.clone(), TwoFaChallenge::new(email.clone(), TwoFaCode::default(), client))
         .await
         .is_ok();
 
-    if !sent {
+    if !added_code {
         return (jar, Err(AuthApiError::UnexpectedError));
     }
 
     let res = Json(LoginResponse::TwoFactorAuth(TwoFactorAuthResponse {
         message: String::from("2FA required"),
-        login_id: login_id.as_ref().to_string()
+        login_id: login_id.as_ref().to_string(),
+        two_fa_method: method,
     }));
 
     (jar, Ok((StatusCode::PARTIAL_CONTENT, res)))
 }
 
-async fn handle_no_2fa(email: &Email, jar: CookieJar) -> (
+async fn handle_no_2fa(user: &User, jar: CookieJar, mode: ResponseMode, client: ClientInfo, state: &AppState) -> (
     CookieJar,
     Result<(StatusCode, Json<LoginResponse>), AuthApiError>,
 ) {
-    let auth_cookie = match generate_auth_cookie(email) {
-        Ok(cookie) => cookie,
-        Err(_) => return (jar, Err(AuthApiError::UnexpectedError)),
+    let (auth_cookie, refresh_cookie) = match start_session(user, vec![AuthMethod::Password], &client, state).await {
+        Ok(cookies) => cookies,
+        Err(e) => return (jar, Err(e)),
     };
 
-    let jar = jar.add(auth_cookie);
-    (jar, Ok((StatusCode::OK, Json(LoginResponse::SingleFactorAuth))))
+    finish_login(&user.email, client, state).await;
+
+    let (jar, tokens) = hand_out_tokens(jar, mode, auth_cookie, Some(refresh_cookie));
+    let response = tokens.map_or(LoginResponse::SingleFactorAuth, LoginResponse::Tokens);
+
+    (jar, Ok((StatusCode::OK, Json(response))))
 }
\ No newline at end of file
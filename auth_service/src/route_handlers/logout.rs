@@ -6,39 +6,75 @@ use crate::{
     app::state::AppState, 
     services::{
         api::AuthApiError, 
-        auth::validate_token, 
-        constants::JWT_COOKIE_NAME
+        auth::{revoke_refresh_token, validate_token, Claims}, 
+        auth_token::AuthToken,
+        constants::{JWT_COOKIE_NAME, REFRESH_COOKIE_NAME},
+        refresh_tokens::RefreshToken,
+        sessions::SessionId
     }
 };
 
-pub async fn logout(State(state): State<AppState>, jar: CookieJar) -> (CookieJar, Result<impl IntoResponse, AuthApiError>) {
-    let cookie = match jar.get(JWT_COOKIE_NAME) {
-        Some(cookie) => cookie,
-        None => return (jar, Err(AuthApiError::MissingToken))
-    };
+use super::end_session;
 
+pub async fn logout(
+    State(state): State<AppState>,
+    token: AuthToken,
+    jar: CookieJar
+) -> (CookieJar, Result<impl IntoResponse, AuthApiError>) {
     // Validate token
-    let token = cookie.value().to_owned();
-    let _ = match validate_token(&token, state.banned_token_store.clone()).await {
+    let token = token.as_ref();
+    let claims = match validate_token(
+        token,
+        state.banned_token_store.clone(),
+        state.session_store.clone(),
+        state.keyring.clone()
+    )
+    .await
+    {
         Ok(claims) => claims,
         Err(_) => return (jar, Err(AuthApiError::InvalidToken)),
     };
 
+    match log_out(token, &claims, &jar, &state).await {
+        Ok(jar) => (jar, Ok(StatusCode::OK)),
+        Err(e) => (jar, Err(e)),
+    }
+}
+
+// Ban the validated auth token, end the session it was issued for along with every refresh token
+// of it, and return the jar without the auth and refresh cookies
+pub(crate) async fn log_out(
+    token: &str,
+    claims: &Claims,
+    jar: &CookieJar,
+    state: &AppState
+) -> Result<CookieJar, AuthApiError> {
     // Add token to banned list
-    if state
+    state
         .banned_token_store
         .write()
         .await
         .add_token(token.to_owned())
         .await
-        .is_err()
+        .map_err(|_| AuthApiError::UnexpectedError)?;
+
+    // Revoke the refresh token family so the session cannot be resumed
+    if let Some(token) = jar.get(REFRESH_COOKIE_NAME)
+        .and_then(|cookie| RefreshToken::parse(cookie.value().to_owned()).ok())
     {
-        return (jar, Err(AuthApiError::UnexpectedError));
+        revoke_refresh_token(&token, state.refresh_token_store.clone())
+            .await
+            .map_err(|_| AuthApiError::UnexpectedError)?;
     }
 
-    // Remove jwt cookie
-    let jar = jar.remove(cookie::Cookie::from(JWT_COOKIE_NAME));
+    // End the session, including any refresh token of it that isn't in this jar
+    if let Ok(session) = SessionId::parse(claims.sid.clone()) {
+        end_session(&session, state).await?;
+    }
 
-    (jar, Ok(StatusCode::OK))
+    // Remove jwt and refresh cookies
+    Ok(jar
+        .clone()
+        .remove(cookie::Cookie::from(JWT_COOKIE_NAME))
+        .remove(cookie::Cookie::from(REFRESH_COOKIE_NAME)))
 }
-
@@ -0,0 +1,407 @@
+use axum::{
+    extract::{
+        rejection::{FormRejection, QueryRejection},
+        Query, State
+    },
+    http::{header::{AUTHORIZATION, CACHE_CONTROL}, HeaderMap, Uri},
+    response::{IntoResponse, Redirect},
+    Form, Json
+};
+use axum_extra::extract::cookie::Cookie;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use url::{form_urlencoded, Url};
+
+use crate::{
+    app::state::AppState,
+    services::{
+        api::{AuthApiError, OAuthError},
+        auth::{generate_id_token, TOKEN_TTL_SECONDS},
+        auth_token::AuthToken,
+        authorization_codes::{AuthorizationCode, AuthorizationCodeStoreError, AuthorizationGrant, CodeChallenge, CodeVerifier},
+        client_info::ClientInfo,
+        oauth_clients::{OAuthClient, OAuthClientId, OAuthClientSecret, OAuthClientStoreError},
+        oidc::{format_scopes, parse_scopes, Scope},
+        refresh_tokens::RefreshToken,
+        sessions::Session
+    },
+    user::{store::UserStoreError, User}
+};
+
+use super::{authenticated_session, ensure_can_log_in, open_session, refresh_session};
+
+// Where /authorize sends users who aren't logged in, to come back once they are
+pub(crate) const LOGIN_PAGE: &str = "/";
+
+// Nonces are only passed through to ID tokens, but they're kept to a sensible size on the way
+const MAX_NONCE_LENGTH: usize = 255;
+
+#[derive(Deserialize, Debug)]
+pub struct AuthorizeQuery {
+    pub response_type: Option<String>,
+    pub client_id: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    // OpenID Connect parameters. Of `prompt`, only `none` is acted on.
+    pub scope: Option<String>,
+    pub nonce: Option<String>,
+    pub prompt: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TokenRequest {
+    pub grant_type: Option<String>,
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub code_verifier: Option<String>,
+    pub refresh_token: Option<String>,
+    // Public clients only send their id, confidential ones may send their secret here instead of with Basic auth
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+// Named the way RFC 6749 section 5.1 names them, unlike the token responses of /login and /refresh
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OAuthTokenResponse {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+    pub refresh_token: String,
+    // The scopes the client was granted, when it asked for any it can be
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    // Only issued when the `openid` scope was granted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
+}
+
+// Start of the authorization code flow. Users who aren't logged in are sent to the login page,
+// which brings them back here once /login and /verify-2fa have set their auth cookie. Clients are
+// registered by admins, so there's no consent screen: a logged-in user gets a code right away.
+#[tracing::instrument(name = "OAuth authorize", skip_all, err(Debug))]
+pub async fn oauth_authorize(
+    State(state): State<AppState>,
+    token: Option<AuthToken>,
+    uri: Uri,
+    query: Result<Query<AuthorizeQuery>, QueryRejection>
+) -> Result<Redirect, OAuthError> {
+    let Ok(Query(query)) = query else {
+        return Err(OAuthError::InvalidRequest("Parameters are malformed or repeated"));
+    };
+
+    // Until the redirect URI is known to belong to the client, errors are shown here instead of sent there
+    let client_id = query.client_id
+        .and_then(|id| OAuthClientId::parse(id).ok())
+        .ok_or(OAuthError::InvalidRequest("Unknown client_id"))?;
+
+    let oauth_client = match state.oauth_client_store.read().await.get_client(&client_id).await {
+        Ok(client) => client,
+        Err(OAuthClientStoreError::ClientNotFound) => return Err(OAuthError::InvalidRequest("Unknown client_id")),
+        Err(_) => return Err(OAuthError::ServerError),
+    };
+
+    let redirect_uri = query.redirect_uri
+        .filter(|uri| oauth_client.allows_redirect_uri(uri))
+        .ok_or(OAuthError::InvalidRequest("redirect_uri is not registered for the client"))?;
+
+    let redirect = ClientRedirect::new(&redirect_uri, query.state)?;
+
+    if query.response_type.as_deref() != Some("code") {
+        return Ok(redirect.error(OAuthError::UnsupportedResponseType));
+    }
+
+    // PKCE is required of every client, and only with S256
+    if query.code_challenge_method.as_deref() != Some("S256") {
+        return Ok(redirect.error(OAuthError::InvalidRequest("code_challenge_method must be S256")));
+    }
+
+    let Some(Ok(code_challenge)) = query.code_challenge.map(CodeChallenge::parse) else {
+        return Ok(redirect.error(OAuthError::InvalidRequest("code_challenge is missing or malformed")));
+    };
+
+    if query.nonce.as_ref().is_some_and(|nonce| nonce.len() > MAX_NONCE_LENGTH) {
+        return Ok(redirect.error(OAuthError::InvalidRequest("nonce is too long")));
+    }
+
+    let Some((user, session)) = logged_in_session(token, &state).await else {
+        // The client would rather hear the user isn't logged in than have them see the login page
+        if query.prompt.as_deref() == Some("none") {
+            return Ok(redirect.error(OAuthError::LoginRequired));
+        }
+
+        return Ok(login_redirect(&uri));
+    };
+
+    if ensure_can_log_in(&user).is_err() {
+        return Ok(redirect.error(OAuthError::AccessDenied));
+    }
+
+    let code = AuthorizationCode::default();
+    let grant = AuthorizationGrant {
+        client_id: oauth_client.id,
+        redirect_uri,
+        user_id: user.id,
+        code_challenge,
+        scopes: query.scope.as_deref().map(parse_scopes).unwrap_or_default(),
+        nonce: query.nonce,
+        authenticated_at: session.authenticated_at,
+        auth_methods: session.auth_methods,
+    };
+
+    if state.authorization_code_store.write().await.add_code(code.clone(), grant).await.is_err() {
+        return Ok(redirect.error(OAuthError::ServerError));
+    }
+
+    Ok(redirect.with_params(&[("code", code.as_ref())]))
+}
+
+// Every exchange starts a session of its own, listed among the user's devices like any login
+#[tracing::instrument(name = "OAuth token", skip_all, err(Debug))]
+pub async fn oauth_token(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    headers: HeaderMap,
+    request: Result<Form<TokenRequest>, FormRejection>
+) -> Result<impl IntoResponse, OAuthError> {
+    let Ok(Form(request)) = request else {
+        return Err(OAuthError::InvalidRequest("Expected form encoded parameters, each sent once"));
+    };
+
+    let oauth_client = authenticate_client(&headers, &request, &state).await?;
+
+    let response = match request.grant_type.as_deref() {
+        Some("authorization_code") => exchange_code(&oauth_client, request, client, &state).await?,
+        Some("refresh_token") => refresh_grant(&oauth_client, request, &state).await?,
+        Some(_) => return Err(OAuthError::UnsupportedGrantType),
+        None => return Err(OAuthError::InvalidRequest("grant_type is missing")),
+    };
+
+    Ok(([(CACHE_CONTROL, "no-store")], Json(response)))
+}
+
+async fn exchange_code(
+    oauth_client: &OAuthClient,
+    request: TokenRequest,
+    client: ClientInfo,
+    state: &AppState
+) -> Result<OAuthTokenResponse, OAuthError> {
+    let code = request.code.ok_or(OAuthError::InvalidRequest("code is missing"))?;
+    let redirect_uri = request.redirect_uri.ok_or(OAuthError::InvalidRequest("redirect_uri is missing"))?;
+    let code_verifier = request.code_verifier
+        .ok_or(OAuthError::InvalidRequest("code_verifier is missing"))
+        .and_then(|verifier| CodeVerifier::parse(verifier).map_err(|_| OAuthError::InvalidRequest("code_verifier is malformed")))?;
+
+    let code = AuthorizationCode::parse(code).map_err(|_| OAuthError::InvalidGrant)?;
+
+    // Taken before it's checked, so a code is used up by a failed exchange as well
+    let grant = match state.authorization_code_store.write().await.take_code(&code).await {
+        Ok(grant) => grant,
+        Err(AuthorizationCodeStoreError::CodeNotFound) => return Err(OAuthError::InvalidGrant),
+        Err(AuthorizationCodeStoreError::UnexpectedError) => return Err(OAuthError::ServerError),
+    };
+
+    let valid = grant.client_id == oauth_client.id
+        && grant.redirect_uri == redirect_uri
+        && grant.code_challenge.is_satisfied_by(&code_verifier);
+
+    if !valid {
+        return Err(OAuthError::InvalidGrant);
+    }
+
+    // The account may have been deleted since the code was issued
+    let user = match state.user_store.read().await.get_user_by_id(&grant.user_id).await {
+        Ok(user) => user,
+        Err(UserStoreError::UserNotFound) => return Err(OAuthError::InvalidGrant),
+        Err(_) => return Err(OAuthError::ServerError),
+    };
+
+    let session = Session::new(user.id.clone(), client, Utc::now().timestamp())
+        .authenticated_with(grant.auth_methods, grant.authenticated_at)
+        .for_oauth_client(oauth_client.id.clone(), grant.scopes);
+
+    let (auth_cookie, refresh_cookie) = open_session(&user, session.clone(), state).await.map_err(grant_error)?;
+
+    token_response(&user, &session, grant.nonce, auth_cookie, refresh_cookie, state).await
+}
+
+async fn refresh_grant(
+    oauth_client: &OAuthClient,
+    request: TokenRequest,
+    state: &AppState
+) -> Result<OAuthTokenResponse, OAuthError> {
+    let token = request.refresh_token.ok_or(OAuthError::InvalidRequest("refresh_token is missing"))?;
+    let token = RefreshToken::parse(token).map_err(|_| OAuthError::InvalidGrant)?;
+
+    let (user, session, auth_cookie, refresh_cookie) = refresh_session(&token, Some(&oauth_client.id), state)
+        .await
+        .map_err(grant_error)?;
+
+    token_response(&user, &session, None, auth_cookie, refresh_cookie, state).await
+}
+
+// The tokens minted for the session, along with a new ID token when it's an OpenID Connect session
+async fn token_response(
+    user: &User,
+    session: &Session,
+    nonce: Option<String>,
+    auth_cookie: Cookie<'static>,
+    refresh_cookie: Cookie<'static>,
+    state: &AppState
+) -> Result<OAuthTokenResponse, OAuthError> {
+    let id_token = match session.scopes.contains(&Scope::OpenId) {
+        true => Some(
+            generate_id_token(user, session, nonce, state.keyring.clone())
+                .await
+                .map_err(|_| OAuthError::ServerError)?
+        ),
+        false => None,
+    };
+
+    Ok(OAuthTokenResponse {
+        access_token: auth_cookie.value().to_owned(),
+        token_type: String::from("Bearer"),
+        expires_in: TOKEN_TTL_SECONDS,
+        refresh_token: refresh_cookie.value().to_owned(),
+        scope: (!session.scopes.is_empty()).then(|| format_scopes(&session.scopes)),
+        id_token,
+    })
+}
+
+// The user and browser session behind the auth token /authorize was called with, if it still validates
+async fn logged_in_session(token: Option<AuthToken>, state: &AppState) -> Option<(User, Session)> {
+    let (user, session) = authenticated_session(&token?, state).await.ok()?;
+
+    let session = state.session_store
+        .read()
+        .await
+        .get_session(&session, Utc::now().timestamp())
+        .await
+        .ok()?;
+
+    Some((user, session))
+}
+
+// Confidential clients have to prove who they are, with Basic auth or in the form.
+// Public clients have no secret, their codes are protected by PKCE alone.
+async fn authenticate_client(headers: &HeaderMap, request: &TokenRequest, state: &AppState) -> Result<OAuthClient, OAuthError> {
+    let (client_id, secret) = match basic_credentials(headers)? {
+        Some((client_id, secret)) => {
+            if request.client_secret.is_some() {
+                return Err(OAuthError::InvalidRequest("Client credentials must be sent one way only"));
+            }
+
+            if request.client_id.as_ref().is_some_and(|id| *id != client_id) {
+                return Err(OAuthError::InvalidClient);
+            }
+
+            (client_id, Some(secret))
+        }
+        None => (
+            request.client_id.clone().ok_or(OAuthError::InvalidRequest("client_id is missing"))?,
+            request.client_secret.clone(),
+        ),
+    };
+
+    let client_id = OAuthClientId::parse(client_id).map_err(|_| OAuthError::InvalidClient)?;
+    let store = state.oauth_client_store.read().await;
+
+    let oauth_client = match store.get_client(&client_id).await {
+        Ok(client) => client,
+        Err(OAuthClientStoreError::ClientNotFound) => return Err(OAuthError::InvalidClient),
+        Err(_) => return Err(OAuthError::ServerError),
+    };
+
+    match (oauth_client.confidential, secret) {
+        (true, Some(secret)) => {
+            let secret = OAuthClientSecret::parse(secret).map_err(|_| OAuthError::InvalidClient)?;
+
+            match store.validate_secret(&client_id, &secret).await {
+                Ok(()) => {}
+                Err(OAuthClientStoreError::UnexpectedError) => return Err(OAuthError::ServerError),
+                Err(_) => return Err(OAuthError::InvalidClient),
+            }
+        }
+        (false, None) => {}
+        // A public client sending a secret was handed one by mistake, or isn't that client
+        (true, None) | (false, Some(_)) => return Err(OAuthError::InvalidClient),
+    }
+
+    Ok(oauth_client)
+}
+
+// The client id and secret of an `Authorization: Basic` header. They're made of characters form
+// encoding leaves alone, so unlike RFC 6749 section 2.3.1 allows for, there's nothing to decode.
+fn basic_credentials(headers: &HeaderMap) -> Result<Option<(String, String)>, OAuthError> {
+    let Some(value) = headers.get(AUTHORIZATION) else {
+        return Ok(None);
+    };
+
+    let credentials = value
+        .to_str()
+        .ok()
+        .and_then(|value| value.split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("basic"))
+        .and_then(|(_, encoded)| STANDARD.decode(encoded.trim()).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .ok_or(OAuthError::InvalidClient)?;
+
+    let (client_id, secret) = credentials.split_once(':').ok_or(OAuthError::InvalidClient)?;
+
+    Ok(Some((client_id.to_owned(), secret.to_owned())))
+}
+
+// Whatever kept the session from getting tokens, the client can only start over
+fn grant_error(e: AuthApiError) -> OAuthError {
+    match e {
+        AuthApiError::UnexpectedError => OAuthError::ServerError,
+        _ => OAuthError::InvalidGrant,
+    }
+}
+
+// Back to the client's registered redirect URI, carrying the client's `state` along
+pub(crate) struct ClientRedirect {
+    url: Url,
+    state: Option<String>,
+}
+
+impl ClientRedirect {
+    pub(crate) fn new(redirect_uri: &str, state: Option<String>) -> Result<Self, OAuthError> {
+        let url = Url::parse(redirect_uri).map_err(|_| OAuthError::ServerError)?;
+        Ok(Self { url, state })
+    }
+
+    pub(crate) fn with_params(&self, params: &[(&str, &str)]) -> Redirect {
+        let mut url = self.url.clone();
+
+        {
+            let mut query = url.query_pairs_mut();
+            query.extend_pairs(params);
+
+            if let Some(state) = &self.state {
+                query.append_pair("state", state);
+            }
+        }
+
+        Redirect::to(url.as_str())
+    }
+
+    fn error(&self, error: OAuthError) -> Redirect {
+        match error.description() {
+            Some(description) => self.with_params(&[("error", error.code()), ("error_description", description)]),
+            None => self.with_params(&[("error", error.code())]),
+        }
+    }
+}
+
+fn login_redirect(uri: &Uri) -> Redirect {
+    let return_to = uri.path_and_query().map_or(uri.path(), |path_and_query| path_and_query.as_str());
+
+    let query = form_urlencoded::Serializer::new(String::new())
+        .append_pair("returnTo", return_to)
+        .finish();
+
+    Redirect::to(&format!("{}?{}", LOGIN_PAGE, query))
+}
@@ -0,0 +1,163 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::state::AppState,
+    services::{
+        api::AuthApiError,
+        auth_token::AuthToken,
+        oauth_clients::{OAuthClient, OAuthClientId, OAuthClientSecret, OAuthClientStoreError, RedirectUri}
+    }
+};
+
+use super::admin::{authorize_admin, AdminActionResponse};
+
+const MAX_CLIENT_NAME_LENGTH: usize = 100;
+
+#[derive(Deserialize, Debug)]
+pub struct RegisterOAuthClientRequest {
+    pub name: String,
+    #[serde(rename = "redirectUris")]
+    pub redirect_uris: Vec<String>,
+    // Public clients, like SPAs and native apps, get no secret
+    #[serde(default = "confidential_by_default")]
+    pub confidential: bool,
+}
+
+fn confidential_by_default() -> bool {
+    true
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeleteOAuthClientRequest {
+    #[serde(rename = "clientId")]
+    pub client_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OAuthClientResponse {
+    #[serde(rename = "clientId")]
+    pub client_id: String,
+    pub name: String,
+    #[serde(rename = "redirectUris")]
+    pub redirect_uris: Vec<String>,
+    pub confidential: bool,
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+    // Only in the response to registering a confidential client, it can't be looked up again
+    #[serde(rename = "clientSecret", default, skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+}
+
+impl From<OAuthClient> for OAuthClientResponse {
+    fn from(client: OAuthClient) -> Self {
+        Self {
+            client_id: client.id.as_ref().to_owned(),
+            name: client.name,
+            redirect_uris: client.redirect_uris.into_iter().map(|uri| uri.as_ref().to_owned()).collect(),
+            confidential: client.confidential,
+            created_at: client.created_at,
+            client_secret: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OAuthClientsResponse {
+    pub clients: Vec<OAuthClientResponse>,
+}
+
+#[tracing::instrument(name = "Admin register OAuth client", skip_all, err(Debug))]
+pub async fn admin_register_oauth_client(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    token: Option<AuthToken>,
+    Json(request): Json<RegisterOAuthClientRequest>
+) -> Result<impl IntoResponse, AuthApiError> {
+    authorize_admin(&headers, token.as_ref(), &state).await?;
+
+    let name = request.name.trim().to_owned();
+    if name.is_empty() || name.chars().count() > MAX_CLIENT_NAME_LENGTH {
+        return Err(AuthApiError::InvalidCredentials);
+    }
+
+    if request.redirect_uris.is_empty() {
+        return Err(AuthApiError::InvalidCredentials);
+    }
+
+    let redirect_uris = request.redirect_uris
+        .into_iter()
+        .map(RedirectUri::parse)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| AuthApiError::InvalidCredentials)?;
+
+    let client = OAuthClient {
+        id: OAuthClientId::default(),
+        name,
+        redirect_uris,
+        confidential: request.confidential,
+        created_at: Utc::now().timestamp(),
+    };
+    let secret = client.confidential.then(OAuthClientSecret::default);
+
+    state.oauth_client_store
+        .write()
+        .await
+        .add_client(client.clone(), secret.clone())
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?;
+
+    let mut response = OAuthClientResponse::from(client);
+    response.client_secret = secret.map(|secret| secret.as_ref().to_owned());
+
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+#[tracing::instrument(name = "Admin list OAuth clients", skip_all, err(Debug))]
+pub async fn admin_list_oauth_clients(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    token: Option<AuthToken>
+) -> Result<impl IntoResponse, AuthApiError> {
+    authorize_admin(&headers, token.as_ref(), &state).await?;
+
+    let clients = state.oauth_client_store
+        .read()
+        .await
+        .list_clients()
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?;
+
+    Ok((StatusCode::OK, Json(OAuthClientsResponse {
+        clients: clients.into_iter().map(OAuthClientResponse::from).collect(),
+    })))
+}
+
+// Ends every session the client started, so the tokens it was given stop validating
+#[tracing::instrument(name = "Admin delete OAuth client", skip_all, err(Debug))]
+pub async fn admin_delete_oauth_client(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    token: Option<AuthToken>,
+    Json(request): Json<DeleteOAuthClientRequest>
+) -> Result<impl IntoResponse, AuthApiError> {
+    authorize_admin(&headers, token.as_ref(), &state).await?;
+
+    let client_id = OAuthClientId::parse(request.client_id).map_err(|_| AuthApiError::InvalidCredentials)?;
+
+    match state.oauth_client_store.write().await.delete_client(&client_id).await {
+        Ok(()) => {}
+        Err(OAuthClientStoreError::ClientNotFound) => return Err(AuthApiError::OAuthClientNotFound),
+        Err(_) => return Err(AuthApiError::UnexpectedError),
+    }
+
+    Ok((StatusCode::OK, Json(AdminActionResponse {
+        message: String::from("OAuth client deleted"),
+    })))
+}
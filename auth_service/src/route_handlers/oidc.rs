@@ -0,0 +1,228 @@
+use axum::{
+    extract::{
+        rejection::{FormRejection, QueryRejection},
+        Query, State
+    },
+    http::header::CACHE_CONTROL,
+    response::{IntoResponse, Redirect},
+    Form, Json
+};
+use axum_extra::extract::CookieJar;
+use jsonwebtoken::Algorithm;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::{config::AppConfig, state::AppState},
+    services::{
+        api::OAuthError,
+        auth::{decode_id_token_hint, validate_access_token, validate_token},
+        auth_token::AuthToken,
+        constants::OIDC_ISSUER,
+        oauth_clients::{OAuthClientId, OAuthClientStoreError},
+        oidc::{format_scopes, Scope, UserInfo, SUPPORTED_SCOPES},
+        sessions::SessionId
+    },
+    user::store::UserStoreError
+};
+
+use super::{end_session, log_out, ClientRedirect, LOGIN_PAGE};
+
+// What OpenID Connect clients need to know about the provider, served at /.well-known/openid-configuration
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenIdConfiguration {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: String,
+    pub jwks_uri: String,
+    pub end_session_endpoint: String,
+    pub scopes_supported: Vec<String>,
+    pub response_types_supported: Vec<String>,
+    pub response_modes_supported: Vec<String>,
+    pub grant_types_supported: Vec<String>,
+    pub subject_types_supported: Vec<String>,
+    pub id_token_signing_alg_values_supported: Vec<Algorithm>,
+    pub token_endpoint_auth_methods_supported: Vec<String>,
+    pub code_challenge_methods_supported: Vec<String>,
+    pub claims_supported: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct EndSessionRequest {
+    pub id_token_hint: Option<String>,
+    pub client_id: Option<String>,
+    pub post_logout_redirect_uri: Option<String>,
+    pub state: Option<String>,
+}
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+pub async fn openid_configuration(State(state): State<AppState>) -> Json<OpenIdConfiguration> {
+    let AppConfig { paths } = AppConfig::config();
+    let endpoint = |path: &str| format!("{}{}", *OIDC_ISSUER, path);
+
+    // ID tokens are signed like auth tokens, so with whichever key currently signs those
+    let algorithm = state.keyring.read().await.signing_key().algorithm();
+
+    Json(OpenIdConfiguration {
+        issuer: OIDC_ISSUER.clone(),
+        authorization_endpoint: endpoint(paths.oauth_authorize),
+        token_endpoint: endpoint(paths.oauth_token),
+        userinfo_endpoint: endpoint(paths.userinfo),
+        jwks_uri: endpoint(paths.jwks),
+        end_session_endpoint: endpoint(paths.end_session),
+        scopes_supported: format_scopes(&SUPPORTED_SCOPES).split(' ').map(str::to_owned).collect(),
+        response_types_supported: strings(&["code"]),
+        response_modes_supported: strings(&["query"]),
+        grant_types_supported: strings(&["authorization_code", "refresh_token"]),
+        subject_types_supported: strings(&["public"]),
+        id_token_signing_alg_values_supported: vec![algorithm],
+        token_endpoint_auth_methods_supported: strings(&["client_secret_basic", "client_secret_post", "none"]),
+        code_challenge_methods_supported: strings(&["S256"]),
+        claims_supported: strings(&[
+            "iss", "sub", "aud", "exp", "iat", "auth_time", "nonce", "amr", "sid", "email", "email_verified",
+        ]),
+    })
+}
+
+// Claims about the user, for an access token /token issued with the `openid` scope
+#[tracing::instrument(name = "OIDC userinfo", skip_all, err(Debug))]
+pub async fn userinfo(
+    State(state): State<AppState>,
+    token: Option<AuthToken>
+) -> Result<impl IntoResponse, OAuthError> {
+    let token = token.ok_or(OAuthError::InvalidToken)?;
+
+    let (_, session) = validate_access_token(
+        token.as_ref(),
+        state.banned_token_store.clone(),
+        state.session_store.clone(),
+        state.keyring.clone()
+    )
+    .await
+    .map_err(|_| OAuthError::InvalidToken)?;
+
+    if !session.scopes.contains(&Scope::OpenId) {
+        return Err(OAuthError::InsufficientScope);
+    }
+
+    let user = match state.user_store.read().await.get_user_by_id(&session.user_id).await {
+        Ok(user) => user,
+        Err(UserStoreError::UserNotFound) => return Err(OAuthError::InvalidToken),
+        Err(_) => return Err(OAuthError::ServerError),
+    };
+
+    Ok(([(CACHE_CONTROL, "no-store")], Json(UserInfo::new(&user, &session.scopes))))
+}
+
+// RP-initiated logout, where a client sends the user here with the ID token it holds. OpenID Connect
+// lets it do so with a GET or a form POST.
+#[tracing::instrument(name = "OIDC end session", skip_all)]
+pub async fn oidc_end_session(
+    State(state): State<AppState>,
+    token: Option<AuthToken>,
+    jar: CookieJar,
+    request: Result<Query<EndSessionRequest>, QueryRejection>
+) -> (CookieJar, Result<Redirect, OAuthError>) {
+    match request {
+        Ok(Query(request)) => end_client_session(request, token, jar, &state).await,
+        Err(_) => (jar, Err(OAuthError::InvalidRequest("Parameters are malformed or repeated"))),
+    }
+}
+
+#[tracing::instrument(name = "OIDC end session form", skip_all)]
+pub async fn oidc_end_session_form(
+    State(state): State<AppState>,
+    token: Option<AuthToken>,
+    jar: CookieJar,
+    request: Result<Form<EndSessionRequest>, FormRejection>
+) -> (CookieJar, Result<Redirect, OAuthError>) {
+    match request {
+        Ok(Form(request)) => end_client_session(request, token, jar, &state).await,
+        Err(_) => (jar, Err(OAuthError::InvalidRequest("Expected form encoded parameters, each sent once"))),
+    }
+}
+
+// Ends the session named by the ID token, and logs the browser out like /logout when it's logged in
+// as the same user. The ID token is required, so a page can't log users out by linking here, and
+// since clients are registered by admins there's no page asking the user to confirm either.
+async fn end_client_session(
+    request: EndSessionRequest,
+    token: Option<AuthToken>,
+    jar: CookieJar,
+    state: &AppState
+) -> (CookieJar, Result<Redirect, OAuthError>) {
+    let (sid, sub, redirect) = match check_end_session_request(request, state).await {
+        Ok(checked) => checked,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    if end_session(&sid, state).await.is_err() {
+        return (jar, Err(OAuthError::ServerError));
+    }
+
+    let browser = match &token {
+        Some(token) => validate_token(
+            token.as_ref(),
+            state.banned_token_store.clone(),
+            state.session_store.clone(),
+            state.keyring.clone()
+        )
+        .await
+        .ok(),
+        None => None,
+    };
+
+    let jar = match (token, browser) {
+        (Some(token), Some(claims)) if claims.sub == sub => match log_out(token.as_ref(), &claims, &jar, state).await {
+            Ok(jar) => jar,
+            Err(_) => return (jar, Err(OAuthError::ServerError)),
+        },
+        _ => jar,
+    };
+
+    let redirect = match redirect {
+        Some(redirect) => redirect.with_params(&[]),
+        None => Redirect::to(LOGIN_PAGE),
+    };
+
+    (jar, Ok(redirect))
+}
+
+// The session and user the ID token hint names, and where to send the user afterwards if the client asked
+async fn check_end_session_request(
+    request: EndSessionRequest,
+    state: &AppState
+) -> Result<(SessionId, String, Option<ClientRedirect>), OAuthError> {
+    let hint = request.id_token_hint.ok_or(OAuthError::InvalidRequest("id_token_hint is missing"))?;
+
+    let claims = decode_id_token_hint(&hint, state.keyring.clone())
+        .await
+        .ok_or(OAuthError::InvalidRequest("id_token_hint is not an ID token issued here"))?;
+
+    if request.client_id.as_ref().is_some_and(|client_id| *client_id != claims.aud) {
+        return Err(OAuthError::InvalidRequest("client_id is not the audience of id_token_hint"));
+    }
+
+    let client_id = OAuthClientId::parse(claims.aud).map_err(|_| OAuthError::InvalidRequest("Unknown client_id"))?;
+
+    let oauth_client = match state.oauth_client_store.read().await.get_client(&client_id).await {
+        Ok(client) => client,
+        Err(OAuthClientStoreError::ClientNotFound) => return Err(OAuthError::InvalidRequest("Unknown client_id")),
+        Err(_) => return Err(OAuthError::ServerError),
+    };
+
+    // Clients are sent back to one of their registered redirect URIs, like from /authorize
+    let redirect = match request.post_logout_redirect_uri {
+        Some(uri) if oauth_client.allows_redirect_uri(&uri) => Some(ClientRedirect::new(&uri, request.state)?),
+        Some(_) => return Err(OAuthError::InvalidRequest("post_logout_redirect_uri is not registered for the client")),
+        None => None,
+    };
+
+    let session = SessionId::parse(claims.sid)
+        .map_err(|_| OAuthError::InvalidRequest("id_token_hint is not an ID token issued here"))?;
+
+    Ok((session, claims.user_info.sub, redirect))
+}
@@ -0,0 +1,348 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::state::AppState,
+    services::{
+        api::AuthApiError,
+        auth::{generate_invitation_token, invitation_token_recipient, redeem_invitation_token, LinkTokenError},
+        auth_token::AuthToken,
+        constants::ORG_INVITATION_URL,
+        invitations::Invitation,
+        organizations::{Membership, OrgRole, Organization, OrganizationId, OrganizationStoreError}
+    },
+    user::{store::UserStoreError, Email, Password, TwoFaMethod, User, UserId}
+};
+
+use super::{authenticated_session, authenticated_user, hand_out_tokens, issue_auth_cookie, ResponseMode, TokenResponse};
+
+const MAX_ORGANIZATION_NAME_LENGTH: usize = 100;
+
+#[derive(Deserialize, Debug)]
+pub struct CreateOrganizationRequest {
+    pub name: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct InviteMemberRequest {
+    #[serde(rename = "orgId")]
+    pub org_id: String,
+    pub email: String,
+    #[serde(default = "default_invited_role")]
+    pub role: OrgRole,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AcceptInvitationRequest {
+    pub token: String,
+    // Only needed when the invitee doesn't have an account yet
+    pub password: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SwitchOrganizationRequest {
+    #[serde(rename = "orgId")]
+    pub org_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrganizationResponse {
+    pub id: String,
+    pub name: String,
+    pub role: OrgRole,
+    // Whether auth tokens are currently issued for this organization
+    pub active: bool,
+    // The auth token minted for the organization, when the client authenticated with a bearer token
+    #[serde(flatten)]
+    pub tokens: Option<TokenResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrganizationsResponse {
+    pub organizations: Vec<OrganizationResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InvitationResponse {
+    pub message: String,
+    #[serde(rename = "orgId", default, skip_serializing_if = "Option::is_none")]
+    pub org_id: Option<String>,
+}
+
+fn default_invited_role() -> OrgRole {
+    OrgRole::Member
+}
+
+impl OrganizationResponse {
+    fn new(membership: Membership, active: bool) -> Self {
+        Self {
+            id: membership.organization.id.as_ref().to_owned(),
+            name: membership.organization.name,
+            role: membership.role,
+            active,
+            tokens: None,
+        }
+    }
+}
+
+// The creator owns the new organization and starts acting in it right away
+#[tracing::instrument(name = "Create organization", skip_all)]
+pub async fn create_organization(
+    State(state): State<AppState>,
+    token: AuthToken,
+    jar: CookieJar,
+    Json(request): Json<CreateOrganizationRequest>
+) -> (CookieJar, Result<impl IntoResponse, AuthApiError>) {
+    let (user, session) = match authenticated_session(&token, &state).await {
+        Ok(authenticated) => authenticated,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    let name = request.name.trim();
+
+    if name.is_empty() || name.chars().count() > MAX_ORGANIZATION_NAME_LENGTH {
+        return (jar, Err(AuthApiError::InvalidCredentials));
+    }
+
+    let organization = Organization {
+        id: OrganizationId::default(),
+        name: name.to_owned(),
+        created_at: Utc::now().timestamp(),
+    };
+
+    let mut organization_store = state.organization_store.write().await;
+
+    let created = async {
+        organization_store.add_organization(organization.clone()).await?;
+        organization_store.add_member(&organization.id, &user.id, OrgRole::Owner).await?;
+        organization_store.set_active_organization(&user.id, &organization.id).await
+    };
+
+    if created.await.is_err() {
+        return (jar, Err(AuthApiError::UnexpectedError));
+    }
+
+    drop(organization_store);
+
+    let auth_cookie = match issue_auth_cookie(&user, &session, &state).await {
+        Ok(cookie) => cookie,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    let membership = Membership { organization, role: OrgRole::Owner };
+
+    let (jar, tokens) = hand_out_tokens(jar, ResponseMode::from(&token), auth_cookie, None);
+    let response = OrganizationResponse { tokens, ..OrganizationResponse::new(membership, true) };
+
+    (jar, Ok((StatusCode::CREATED, Json(response))))
+}
+
+#[tracing::instrument(name = "List organizations", skip_all)]
+pub async fn list_organizations(
+    State(state): State<AppState>,
+    token: AuthToken
+) -> Result<impl IntoResponse, AuthApiError> {
+    let user = authenticated_user(&token, &state).await?;
+
+    let organization_store = state.organization_store.read().await;
+
+    let (memberships, active) = match (
+        organization_store.get_memberships(&user.id).await,
+        organization_store.get_active_membership(&user.id).await
+    ) {
+        (Ok(memberships), Ok(active)) => (memberships, active.map(|m| m.organization.id)),
+        _ => return Err(AuthApiError::UnexpectedError),
+    };
+
+    let organizations = memberships
+        .into_iter()
+        .map(|membership| {
+            let is_active = active.as_ref() == Some(&membership.organization.id);
+            OrganizationResponse::new(membership, is_active)
+        })
+        .collect();
+
+    Ok((StatusCode::OK, Json(OrganizationsResponse { organizations })))
+}
+
+#[tracing::instrument(name = "Invite organization member", skip_all)]
+pub async fn invite_member(
+    State(state): State<AppState>,
+    token: AuthToken,
+    Json(request): Json<InviteMemberRequest>
+) -> Result<impl IntoResponse, AuthApiError> {
+    let user = authenticated_user(&token, &state).await?;
+
+    let (org_id, email) = match (OrganizationId::parse(request.org_id), Email::parse(request.email)) {
+        (Ok(org_id), Ok(email)) => (org_id, email),
+        _ => return Err(AuthApiError::InvalidCredentials),
+    };
+
+    let membership = match state.organization_store.read().await.get_membership(&org_id, &user.id).await {
+        Ok(membership) if membership.role.can_invite(request.role) => membership,
+        Ok(_) | Err(OrganizationStoreError::MembershipNotFound) => return Err(AuthApiError::MissingPermission),
+        Err(_) => return Err(AuthApiError::UnexpectedError),
+    };
+
+    if let Ok(invitee) = state.user_store.read().await.get_user(&email).await {
+        if state.organization_store.read().await.get_membership(&org_id, &invitee.id).await.is_ok() {
+            return Err(AuthApiError::UserAlreadyExists);
+        }
+    }
+
+    let invitation = Invitation { org_id, email: email.clone(), role: request.role };
+
+    let token = match generate_invitation_token(invitation, state.invitation_store.clone(), state.keyring.clone()).await {
+        Ok(token) => token,
+        Err(_) => return Err(AuthApiError::UnexpectedError),
+    };
+
+    let organization_name = membership.organization.name;
+
+    let content = format!(
+        "You've been invited to join {} as {}. Follow the link to accept, or to create your account \
+        if you don't have one yet. The link expires in 7 days: {}?token={}",
+        organization_name,
+        request.role.as_ref(),
+        ORG_INVITATION_URL.as_str(),
+        token
+    );
+
+    let subject = format!("You've been invited to join {}", organization_name);
+
+    if state.email_client.send_email(&email, &subject, &content).await.is_err() {
+        return Err(AuthApiError::UnexpectedError);
+    }
+
+    Ok((StatusCode::ACCEPTED, Json(InvitationResponse {
+        message: String::from("Invitation sent"),
+        org_id: None,
+    })))
+}
+
+// Following the emailed link proves the invitee owns the address, so someone without an
+// account can sign up here, with their address already verified
+#[tracing::instrument(name = "Accept organization invitation", skip_all, err(Debug))]
+pub async fn accept_invitation(
+    State(state): State<AppState>,
+    Json(request): Json<AcceptInvitationRequest>
+) -> Result<impl IntoResponse, AuthApiError> {
+    let email = invitation_token_recipient(&request.token, state.keyring.clone())
+        .await
+        .ok_or(AuthApiError::InvalidToken)?;
+
+    let existing_user = match state.user_store.read().await.get_user(&email).await {
+        Ok(user) => Some(user),
+        Err(UserStoreError::UserNotFound) => None,
+        Err(_) => return Err(AuthApiError::UnexpectedError),
+    };
+
+    // Checked before the invitation is used up, so a missing password can be fixed with the same link
+    let password = match (&existing_user, request.password) {
+        (Some(_), _) => None,
+        (None, Some(password)) => Some(Password::parse(password).map_err(|_| AuthApiError::InvalidCredentials)?),
+        (None, None) => return Err(AuthApiError::InvalidCredentials),
+    };
+
+    let invitation = redeem_invitation_token(&request.token, state.invitation_store.clone(), state.keyring.clone())
+        .await
+        .map_err(|e| match e {
+            LinkTokenError::InvalidToken => AuthApiError::InvalidToken,
+            LinkTokenError::UnexpectedError => AuthApiError::UnexpectedError,
+        })?;
+
+    let (user, status) = match (existing_user, password) {
+        (Some(user), _) => (user, StatusCode::OK),
+        (None, password) => {
+            let user = User {
+                id: UserId::default(),
+                email: invitation.email.clone(),
+                password: password.ok_or(AuthApiError::InvalidCredentials)?,
+                two_fa_method: TwoFaMethod::None,
+                email_verified: true,
+                created_at: Utc::now().timestamp(),
+                delete_after: None,
+                locked: false,
+                password_reset_required: false,
+            };
+
+            state.user_store
+                .write()
+                .await
+                .add_user(user.clone())
+                .await
+                .map_err(|e| match e {
+                    UserStoreError::UserAlreadyExists => AuthApiError::UserAlreadyExists,
+                    _ => AuthApiError::UnexpectedError,
+                })?;
+
+            (user, StatusCode::CREATED)
+        }
+    };
+
+    let mut organization_store = state.organization_store.write().await;
+
+    organization_store
+        .add_member(&invitation.org_id, &user.id, invitation.role)
+        .await
+        .map_err(|e| match e {
+            // The organization was deleted since the invitation was sent
+            OrganizationStoreError::OrganizationNotFound => AuthApiError::InvalidToken,
+            _ => AuthApiError::UnexpectedError,
+        })?;
+
+    organization_store
+        .set_active_organization(&user.id, &invitation.org_id)
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?;
+
+    Ok((status, Json(InvitationResponse {
+        message: String::from("Invitation accepted"),
+        org_id: Some(invitation.org_id.as_ref().to_owned()),
+    })))
+}
+
+// Tokens carry a single organization, so acting in another one means minting a new token for it
+#[tracing::instrument(name = "Switch organization", skip_all)]
+pub async fn switch_organization(
+    State(state): State<AppState>,
+    token: AuthToken,
+    jar: CookieJar,
+    Json(request): Json<SwitchOrganizationRequest>
+) -> (CookieJar, Result<impl IntoResponse, AuthApiError>) {
+    let (user, session) = match authenticated_session(&token, &state).await {
+        Ok(authenticated) => authenticated,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    let org_id = match OrganizationId::parse(request.org_id) {
+        Ok(org_id) => org_id,
+        Err(_) => return (jar, Err(AuthApiError::InvalidCredentials)),
+    };
+
+    let mut organization_store = state.organization_store.write().await;
+
+    let membership = match organization_store.get_membership(&org_id, &user.id).await {
+        Ok(membership) => membership,
+        Err(OrganizationStoreError::MembershipNotFound) => return (jar, Err(AuthApiError::MissingPermission)),
+        Err(_) => return (jar, Err(AuthApiError::UnexpectedError)),
+    };
+
+    if organization_store.set_active_organization(&user.id, &org_id).await.is_err() {
+        return (jar, Err(AuthApiError::UnexpectedError));
+    }
+
+    drop(organization_store);
+
+    let auth_cookie = match issue_auth_cookie(&user, &session, &state).await {
+        Ok(cookie) => cookie,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    let (jar, tokens) = hand_out_tokens(jar, ResponseMode::from(&token), auth_cookie, None);
+    let response = OrganizationResponse { tokens, ..OrganizationResponse::new(membership, true) };
+
+    (jar, Ok((StatusCode::OK, Json(response))))
+}
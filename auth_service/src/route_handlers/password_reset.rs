@@ -0,0 +1,121 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::state::AppState,
+    services::{
+        api::AuthApiError,
+        auth::{generate_password_reset_token, redeem_password_reset_token, revoke_user_sessions, LinkTokenError},
+        constants::PASSWORD_RESET_URL
+    },
+    user::{store::UserStoreError, Email, Password}
+};
+
+#[derive(Deserialize, Debug)]
+pub struct PasswordResetRequest {
+    pub email: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PasswordResetConfirmRequest {
+    pub token: String,
+    #[serde(rename = "newPassword")]
+    pub new_password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordResetResponse {
+    pub message: String,
+}
+
+#[tracing::instrument(name = "Request password reset", skip_all, err(Debug))]
+pub async fn password_reset_request(
+    State(state): State<AppState>,
+    Json(request): Json<PasswordResetRequest>
+) -> Result<impl IntoResponse, AuthApiError> {
+    let email = Email::parse(request.email).map_err(|_| AuthApiError::InvalidCredentials)?;
+
+    // The link is sent in the background so the response looks and takes the same
+    // whether or not the account exists, which keeps the endpoint useless for enumeration
+    tokio::spawn(send_password_reset_link(email, state));
+
+    Ok((StatusCode::ACCEPTED, Json(PasswordResetResponse {
+        message: String::from("If an account exists for this email, a password reset link has been sent"),
+    })))
+}
+
+#[tracing::instrument(name = "Confirm password reset", skip_all, err(Debug))]
+pub async fn password_reset_confirm(
+    State(state): State<AppState>,
+    Json(request): Json<PasswordResetConfirmRequest>
+) -> Result<impl IntoResponse, AuthApiError> {
+    let password = Password::parse(request.new_password).map_err(|_| AuthApiError::InvalidCredentials)?;
+
+    let email = redeem_password_reset_token(
+        &request.token,
+        state.password_reset_token_store.clone(),
+        state.keyring.clone()
+    )
+    .await
+    .map_err(|e| match e {
+        LinkTokenError::InvalidToken => AuthApiError::InvalidToken,
+        LinkTokenError::UnexpectedError => AuthApiError::UnexpectedError,
+    })?;
+
+    let mut user_store = state.user_store.write().await;
+
+    let user = user_store
+        .get_user(&email)
+        .await
+        .map_err(|e| match e {
+            // The account was deleted or moved to another address since the link was sent
+            UserStoreError::UserNotFound => AuthApiError::InvalidToken,
+            _ => AuthApiError::UnexpectedError,
+        })?;
+
+    user_store
+        .update_password(&email, password)
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?;
+
+    drop(user_store);
+
+    // Whoever knew the old password may still be logged in, so end every session
+    revoke_user_sessions(&user, state.refresh_token_store.clone(), state.banned_token_store.clone(), state.session_store.clone())
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?;
+
+    Ok((StatusCode::OK, Json(PasswordResetResponse {
+        message: String::from("Password has been reset"),
+    })))
+}
+
+pub(crate) async fn send_password_reset_link(email: Email, state: AppState) {
+    if state.user_store.read().await.get_user(&email).await.is_err() {
+        return;
+    }
+
+    let token = match generate_password_reset_token(
+        &email,
+        state.password_reset_token_store.clone(),
+        state.keyring.clone()
+    )
+    .await
+    {
+        Ok(token) => token,
+        Err(e) => {
+            tracing::error!("Failed to generate password reset token: {:?}", e);
+            return;
+        }
+    };
+
+    let content = format!(
+        "Use this link to reset your password, it expires in 15 minutes: {}?token={}",
+        PASSWORD_RESET_URL.as_str(),
+        token
+    );
+
+    if let Err(e) = state.email_client.send_email(&email, "Reset your password", &content).await {
+        tracing::error!("Failed to send password reset email: {:?}", e);
+    }
+}
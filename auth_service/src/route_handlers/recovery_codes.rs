@@ -0,0 +1,87 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::state::AppState,
+    services::{api::AuthApiError, auth_token::AuthToken, recovery_codes::RecoveryCode},
+    user::Email
+};
+
+use super::authenticated_user;
+
+// Codes are only ever shown in the response that issues them
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecoveryCodesResponse {
+    #[serde(rename = "recoveryCodes", default, skip_serializing_if = "Option::is_none")]
+    pub recovery_codes: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecoveryCodeCountResponse {
+    pub remaining: usize,
+}
+
+// Issue a new set of codes, invalidating whatever the user had left
+pub async fn regenerate_recovery_codes(
+    State(state): State<AppState>,
+    token: AuthToken
+) -> Result<impl IntoResponse, AuthApiError> {
+    let user = authenticated_user(&token, &state).await?;
+
+    if !user.requires_2fa() {
+        return Err(AuthApiError::InvalidCredentials);
+    }
+
+    let recovery_codes = issue_recovery_codes(&user.email, &state).await?;
+
+    Ok((StatusCode::OK, Json(RecoveryCodesResponse { recovery_codes: Some(recovery_codes) })))
+}
+
+pub async fn get_recovery_codes(
+    State(state): State<AppState>,
+    token: AuthToken
+) -> Result<impl IntoResponse, AuthApiError> {
+    let email = authenticated_user(&token, &state).await?.email;
+
+    let remaining = match state.recovery_code_store.read().await.count_codes(&email).await {
+        Ok(count) => count,
+        Err(_) => return Err(AuthApiError::UnexpectedError),
+    };
+
+    Ok((StatusCode::OK, Json(RecoveryCodeCountResponse { remaining })))
+}
+
+pub(crate) async fn issue_recovery_codes(email: &Email, state: &AppState) -> Result<Vec<String>, AuthApiError> {
+    let codes = RecoveryCode::generate_set();
+    let displayed = codes.iter().map(RecoveryCode::display).collect();
+
+    state
+        .recovery_code_store
+        .write()
+        .await
+        .set_codes(email, codes)
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?;
+
+    Ok(displayed)
+}
+
+// Enrolling another second factor keeps the codes the user already wrote down
+pub(crate) async fn issue_recovery_codes_if_missing(
+    email: &Email,
+    state: &AppState
+) -> Result<Option<Vec<String>>, AuthApiError> {
+    let remaining = state
+        .recovery_code_store
+        .read()
+        .await
+        .count_codes(email)
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?;
+
+    if remaining > 0 {
+        return Ok(None);
+    }
+
+    issue_recovery_codes(email, state).await.map(Some)
+}
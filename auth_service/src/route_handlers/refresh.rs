@@ -0,0 +1,48 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use serde::Deserialize;
+
+use crate::{
+    app::state::AppState,
+    services::{api::AuthApiError, constants::REFRESH_COOKIE_NAME, refresh_tokens::RefreshToken}
+};
+
+use super::{hand_out_tokens, refresh_session, ResponseMode};
+
+// Clients without a cookie jar send the refresh token they got in a token response
+#[derive(Deserialize, Debug)]
+pub struct RefreshRequest {
+    #[serde(rename = "refreshToken")]
+    pub refresh_token: String,
+}
+
+pub async fn refresh(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    request: Option<Json<RefreshRequest>>
+) -> (CookieJar, Result<impl IntoResponse, AuthApiError>) {
+    // Tokens are handed back the way the refresh token came in
+    let (token, mode) = match (request, jar.get(REFRESH_COOKIE_NAME)) {
+        (Some(Json(request)), _) => (request.refresh_token, ResponseMode::Token),
+        (None, Some(cookie)) => (cookie.value().to_owned(), ResponseMode::Cookie),
+        (None, None) => return (jar, Err(AuthApiError::MissingToken)),
+    };
+
+    let token = match RefreshToken::parse(token) {
+        Ok(token) => token,
+        Err(_) => return (jar, Err(AuthApiError::InvalidToken))
+    };
+
+    // Sessions of OAuth clients are refreshed at /token, where the client authenticates
+    let (_, _, auth_cookie, refresh_cookie) = match refresh_session(&token, None, &state).await {
+        Ok(refreshed) => refreshed,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    let (jar, tokens) = hand_out_tokens(jar, mode, auth_cookie, Some(refresh_cookie));
+
+    match tokens {
+        Some(tokens) => (jar, Ok((StatusCode::OK, Json(tokens)).into_response())),
+        None => (jar, Ok(StatusCode::OK.into_response())),
+    }
+}
@@ -0,0 +1,94 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::state::AppState,
+    services::{
+        api::AuthApiError,
+        two_fa::{LoginId, TWO_FA_RESEND_COOLDOWN_SECONDS}
+    },
+    user::TwoFaMethod
+};
+
+use super::send_2fa_code;
+
+#[derive(Deserialize, Debug)]
+pub struct Resend2FARequest {
+    #[serde(rename = "loginAttemptId")]
+    pub login_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Resend2FAResponse {
+    pub message: String,
+    // Seconds until the code can be resent again, for the UI to count down
+    #[serde(rename = "cooldownSeconds")]
+    pub cooldown_seconds: i64,
+    #[serde(rename = "resendsRemaining")]
+    pub resends_remaining: u32,
+}
+
+#[tracing::instrument(name = "Resend 2FA code", skip_all, err(Debug))]
+pub async fn resend_2fa(
+    State(state): State<AppState>,
+    Json(request): Json<Resend2FARequest>
+) -> Result<impl IntoResponse, AuthApiError> {
+    let login_id = LoginId::parse(request.login_id).map_err(|_| AuthApiError::InvalidCredentials)?;
+
+    let email = state
+        .two_fa_code
+        .read()
+        .await
+        .get_challenge(&login_id)
+        .await
+        .map_err(|_| AuthApiError::IncorrectCredentials)?
+        .email;
+
+    let two_fa_method = state
+        .user_store
+        .read()
+        .await
+        .get_user(&email)
+        .await
+        .map_err(|_| AuthApiError::IncorrectCredentials)?
+        .two_fa_method;
+
+    // Authenticator apps and passkeys have nothing to send
+    if two_fa_method != TwoFaMethod::Email {
+        return Err(AuthApiError::InvalidCredentials);
+    }
+
+    let mut two_fa_code_store = state.two_fa_code.write().await;
+
+    // Checked again under the write lock so concurrent requests can't both slip past the limits
+    let mut challenge = two_fa_code_store
+        .get_challenge(&login_id)
+        .await
+        .map_err(|_| AuthApiError::IncorrectCredentials)?;
+
+    if challenge.resends_remaining() == 0 {
+        return Err(AuthApiError::TooManyResends);
+    }
+
+    let cooldown_remaining = challenge.resend_cooldown_remaining();
+    if cooldown_remaining > 0 {
+        return Err(AuthApiError::ResendCooldown(cooldown_remaining));
+    }
+
+    challenge.prepare_resend();
+
+    two_fa_code_store
+        .update_challenge(&login_id, challenge.clone())
+        .await
+        .map_err(|_| AuthApiError::IncorrectCredentials)?;
+
+    drop(two_fa_code_store);
+
+    send_2fa_code(&challenge.email, &challenge.code, &state).await?;
+
+    Ok((StatusCode::OK, Json(Resend2FAResponse {
+        message: String::from("2FA code sent"),
+        cooldown_seconds: TWO_FA_RESEND_COOLDOWN_SECONDS,
+        resends_remaining: challenge.resends_remaining(),
+    })))
+}
@@ -0,0 +1,172 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::state::AppState,
+    services::{
+        api::AuthApiError,
+        auth_token::AuthToken,
+        constants::{JWT_COOKIE_NAME, REFRESH_COOKIE_NAME},
+        refresh_tokens::RefreshTokenFamily,
+        sessions::{Session, SessionId, SessionStoreError}
+    }
+};
+
+use super::authenticated_session;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionResponse {
+    pub id: String,
+    pub device: Option<String>,
+    pub ip: Option<String>,
+    #[serde(rename = "userAgent")]
+    pub user_agent: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+    #[serde(rename = "lastSeenAt")]
+    pub last_seen_at: i64,
+    // The OAuth client the session was started for, if any
+    #[serde(rename = "oauthClientId", default)]
+    pub oauth_client_id: Option<String>,
+    // Whether this is the session making the request
+    pub current: bool,
+}
+
+impl SessionResponse {
+    fn new(session: Session, current: &SessionId) -> Self {
+        Self {
+            current: session.id == *current,
+            id: session.id.as_ref().to_owned(),
+            device: session.device,
+            ip: session.client.ip,
+            user_agent: session.client.user_agent,
+            created_at: session.created_at,
+            last_seen_at: session.last_seen_at,
+            oauth_client_id: session.oauth_client_id.map(|id| id.as_ref().to_owned()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionsResponse {
+    pub sessions: Vec<SessionResponse>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RevokeSessionRequest {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionActionResponse {
+    pub message: String,
+}
+
+// The devices the user is logged in on, most recently seen first
+#[tracing::instrument(name = "List sessions", skip_all, err(Debug))]
+pub async fn list_sessions(
+    State(state): State<AppState>,
+    token: AuthToken
+) -> Result<impl IntoResponse, AuthApiError> {
+    let (user, current) = authenticated_session(&token, &state).await?;
+
+    let sessions = state.session_store
+        .read()
+        .await
+        .list_sessions(&user.id, Utc::now().timestamp())
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?;
+
+    let sessions = sessions
+        .into_iter()
+        .map(|session| SessionResponse::new(session, &current))
+        .collect();
+
+    Ok((StatusCode::OK, Json(SessionsResponse { sessions })))
+}
+
+// Logs one of the user's devices out. Revoking the current session works like logging out.
+#[tracing::instrument(name = "Revoke session", skip_all)]
+pub async fn revoke_session(
+    State(state): State<AppState>,
+    token: AuthToken,
+    jar: CookieJar,
+    Json(request): Json<RevokeSessionRequest>
+) -> (CookieJar, Result<impl IntoResponse, AuthApiError>) {
+    let (user, current) = match authenticated_session(&token, &state).await {
+        Ok(authenticated) => authenticated,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    let session_id = match SessionId::parse(request.session_id) {
+        Ok(session_id) => session_id,
+        Err(_) => return (jar, Err(AuthApiError::InvalidCredentials)),
+    };
+
+    // Sessions of other users are reported as missing, so their ids can't be probed
+    match state.session_store.read().await.get_session(&session_id, Utc::now().timestamp()).await {
+        Ok(session) if session.user_id == user.id => {}
+        Ok(_) | Err(SessionStoreError::SessionNotFound) => return (jar, Err(AuthApiError::SessionNotFound)),
+        Err(SessionStoreError::UnexpectedError) => return (jar, Err(AuthApiError::UnexpectedError)),
+    }
+
+    if let Err(e) = end_session(&session_id, &state).await {
+        return (jar, Err(e));
+    }
+
+    // Removal cookies need the path of the originals, which this route's own path isn't
+    let jar = if session_id == current {
+        jar.remove(Cookie::build(JWT_COOKIE_NAME).path("/"))
+            .remove(Cookie::build(REFRESH_COOKIE_NAME).path("/"))
+    } else {
+        jar
+    };
+
+    (jar, Ok((StatusCode::OK, Json(SessionActionResponse {
+        message: String::from("Session revoked"),
+    }))))
+}
+
+// Logs every device out except the one making the request
+#[tracing::instrument(name = "Revoke other sessions", skip_all, err(Debug))]
+pub async fn revoke_other_sessions(
+    State(state): State<AppState>,
+    token: AuthToken
+) -> Result<impl IntoResponse, AuthApiError> {
+    let (user, current) = authenticated_session(&token, &state).await?;
+
+    let sessions = state.session_store
+        .read()
+        .await
+        .list_sessions(&user.id, Utc::now().timestamp())
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?;
+
+    for session in sessions.iter().filter(|session| session.id != current) {
+        end_session(&session.id, &state).await?;
+    }
+
+    Ok((StatusCode::OK, Json(SessionActionResponse {
+        message: String::from("Other sessions revoked"),
+    })))
+}
+
+// Its auth tokens stop validating right away and its refresh tokens are deleted
+pub(crate) async fn end_session(session: &SessionId, state: &AppState) -> Result<(), AuthApiError> {
+    state.session_store
+        .write()
+        .await
+        .remove_session(session)
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?;
+
+    state.refresh_token_store
+        .write()
+        .await
+        .revoke_family(&RefreshTokenFamily::from(session))
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)
+}
@@ -1,9 +1,12 @@
 
 use axum::{extract::State, response::IntoResponse, Json};
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
-use crate::{app::state::AppState, services::api::AuthApiError, user::{Email, Password, User}};
+use crate::{app::state::AppState, services::api::AuthApiError, user::{Email, Password, TwoFaMethod, User, UserId}};
+
+use super::{issue_recovery_codes, send_verification_email};
 
 #[derive(Deserialize, Validate, Debug)]
 pub struct SignupRequest {
@@ -22,9 +25,15 @@ pub async fn signup(State(state): State<AppState>, Json(SignupRequest {email, pa
         .map_err(|_| AuthApiError::InvalidCredentials)?;
 
     let user = User { 
+        id: UserId::default(),
         email: user_email, 
         password: user_password, 
-        requires_2fa
+        two_fa_method: if requires_2fa { TwoFaMethod::Email } else { TwoFaMethod::None },
+        email_verified: false,
+        created_at: Utc::now().timestamp(),
+        delete_after: None,
+        locked: false,
+        password_reset_required: false
     };
 
     let mut user_store = state.user_store.write().await;
@@ -33,12 +42,29 @@ pub async fn signup(State(state): State<AppState>, Json(SignupRequest {email, pa
         return Err(AuthApiError::UserAlreadyExists);
     }
 
+    let email = user.email.clone();
+
     if user_store.add_user(user).await.is_err() {
         return Err(AuthApiError::UnexpectedError);
     }
 
+    drop(user_store);
+
+    // The account is usable either way, a lost confirmation email can be resent
+    if let Err(e) = send_verification_email(&email, &state).await {
+        tracing::error!("Failed to send verification email: {:?}", e);
+    }
+
+    // Email 2FA is enrolled at signup, so this is when the user gets their recovery codes
+    let recovery_codes = if requires_2fa {
+        Some(issue_recovery_codes(&email, &state).await?)
+    } else {
+        None
+    };
+
     let response = Json(SignupResponse {
         message: "User created successfully!".to_string(),
+        recovery_codes,
     });
 
     Ok((axum::http::StatusCode::CREATED, response))
@@ -48,4 +74,6 @@ pub async fn signup(State(state): State<AppState>, Json(SignupRequest {email, pa
 #[derive(Serialize, Debug, Deserialize, PartialEq)]
 pub struct SignupResponse {
     pub message: String,
+    #[serde(rename = "recoveryCodes", default, skip_serializing_if = "Option::is_none")]
+    pub recovery_codes: Option<Vec<String>>,
 }
@@ -0,0 +1,107 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::state::AppState,
+    services::{
+        api::AuthApiError,
+        auth_token::AuthToken,
+        constants::{APP_NAME, TOTP_SKEW_STEPS},
+        totp::{qr_code_svg, TotpCode, TotpSecret}
+    },
+    user::TwoFaMethod
+};
+
+use super::{authenticated_user, ensure_current_password, issue_recovery_codes_if_missing, RecoveryCodesResponse};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TotpEnrollResponse {
+    pub secret: String,
+    #[serde(rename = "otpauthUri")]
+    pub otpauth_uri: String,
+    #[serde(rename = "qrCodeSvg")]
+    pub qr_code_svg: String,
+}
+
+#[derive(Deserialize)]
+pub struct TotpEnrollRequest {
+    password: String,
+}
+
+#[derive(Deserialize)]
+pub struct TotpConfirmRequest {
+    code: String,
+    password: String,
+}
+
+// Start enrolling an authenticator app. The secret only takes effect once confirmed with a valid code.
+pub async fn totp_enroll(
+    State(state): State<AppState>,
+    token: AuthToken,
+    Json(request): Json<TotpEnrollRequest>
+) -> Result<impl IntoResponse, AuthApiError> {
+    let email = authenticated_user(&token, &state).await?.email;
+
+    ensure_current_password(&email, request.password, &state).await?;
+
+    let secret = TotpSecret::default();
+    let otpauth_uri = secret.otpauth_uri(APP_NAME, &email);
+
+    let qr_code_svg = match qr_code_svg(&otpauth_uri) {
+        Ok(svg) => svg,
+        Err(_) => return Err(AuthApiError::UnexpectedError),
+    };
+
+    if state.totp_store.write().await.set_pending_secret(email, secret.clone()).await.is_err() {
+        return Err(AuthApiError::UnexpectedError);
+    }
+
+    let response = Json(TotpEnrollResponse {
+        secret: secret.encode(),
+        otpauth_uri,
+        qr_code_svg,
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+pub async fn totp_confirm(
+    State(state): State<AppState>,
+    token: AuthToken,
+    Json(request): Json<TotpConfirmRequest>
+) -> Result<impl IntoResponse, AuthApiError> {
+    let email = authenticated_user(&token, &state).await?.email;
+
+    ensure_current_password(&email, request.password, &state).await?;
+
+    let code = match TotpCode::parse(request.code) {
+        Ok(code) => code,
+        Err(_) => return Err(AuthApiError::InvalidCredentials),
+    };
+
+    let mut totp_store = state.totp_store.write().await;
+
+    let secret = match totp_store.get_pending_secret(&email).await {
+        Ok(secret) => secret,
+        Err(_) => return Err(AuthApiError::IncorrectCredentials),
+    };
+
+    let step = match secret.verify_now(&code, *TOTP_SKEW_STEPS) {
+        Some(step) => step,
+        None => return Err(AuthApiError::IncorrectCredentials),
+    };
+
+    if totp_store.confirm_secret(&email, step).await.is_err() {
+        return Err(AuthApiError::UnexpectedError);
+    }
+
+    drop(totp_store);
+
+    if state.user_store.write().await.set_two_fa_method(&email, TwoFaMethod::Totp).await.is_err() {
+        return Err(AuthApiError::UnexpectedError);
+    }
+
+    let recovery_codes = issue_recovery_codes_if_missing(&email, &state).await?;
+
+    Ok((StatusCode::OK, Json(RecoveryCodesResponse { recovery_codes })))
+}
@@ -0,0 +1,72 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::state::AppState,
+    services::{
+        api::AuthApiError,
+        auth::{generate_account_unlock_token, validate_account_unlock_token},
+        constants::{ACCOUNT_UNLOCK_URL, LOGIN_LOCKOUT_POLICY}
+    },
+    user::Email
+};
+
+#[derive(Deserialize, Debug)]
+pub struct UnlockAccountRequest {
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnlockAccountResponse {
+    pub message: String,
+}
+
+// Lifts a lockout from failed logins early, through the link emailed when it started
+#[tracing::instrument(name = "Unlock account", skip_all, err(Debug))]
+pub async fn unlock_account(
+    State(state): State<AppState>,
+    Json(request): Json<UnlockAccountRequest>
+) -> Result<impl IntoResponse, AuthApiError> {
+    let email = validate_account_unlock_token(&request.token, state.keyring.clone())
+        .await
+        .ok_or(AuthApiError::InvalidToken)?;
+
+    state.login_attempt_store
+        .write()
+        .await
+        .clear(&email)
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?;
+
+    Ok((StatusCode::OK, Json(UnlockAccountResponse {
+        message: String::from("Account unlocked"),
+    })))
+}
+
+pub(crate) async fn send_account_unlock_link(email: Email, locked_until: i64, state: AppState) {
+    let token = match generate_account_unlock_token(&email, locked_until, state.keyring.clone()).await {
+        Ok(token) => token,
+        Err(e) => {
+            tracing::error!("Failed to generate account unlock token: {:?}", e);
+            return;
+        }
+    };
+
+    // Rounded up, a lockout is never shorter than a minute
+    let minutes = (locked_until - Utc::now().timestamp() + 59) / 60;
+
+    let content = format!(
+        "Your account was locked for {} minutes after {} failed login attempts in a row. \
+        If they weren't yours, someone may be guessing your password and you should change it. \
+        If they were, you can unlock your account right away with this link: {}?token={}",
+        minutes.max(1),
+        LOGIN_LOCKOUT_POLICY.threshold,
+        ACCOUNT_UNLOCK_URL.as_str(),
+        token
+    );
+
+    if let Err(e) = state.email_client.send_email(&email, "Your account has been locked", &content).await {
+        tracing::error!("Failed to send account unlock email: {:?}", e);
+    }
+}
@@ -1,5 +1,6 @@
 use axum::{
     extract::State, 
+    http::StatusCode,
     response::IntoResponse, 
     Json
 };
@@ -9,25 +10,34 @@ use serde::Deserialize;
 use crate::{
     app::state::AppState, 
     services::{
-        api::AuthApiError, 
-            auth::generate_auth_cookie, 
+        api::{AuthApiError, TwoFaCodeError}, 
+            client_info::ClientInfo,
+            constants::{TOTP_SKEW_STEPS, TWO_FA_MAX_ATTEMPTS},
+            oidc::AuthMethod,
+            recovery_codes::RecoveryCode,
+            totp::TotpCode,
             two_fa::{LoginId, TwoFaCode}
         }, 
-    user::Email
+    user::{Email, TwoFaMethod}
 };
 
+use super::{finish_login, hand_out_tokens, start_session, ResponseMode};
+
 #[derive(Deserialize, Debug)]
 pub struct Verify2FARequest {
     pub email: String,
-    #[serde(rename = "lodinId")]
+    #[serde(rename = "loginAttemptId")]
     pub login_id: String,
     #[serde(rename = "2FACode")]
-    pub two_fa_code: String 
+    pub two_fa_code: String,
+    #[serde(rename = "responseMode", default)]
+    pub response_mode: ResponseMode,
 }
 
 pub async fn verify_2fa(
     jar: CookieJar,
     State(state): State<AppState>,
+    client: ClientInfo,
     Json(request): Json<Verify2FARequest>
 ) -> (CookieJar, Result<impl IntoResponse, AuthApiError>) {
     let email = match Email::parse(request.email) {
@@ -40,32 +50,97 @@ pub async fn verify_2fa(
         Err(_) => return (jar, Err(AuthApiError::InvalidCredentials))
     };
 
-    let two_fa_code_req = match TwoFaCode::parse(request.two_fa_code) {
-        Ok(code) => code,
-        Err(_) => return (jar, Err(AuthApiError::InvalidToken))
+    // Every method uses six-digit codes, the stricter per-method checks happen below.
+    // Anything else has to be one of the user's recovery codes.
+    let factor = match TotpCode::parse(request.two_fa_code.clone()) {
+        Ok(code) => SecondFactor::Code(code),
+        Err(_) => match RecoveryCode::parse(request.two_fa_code) {
+            Ok(code) => SecondFactor::RecoveryCode(code),
+            Err(_) => return (jar, Err(AuthApiError::InvalidCredentials))
+        }
+    };
+
+    let user = match state.user_store.read().await.get_user(&email).await {
+        Ok(user) => user,
+        Err(_) => return (jar, Err(AuthApiError::IncorrectCredentials))
     };
 
     let mut two_fa_code_store = state.two_fa_code.write().await;
 
-    let two_fa_tup = match two_fa_code_store.get_code(&email).await {
-        Ok(tup) => tup,
+    let challenge = match two_fa_code_store.get_challenge(&login_id_req).await {
+        Ok(challenge) => challenge,
         Err(_) => return (jar, Err(AuthApiError::IncorrectCredentials))
     };
 
-    if two_fa_code_store.remove_code(&email).await.is_err() {
+    // Someone else's login attempt doesn't use up any of its tries
+    if challenge.email != email {
+        return (jar, Err(AuthApiError::IncorrectCredentials));
+    }
+
+    let verified = match (factor, user.two_fa_method) {
+        (SecondFactor::RecoveryCode(recovery_code), _) => use_recovery_code(&email, &recovery_code, &state).await,
+        (SecondFactor::Code(code_req), TwoFaMethod::Totp) => verify_totp(&email, &code_req, &state).await,
+        // Passkey logins are completed by /webauthn/login/finish
+        (SecondFactor::Code(_), TwoFaMethod::Passkey) => false,
+        (SecondFactor::Code(code_req), _) => {
+            let two_fa_code_req = match TwoFaCode::parse(code_req.as_ref().to_owned()) {
+                Ok(code) => code,
+                Err(_) => return (jar, Err(AuthApiError::InvalidCredentials))
+            };
+
+            two_fa_code_req.eq(&challenge.code)
+        }
+    };
+
+    if !verified {
+        let error = match two_fa_code_store.record_failed_attempt(&login_id_req, *TWO_FA_MAX_ATTEMPTS).await {
+            Ok(()) => AuthApiError::IncorrectCredentials,
+            Err(TwoFaCodeError::TooManyAttempts) => AuthApiError::TooManyAttempts,
+            Err(_) => AuthApiError::UnexpectedError,
+        };
+
+        return (jar, Err(error));
+    }
+
+    if two_fa_code_store.remove_challenge(&login_id_req).await.is_err() {
         return (jar, Err(AuthApiError::UnexpectedError));
     }
 
-    let cookie = match generate_auth_cookie(&email) {
-        Ok(cookie) => cookie,
-        Err(_) => return (jar, Err(AuthApiError::UnexpectedError)),
+    let (cookie, refresh_cookie) = match start_session(&user, vec![AuthMethod::Password, AuthMethod::OneTimeCode], &client, &state).await {
+        Ok(cookies) => cookies,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    finish_login(&email, client, &state).await;
+
+    let (jar, tokens) = hand_out_tokens(jar, request.response_mode, cookie, Some(refresh_cookie));
+
+    match tokens {
+        Some(tokens) => (jar, Ok((StatusCode::OK, Json(tokens)).into_response())),
+        None => (jar, Ok(StatusCode::OK.into_response())),
+    }
+}
+
+enum SecondFactor {
+    Code(TotpCode),
+    RecoveryCode(RecoveryCode),
+}
+
+async fn use_recovery_code(email: &Email, code: &RecoveryCode, state: &AppState) -> bool {
+    state.recovery_code_store.write().await.use_code(email, code).await.is_ok()
+}
+
+// Accept each authenticator code at most once, so a code observed in transit cannot be replayed
+async fn verify_totp(email: &Email, code: &TotpCode, state: &AppState) -> bool {
+    let mut totp_store = state.totp_store.write().await;
+
+    let secret = match totp_store.get_secret(email).await {
+        Ok(secret) => secret,
+        Err(_) => return false,
     };
 
-    match two_fa_tup {
-        (id, code) if !login_id_req.eq(&id) || !two_fa_code_req.eq(&code) => (
-            jar, 
-            Err(AuthApiError::IncorrectCredentials)
-        ),
-        _ =>  (jar.add(cookie), Ok(()))
+    match secret.verify_now(code, *TOTP_SKEW_STEPS) {
+        Some(step) => totp_store.use_step(email, step).await.is_ok(),
+        None => false,
     }
 }
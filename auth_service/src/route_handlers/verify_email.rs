@@ -0,0 +1,93 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::state::AppState,
+    services::{
+        api::AuthApiError,
+        auth::{generate_email_verification_token, validate_email_verification_token},
+        constants::EMAIL_VERIFICATION_URL
+    },
+    user::Email
+};
+
+#[derive(Deserialize, Debug)]
+pub struct VerifyEmailRequest {
+    pub token: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ResendVerificationEmailRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyEmailResponse {
+    pub message: String,
+}
+
+#[tracing::instrument(name = "Verify email", skip_all, err(Debug))]
+pub async fn verify_email(
+    State(state): State<AppState>,
+    Json(request): Json<VerifyEmailRequest>
+) -> Result<impl IntoResponse, AuthApiError> {
+    let email = validate_email_verification_token(&request.token, state.keyring.clone())
+        .await
+        .ok_or(AuthApiError::InvalidToken)?;
+
+    // The account may have been deleted since the link was sent
+    state
+        .user_store
+        .write()
+        .await
+        .mark_email_verified(&email)
+        .await
+        .map_err(|_| AuthApiError::InvalidToken)?;
+
+    Ok((StatusCode::OK, Json(VerifyEmailResponse {
+        message: String::from("Email verified"),
+    })))
+}
+
+#[tracing::instrument(name = "Resend verification email", skip_all, err(Debug))]
+pub async fn resend_verification_email(
+    State(state): State<AppState>,
+    Json(request): Json<ResendVerificationEmailRequest>
+) -> Result<impl IntoResponse, AuthApiError> {
+    let email = Email::parse(request.email).map_err(|_| AuthApiError::InvalidCredentials)?;
+
+    // Like password resets, answer the same way whether or not there is an unverified account
+    tokio::spawn(async move {
+        let unverified = matches!(
+            state.user_store.read().await.get_user(&email).await,
+            Ok(user) if !user.email_verified
+        );
+
+        if unverified {
+            if let Err(e) = send_verification_email(&email, &state).await {
+                tracing::error!("Failed to resend verification email: {:?}", e);
+            }
+        }
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(VerifyEmailResponse {
+        message: String::from("If this email is awaiting verification, a new confirmation link has been sent"),
+    })))
+}
+
+pub(crate) async fn send_verification_email(email: &Email, state: &AppState) -> Result<(), AuthApiError> {
+    let token = generate_email_verification_token(email, state.keyring.clone())
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?;
+
+    let content = format!(
+        "Confirm your email address with this link, it expires in 24 hours: {}?token={}",
+        EMAIL_VERIFICATION_URL.as_str(),
+        token
+    );
+
+    state.email_client
+        .send_email(email, "Confirm your email address", &content)
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)
+}
@@ -5,15 +5,34 @@ use crate::{app::state::AppState, services::{api::AuthApiError, auth::validate_t
 
 pub async fn verify_token(
     State(state): State<AppState>,
-    Json(VerifyTokenReq {token}): Json<VerifyTokenReq>
+    Json(VerifyTokenReq {token, required_role, required_permission}): Json<VerifyTokenReq>
 ) -> Result<StatusCode, AuthApiError> {
-    match validate_token(&token, state.banned_token_store.clone()).await {
-        Ok(_) => Ok(StatusCode::OK),
-        Err(_) => Err(AuthApiError::InvalidToken)
+    let claims = validate_token(
+        &token,
+        state.banned_token_store.clone(),
+        state.session_store.clone(),
+        state.keyring.clone()
+    )
+    .await
+    .map_err(|_| AuthApiError::InvalidToken)?;
+
+    if required_role.is_some_and(|role| !claims.has_role(&role)) {
+        return Err(AuthApiError::MissingPermission);
+    }
+
+    if required_permission.is_some_and(|permission| !claims.has_permission(&permission)) {
+        return Err(AuthApiError::MissingPermission);
     }
+
+    Ok(StatusCode::OK)
 }
 
 #[derive(Debug, Deserialize)]
 pub struct VerifyTokenReq {
-    token: String
+    token: String,
+    // Callers can also have the token checked for a role or permission, which answers 403 when it's missing
+    #[serde(rename = "requiredRole")]
+    required_role: Option<String>,
+    #[serde(rename = "requiredPermission")]
+    required_permission: Option<String>,
 }
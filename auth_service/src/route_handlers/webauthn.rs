@@ -0,0 +1,276 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::state::AppState,
+    services::{
+        api::AuthApiError,
+        auth_token::AuthToken,
+        client_info::ClientInfo,
+        constants::UNVERIFIED_LOGIN_POLICY,
+        oidc::AuthMethod,
+        passkeys::Passkey,
+        two_fa::LoginId,
+        webauthn::{
+            client_data_challenge, creation_options, request_options, verify_assertion, verify_registration,
+            AuthenticationCredential, Challenge, CreationOptions, RegistrationCredential, RelyingParty,
+            RequestOptions, WebauthnError
+        },
+        webauthn_challenges::Ceremony
+    },
+    user::{Email, TwoFaMethod}
+};
+
+use super::{authenticated_user, ensure_current_password, finish_login, issue_recovery_codes_if_missing, start_session, RecoveryCodesResponse};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebauthnRegisterStartResponse {
+    #[serde(rename = "publicKey")]
+    pub public_key: CreationOptions,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebauthnLoginStartResponse {
+    #[serde(rename = "publicKey")]
+    pub public_key: RequestOptions,
+}
+
+#[derive(Deserialize)]
+pub struct WebauthnRegisterStartRequest {
+    password: String,
+}
+
+// Both fields are set when a passkey is the second factor after a password,
+// and both are left out for a passwordless login
+#[derive(Deserialize)]
+pub struct WebauthnLoginStartRequest {
+    email: Option<String>,
+    #[serde(rename = "loginAttemptId")]
+    login_id: Option<String>,
+}
+
+// A passkey can log in without a password, so only the password lets one be added
+pub async fn webauthn_register_start(
+    State(state): State<AppState>,
+    token: AuthToken,
+    Json(request): Json<WebauthnRegisterStartRequest>
+) -> Result<impl IntoResponse, AuthApiError> {
+    let email = authenticated_user(&token, &state).await?.email;
+
+    ensure_current_password(&email, request.password, &state).await?;
+
+    let existing = match state.passkey_store.read().await.get_passkeys(&email).await {
+        Ok(passkeys) => passkeys,
+        Err(_) => return Err(AuthApiError::UnexpectedError),
+    };
+
+    // Reusing the handle lets authenticators replace a user's old passkey instead of piling up new ones
+    let user_handle = existing
+        .first()
+        .map(|passkey| passkey.user_handle.clone())
+        .unwrap_or_default();
+
+    let challenge = Challenge::default();
+    let options = creation_options(&RelyingParty::configured(), &email, &user_handle, &challenge, &existing);
+
+    let added = state
+        .webauthn_challenge_store
+        .write()
+        .await
+        .add_challenge(challenge, Ceremony::Registration { email, user_handle })
+        .await
+        .is_ok();
+
+    if !added {
+        return Err(AuthApiError::UnexpectedError);
+    }
+
+    Ok((StatusCode::OK, Json(WebauthnRegisterStartResponse { public_key: options })))
+}
+
+pub async fn webauthn_register_finish(
+    State(state): State<AppState>,
+    token: AuthToken,
+    Json(credential): Json<RegistrationCredential>
+) -> Result<impl IntoResponse, AuthApiError> {
+    let user = authenticated_user(&token, &state).await?;
+    let email = user.email;
+
+    let challenge = match client_data_challenge(&credential.response.client_data_json) {
+        Ok(challenge) => challenge,
+        Err(_) => return Err(AuthApiError::InvalidCredentials),
+    };
+
+    let user_handle = match state.webauthn_challenge_store.write().await.take_challenge(&challenge).await {
+        Ok(Ceremony::Registration { email: ceremony_email, user_handle }) if ceremony_email == email => user_handle,
+        _ => return Err(AuthApiError::IncorrectCredentials),
+    };
+
+    let registered = match verify_registration(&RelyingParty::configured(), &challenge, &credential, false) {
+        Ok(registered) => registered,
+        Err(_) => return Err(AuthApiError::IncorrectCredentials),
+    };
+
+    let passkey = Passkey {
+        credential_id: registered.credential_id,
+        email: email.clone(),
+        user_handle,
+        public_key: registered.public_key,
+        sign_count: registered.sign_count,
+    };
+
+    if state.passkey_store.write().await.add_passkey(passkey).await.is_err() {
+        return Err(AuthApiError::IncorrectCredentials);
+    }
+
+    // An account that already has a second factor keeps it, the passkey still works for passwordless logins
+    if user.two_fa_method == TwoFaMethod::None
+        && state.user_store.write().await.set_two_fa_method(&email, TwoFaMethod::Passkey).await.is_err()
+    {
+        return Err(AuthApiError::UnexpectedError);
+    }
+
+    let recovery_codes = issue_recovery_codes_if_missing(&email, &state).await?;
+
+    Ok((StatusCode::CREATED, Json(RecoveryCodesResponse { recovery_codes })))
+}
+
+pub async fn webauthn_login_start(
+    State(state): State<AppState>,
+    Json(request): Json<WebauthnLoginStartRequest>
+) -> Result<impl IntoResponse, AuthApiError> {
+    let login_attempt = match (request.email, request.login_id) {
+        (Some(email), Some(login_id)) => {
+            let email = Email::parse(email).map_err(|_| AuthApiError::InvalidCredentials)?;
+            let login_id = LoginId::parse(login_id).map_err(|_| AuthApiError::InvalidCredentials)?;
+            Some((email, login_id))
+        }
+        (None, None) => None,
+        _ => return Err(AuthApiError::InvalidCredentials),
+    };
+
+    // Only reveal which passkeys a user has once their password has been checked
+    let allowed = match &login_attempt {
+        Some((email, login_id)) => {
+            if !is_current_login_attempt(email, login_id, &state).await {
+                return Err(AuthApiError::IncorrectCredentials);
+            }
+
+            state
+                .passkey_store
+                .read()
+                .await
+                .get_passkeys(email)
+                .await
+                .map_err(|_| AuthApiError::UnexpectedError)?
+        }
+        None => Vec::new(),
+    };
+
+    let challenge = Challenge::default();
+    let options = request_options(&RelyingParty::configured(), &challenge, &allowed, login_attempt.is_none());
+
+    state
+        .webauthn_challenge_store
+        .write()
+        .await
+        .add_challenge(challenge, Ceremony::Authentication { login_attempt })
+        .await
+        .map_err(|_| AuthApiError::UnexpectedError)?;
+
+    Ok((StatusCode::OK, Json(WebauthnLoginStartResponse { public_key: options })))
+}
+
+pub async fn webauthn_login_finish(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    jar: CookieJar,
+    Json(credential): Json<AuthenticationCredential>
+) -> (CookieJar, Result<impl IntoResponse, AuthApiError>) {
+    let challenge = match client_data_challenge(&credential.response.client_data_json) {
+        Ok(challenge) => challenge,
+        Err(_) => return (jar, Err(AuthApiError::InvalidCredentials)),
+    };
+
+    let login_attempt = match state.webauthn_challenge_store.write().await.take_challenge(&challenge).await {
+        Ok(Ceremony::Authentication { login_attempt }) => login_attempt,
+        _ => return (jar, Err(AuthApiError::IncorrectCredentials)),
+    };
+
+    let mut passkey_store = state.passkey_store.write().await;
+
+    let passkey = match passkey_store.get_passkey(&credential.id).await {
+        Ok(passkey) => passkey,
+        Err(_) => return (jar, Err(AuthApiError::IncorrectCredentials)),
+    };
+
+    if let Some((email, login_id)) = &login_attempt {
+        if passkey.email != *email || !is_current_login_attempt(email, login_id, &state).await {
+            return (jar, Err(AuthApiError::IncorrectCredentials));
+        }
+    }
+
+    // A passkey alone only stands in for a password if the authenticator verified the user
+    let require_user_verification = login_attempt.is_none();
+
+    let sign_count = match verify_assertion(
+        &RelyingParty::configured(),
+        &challenge,
+        &credential,
+        &passkey,
+        require_user_verification
+    ) {
+        Ok(sign_count) => sign_count,
+        Err(WebauthnError::CounterRegression) => {
+            tracing::warn!("Passkey signature counter went backwards, the authenticator may have been cloned");
+            return (jar, Err(AuthApiError::IncorrectCredentials));
+        }
+        Err(_) => return (jar, Err(AuthApiError::IncorrectCredentials)),
+    };
+
+    if passkey_store.update_sign_count(&passkey.credential_id, sign_count).await.is_err() {
+        return (jar, Err(AuthApiError::UnexpectedError));
+    }
+
+    drop(passkey_store);
+
+    let user = match state.user_store.read().await.get_user(&passkey.email).await {
+        Ok(user) => user,
+        Err(_) => return (jar, Err(AuthApiError::IncorrectCredentials)),
+    };
+
+    // A passwordless login skips /login, so the verification policy is enforced here instead
+    if login_attempt.is_none() && !UNVERIFIED_LOGIN_POLICY.allows_login(&user, Utc::now().timestamp()) {
+        return (jar, Err(AuthApiError::EmailNotVerified));
+    }
+
+    if let Some((_, login_id)) = &login_attempt {
+        if state.two_fa_code.write().await.remove_challenge(login_id).await.is_err() {
+            return (jar, Err(AuthApiError::UnexpectedError));
+        }
+    }
+
+    // The passkey is either the second factor after a password, or the only one
+    let methods = match login_attempt {
+        Some(_) => vec![AuthMethod::Password, AuthMethod::Passkey],
+        None => vec![AuthMethod::Passkey],
+    };
+
+    let (auth_cookie, refresh_cookie) = match start_session(&user, methods, &client, &state).await {
+        Ok(cookies) => cookies,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    finish_login(&user.email, client, &state).await;
+
+    (jar.add(auth_cookie).add(refresh_cookie), Ok(StatusCode::OK))
+}
+
+async fn is_current_login_attempt(email: &Email, login_id: &LoginId, state: &AppState) -> bool {
+    match state.two_fa_code.read().await.get_challenge(login_id).await {
+        Ok(challenge) => challenge.email == *email,
+        Err(_) => false,
+    }
+}
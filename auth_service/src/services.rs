@@ -1,15 +1,51 @@
 use async_trait::async_trait;
 
-use crate::{app::email_client::{EmailClient, SendEmail}, user::Email};
+use crate::{app::email_client::EmailClient, user::Email};
 
 pub mod api;
 pub mod auth;
+pub mod auth_token;
+pub mod authorization_codes;
+pub mod client_info;
+pub mod keys;
 pub mod tokens;
+pub mod refresh_tokens;
+pub mod sessions;
 pub mod constants;
 pub mod two_fa;
+pub mod totp;
+pub mod recovery_codes;
+pub mod passkeys;
+pub mod webauthn;
+pub mod webauthn_challenges;
+pub mod password_reset_tokens;
+pub mod email_changes;
+pub mod login_history;
+pub mod login_attempts;
+pub mod rate_limits;
+pub mod organizations;
+pub mod invitations;
+pub mod oauth_clients;
+pub mod oidc;
 pub mod postgres_user_store;
+pub mod postgres_refresh_token_store;
+pub mod postgres_totp_store;
+pub mod postgres_passkey_store;
+pub mod postgres_recovery_code_store;
+pub mod postgres_login_history_store;
+pub mod postgres_organization_store;
+pub mod postgres_session_store;
+pub mod postgres_oauth_client_store;
 pub mod redis_banned_token_store;
 pub mod redis_two_fa_code_store;
+pub mod redis_refresh_token_store;
+pub mod redis_webauthn_challenge_store;
+pub mod redis_password_reset_token_store;
+pub mod redis_email_change_store;
+pub mod redis_invitation_store;
+pub mod redis_login_attempt_store;
+pub mod redis_rate_limit_store;
+pub mod redis_authorization_code_store;
 pub mod tracing;
 pub mod postmark_email_client;
 
@@ -1,5 +1,5 @@
 use axum::{
-    http::StatusCode,
+    http::{header::{CACHE_CONTROL, RETRY_AFTER, WWW_AUTHENTICATE}, StatusCode},
     response::{IntoResponse, Response},
     Json
 };
@@ -12,35 +12,162 @@ pub enum AuthApiError {
     UnexpectedError,
     MissingToken,
     InvalidToken,
-    IncorrectCredentials
+    IncorrectCredentials,
+    EmailNotVerified,
+    // Locked by an admin
+    AccountLocked,
+    // Locked after too many wrong passwords, for this many more seconds
+    AccountTemporarilyLocked(i64),
+    // An admin requires a new password, set through the password reset flow
+    PasswordResetRequired,
+    UserNotFound,
+    // Not a live session of the caller
+    SessionNotFound,
+    OAuthClientNotFound,
+    // The token is valid but lacks the role or permission the caller asked for
+    MissingPermission,
+    TooManyAttempts,
+    TooManyResends,
+    // Seconds the client has to wait before trying again
+    ResendCooldown(i64),
+    // Over the rate limit of the route, for this many more seconds
+    RateLimited(i64)
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct ErrorResponse {
     pub error: String,
+    #[serde(rename = "retryAfter", default, skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<i64>,
 }
 
 impl IntoResponse for AuthApiError {
     fn into_response(self) -> Response {
+        let retry_after = match self {
+            AuthApiError::ResendCooldown(seconds)
+            | AuthApiError::AccountTemporarilyLocked(seconds)
+            | AuthApiError::RateLimited(seconds) => Some(seconds),
+            _ => None,
+        };
+
         let (status, error_message) = match self {
             AuthApiError::UserAlreadyExists => (StatusCode::CONFLICT, "User already exists"),
             AuthApiError::InvalidCredentials => (StatusCode::BAD_REQUEST, "Invalid credentials"),
             AuthApiError::MissingToken => (StatusCode::BAD_REQUEST, "Missing auth token"),
             AuthApiError::InvalidToken => (StatusCode::UNAUTHORIZED, "Invalid auth token"),
             AuthApiError::IncorrectCredentials => (StatusCode::UNAUTHORIZED, "Incorrect credentials"),
+            AuthApiError::EmailNotVerified => (StatusCode::FORBIDDEN, "Email not verified"),
+            AuthApiError::AccountLocked => (StatusCode::FORBIDDEN, "Account locked"),
+            AuthApiError::PasswordResetRequired => (StatusCode::FORBIDDEN, "Password reset required"),
+            AuthApiError::UserNotFound => (StatusCode::NOT_FOUND, "User not found"),
+            AuthApiError::SessionNotFound => (StatusCode::NOT_FOUND, "Session not found"),
+            AuthApiError::OAuthClientNotFound => (StatusCode::NOT_FOUND, "OAuth client not found"),
+            AuthApiError::MissingPermission => (StatusCode::FORBIDDEN, "Missing permission"),
+            AuthApiError::TooManyAttempts => (StatusCode::TOO_MANY_REQUESTS, "Too many attempts"),
+            AuthApiError::TooManyResends => (StatusCode::TOO_MANY_REQUESTS, "Too many resends"),
+            AuthApiError::ResendCooldown(_) => (StatusCode::TOO_MANY_REQUESTS, "Resend cooldown"),
+            AuthApiError::RateLimited(_) => (StatusCode::TOO_MANY_REQUESTS, "Too many requests"),
+            AuthApiError::AccountTemporarilyLocked(_) => {
+                (StatusCode::TOO_MANY_REQUESTS, "Account temporarily locked")
+            }
             AuthApiError::UnexpectedError => {
                 (StatusCode::INTERNAL_SERVER_ERROR, "Unexpected error")
             }
         };
         let body = Json(ErrorResponse {
             error: error_message.to_string(),
+            retry_after,
         });
-        (status, body).into_response()
+
+        match retry_after {
+            Some(seconds) => (status, [(RETRY_AFTER, seconds.to_string())], body).into_response(),
+            None => (status, body).into_response(),
+        }
+    }
+}
+
+// Errors of the OAuth endpoints, which clients expect in the shape RFC 6749 section 5.2 gives them
+#[derive(Debug, PartialEq)]
+pub enum OAuthError {
+    // A parameter is missing, repeated or malformed, described for the developer of the client
+    InvalidRequest(&'static str),
+    InvalidClient,
+    // The code or refresh token is unknown, expired, used up, or was issued to another client
+    InvalidGrant,
+    UnsupportedGrantType,
+    UnsupportedResponseType,
+    // The user can't authorize the client, e.g. their account is locked
+    AccessDenied,
+    // The client asked with `prompt=none` not to show the login page, but the user isn't logged in
+    LoginRequired,
+    // Errors of /userinfo, which RFC 6750 describes in a `WWW-Authenticate: Bearer` header:
+    // the access token is missing or doesn't validate, or wasn't issued for the `openid` scope
+    InvalidToken,
+    InsufficientScope,
+    ServerError,
+}
+
+impl OAuthError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            OAuthError::InvalidRequest(_) => "invalid_request",
+            OAuthError::InvalidClient => "invalid_client",
+            OAuthError::InvalidGrant => "invalid_grant",
+            OAuthError::UnsupportedGrantType => "unsupported_grant_type",
+            OAuthError::UnsupportedResponseType => "unsupported_response_type",
+            OAuthError::AccessDenied => "access_denied",
+            OAuthError::LoginRequired => "login_required",
+            OAuthError::InvalidToken => "invalid_token",
+            OAuthError::InsufficientScope => "insufficient_scope",
+            OAuthError::ServerError => "server_error",
+        }
+    }
+
+    pub fn description(&self) -> Option<&'static str> {
+        match self {
+            OAuthError::InvalidRequest(description) => Some(description),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OAuthErrorResponse {
+    pub error: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_description: Option<String>,
+}
+
+impl IntoResponse for OAuthError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            OAuthError::InvalidClient | OAuthError::InvalidToken => StatusCode::UNAUTHORIZED,
+            OAuthError::InsufficientScope => StatusCode::FORBIDDEN,
+            OAuthError::ServerError => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        };
+
+        let body = Json(OAuthErrorResponse {
+            error: self.code().to_owned(),
+            error_description: self.description().map(str::to_owned),
+        });
+
+        match self {
+            OAuthError::InvalidClient => {
+                (status, [(WWW_AUTHENTICATE, "Basic"), (CACHE_CONTROL, "no-store")], body).into_response()
+            }
+            OAuthError::InvalidToken | OAuthError::InsufficientScope => {
+                let challenge = format!("Bearer error=\"{}\"", self.code());
+                (status, [(WWW_AUTHENTICATE, challenge.as_str()), (CACHE_CONTROL, "no-store")], body).into_response()
+            }
+            _ => (status, [(CACHE_CONTROL, "no-store")], body).into_response(),
+        }
     }
 }
 
 #[derive(Debug, PartialEq)]
 pub enum TwoFaCodeError {
     LoginAttemptIdNotFound,
+    TooManyAttempts,
     UnexpectedError,
 }
\ No newline at end of file
@@ -1,22 +1,50 @@
 use axum_extra::extract::cookie::{Cookie, SameSite};
 use chrono::Utc;
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Validation};
-use serde::{Deserialize, Serialize};
+use jsonwebtoken::{decode, decode_header, encode, Header, Validation};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::user::Email;
+use crate::user::{roles::permissions_of, Email, Role, User, UserId};
 
 #[allow(unused_imports)]
 use super::{
-    constants::{JWT_COOKIE_NAME, JWT_SECRET}, 
+    constants::{JWT_COOKIE_NAME, JWT_EMAIL_CLAIM, OIDC_ISSUER, REFRESH_COOKIE_NAME}, 
+    email_changes::{
+        EmailChange, EmailChangeId, EmailChangeStoreError, EmailChangeStoreType, EMAIL_CHANGE_TTL_SECONDS
+    },
+    invitations::{Invitation, InvitationId, InvitationStoreError, InvitationStoreType, INVITATION_TTL_SECONDS},
+    organizations::Membership,
+    keys::{Keyring, KeyringType, SigningKey},
+    oidc::{amr, format_scopes, UserInfo},
+    password_reset_tokens::{
+        PasswordResetId, PasswordResetTokenStoreError, PasswordResetTokenStoreType, PASSWORD_RESET_TTL_SECONDS
+    },
+    refresh_tokens::{
+        RefreshToken, RefreshTokenFamily, RefreshTokenRecord, RefreshTokenStore, 
+        RefreshTokenStoreError, RefreshTokenStoreType
+    },
+    sessions::{Session, SessionId, SessionStoreType},
     tokens::{BannedTokenStoreType, BannedTokenStore}
 };
 
-// This is definitely NOT a good secret. We will update it soon!
-// const JWT_SECRET: &str = "secret";
+// Create cookie with a new JWT auth token for the given session
+pub async fn generate_auth_cookie(
+    user: &User,
+    session: &SessionId,
+    grants: &Grants,
+    keyring: KeyringType
+) -> Result<Cookie<'static>, GenerateTokenError> {
+    let token = generate_auth_token(user, session, grants, *JWT_EMAIL_CLAIM, &*keyring.read().await)?;
+    Ok(create_auth_cookie(token))
+}
 
-// Create cookie with a new JWT auth token
-pub fn generate_auth_cookie(email: &Email) -> Result<Cookie<'static>, GenerateTokenError> {
-    let token = generate_auth_token(email)?;
+// Create cookie with a new access token for a session an OAuth client started. /token hands out its value
+// in the response body, it's never set as a cookie.
+pub async fn generate_access_token_cookie(
+    user: &User,
+    session: &Session,
+    keyring: KeyringType
+) -> Result<Cookie<'static>, GenerateTokenError> {
+    let token = generate_access_token(user, session, &*keyring.read().await)?;
     Ok(create_auth_cookie(token))
 }
 
@@ -31,22 +59,197 @@ fn create_auth_cookie(token: String) -> Cookie<'static> {
     cookie
 }
 
+// Create cookie with a new refresh token in the token family of the session
+pub async fn generate_refresh_cookie(
+    email: &Email, 
+    session: &SessionId,
+    refresh_token_store: RefreshTokenStoreType
+) -> Result<Cookie<'static>, GenerateTokenError> {
+    let mut store = refresh_token_store.write().await;
+    let token = issue_refresh_token(email, RefreshTokenFamily::from(session), &mut *store).await?;
+    Ok(create_refresh_cookie(token))
+}
+
+// Create cookie and set the value to the passed-in refresh token
+fn create_refresh_cookie(token: RefreshToken) -> Cookie<'static> {
+    Cookie::build((REFRESH_COOKIE_NAME, token.as_ref().to_owned()))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .build()
+}
+
 #[derive(Debug)]
 pub enum GenerateTokenError {
     TokenError(jsonwebtoken::errors::Error),
     UnexpectedError,
 }
 
+#[derive(Debug, PartialEq)]
+pub enum RefreshTokenError {
+    InvalidToken,
+    TokenReused,
+    UnexpectedError,
+}
+
 // This value determines how long the JWT auth token is valid for
 pub const TOKEN_TTL_SECONDS: i64 = 600; // 10 minutes
 
-// Create JWT auth token
-fn generate_auth_token(email: &Email) -> Result<String, GenerateTokenError> {
+// This value determines how long a refresh token can be exchanged for a new auth token
+pub const REFRESH_TOKEN_TTL_SECONDS: i64 = 2_592_000; // 30 days
+
+// Exchange a refresh token for the next one in its family, which is also the session it belongs to.
+// Presenting a token that was already rotated means it leaked, so the whole family is revoked.
+pub async fn rotate_refresh_token(
+    token: &RefreshToken, 
+    refresh_token_store: RefreshTokenStoreType
+) -> Result<(Email, SessionId, Cookie<'static>), RefreshTokenError> {
+    let mut store = refresh_token_store.write().await;
+
+    let record = match store.get_token(token).await {
+        Ok(record) => record,
+        Err(RefreshTokenStoreError::TokenNotFound) => return Err(RefreshTokenError::InvalidToken),
+        Err(_) => return Err(RefreshTokenError::UnexpectedError),
+    };
+
+    // Whether the token was already used is settled by marking it, so two refreshes racing
+    // with the same token can't both rotate it
+    match store.mark_used(token).await {
+        Ok(()) => {}
+        Err(RefreshTokenStoreError::TokenAlreadyUsed) => {
+            store.revoke_family(&record.family)
+                .await
+                .map_err(|_| RefreshTokenError::UnexpectedError)?;
+            return Err(RefreshTokenError::TokenReused);
+        }
+        Err(RefreshTokenStoreError::TokenNotFound) => return Err(RefreshTokenError::InvalidToken),
+        Err(_) => return Err(RefreshTokenError::UnexpectedError),
+    }
+
+    let session = SessionId::parse(record.family.as_ref().to_owned())
+        .map_err(|_| RefreshTokenError::UnexpectedError)?;
+
+    let next_token = issue_refresh_token(&record.email, record.family, &mut *store)
+        .await
+        .map_err(|_| RefreshTokenError::UnexpectedError)?;
+
+    Ok((record.email, session, create_refresh_cookie(next_token)))
+}
+
+// Revoke every refresh token that shares a family with the passed-in token
+pub async fn revoke_refresh_token(
+    token: &RefreshToken, 
+    refresh_token_store: RefreshTokenStoreType
+) -> Result<(), RefreshTokenStoreError> {
+    let mut store = refresh_token_store.write().await;
+
+    match store.get_token(token).await {
+        Ok(record) => store.revoke_family(&record.family).await,
+        Err(RefreshTokenStoreError::TokenNotFound) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+// End every session of the user: their refresh tokens are deleted and auth tokens issued until now stop validating
+pub async fn revoke_user_sessions(
+    user: &User,
+    refresh_token_store: RefreshTokenStoreType,
+    banned_token_store: BannedTokenStoreType,
+    session_store: SessionStoreType
+) -> Result<(), RefreshTokenStoreError> {
+    refresh_token_store.write().await.revoke_all(&user.email).await?;
+
+    session_store
+        .write()
+        .await
+        .remove_user_sessions(&user.id, None)
+        .await
+        .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+    banned_token_store
+        .write()
+        .await
+        .revoke_user_tokens(&user.id, Utc::now().timestamp_millis())
+        .await
+        .map_err(|_| RefreshTokenStoreError::UnexpectedError)
+}
+
+// End every session of the user except the caller's, which continues with the returned auth and refresh cookies
+pub async fn restart_session(
+    user: &User,
+    session: &SessionId,
+    grants: &Grants,
+    refresh_token_store: RefreshTokenStoreType,
+    banned_token_store: BannedTokenStoreType,
+    session_store: SessionStoreType,
+    keyring: KeyringType
+) -> Result<(Cookie<'static>, Cookie<'static>), GenerateTokenError> {
+    refresh_token_store
+        .write()
+        .await
+        .revoke_all(&user.email)
+        .await
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
+
+    session_store
+        .write()
+        .await
+        .remove_user_sessions(&user.id, Some(session))
+        .await
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
+
+    // Including the caller's own auth token, which is replaced below
+    banned_token_store
+        .write()
+        .await
+        .revoke_user_tokens(&user.id, Utc::now().timestamp_millis())
+        .await
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
+
+    let auth_cookie = generate_auth_cookie(user, session, grants, keyring).await?;
+    let refresh_cookie = generate_refresh_cookie(&user.email, session, refresh_token_store).await?;
+
+    Ok((auth_cookie, refresh_cookie))
+}
+
+// Create and persist a new refresh token in the given family
+async fn issue_refresh_token(
+    email: &Email, 
+    family: RefreshTokenFamily, 
+    store: &mut (dyn RefreshTokenStore + Send + Sync)
+) -> Result<RefreshToken, GenerateTokenError> {
+    let expires_at = Utc::now().timestamp() + REFRESH_TOKEN_TTL_SECONDS;
+    let token = RefreshToken::default();
+
+    let record = RefreshTokenRecord {
+        email: email.clone(),
+        family,
+        used: false,
+        expires_at,
+    };
+
+    store.add_token(token.clone(), record)
+        .await
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
+
+    Ok(token)
+}
+
+// Create JWT auth token for the user id, their session and what they're granted, adding their address as the `email` claim if asked to
+fn generate_auth_token(
+    user: &User,
+    session: &SessionId,
+    grants: &Grants,
+    email_claim: bool,
+    keyring: &Keyring
+) -> Result<String, GenerateTokenError> {
+    let now = Utc::now();
+
     let delta = chrono::Duration::try_seconds(TOKEN_TTL_SECONDS)
         .ok_or(GenerateTokenError::UnexpectedError)?;
 
     // Create JWT expiration time
-    let exp = Utc::now()
+    let exp = now
         .checked_add_signed(delta)
         .ok_or(GenerateTokenError::UnexpectedError)?
         .timestamp();
@@ -56,51 +259,606 @@ fn generate_auth_token(email: &Email) -> Result<String, GenerateTokenError> {
         .try_into()
         .map_err(|_| GenerateTokenError::UnexpectedError)?;
 
-    let sub = email.as_ref().to_owned();
+    let iat = now.timestamp_millis() as f64 / 1000.0;
+
+    let sub = user.id.as_ref().to_owned();
+    let email = email_claim.then(|| user.email.as_ref().to_owned());
 
-    let claims = Claims { sub, exp };
+    let claims = Claims {
+        sub,
+        sid: session.as_ref().to_owned(),
+        email,
+        roles: grants.roles.iter().map(|role| role.name.clone()).collect(),
+        permissions: permissions_of(&grants.roles),
+        org_id: grants.membership.as_ref().map(|m| m.organization.id.as_ref().to_owned()),
+        org_role: grants.membership.as_ref().map(|m| m.role.as_ref().to_owned()),
+        exp,
+        iat,
+    };
 
-    create_token(&claims).map_err(GenerateTokenError::TokenError)
+    create_token(&claims, keyring.signing_key()).map_err(GenerateTokenError::TokenError)
 }
 
-// Check if JWT auth token is valid by decoding it using the JWT secret
-pub async fn validate_token(token: &str, banned_token_store: BannedTokenStoreType) -> Result<Claims, jsonwebtoken::errors::Error> {
-    match banned_token_store.read().await.contains_token(token).await {
-        Ok(value) => {
-            if value {
-                return Err(jsonwebtoken::errors::Error::from(
-                    jsonwebtoken::errors::ErrorKind::InvalidToken,
-                ));
-            }
+// Create an access token for the OAuth client of the session, carrying the scopes it was granted and nothing more
+fn generate_access_token(user: &User, session: &Session, keyring: &Keyring) -> Result<String, GenerateTokenError> {
+    let client_id = session.oauth_client_id.as_ref().ok_or(GenerateTokenError::UnexpectedError)?;
+    let now = Utc::now();
+
+    let exp: usize = (now.timestamp() + TOKEN_TTL_SECONDS)
+        .try_into()
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
+
+    let claims = AccessTokenClaims {
+        iss: OIDC_ISSUER.clone(),
+        sub: user.id.as_ref().to_owned(),
+        aud: client_id.as_ref().to_owned(),
+        sid: session.id.as_ref().to_owned(),
+        scope: format_scopes(&session.scopes),
+        exp,
+        iat: now.timestamp_millis() as f64 / 1000.0,
+    };
+
+    let key = keyring.signing_key();
+    let mut header = Header::new(key.algorithm());
+    header.kid = Some(key.kid().to_owned());
+    header.typ = Some(ACCESS_TOKEN_TYPE.to_owned());
+
+    encode(&header, &claims, key.encoding_key()).map_err(GenerateTokenError::TokenError)
+}
+
+// Check if JWT auth token is valid by decoding it with the key named in its `kid` header,
+// and that the session it was issued for hasn't ended since. Sessions an OAuth client started
+// only ever get access tokens, so tokens naming one aren't accepted here either.
+pub async fn validate_token(
+    token: &str, 
+    banned_token_store: BannedTokenStoreType, 
+    session_store: SessionStoreType,
+    keyring: KeyringType
+) -> Result<Claims, jsonwebtoken::errors::Error> {
+    ensure_not_banned(token, &banned_token_store).await?;
+
+    let claims = {
+        let keyring = keyring.read().await;
+        let key = decode_header(token)?
+            .kid
+            .and_then(|kid| keyring.verification_key(&kid))
+            .ok_or(jsonwebtoken::errors::ErrorKind::InvalidToken)?;
+
+        decode::<Claims>(
+            token,
+            key.decoding_key(),
+            &Validation::new(key.algorithm()),
+        )?
+        .claims
+    };
+
+    let user_id = UserId::parse(claims.sub.clone())
+        .map_err(|_| jsonwebtoken::errors::ErrorKind::InvalidToken)?;
+
+    ensure_not_revoked(&user_id, claims.iat, &banned_token_store).await?;
+
+    let session_id = SessionId::parse(claims.sid.clone())
+        .map_err(|_| jsonwebtoken::errors::ErrorKind::InvalidToken)?;
+
+    match session_store.read().await.get_session(&session_id, Utc::now().timestamp()).await {
+        Ok(session) if session.user_id == user_id && session.oauth_client_id.is_none() => Ok(claims),
+        _ => Err(jsonwebtoken::errors::ErrorKind::InvalidToken.into()),
+    }
+}
+
+// Check an access token /token issued, which is only valid for as long as the session of its client lasts
+pub async fn validate_access_token(
+    token: &str,
+    banned_token_store: BannedTokenStoreType,
+    session_store: SessionStoreType,
+    keyring: KeyringType
+) -> Result<(AccessTokenClaims, Session), jsonwebtoken::errors::Error> {
+    ensure_not_banned(token, &banned_token_store).await?;
+
+    let claims = {
+        let keyring = keyring.read().await;
+        let header = decode_header(token)?;
+
+        if header.typ.as_deref() != Some(ACCESS_TOKEN_TYPE) {
+            return Err(jsonwebtoken::errors::ErrorKind::InvalidToken.into());
         }
-        Err(_) => {
-            return Err(jsonwebtoken::errors::Error::from(
-                jsonwebtoken::errors::ErrorKind::InvalidToken,
-            ));
+
+        let key = header
+            .kid
+            .and_then(|kid| keyring.verification_key(&kid))
+            .ok_or(jsonwebtoken::errors::ErrorKind::InvalidToken)?;
+
+        // Any client's token is accepted, the audience is checked against the session below
+        let mut validation = Validation::new(key.algorithm());
+        validation.validate_aud = false;
+        validation.set_issuer(&[OIDC_ISSUER.as_str()]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+        decode::<AccessTokenClaims>(token, key.decoding_key(), &validation)?.claims
+    };
+
+    let user_id = UserId::parse(claims.sub.clone())
+        .map_err(|_| jsonwebtoken::errors::ErrorKind::InvalidToken)?;
+
+    ensure_not_revoked(&user_id, claims.iat, &banned_token_store).await?;
+
+    let session_id = SessionId::parse(claims.sid.clone())
+        .map_err(|_| jsonwebtoken::errors::ErrorKind::InvalidToken)?;
+
+    let session = session_store
+        .read()
+        .await
+        .get_session(&session_id, Utc::now().timestamp())
+        .await
+        .map_err(|_| jsonwebtoken::errors::ErrorKind::InvalidToken)?;
+
+    let issued_to_client = session.oauth_client_id.as_ref().is_some_and(|client_id| client_id.as_ref() == claims.aud);
+
+    if session.user_id != user_id || !issued_to_client {
+        return Err(jsonwebtoken::errors::ErrorKind::InvalidToken.into());
+    }
+
+    Ok((claims, session))
+}
+
+// Tokens that were logged out are refused
+async fn ensure_not_banned(token: &str, banned_token_store: &BannedTokenStoreType) -> Result<(), jsonwebtoken::errors::Error> {
+    match banned_token_store.read().await.contains_token(token).await {
+        Ok(false) => Ok(()),
+        _ => Err(jsonwebtoken::errors::ErrorKind::InvalidToken.into()),
+    }
+}
+
+// Tokens issued before the user's sessions were revoked, e.g. by a password reset, are no longer valid
+async fn ensure_not_revoked(
+    user_id: &UserId,
+    iat: f64,
+    banned_token_store: &BannedTokenStoreType
+) -> Result<(), jsonwebtoken::errors::Error> {
+    match banned_token_store.read().await.user_tokens_revoked_before(user_id).await {
+        Ok(Some(revoked_before_ms)) if iat * 1000.0 < revoked_before_ms as f64 => {
+            Err(jsonwebtoken::errors::ErrorKind::InvalidToken.into())
         }
+        Ok(_) => Ok(()),
+        Err(_) => Err(jsonwebtoken::errors::ErrorKind::InvalidToken.into()),
     }
+}
+
+// Create JWT auth token by encoding claims with the signing key, naming it in the `kid` header
+fn create_token<T: Serialize>(claims: &T, key: &SigningKey) -> Result<String, jsonwebtoken::errors::Error> {
+    let mut header = Header::new(key.algorithm());
+    header.kid = Some(key.kid().to_owned());
 
-    decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(JWT_SECRET.as_bytes()),
-        &Validation::default(),
-    )
-    .map(|data| data.claims)
+    encode(&header, &claims, key.encoding_key())
 }
 
-// Create JWT auth token by encoding claims using the JWT secret
-fn create_token(claims: &Claims) -> Result<String, jsonwebtoken::errors::Error> {
-    encode(
-        &jsonwebtoken::Header::default(),
-        &claims,
-        &EncodingKey::from_secret(JWT_SECRET.as_bytes()),
-    )
+// Everything an auth token grants beyond identifying the user
+#[derive(Debug, Default)]
+pub struct Grants {
+    pub roles: Vec<Role>,
+    pub membership: Option<Membership>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
+    // The user id, which unlike the address stays the same for the lifetime of the account
+    pub sub: String,
+    // The session the token was issued for, the token stops validating once it ends
+    pub sid: String,
+    // Only present when JWT_EMAIL_CLAIM is set, so tokens don't leak the address by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    // Role names and the permissions they grant, as of when the token was issued
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub permissions: Vec<String>,
+    // The organization the user is acting in and their role there, absent until they pick one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub org_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub org_role: Option<String>,
+    pub exp: usize,
+    // Fractional seconds, which RFC 7519 allows, so revoking a user's tokens can tell apart
+    // the ones issued just before from the ones issued just after
+    pub iat: f64,
+}
+
+impl Claims {
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.permissions.iter().any(|p| p == permission)
+    }
+}
+
+// The `typ` header of access tokens, as RFC 9068 names it, so no other token signed here passes for one
+const ACCESS_TOKEN_TYPE: &str = "at+jwt";
+
+// An access token of an OAuth client. The client is its audience, which keeps `validate_token` and the auth
+// middleware from accepting it, and it carries the scopes the client was granted instead of the user's roles.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccessTokenClaims {
+    pub iss: String,
     pub sub: String,
+    pub aud: String,
+    pub sid: String,
+    pub scope: String,
     pub exp: usize,
+    pub iat: f64,
+}
+
+// Tokens in emailed links are signed like auth tokens, so they carry an audience that `validate_token` refuses
+const PASSWORD_RESET_AUDIENCE: &str = "password-reset";
+const EMAIL_VERIFICATION_AUDIENCE: &str = "email-verification";
+const EMAIL_CHANGE_CONFIRM_AUDIENCE: &str = "email-change-confirm";
+const EMAIL_CHANGE_CANCEL_AUDIENCE: &str = "email-change-cancel";
+const INVITATION_AUDIENCE: &str = "org-invitation";
+const ACCOUNT_UNLOCK_AUDIENCE: &str = "account-unlock";
+
+// This value determines how long an email confirmation link can be used for
+pub const EMAIL_VERIFICATION_TTL_SECONDS: i64 = 86_400; // 24 hours
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SingleUseLinkClaims {
+    sub: String,
+    exp: usize,
+    jti: String,
+    aud: String,
+}
+
+// Claims of links that may be followed more than once
+#[derive(Debug, Serialize, Deserialize)]
+struct ReusableLinkClaims {
+    sub: String,
+    exp: usize,
+    aud: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LinkTokenError {
+    InvalidToken,
+    UnexpectedError,
+}
+
+// The two links of an email change, the confirm link goes to the new address and the cancel link to the old one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmailChangeAction {
+    Confirm,
+    Cancel,
+}
+
+impl EmailChangeAction {
+    fn audience(&self) -> &'static str {
+        match self {
+            EmailChangeAction::Confirm => EMAIL_CHANGE_CONFIRM_AUDIENCE,
+            EmailChangeAction::Cancel => EMAIL_CHANGE_CANCEL_AUDIENCE,
+        }
+    }
+
+    fn recipient(change: &EmailChange, action: EmailChangeAction) -> &Email {
+        match action {
+            EmailChangeAction::Confirm => &change.new_email,
+            EmailChangeAction::Cancel => &change.email,
+        }
+    }
+}
+
+// Create a signed password reset token and remember its id, so the link can only be used once
+pub async fn generate_password_reset_token(
+    email: &Email,
+    password_reset_token_store: PasswordResetTokenStoreType,
+    keyring: KeyringType
+) -> Result<String, GenerateTokenError> {
+    let exp: usize = (Utc::now().timestamp() + PASSWORD_RESET_TTL_SECONDS)
+        .try_into()
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
+
+    let id = PasswordResetId::default();
+
+    let claims = SingleUseLinkClaims {
+        sub: email.as_ref().to_owned(),
+        exp,
+        jti: id.as_ref().to_owned(),
+        aud: PASSWORD_RESET_AUDIENCE.to_owned(),
+    };
+
+    let token = create_token(&claims, keyring.read().await.signing_key())
+        .map_err(GenerateTokenError::TokenError)?;
+
+    password_reset_token_store
+        .write()
+        .await
+        .add_token(id, email.clone())
+        .await
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
+
+    Ok(token)
+}
+
+// Check a password reset token and use it up, returning the user it was issued to
+pub async fn redeem_password_reset_token(
+    token: &str,
+    password_reset_token_store: PasswordResetTokenStoreType,
+    keyring: KeyringType
+) -> Result<Email, LinkTokenError> {
+    let claims: SingleUseLinkClaims = decode_audience_token(token, &*keyring.read().await, PASSWORD_RESET_AUDIENCE)
+        .ok_or(LinkTokenError::InvalidToken)?;
+
+    let id = PasswordResetId::parse(claims.jti).map_err(|_| LinkTokenError::InvalidToken)?;
+
+    let email = match password_reset_token_store.write().await.take_token(&id).await {
+        Ok(email) => email,
+        Err(PasswordResetTokenStoreError::TokenNotFound) => return Err(LinkTokenError::InvalidToken),
+        Err(PasswordResetTokenStoreError::UnexpectedError) => return Err(LinkTokenError::UnexpectedError),
+    };
+
+    if email.as_ref() != claims.sub {
+        return Err(LinkTokenError::InvalidToken);
+    }
+
+    Ok(email)
+}
+
+// Create the signed confirm and cancel tokens of an email change. Both point at the same
+// pending change, so whichever link is used first settles it and the other stops working.
+pub async fn generate_email_change_tokens(
+    change: EmailChange,
+    email_change_store: EmailChangeStoreType,
+    keyring: KeyringType
+) -> Result<(String, String), GenerateTokenError> {
+    let exp: usize = (Utc::now().timestamp() + EMAIL_CHANGE_TTL_SECONDS)
+        .try_into()
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
+
+    let id = EmailChangeId::default();
+
+    let tokens = {
+        let keyring = keyring.read().await;
+        let sign = |action: EmailChangeAction| {
+            let claims = SingleUseLinkClaims {
+                sub: EmailChangeAction::recipient(&change, action).as_ref().to_owned(),
+                exp,
+                jti: id.as_ref().to_owned(),
+                aud: action.audience().to_owned(),
+            };
+
+            create_token(&claims, keyring.signing_key()).map_err(GenerateTokenError::TokenError)
+        };
+
+        (sign(EmailChangeAction::Confirm)?, sign(EmailChangeAction::Cancel)?)
+    };
+
+    email_change_store
+        .write()
+        .await
+        .add_change(id, change)
+        .await
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
+
+    Ok(tokens)
+}
+
+// Check the confirm or cancel token of an email change and settle the pending change it belongs to
+pub async fn redeem_email_change_token(
+    token: &str,
+    action: EmailChangeAction,
+    email_change_store: EmailChangeStoreType,
+    keyring: KeyringType
+) -> Result<EmailChange, LinkTokenError> {
+    let claims: SingleUseLinkClaims = decode_audience_token(token, &*keyring.read().await, action.audience())
+        .ok_or(LinkTokenError::InvalidToken)?;
+
+    let id = EmailChangeId::parse(claims.jti).map_err(|_| LinkTokenError::InvalidToken)?;
+
+    let change = match email_change_store.write().await.take_change(&id).await {
+        Ok(change) => change,
+        Err(EmailChangeStoreError::ChangeNotFound) => return Err(LinkTokenError::InvalidToken),
+        Err(EmailChangeStoreError::UnexpectedError) => return Err(LinkTokenError::UnexpectedError),
+    };
+
+    if EmailChangeAction::recipient(&change, action).as_ref() != claims.sub {
+        return Err(LinkTokenError::InvalidToken);
+    }
+
+    Ok(change)
+}
+
+// Create a signed invitation token addressed to the invitee and remember the invitation, so it can only be accepted once
+pub async fn generate_invitation_token(
+    invitation: Invitation,
+    invitation_store: InvitationStoreType,
+    keyring: KeyringType
+) -> Result<String, GenerateTokenError> {
+    let exp: usize = (Utc::now().timestamp() + INVITATION_TTL_SECONDS)
+        .try_into()
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
+
+    let id = InvitationId::default();
+
+    let claims = SingleUseLinkClaims {
+        sub: invitation.email.as_ref().to_owned(),
+        exp,
+        jti: id.as_ref().to_owned(),
+        aud: INVITATION_AUDIENCE.to_owned(),
+    };
+
+    let token = create_token(&claims, keyring.read().await.signing_key())
+        .map_err(GenerateTokenError::TokenError)?;
+
+    invitation_store
+        .write()
+        .await
+        .add_invitation(id, invitation)
+        .await
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
+
+    Ok(token)
+}
+
+// The invitee of a correctly signed invitation token, without using the invitation up
+pub async fn invitation_token_recipient(token: &str, keyring: KeyringType) -> Option<Email> {
+    let claims: SingleUseLinkClaims = decode_audience_token(token, &*keyring.read().await, INVITATION_AUDIENCE)?;
+    Email::parse(claims.sub).ok()
+}
+
+// Check an invitation token and use it up, returning the invitation it stands for
+pub async fn redeem_invitation_token(
+    token: &str,
+    invitation_store: InvitationStoreType,
+    keyring: KeyringType
+) -> Result<Invitation, LinkTokenError> {
+    let claims: SingleUseLinkClaims = decode_audience_token(token, &*keyring.read().await, INVITATION_AUDIENCE)
+        .ok_or(LinkTokenError::InvalidToken)?;
+
+    let id = InvitationId::parse(claims.jti).map_err(|_| LinkTokenError::InvalidToken)?;
+
+    let invitation = match invitation_store.write().await.take_invitation(&id).await {
+        Ok(invitation) => invitation,
+        Err(InvitationStoreError::InvitationNotFound) => return Err(LinkTokenError::InvalidToken),
+        Err(InvitationStoreError::UnexpectedError) => return Err(LinkTokenError::UnexpectedError),
+    };
+
+    if invitation.email.as_ref() != claims.sub {
+        return Err(LinkTokenError::InvalidToken);
+    }
+
+    Ok(invitation)
+}
+
+// Create a signed token for the link that confirms the user owns their email address
+pub async fn generate_email_verification_token(email: &Email, keyring: KeyringType) -> Result<String, GenerateTokenError> {
+    let exp: usize = (Utc::now().timestamp() + EMAIL_VERIFICATION_TTL_SECONDS)
+        .try_into()
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
+
+    let claims = ReusableLinkClaims {
+        sub: email.as_ref().to_owned(),
+        exp,
+        aud: EMAIL_VERIFICATION_AUDIENCE.to_owned(),
+    };
+
+    create_token(&claims, keyring.read().await.signing_key()).map_err(GenerateTokenError::TokenError)
+}
+
+// Check an email verification token, returning the address it confirms.
+// Verifying twice does no harm, so unlike reset tokens these aren't tracked.
+pub async fn validate_email_verification_token(token: &str, keyring: KeyringType) -> Option<Email> {
+    let claims: ReusableLinkClaims =
+        decode_audience_token(token, &*keyring.read().await, EMAIL_VERIFICATION_AUDIENCE)?;
+
+    Email::parse(claims.sub).ok()
+}
+
+// Create a signed token for the link that lifts a lockout after failed logins, it expires with the lockout
+pub async fn generate_account_unlock_token(
+    email: &Email,
+    locked_until: i64,
+    keyring: KeyringType
+) -> Result<String, GenerateTokenError> {
+    let exp: usize = locked_until.try_into().map_err(|_| GenerateTokenError::UnexpectedError)?;
+
+    let claims = ReusableLinkClaims {
+        sub: email.as_ref().to_owned(),
+        exp,
+        aud: ACCOUNT_UNLOCK_AUDIENCE.to_owned(),
+    };
+
+    create_token(&claims, keyring.read().await.signing_key()).map_err(GenerateTokenError::TokenError)
+}
+
+// Check an account unlock token, returning the address of the account to unlock.
+// Unlocking twice does no harm either, so these aren't tracked.
+pub async fn validate_account_unlock_token(token: &str, keyring: KeyringType) -> Option<Email> {
+    let claims: ReusableLinkClaims =
+        decode_audience_token(token, &*keyring.read().await, ACCOUNT_UNLOCK_AUDIENCE)?;
+
+    Email::parse(claims.sub).ok()
+}
+
+// The ID token of an OpenID Connect session, telling its client who the user is and how they logged in
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    // The OAuth client the session belongs to. Having an audience is also what keeps
+    // `validate_token` from accepting an ID token as an auth token.
+    pub aud: String,
+    pub exp: usize,
+    pub iat: i64,
+    pub auth_time: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    pub amr: Vec<String>,
+    // The session the client holds, which is what RP-initiated logout ends
+    pub sid: String,
+    #[serde(flatten)]
+    pub user_info: UserInfo,
+}
+
+// Create an ID token for a session an OAuth client started. Only the one issued with the
+// code exchange carries the nonce, refreshed ID tokens leave it out as OpenID Connect asks.
+pub async fn generate_id_token(
+    user: &User,
+    session: &Session,
+    nonce: Option<String>,
+    keyring: KeyringType
+) -> Result<String, GenerateTokenError> {
+    let client_id = session.oauth_client_id.as_ref().ok_or(GenerateTokenError::UnexpectedError)?;
+    let now = Utc::now().timestamp();
+    let exp: usize = (now + TOKEN_TTL_SECONDS)
+        .try_into()
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
+
+    let claims = IdTokenClaims {
+        iss: OIDC_ISSUER.clone(),
+        aud: client_id.as_ref().to_owned(),
+        exp,
+        iat: now,
+        auth_time: session.authenticated_at,
+        nonce,
+        amr: amr(&session.auth_methods),
+        sid: session.id.as_ref().to_owned(),
+        user_info: UserInfo::new(user, &session.scopes),
+    };
+
+    create_token(&claims, keyring.read().await.signing_key()).map_err(GenerateTokenError::TokenError)
+}
+
+// Check an ID token a client sent back as `id_token_hint`. It may have expired, which logging out
+// allows for, so only the signature and issuer are checked and the audience is left to the caller.
+pub async fn decode_id_token_hint(token: &str, keyring: KeyringType) -> Option<IdTokenClaims> {
+    let keyring = keyring.read().await;
+    let key = decode_header(token)
+        .ok()
+        .and_then(|header| header.kid)
+        .and_then(|kid| keyring.verification_key(&kid))?;
+
+    let mut validation = Validation::new(key.algorithm());
+    validation.validate_exp = false;
+    validation.validate_aud = false;
+    validation.set_issuer(&[OIDC_ISSUER.as_str()]);
+    validation.set_required_spec_claims(&["iss", "aud", "sub"]);
+
+    decode::<IdTokenClaims>(token, key.decoding_key(), &validation)
+        .ok()
+        .map(|data| data.claims)
+}
+
+// Decode a token with the key named in its `kid` header, only accepting it for the given audience
+fn decode_audience_token<T: DeserializeOwned>(token: &str, keyring: &Keyring, audience: &str) -> Option<T> {
+    let key = decode_header(token)
+        .ok()
+        .and_then(|header| header.kid)
+        .and_then(|kid| keyring.verification_key(&kid))?;
+
+    let mut validation = Validation::new(key.algorithm());
+    validation.set_audience(&[audience]);
+
+    decode::<T>(token, key.decoding_key(), &validation)
+        .ok()
+        .map(|data| data.claims)
 }
 
 #[cfg(test)]
@@ -110,14 +868,61 @@ mod tests {
     use tokio::sync::RwLock;
 
 
-    use crate::services::tokens::BannedTokens;
+    use crate::services::{
+        email_changes::EmailChanges,
+        invitations::Invitations,
+        oauth_clients::OAuthClientId,
+        oidc::{AuthMethod, Scope},
+        organizations::{OrgRole, Organization, OrganizationId},
+        password_reset_tokens::PasswordResetTokens,
+        client_info::ClientInfo,
+        refresh_tokens::RefreshTokens,
+        sessions::{Session, Sessions},
+        tokens::BannedTokens
+    };
 
     use super::*;
 
+    const RSA_PEM: &[u8] = include_bytes!("../../tests/fixtures/keys/rsa.pem");
+
+    fn keyring() -> KeyringType {
+        keyring_with(SigningKey::from_secret("test", b"secret"))
+    }
+
+    fn keyring_with(signing_key: SigningKey) -> KeyringType {
+        Arc::new(RwLock::new(Keyring::new(signing_key)))
+    }
+
+    fn test_user(email: &str) -> User {
+        User {
+            id: UserId::default(),
+            email: Email::parse(email.to_owned()).unwrap(),
+            password: crate::user::Password::parse("password123".to_owned()).unwrap(),
+            two_fa_method: crate::user::TwoFaMethod::None,
+            email_verified: true,
+            created_at: 0,
+            delete_after: None,
+            locked: false,
+            password_reset_required: false,
+        }
+    }
+
+    fn session_store() -> SessionStoreType {
+        Arc::new(RwLock::new(Sessions::default()))
+    }
+
+    // Record a live session of the user, as logging in would
+    async fn start_session(user: &User, session_store: &SessionStoreType) -> SessionId {
+        let session = Session::new(user.id.clone(), ClientInfo::default(), Utc::now().timestamp());
+        let id = session.id.clone();
+        session_store.write().await.add_session(session).await.unwrap();
+        id
+    }
+
     #[tokio::test]
     async fn test_generate_auth_cookie() {
-        let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let cookie = generate_auth_cookie(&email).unwrap();
+        let user = test_user("test@example.com");
+        let cookie = generate_auth_cookie(&user, &SessionId::default(), &Grants::default(), keyring()).await.unwrap();
         assert_eq!(cookie.name(), JWT_COOKIE_NAME);
         assert_eq!(cookie.value().split('.').count(), 3);
         assert_eq!(cookie.path(), Some("/"));
@@ -138,18 +943,23 @@ mod tests {
 
     #[tokio::test]
     async fn test_generate_auth_token() {
-        let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let result = generate_auth_token(&email).unwrap();
+        let session_store = session_store();
+        let user = test_user("test@example.com");
+        let session = start_session(&user, &session_store).await;
+        let result = generate_auth_token(&user, &session, &Grants::default(), false, &*keyring().read().await).unwrap();
         assert_eq!(result.split('.').count(), 3);
     }
 
     #[tokio::test]
     async fn test_validate_token_with_valid_token() {
-        let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let token = generate_auth_token(&email).unwrap();
+        let session_store = session_store();
+        let user = test_user("test@example.com");
+        let session = start_session(&user, &session_store).await;
+        let keyring = keyring();
+        let token = generate_auth_token(&user, &session, &Grants::default(), false, &*keyring.read().await).unwrap();
         let banned_token_store = Arc::new(RwLock::new(BannedTokens::default()));
-        let result = validate_token(&token, banned_token_store).await.unwrap();
-        assert_eq!(result.sub, "test@example.com");
+        let result = validate_token(&token, banned_token_store, session_store.clone(), keyring).await.unwrap();
+        assert_eq!(result.sub, user.id.as_ref());
 
         let exp = Utc::now()
             .checked_add_signed(chrono::Duration::try_minutes(9).expect("valid duration"))
@@ -159,22 +969,499 @@ mod tests {
         assert!(result.exp > exp as usize);
     }
 
+    #[tokio::test]
+    async fn test_email_claim_is_opt_in() {
+        let session_store = session_store();
+        let user = test_user("test@example.com");
+        let session = start_session(&user, &session_store).await;
+        let keyring = keyring();
+        let banned_token_store = Arc::new(RwLock::new(BannedTokens::default()));
+
+        let token = generate_auth_token(&user, &session, &Grants::default(), false, &*keyring.read().await).unwrap();
+        let claims = validate_token(&token, banned_token_store.clone(), session_store.clone(), keyring.clone()).await.unwrap();
+        assert_eq!(claims.email, None);
+
+        let token = generate_auth_token(&user, &session, &Grants::default(), true, &*keyring.read().await).unwrap();
+        let claims = validate_token(&token, banned_token_store, session_store.clone(), keyring).await.unwrap();
+        assert_eq!(claims.sub, user.id.as_ref());
+        assert_eq!(claims.email.as_deref(), Some("test@example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_roles_claim() {
+        let session_store = session_store();
+        let user = test_user("test@example.com");
+        let session = start_session(&user, &session_store).await;
+        let keyring = keyring();
+        let banned_token_store = Arc::new(RwLock::new(BannedTokens::default()));
+
+        let roles = vec![
+            Role::new("admin", &["users:read", "users:write"]),
+            Role::new("support", &["users:read"]),
+        ];
+
+        let grants = Grants { roles, membership: None };
+        let token = generate_auth_token(&user, &session, &grants, false, &*keyring.read().await).unwrap();
+        let claims = validate_token(&token, banned_token_store, session_store.clone(), keyring).await.unwrap();
+        assert_eq!(claims.roles, vec!["admin", "support"]);
+        assert_eq!(claims.permissions, vec!["users:read", "users:write"]);
+        assert!(claims.has_role("support"));
+        assert!(!claims.has_role("auditor"));
+        assert!(claims.has_permission("users:write"));
+        assert!(!claims.has_permission("logs:read"));
+        assert_eq!(claims.org_id, None);
+    }
+
+    #[tokio::test]
+    async fn test_org_claims() {
+        let session_store = session_store();
+        let user = test_user("test@example.com");
+        let session = start_session(&user, &session_store).await;
+        let keyring = keyring();
+        let banned_token_store = Arc::new(RwLock::new(BannedTokens::default()));
+
+        let organization = Organization { id: OrganizationId::default(), name: "Acme".to_owned(), created_at: 0 };
+        let grants = Grants {
+            roles: vec![],
+            membership: Some(Membership { organization: organization.clone(), role: OrgRole::Admin }),
+        };
+
+        let token = generate_auth_token(&user, &session, &grants, false, &*keyring.read().await).unwrap();
+        let claims = validate_token(&token, banned_token_store, session_store.clone(), keyring).await.unwrap();
+        assert_eq!(claims.org_id.as_deref(), Some(organization.id.as_ref()));
+        assert_eq!(claims.org_role.as_deref(), Some("admin"));
+    }
+
+    #[tokio::test]
+    async fn test_invitation_token_is_single_use() {
+        let invitation = Invitation {
+            org_id: OrganizationId::default(),
+            email: Email::parse("invitee@example.com".to_owned()).unwrap(),
+            role: OrgRole::Member,
+        };
+        let keyring = keyring();
+        let store = Arc::new(RwLock::new(Invitations::default()));
+        let token = generate_invitation_token(invitation.clone(), store.clone(), keyring.clone()).await.unwrap();
+
+        // Peeking doesn't use the invitation up
+        assert_eq!(invitation_token_recipient(&token, keyring.clone()).await, Some(invitation.email.clone()));
+
+        let result = redeem_invitation_token(&token, store.clone(), keyring.clone()).await;
+        assert_eq!(result, Ok(invitation));
+
+        let result = redeem_invitation_token(&token, store, keyring.clone()).await;
+        assert_eq!(result, Err(LinkTokenError::InvalidToken));
+
+        // Other link tokens aren't invitations
+        let reset_store = Arc::new(RwLock::new(PasswordResetTokens::default()));
+        let email = Email::parse("invitee@example.com".to_owned()).unwrap();
+        let reset_token = generate_password_reset_token(&email, reset_store, keyring.clone()).await.unwrap();
+        assert_eq!(invitation_token_recipient(&reset_token, keyring).await, None);
+    }
+
     #[tokio::test]
     async fn test_validate_token_with_invalid_token() {
+        let session_store = session_store();
+        let keyring = keyring();
         let token = "invalid_token".to_owned();
         let banned_token_store = Arc::new(RwLock::new(BannedTokens::default()));
-        let result = validate_token(&token, banned_token_store).await;
+        let result = validate_token(&token, banned_token_store, session_store.clone(), keyring).await;
         assert!(result.is_err());
     }
 
     #[tokio::test]
     async fn test_validate_token_with_banned_token() {
-        let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let token = generate_auth_token(&email).unwrap();
+        let session_store = session_store();
+        let user = test_user("test@example.com");
+        let session = start_session(&user, &session_store).await;
+        let keyring = keyring();
+        let token = generate_auth_token(&user, &session, &Grants::default(), false, &*keyring.read().await).unwrap();
         let mut hs = BannedTokens::default();
         hs.add_token(token.clone()).await.unwrap();
         let banned_token_store = Arc::new(RwLock::new(hs));
-        let result = validate_token(&token, banned_token_store).await;
+        let result = validate_token(&token, banned_token_store, session_store.clone(), keyring).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_signed_with_rsa_key() {
+        let session_store = session_store();
+        let user = test_user("test@example.com");
+        let session = start_session(&user, &session_store).await;
+        let keyring = keyring_with(SigningKey::from_pem("rsa", jsonwebtoken::Algorithm::RS256, RSA_PEM).unwrap());
+        let token = generate_auth_token(&user, &session, &Grants::default(), false, &*keyring.read().await).unwrap();
+
+        let header = decode_header(&token).unwrap();
+        assert_eq!(header.alg, jsonwebtoken::Algorithm::RS256);
+        assert_eq!(header.kid.as_deref(), Some("rsa"));
+
+        let banned_token_store = Arc::new(RwLock::new(BannedTokens::default()));
+        let result = validate_token(&token, banned_token_store, session_store.clone(), keyring).await.unwrap();
+        assert_eq!(result.sub, user.id.as_ref());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_with_unknown_kid() {
+        let session_store = session_store();
+        let user = test_user("test@example.com");
+        let session = start_session(&user, &session_store).await;
+        let other_keyring = Keyring::new(SigningKey::from_secret("other", b"secret"));
+        let token = generate_auth_token(&user, &session, &Grants::default(), false, &other_keyring).unwrap();
+        let banned_token_store = Arc::new(RwLock::new(BannedTokens::default()));
+        let result = validate_token(&token, banned_token_store, session_store.clone(), keyring()).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_signed_with_retired_key() {
+        let session_store = session_store();
+        let user = test_user("test@example.com");
+        let session = start_session(&user, &session_store).await;
+        let keyring = keyring();
+        let token = generate_auth_token(&user, &session, &Grants::default(), false, &*keyring.read().await).unwrap();
+
+        keyring
+            .write()
+            .await
+            .rotate(SigningKey::from_pem("rsa", jsonwebtoken::Algorithm::RS256, RSA_PEM).unwrap())
+            .unwrap();
+
+        let banned_token_store = Arc::new(RwLock::new(BannedTokens::default()));
+        let result = validate_token(&token, banned_token_store, session_store.clone(), keyring).await.unwrap();
+        assert_eq!(result.sub, user.id.as_ref());
+    }
+
+    #[tokio::test]
+    async fn test_generate_refresh_cookie() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let session = SessionId::default();
+        let refresh_token_store = Arc::new(RwLock::new(RefreshTokens::default()));
+        let cookie = generate_refresh_cookie(&email, &session, refresh_token_store.clone()).await.unwrap();
+        assert_eq!(cookie.name(), REFRESH_COOKIE_NAME);
+        assert_eq!(cookie.path(), Some("/"));
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(cookie.same_site(), Some(SameSite::Lax));
+
+        let token = RefreshToken::parse(cookie.value().to_owned()).unwrap();
+        let record = refresh_token_store.read().await.get_token(&token).await.unwrap();
+        assert_eq!(record.email, email);
+        assert!(!record.used);
+    }
+
+    #[tokio::test]
+    async fn test_rotate_refresh_token() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let session = SessionId::default();
+        let refresh_token_store = Arc::new(RwLock::new(RefreshTokens::default()));
+        let cookie = generate_refresh_cookie(&email, &session, refresh_token_store.clone()).await.unwrap();
+        let token = RefreshToken::parse(cookie.value().to_owned()).unwrap();
+
+        let (result_email, result_session, next_cookie) = rotate_refresh_token(&token, refresh_token_store.clone()).await.unwrap();
+        assert_eq!(result_email, email);
+        assert_eq!(result_session, session);
+        assert_ne!(next_cookie.value(), cookie.value());
+
+        let store = refresh_token_store.read().await;
+        let next_token = RefreshToken::parse(next_cookie.value().to_owned()).unwrap();
+        let old_record = store.get_token(&token).await.unwrap();
+        let next_record = store.get_token(&next_token).await.unwrap();
+        assert!(old_record.used);
+        assert!(!next_record.used);
+        assert_eq!(old_record.family, next_record.family);
+    }
+
+    #[tokio::test]
+    async fn test_rotate_reused_refresh_token_revokes_family() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let session = SessionId::default();
+        let refresh_token_store = Arc::new(RwLock::new(RefreshTokens::default()));
+        let cookie = generate_refresh_cookie(&email, &session, refresh_token_store.clone()).await.unwrap();
+        let token = RefreshToken::parse(cookie.value().to_owned()).unwrap();
+
+        let (_, _, next_cookie) = rotate_refresh_token(&token, refresh_token_store.clone()).await.unwrap();
+        let next_token = RefreshToken::parse(next_cookie.value().to_owned()).unwrap();
+
+        let result = rotate_refresh_token(&token, refresh_token_store.clone()).await;
+        assert_eq!(result.unwrap_err(), RefreshTokenError::TokenReused);
+
+        let result = rotate_refresh_token(&next_token, refresh_token_store.clone()).await;
+        assert_eq!(result.unwrap_err(), RefreshTokenError::InvalidToken);
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_issued_before_revocation() {
+        let session_store = session_store();
+        let user = test_user("test@example.com");
+        let session = start_session(&user, &session_store).await;
+        let keyring = keyring();
+        let token = generate_auth_token(&user, &session, &Grants::default(), false, &*keyring.read().await).unwrap();
+        let mut hs = BannedTokens::default();
+        hs.revoke_user_tokens(&user.id, Utc::now().timestamp_millis() + 1).await.unwrap();
+        let banned_token_store = Arc::new(RwLock::new(hs));
+        let result = validate_token(&token, banned_token_store.clone(), session_store.clone(), keyring.clone()).await;
+        assert!(result.is_err());
+
+        // Tokens of other users are unaffected
+        let other = test_user("other@example.com");
+        let other_session = start_session(&other, &session_store).await;
+        let token = generate_auth_token(&other, &other_session, &Grants::default(), false, &*keyring.read().await).unwrap();
+        let result = validate_token(&token, banned_token_store, session_store.clone(), keyring).await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_password_reset_token_is_single_use() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let keyring = keyring();
+        let store = Arc::new(RwLock::new(PasswordResetTokens::default()));
+        let token = generate_password_reset_token(&email, store.clone(), keyring.clone()).await.unwrap();
+
+        let result = redeem_password_reset_token(&token, store.clone(), keyring.clone()).await;
+        assert_eq!(result, Ok(email));
+
+        let result = redeem_password_reset_token(&token, store, keyring).await;
+        assert_eq!(result, Err(LinkTokenError::InvalidToken));
+    }
+
+    #[tokio::test]
+    async fn test_password_reset_token_is_not_an_auth_token() {
+        let session_store = session_store();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let keyring = keyring();
+        let store = Arc::new(RwLock::new(PasswordResetTokens::default()));
+        let token = generate_password_reset_token(&email, store.clone(), keyring.clone()).await.unwrap();
+
+        let banned_token_store = Arc::new(RwLock::new(BannedTokens::default()));
+        let result = validate_token(&token, banned_token_store, session_store.clone(), keyring.clone()).await;
         assert!(result.is_err());
+
+        // Nor can an auth token be used to reset the password
+        let auth_token = generate_auth_token(&test_user("test@example.com"), &SessionId::default(), &Grants::default(), false, &*keyring.read().await).unwrap();
+        let result = redeem_password_reset_token(&auth_token, store, keyring).await;
+        assert_eq!(result, Err(LinkTokenError::InvalidToken));
+    }
+
+    #[tokio::test]
+    async fn test_email_verification_token() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let keyring = keyring();
+        let token = generate_email_verification_token(&email, keyring.clone()).await.unwrap();
+
+        assert_eq!(validate_email_verification_token(&token, keyring.clone()).await, Some(email.clone()));
+
+        // Tokens meant for other purposes don't verify an address
+        let store = Arc::new(RwLock::new(PasswordResetTokens::default()));
+        let reset_token = generate_password_reset_token(&email, store.clone(), keyring.clone()).await.unwrap();
+        assert_eq!(validate_email_verification_token(&reset_token, keyring.clone()).await, None);
+
+        let result = redeem_password_reset_token(&token, store, keyring).await;
+        assert_eq!(result, Err(LinkTokenError::InvalidToken));
+    }
+
+    #[tokio::test]
+    async fn test_account_unlock_token_expires_with_lockout() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let keyring = keyring();
+        let now = Utc::now().timestamp();
+
+        let token = generate_account_unlock_token(&email, now + 60, keyring.clone()).await.unwrap();
+        assert_eq!(validate_account_unlock_token(&token, keyring.clone()).await, Some(email.clone()));
+
+        let token = generate_account_unlock_token(&email, now - 120, keyring.clone()).await.unwrap();
+        assert_eq!(validate_account_unlock_token(&token, keyring.clone()).await, None);
+
+        let verification_token = generate_email_verification_token(&email, keyring.clone()).await.unwrap();
+        assert_eq!(validate_account_unlock_token(&verification_token, keyring).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_restart_session_keeps_only_the_callers_session() {
+        let session_store = session_store();
+        let user = test_user("test@example.com");
+        let session = start_session(&user, &session_store).await;
+        let other_session = start_session(&user, &session_store).await;
+        let keyring = keyring();
+        let refresh_token_store = Arc::new(RwLock::new(RefreshTokens::default()));
+        let banned_token_store = Arc::new(RwLock::new(BannedTokens::default()));
+
+        let old_token = generate_auth_token(&user, &session, &Grants::default(), false, &*keyring.read().await).unwrap();
+        let other_token = generate_auth_token(&user, &other_session, &Grants::default(), false, &*keyring.read().await).unwrap();
+        let old_refresh = generate_refresh_cookie(&user.email, &other_session, refresh_token_store.clone()).await.unwrap();
+
+        // Revocation has millisecond precision
+        tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+
+        let (auth_cookie, refresh_cookie) = restart_session(
+            &user,
+            &session,
+            &Grants::default(),
+            refresh_token_store.clone(),
+            banned_token_store.clone(),
+            session_store.clone(),
+            keyring.clone()
+        )
+        .await
+        .unwrap();
+
+        assert!(validate_token(&old_token, banned_token_store.clone(), session_store.clone(), keyring.clone()).await.is_err());
+        assert!(validate_token(&other_token, banned_token_store.clone(), session_store.clone(), keyring.clone()).await.is_err());
+
+        // The caller carries on in the same session
+        let claims = validate_token(auth_cookie.value(), banned_token_store, session_store.clone(), keyring).await.unwrap();
+        assert_eq!(claims.sid, session.as_ref());
+
+        let now = Utc::now().timestamp();
+        assert!(session_store.read().await.get_session(&session, now).await.is_ok());
+        assert!(session_store.read().await.get_session(&other_session, now).await.is_err());
+
+        let store = refresh_token_store.read().await;
+        let old_refresh = RefreshToken::parse(old_refresh.value().to_owned()).unwrap();
+        let new_refresh = RefreshToken::parse(refresh_cookie.value().to_owned()).unwrap();
+        assert_eq!(store.get_token(&old_refresh).await, Err(RefreshTokenStoreError::TokenNotFound));
+        assert_eq!(store.get_token(&new_refresh).await.unwrap().family.as_ref(), session.as_ref());
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_validate_token_of_ended_session() {
+        let session_store = session_store();
+        let user = test_user("test@example.com");
+        let session = start_session(&user, &session_store).await;
+        let keyring = keyring();
+        let banned_token_store = Arc::new(RwLock::new(BannedTokens::default()));
+
+        let token = generate_auth_token(&user, &session, &Grants::default(), false, &*keyring.read().await).unwrap();
+        let claims = validate_token(&token, banned_token_store.clone(), session_store.clone(), keyring.clone()).await.unwrap();
+        assert_eq!(claims.sid, session.as_ref());
+
+        session_store.write().await.remove_session(&session).await.unwrap();
+        let result = validate_token(&token, banned_token_store.clone(), session_store.clone(), keyring.clone()).await;
+        assert!(result.is_err());
+
+        // Nor does a token name another user's session
+        let other = test_user("other@example.com");
+        let other_session = start_session(&other, &session_store).await;
+        let token = generate_auth_token(&user, &other_session, &Grants::default(), false, &*keyring.read().await).unwrap();
+        let result = validate_token(&token, banned_token_store, session_store, keyring).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_email_change_tokens_settle_the_change_once() {
+        let change = EmailChange {
+            email: Email::parse("old@example.com".to_owned()).unwrap(),
+            new_email: Email::parse("new@example.com".to_owned()).unwrap(),
+        };
+        let keyring = keyring();
+        let store = Arc::new(RwLock::new(EmailChanges::default()));
+        let (confirm, cancel) = generate_email_change_tokens(change.clone(), store.clone(), keyring.clone())
+            .await
+            .unwrap();
+
+        // Each token only works for its own action
+        let result = redeem_email_change_token(&confirm, EmailChangeAction::Cancel, store.clone(), keyring.clone()).await;
+        assert_eq!(result, Err(LinkTokenError::InvalidToken));
+
+        let result = redeem_email_change_token(&cancel, EmailChangeAction::Cancel, store.clone(), keyring.clone()).await;
+        assert_eq!(result, Ok(change));
+
+        // Cancelling used up the pending change, so the confirm link no longer works
+        let result = redeem_email_change_token(&confirm, EmailChangeAction::Confirm, store, keyring).await;
+        assert_eq!(result, Err(LinkTokenError::InvalidToken));
+    }
+
+    #[tokio::test]
+    async fn test_id_token_describes_the_oauth_session() {
+        let session_store = session_store();
+        let user = test_user("test@example.com");
+        let keyring = keyring();
+        let session = Session::new(user.id.clone(), ClientInfo::default(), 2000)
+            .authenticated_with(vec![AuthMethod::Password, AuthMethod::OneTimeCode], 1000)
+            .for_oauth_client(OAuthClientId::default(), vec![Scope::OpenId, Scope::Email]);
+        session_store.write().await.add_session(session.clone()).await.unwrap();
+
+        let token = generate_id_token(&user, &session, Some("n-0S6_WzA2Mj".to_owned()), keyring.clone()).await.unwrap();
+        let claims = decode_id_token_hint(&token, keyring.clone()).await.unwrap();
+
+        assert_eq!(claims.iss, *OIDC_ISSUER);
+        assert_eq!(claims.aud, session.oauth_client_id.as_ref().unwrap().as_ref());
+        assert_eq!(claims.auth_time, 1000);
+        assert_eq!(claims.nonce.as_deref(), Some("n-0S6_WzA2Mj"));
+        assert_eq!(claims.amr, vec!["pwd", "otp", "mfa"]);
+        assert_eq!(claims.sid, session.id.as_ref());
+        assert_eq!(claims.user_info, UserInfo {
+            sub: user.id.as_ref().to_owned(),
+            email: Some("test@example.com".to_owned()),
+            email_verified: Some(true),
+        });
+
+        // An ID token is no auth token, even though the session it names is live
+        let banned_token_store = Arc::new(RwLock::new(BannedTokens::default()));
+        let result = validate_token(&token, banned_token_store, session_store, keyring.clone()).await;
+        assert!(result.is_err());
+
+        // Nor is an auth token an ID token
+        let auth_token = generate_auth_token(&user, &session.id, &Grants::default(), false, &*keyring.read().await).unwrap();
+        assert!(decode_id_token_hint(&auth_token, keyring).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_access_token_only_validates_for_its_client() {
+        let session_store = session_store();
+        let banned_token_store: BannedTokenStoreType = Arc::new(RwLock::new(BannedTokens::default()));
+        let user = test_user("test@example.com");
+        let keyring = keyring();
+        let session = Session::new(user.id.clone(), ClientInfo::default(), Utc::now().timestamp())
+            .for_oauth_client(OAuthClientId::default(), vec![Scope::OpenId]);
+        session_store.write().await.add_session(session.clone()).await.unwrap();
+
+        let token = generate_access_token(&user, &session, &*keyring.read().await).unwrap();
+        let (claims, _) = validate_access_token(&token, banned_token_store.clone(), session_store.clone(), keyring.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(claims.aud, session.oauth_client_id.as_ref().unwrap().as_ref());
+        assert_eq!(claims.scope, "openid");
+
+        // Where first-party auth tokens are checked, neither the access token nor any token of the session passes
+        let result = validate_token(&token, banned_token_store.clone(), session_store.clone(), keyring.clone()).await;
+        assert!(result.is_err());
+
+        let auth_token = generate_auth_token(&user, &session.id, &Grants::default(), false, &*keyring.read().await).unwrap();
+        let result = validate_token(&auth_token, banned_token_store.clone(), session_store.clone(), keyring.clone()).await;
+        assert!(result.is_err());
+
+        // And an auth token or ID token doesn't pass for an access token
+        let result = validate_access_token(&auth_token, banned_token_store.clone(), session_store.clone(), keyring.clone()).await;
+        assert!(result.is_err());
+
+        let id_token = generate_id_token(&user, &session, None, keyring.clone()).await.unwrap();
+        let result = validate_access_token(&id_token, banned_token_store.clone(), session_store.clone(), keyring.clone()).await;
+        assert!(result.is_err());
+
+        // Once the session ends, so does the access token
+        session_store.write().await.remove_session(&session.id).await.unwrap();
+        let result = validate_access_token(&token, banned_token_store, session_store, keyring).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_id_token_hint_may_have_expired() {
+        let user = test_user("test@example.com");
+        let keyring = keyring();
+        let session = Session::new(user.id.clone(), ClientInfo::default(), 1000)
+            .for_oauth_client(OAuthClientId::default(), vec![Scope::OpenId]);
+
+        let token = generate_id_token(&user, &session, None, keyring.clone()).await.unwrap();
+        let mut claims = decode_id_token_hint(&token, keyring.clone()).await.unwrap();
+        assert_eq!(claims.user_info.email, None);
+
+        claims.exp = 1000;
+        let expired = create_token(&claims, keyring.read().await.signing_key()).unwrap();
+        assert!(decode_id_token_hint(&expired, keyring.clone()).await.is_some());
+
+        // But it has to come from this issuer
+        claims.iss = "https://other.example.com".to_owned();
+        let foreign = create_token(&claims, keyring.read().await.signing_key()).unwrap();
+        assert!(decode_id_token_hint(&foreign, keyring).await.is_none());
+    }
+}
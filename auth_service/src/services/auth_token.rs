@@ -0,0 +1,93 @@
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header::AUTHORIZATION, request::Parts, HeaderMap}
+};
+use axum_extra::extract::CookieJar;
+
+use super::{api::AuthApiError, constants::JWT_COOKIE_NAME};
+
+const BEARER_SCHEME: &str = "bearer";
+
+// The auth token a request is authenticated with. Browsers send it in the auth cookie,
+// other clients in an `Authorization: Bearer` header, which wins when both are sent.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AuthToken {
+    Bearer(String),
+    Cookie(String),
+}
+
+impl AuthToken {
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        if let Some(token) = bearer_token(headers) {
+            return Some(Self::Bearer(token));
+        }
+
+        CookieJar::from_headers(headers)
+            .get(JWT_COOKIE_NAME)
+            .map(|cookie| Self::Cookie(cookie.value().to_owned()))
+    }
+
+    // Clients that sent a bearer token get new tokens back in the response body rather than as cookies
+    pub fn is_bearer(&self) -> bool {
+        matches!(self, Self::Bearer(_))
+    }
+}
+
+impl AsRef<str> for AuthToken {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Bearer(token) | Self::Cookie(token) => token,
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AuthToken {
+    type Rejection = AuthApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Self::from_headers(&parts.headers).ok_or(AuthApiError::MissingToken)
+    }
+}
+
+// The scheme is case-insensitive, and a header using any other scheme is ignored
+fn bearer_token(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    let token = token.trim();
+
+    (scheme.eq_ignore_ascii_case(BEARER_SCHEME) && !token.is_empty()).then(|| token.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{header::COOKIE, HeaderValue};
+
+    use super::*;
+
+    fn headers(pairs: &[(axum::http::HeaderName, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    #[test]
+    fn test_bearer_header_wins_over_cookie() {
+        let both = headers(&[(AUTHORIZATION, "Bearer abc"), (COOKIE, "jwt=def")]);
+        assert_eq!(AuthToken::from_headers(&both), Some(AuthToken::Bearer(String::from("abc"))));
+
+        let lowercase = headers(&[(AUTHORIZATION, "bearer abc")]);
+        assert_eq!(AuthToken::from_headers(&lowercase), Some(AuthToken::Bearer(String::from("abc"))));
+    }
+
+    #[test]
+    fn test_other_schemes_fall_back_to_cookie() {
+        let basic = headers(&[(AUTHORIZATION, "Basic dXNlcjpwdw=="), (COOKIE, "jwt=def")]);
+        assert_eq!(AuthToken::from_headers(&basic), Some(AuthToken::Cookie(String::from("def"))));
+
+        let empty = headers(&[(AUTHORIZATION, "Bearer ")]);
+        assert_eq!(AuthToken::from_headers(&empty), None);
+    }
+}
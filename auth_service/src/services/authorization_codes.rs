@@ -0,0 +1,218 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use rand::{distributions::Alphanumeric, Rng};
+use ring::{
+    constant_time,
+    digest::{digest, SHA256}
+};
+use tokio::sync::RwLock;
+
+use crate::user::UserId;
+
+use super::{
+    oauth_clients::OAuthClientId,
+    oidc::{AuthMethod, Scope}
+};
+
+pub type AuthorizationCodeStoreType = Arc<RwLock<dyn AuthorizationCodeStore + Send + Sync>>;
+
+// This value determines how long a client has to exchange an authorization code at /token
+pub const AUTHORIZATION_CODE_TTL_SECONDS: i64 = 60;
+
+const AUTHORIZATION_CODE_LENGTH: usize = 43;
+
+// Code verifiers are 43 to 128 of these characters (RFC 7636 section 4.1)
+const CODE_VERIFIER_MIN_LENGTH: usize = 43;
+const CODE_VERIFIER_MAX_LENGTH: usize = 128;
+const CODE_VERIFIER_SYMBOLS: &[u8] = b"-._~";
+
+// Handed to the client through the redirect URI, and exchanged once for tokens at /token
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AuthorizationCode(String);
+
+impl AuthorizationCode {
+    pub fn parse(code: String) -> Result<Self, String> {
+        if code.len() == AUTHORIZATION_CODE_LENGTH && code.chars().all(|c| c.is_ascii_alphanumeric()) {
+            Ok(Self(code))
+        } else {
+            Err(String::from("Invalid authorization code format"))
+        }
+    }
+}
+
+impl Default for AuthorizationCode {
+    fn default() -> Self {
+        let code = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(AUTHORIZATION_CODE_LENGTH)
+            .map(char::from)
+            .collect();
+        Self(code)
+    }
+}
+
+impl AsRef<str> for AuthorizationCode {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// The S256 PKCE challenge sent to /authorize: the unpadded base64url SHA-256 of the client's code verifier
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeChallenge(String);
+
+impl CodeChallenge {
+    pub fn parse(challenge: String) -> Result<Self, String> {
+        let valid = URL_SAFE_NO_PAD
+            .decode(&challenge)
+            .is_ok_and(|hash| hash.len() == SHA256.output_len());
+
+        if !valid {
+            return Err(String::from("Invalid code challenge format"));
+        }
+
+        Ok(Self(challenge))
+    }
+
+    pub fn is_satisfied_by(&self, verifier: &CodeVerifier) -> bool {
+        let hash = URL_SAFE_NO_PAD.encode(digest(&SHA256, verifier.0.as_bytes()));
+        constant_time::verify_slices_are_equal(hash.as_bytes(), self.0.as_bytes()).is_ok()
+    }
+}
+
+impl AsRef<str> for CodeChallenge {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// The secret behind a code challenge, which only the client that started the authorization knows
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeVerifier(String);
+
+impl CodeVerifier {
+    pub fn parse(verifier: String) -> Result<Self, String> {
+        let valid = (CODE_VERIFIER_MIN_LENGTH..=CODE_VERIFIER_MAX_LENGTH).contains(&verifier.len())
+            && verifier.bytes().all(|b| b.is_ascii_alphanumeric() || CODE_VERIFIER_SYMBOLS.contains(&b));
+
+        if !valid {
+            return Err(String::from("Invalid code verifier format"));
+        }
+
+        Ok(Self(verifier))
+    }
+}
+
+// What a user authorized, checked again when the code is exchanged
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthorizationGrant {
+    pub client_id: OAuthClientId,
+    pub redirect_uri: String,
+    pub user_id: UserId,
+    pub code_challenge: CodeChallenge,
+    pub scopes: Vec<Scope>,
+    // Passed through to the ID token, when the client asked for the `openid` scope
+    pub nonce: Option<String>,
+    // The login of the browser session the user authorized the client from
+    pub authenticated_at: i64,
+    pub auth_methods: Vec<AuthMethod>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AuthorizationCodeStoreError {
+    CodeNotFound,
+    UnexpectedError,
+}
+
+#[async_trait]
+pub trait AuthorizationCodeStore {
+    async fn add_code(&mut self, code: AuthorizationCode, grant: AuthorizationGrant) -> Result<(), AuthorizationCodeStoreError>;
+    // Codes are single use, so reading one also removes it, whether or not the exchange succeeds
+    async fn take_code(&mut self, code: &AuthorizationCode) -> Result<AuthorizationGrant, AuthorizationCodeStoreError>;
+}
+
+#[derive(Default)]
+pub struct AuthorizationCodes {
+    codes: HashMap<AuthorizationCode, (AuthorizationGrant, i64)>,
+}
+
+#[async_trait]
+impl AuthorizationCodeStore for AuthorizationCodes {
+    async fn add_code(&mut self, code: AuthorizationCode, grant: AuthorizationGrant) -> Result<(), AuthorizationCodeStoreError> {
+        let expires_at = Utc::now().timestamp() + AUTHORIZATION_CODE_TTL_SECONDS;
+        self.codes.insert(code, (grant, expires_at));
+        Ok(())
+    }
+
+    async fn take_code(&mut self, code: &AuthorizationCode) -> Result<AuthorizationGrant, AuthorizationCodeStoreError> {
+        self.codes
+            .remove(code)
+            .filter(|(_, expires_at)| *expires_at > Utc::now().timestamp())
+            .map(|(grant, _)| grant)
+            .ok_or(AuthorizationCodeStoreError::CodeNotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The example from RFC 7636 appendix B
+    const VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+    const CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+
+    fn grant() -> AuthorizationGrant {
+        AuthorizationGrant {
+            client_id: OAuthClientId::default(),
+            redirect_uri: String::from("https://app.example.com/callback"),
+            user_id: UserId::default(),
+            code_challenge: CodeChallenge::parse(CHALLENGE.to_owned()).unwrap(),
+            scopes: vec![Scope::OpenId],
+            nonce: Some(String::from("n-0S6_WzA2Mj")),
+            authenticated_at: 1000,
+            auth_methods: vec![AuthMethod::Password],
+        }
+    }
+
+    #[test]
+    fn test_s256_challenge() {
+        let challenge = CodeChallenge::parse(CHALLENGE.to_owned()).unwrap();
+
+        assert!(challenge.is_satisfied_by(&CodeVerifier::parse(VERIFIER.to_owned()).unwrap()));
+        assert!(!challenge.is_satisfied_by(&CodeVerifier::parse(VERIFIER.replace('d', "e")).unwrap()));
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed_pkce_values() {
+        assert!(CodeChallenge::parse(CHALLENGE[1..].to_owned()).is_err());
+        assert!(CodeChallenge::parse(CHALLENGE.replace('-', "+")).is_err());
+        assert!(CodeChallenge::parse(format!("{}=", CHALLENGE)).is_err());
+
+        for verifier in ["short", &"a".repeat(129), &format!("{}+", &VERIFIER[1..])] {
+            assert!(CodeVerifier::parse(verifier.to_owned()).is_err(), "Accepted {:?}", verifier);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_codes_are_single_use() {
+        let mut store = AuthorizationCodes::default();
+        let code = AuthorizationCode::default();
+
+        store.add_code(code.clone(), grant()).await.unwrap();
+
+        assert!(store.take_code(&code).await.is_ok());
+        assert_eq!(store.take_code(&code).await, Err(AuthorizationCodeStoreError::CodeNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_expired_code_is_not_found() {
+        let mut store = AuthorizationCodes::default();
+        let code = AuthorizationCode::default();
+        store.codes.insert(code.clone(), (grant(), Utc::now().timestamp() - 1));
+
+        assert_eq!(store.take_code(&code).await, Err(AuthorizationCodeStoreError::CodeNotFound));
+    }
+}
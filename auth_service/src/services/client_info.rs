@@ -0,0 +1,150 @@
+use std::{convert::Infallible, net::{IpAddr, SocketAddr}};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header::USER_AGENT, request::Parts, HeaderMap}
+};
+use serde::{Deserialize, Serialize};
+
+use super::constants::TRUSTED_PROXY_DEPTH;
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+// Where a request came from, recorded alongside login attempts so users can tell their devices apart
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ClientInfo {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ClientInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let ip = client_ip(&parts.headers, peer_ip(parts), *TRUSTED_PROXY_DEPTH).map(|ip| ip.to_string());
+
+        let user_agent = parts
+            .headers
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+
+        Ok(Self { ip, user_agent })
+    }
+}
+
+impl ClientInfo {
+    // Names the browser and OS from the user agent, e.g. `Firefox on Linux`, for users to recognize their devices by
+    pub fn device(&self) -> Option<String> {
+        let user_agent = self.user_agent.as_deref()?;
+
+        // Order matters, e.g. Edge also claims to be Chrome and Safari, and Android to be Linux
+        let browser = [("Edg/", "Edge"), ("OPR/", "Opera"), ("Firefox/", "Firefox"), ("Chrome/", "Chrome"), ("Safari/", "Safari")]
+            .into_iter()
+            .find(|(marker, _)| user_agent.contains(marker))
+            .map(|(_, name)| name);
+
+        let os = [("Android", "Android"), ("iPhone", "iOS"), ("iPad", "iOS"), ("Windows", "Windows"), ("Mac OS X", "macOS"), ("Linux", "Linux")]
+            .into_iter()
+            .find(|(marker, _)| user_agent.contains(marker))
+            .map(|(_, name)| name);
+
+        match (browser, os) {
+            (Some(browser), Some(os)) => Some(format!("{} on {}", browser, os)),
+            (Some(name), None) | (None, Some(name)) => Some(name.to_owned()),
+            (None, None) => None,
+        }
+    }
+}
+
+// Only present when the server was started with connect info, which App::build does
+pub fn peer_ip(parts: &Parts) -> Option<IpAddr> {
+    parts
+        .extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+}
+
+// Behind `trusted_proxies` proxies that each append the address they got the request from to
+// X-Forwarded-For, the client's address is the one the outermost proxy appended. Anything
+// further left was sent by the client itself and can't be trusted.
+pub fn client_ip(headers: &HeaderMap, peer: Option<IpAddr>, trusted_proxies: usize) -> Option<IpAddr> {
+    if trusted_proxies == 0 {
+        return peer;
+    }
+
+    let forwarded: Vec<&str> = headers
+        .get_all(X_FORWARDED_FOR)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+
+    // Fewer entries than proxies means the request skipped some of them, and whatever is left may have
+    // come from the client, so only the peer can be trusted
+    forwarded
+        .iter()
+        .rev()
+        .nth(trusted_proxies - 1)
+        .and_then(|ip| ip.parse().ok())
+        .or(peer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(forwarded_for: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in forwarded_for {
+            headers.append(X_FORWARDED_FOR, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_client_ip_honors_trusted_proxy_depth() {
+        let peer = Some("10.0.0.1".parse().unwrap());
+        let headers = headers(&["6.6.6.6, 1.2.3.4", "10.0.0.2"]);
+
+        assert_eq!(client_ip(&headers, peer, 0), peer);
+        assert_eq!(client_ip(&headers, peer, 1), Some("10.0.0.2".parse().unwrap()));
+        assert_eq!(client_ip(&headers, peer, 2), Some("1.2.3.4".parse().unwrap()));
+        assert_eq!(client_ip(&headers, peer, 5), peer);
+    }
+
+    #[test]
+    fn test_device() {
+        let client = |user_agent: &str| ClientInfo { ip: None, user_agent: Some(user_agent.to_owned()) };
+
+        let firefox = "Mozilla/5.0 (X11; Linux x86_64; rv:131.0) Gecko/20100101 Firefox/131.0";
+        let edge = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) \
+            Chrome/130.0.0.0 Safari/537.36 Edg/130.0.0.0";
+        let safari = "Mozilla/5.0 (iPhone; CPU iPhone OS 18_0 like Mac OS X) AppleWebKit/605.1.15 \
+            (KHTML, like Gecko) Version/18.0 Mobile/15E148 Safari/604.1";
+
+        assert_eq!(client(firefox).device().as_deref(), Some("Firefox on Linux"));
+        assert_eq!(client(edge).device().as_deref(), Some("Edge on Windows"));
+        assert_eq!(client(safari).device().as_deref(), Some("Safari on iOS"));
+        assert_eq!(client("curl/8.5.0").device(), None);
+        assert_eq!(ClientInfo::default().device(), None);
+    }
+
+    #[test]
+    fn test_client_ip_falls_back_to_peer() {
+        let peer = Some("10.0.0.1".parse().unwrap());
+
+        assert_eq!(client_ip(&HeaderMap::new(), peer, 1), peer);
+        assert_eq!(client_ip(&headers(&["not an ip"]), peer, 1), peer);
+    }
+
+    #[test]
+    fn test_client_ip_ignores_header_shorter_than_proxy_depth() {
+        let peer = Some("10.0.0.1".parse().unwrap());
+
+        assert_eq!(client_ip(&headers(&["6.6.6.6"]), peer, 2), peer);
+    }
+}
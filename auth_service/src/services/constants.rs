@@ -1,14 +1,43 @@
+use std::{collections::HashMap, num::NonZeroU32, str::FromStr};
+
 use dotenvy::dotenv;
+use jsonwebtoken::Algorithm;
 use lazy_static::lazy_static;
 use secrecy::Secret;
 use std::env as std_env;
 
+use crate::{
+    app::config::AppConfig,
+    services::{login_attempts::LockoutPolicy, rate_limits::{RateLimit, RateLimits}},
+    user::UnverifiedLoginPolicy
+};
+
 // Define a lazily evaluated static. lazy_static is needed because std_env::var is not a const function.
 lazy_static! {
     pub static ref JWT_SECRET: String = set_token();
+    pub static ref JWT_SIGNING_KEY_PATH: Option<String> = set_signing_key_path();
+    pub static ref JWT_SIGNING_ALGORITHM: Algorithm = set_signing_algorithm();
+    pub static ref JWT_EMAIL_CLAIM: bool = set_jwt_email_claim();
     pub static ref DATABASE_URL: String = set_db_url();
     pub static ref REDIS_HOST_NAME: String = set_redis_host();
+    pub static ref TOTP_SKEW_STEPS: u64 = set_totp_skew_steps();
+    pub static ref TWO_FA_MAX_ATTEMPTS: u32 = set_two_fa_max_attempts();
+    pub static ref WEBAUTHN_RP_ID: String = set_webauthn_rp_id();
+    pub static ref WEBAUTHN_ORIGIN: String = set_webauthn_origin();
+    pub static ref OIDC_ISSUER: String = set_oidc_issuer();
+    pub static ref PASSWORD_RESET_URL: String = set_password_reset_url();
+    pub static ref EMAIL_VERIFICATION_URL: String = set_email_verification_url();
+    pub static ref EMAIL_CHANGE_CONFIRM_URL: String = set_email_change_confirm_url();
+    pub static ref EMAIL_CHANGE_CANCEL_URL: String = set_email_change_cancel_url();
+    pub static ref ORG_INVITATION_URL: String = set_org_invitation_url();
+    pub static ref UNVERIFIED_LOGIN_POLICY: UnverifiedLoginPolicy = set_unverified_login_policy();
+    pub static ref ACCOUNT_DELETION_GRACE_SECONDS: i64 = set_account_deletion_grace_seconds();
     pub static ref POSTMARK_AUTH_TOKEN: Secret<String> = set_postmark_auth_token();
+    pub static ref ADMIN_API_KEY: Option<Secret<String>> = set_admin_api_key();
+    pub static ref LOGIN_LOCKOUT_POLICY: LockoutPolicy = set_login_lockout_policy();
+    pub static ref ACCOUNT_UNLOCK_URL: String = set_account_unlock_url();
+    pub static ref RATE_LIMITS: RateLimits = set_rate_limits();
+    pub static ref TRUSTED_PROXY_DEPTH: usize = set_trusted_proxy_depth();
 }
 
 fn set_postmark_auth_token() -> Secret<String> {
@@ -33,6 +62,244 @@ fn set_token() -> String {
     secret
 }
 
+fn set_signing_key_path() -> Option<String> {
+    dotenv().ok();
+    std_env::var(env::JWT_SIGNING_KEY_PATH_ENV_VAR).ok().filter(|path| !path.is_empty())
+}
+
+fn set_signing_algorithm() -> Algorithm {
+    dotenv().ok();
+    std_env::var(env::JWT_SIGNING_ALGORITHM_ENV_VAR)
+        .ok()
+        .filter(|alg| !alg.is_empty())
+        .map(|alg| Algorithm::from_str(&alg).expect("JWT_SIGNING_ALGORITHM must be RS256 or EdDSA."))
+        .unwrap_or(DEFAULT_JWT_SIGNING_ALGORITHM)
+}
+
+// Whether auth tokens also carry the user's address in an `email` claim next to the id in `sub`
+fn set_jwt_email_claim() -> bool {
+    dotenv().ok();
+    std_env::var(env::JWT_EMAIL_CLAIM_ENV_VAR)
+        .ok()
+        .filter(|value| !value.is_empty())
+        .map(|value| value.parse::<bool>().expect("JWT_EMAIL_CLAIM must be true or false."))
+        .unwrap_or(DEFAULT_JWT_EMAIL_CLAIM)
+}
+
+fn set_totp_skew_steps() -> u64 {
+    dotenv().ok();
+    std_env::var(env::TOTP_SKEW_STEPS_ENV_VAR)
+        .ok()
+        .filter(|steps| !steps.is_empty())
+        .map(|steps| steps.parse().expect("TOTP_SKEW_STEPS must be a non-negative integer."))
+        .unwrap_or(DEFAULT_TOTP_SKEW_STEPS)
+}
+
+fn set_two_fa_max_attempts() -> u32 {
+    dotenv().ok();
+    std_env::var(env::TWO_FA_MAX_ATTEMPTS_ENV_VAR)
+        .ok()
+        .filter(|attempts| !attempts.is_empty())
+        .map(|attempts| {
+            attempts
+                .parse::<NonZeroU32>()
+                .expect("TWO_FA_MAX_ATTEMPTS must be a positive integer.")
+                .get()
+        })
+        .unwrap_or(DEFAULT_TWO_FA_MAX_ATTEMPTS)
+}
+
+fn set_webauthn_rp_id() -> String {
+    dotenv().ok();
+    std_env::var(env::WEBAUTHN_RP_ID_ENV_VAR)
+        .ok()
+        .filter(|rp_id| !rp_id.is_empty())
+        .unwrap_or(DEFAULT_WEBAUTHN_RP_ID.to_owned())
+}
+
+fn set_webauthn_origin() -> String {
+    dotenv().ok();
+    std_env::var(env::WEBAUTHN_ORIGIN_ENV_VAR)
+        .ok()
+        .filter(|origin| !origin.is_empty())
+        .unwrap_or(DEFAULT_WEBAUTHN_ORIGIN.to_owned())
+}
+
+// Trailing slashes are dropped, the endpoints advertised by discovery are the issuer followed by their path
+fn set_oidc_issuer() -> String {
+    dotenv().ok();
+    std_env::var(env::OIDC_ISSUER_ENV_VAR)
+        .ok()
+        .map(|issuer| issuer.trim_end_matches('/').to_owned())
+        .filter(|issuer| !issuer.is_empty())
+        .unwrap_or(DEFAULT_OIDC_ISSUER.to_owned())
+}
+
+fn set_password_reset_url() -> String {
+    dotenv().ok();
+    std_env::var(env::PASSWORD_RESET_URL_ENV_VAR)
+        .ok()
+        .filter(|url| !url.is_empty())
+        .unwrap_or(DEFAULT_PASSWORD_RESET_URL.to_owned())
+}
+
+fn set_email_verification_url() -> String {
+    dotenv().ok();
+    std_env::var(env::EMAIL_VERIFICATION_URL_ENV_VAR)
+        .ok()
+        .filter(|url| !url.is_empty())
+        .unwrap_or(DEFAULT_EMAIL_VERIFICATION_URL.to_owned())
+}
+
+fn set_email_change_confirm_url() -> String {
+    dotenv().ok();
+    std_env::var(env::EMAIL_CHANGE_CONFIRM_URL_ENV_VAR)
+        .ok()
+        .filter(|url| !url.is_empty())
+        .unwrap_or(DEFAULT_EMAIL_CHANGE_CONFIRM_URL.to_owned())
+}
+
+fn set_email_change_cancel_url() -> String {
+    dotenv().ok();
+    std_env::var(env::EMAIL_CHANGE_CANCEL_URL_ENV_VAR)
+        .ok()
+        .filter(|url| !url.is_empty())
+        .unwrap_or(DEFAULT_EMAIL_CHANGE_CANCEL_URL.to_owned())
+}
+
+fn set_org_invitation_url() -> String {
+    dotenv().ok();
+    std_env::var(env::ORG_INVITATION_URL_ENV_VAR)
+        .ok()
+        .filter(|url| !url.is_empty())
+        .unwrap_or(DEFAULT_ORG_INVITATION_URL.to_owned())
+}
+
+fn set_unverified_login_policy() -> UnverifiedLoginPolicy {
+    dotenv().ok();
+    std_env::var(env::UNVERIFIED_LOGIN_POLICY_ENV_VAR)
+        .ok()
+        .filter(|policy| !policy.is_empty())
+        .map(|policy| {
+            UnverifiedLoginPolicy::parse(&policy)
+                .expect("UNVERIFIED_LOGIN_POLICY must be allow, deny or grace:<hours>.")
+        })
+        .unwrap_or(DEFAULT_UNVERIFIED_LOGIN_POLICY)
+}
+
+// Configured in hours, zero deletes accounts right away
+fn set_account_deletion_grace_seconds() -> i64 {
+    dotenv().ok();
+    std_env::var(env::ACCOUNT_DELETION_GRACE_HOURS_ENV_VAR)
+        .ok()
+        .filter(|hours| !hours.is_empty())
+        .map(|hours| {
+            hours
+                .parse::<u32>()
+                .expect("ACCOUNT_DELETION_GRACE_HOURS must be a non-negative integer.")
+        })
+        .map(|hours| i64::from(hours) * 3600)
+        .unwrap_or(DEFAULT_ACCOUNT_DELETION_GRACE_SECONDS)
+}
+
+fn set_login_lockout_policy() -> LockoutPolicy {
+    dotenv().ok();
+    let threshold = std_env::var(env::LOGIN_LOCKOUT_THRESHOLD_ENV_VAR)
+        .ok()
+        .filter(|threshold| !threshold.is_empty())
+        .map(|threshold| {
+            threshold
+                .parse::<NonZeroU32>()
+                .expect("LOGIN_LOCKOUT_THRESHOLD must be a positive integer.")
+                .get()
+        })
+        .unwrap_or(DEFAULT_LOGIN_LOCKOUT_THRESHOLD);
+
+    let base_seconds = std_env::var(env::LOGIN_LOCKOUT_BASE_SECONDS_ENV_VAR)
+        .ok()
+        .filter(|seconds| !seconds.is_empty())
+        .map(|seconds| {
+            seconds
+                .parse::<NonZeroU32>()
+                .expect("LOGIN_LOCKOUT_BASE_SECONDS must be a positive integer.")
+                .get()
+        })
+        .map(i64::from)
+        .unwrap_or(DEFAULT_LOGIN_LOCKOUT_BASE_SECONDS);
+
+    LockoutPolicy { threshold, base_seconds, max_seconds: MAX_LOGIN_LOCKOUT_SECONDS }
+}
+
+fn set_account_unlock_url() -> String {
+    dotenv().ok();
+    std_env::var(env::ACCOUNT_UNLOCK_URL_ENV_VAR)
+        .ok()
+        .filter(|url| !url.is_empty())
+        .unwrap_or(DEFAULT_ACCOUNT_UNLOCK_URL.to_owned())
+}
+
+fn set_rate_limits() -> RateLimits {
+    dotenv().ok();
+    let paths = AppConfig::config().paths;
+
+    // Per client IP. Routes that send emails or check passwords get the tightest limits.
+    let mut routes: HashMap<String, RateLimit> = [
+        (paths.signup, RateLimit { requests: 20, window_seconds: 3600 }),
+        (paths.login, RateLimit { requests: 30, window_seconds: 60 }),
+        (paths.verify_2fa, RateLimit { requests: 30, window_seconds: 60 }),
+        (paths.resend_2fa, RateLimit { requests: 10, window_seconds: 60 }),
+        (paths.verify_email_resend, RateLimit { requests: 10, window_seconds: 3600 }),
+        (paths.password_reset_request, RateLimit { requests: 10, window_seconds: 3600 }),
+        (paths.password_reset_confirm, RateLimit { requests: 30, window_seconds: 60 }),
+        (paths.unlock_account, RateLimit { requests: 30, window_seconds: 60 }),
+        (paths.webauthn_login_start, RateLimit { requests: 30, window_seconds: 60 }),
+        (paths.webauthn_login_finish, RateLimit { requests: 30, window_seconds: 60 }),
+        (paths.oauth_token, RateLimit { requests: 30, window_seconds: 60 }),
+    ]
+    .into_iter()
+    .map(|(path, limit)| (path.to_owned(), limit))
+    .collect();
+    let mut default = Some(DEFAULT_RATE_LIMIT);
+
+    // e.g. `/login=10/60,/signup=5/3600,*=100/60`, where `*` stands for every other route
+    let overrides = std_env::var(env::RATE_LIMITS_ENV_VAR).ok().filter(|limits| !limits.is_empty());
+
+    for entry in overrides.iter().flat_map(|limits| limits.split(',')) {
+        let (path, limit) = entry
+            .split_once('=')
+            .expect("RATE_LIMITS entries must be written as path=requests/seconds.");
+        let limit = limit.parse::<RateLimit>().unwrap_or_else(|e| panic!("Invalid RATE_LIMITS: {}", e));
+
+        match path.trim() {
+            "*" => default = Some(limit),
+            path => {
+                routes.insert(path.to_owned(), limit);
+            }
+        }
+    }
+
+    RateLimits { routes, default }
+}
+
+fn set_trusted_proxy_depth() -> usize {
+    dotenv().ok();
+    std_env::var(env::TRUSTED_PROXY_DEPTH_ENV_VAR)
+        .ok()
+        .filter(|depth| !depth.is_empty())
+        .map(|depth| {
+            depth
+                .parse::<usize>()
+                .expect("TRUSTED_PROXY_DEPTH must be a non-negative integer.")
+        })
+        .unwrap_or(DEFAULT_TRUSTED_PROXY_DEPTH)
+}
+
+fn set_admin_api_key() -> Option<Secret<String>> {
+    dotenv().ok();
+    // Without a key, only users with the admin role can use the admin API
+    std_env::var(env::ADMIN_API_KEY_ENV_VAR).ok().filter(|key| !key.is_empty()).map(Secret::new)
+}
+
 fn set_redis_host() -> String {
     dotenv().ok();
     std_env::var(env::REDIS_HOST_NAME_ENV_VAR).unwrap_or(DEFAULT_REDIS_HOSTNAME.to_owned())
@@ -41,8 +308,29 @@ fn set_redis_host() -> String {
 pub mod env {
     pub const DATABASE_URL_ENV_VAR: &str = "DATABASE_URL";
     pub const JWT_SECRET_ENV_VAR: &str = "JWT_SECRET";
+    pub const JWT_SIGNING_KEY_PATH_ENV_VAR: &str = "JWT_SIGNING_KEY_PATH";
+    pub const JWT_SIGNING_ALGORITHM_ENV_VAR: &str = "JWT_SIGNING_ALGORITHM";
+    pub const JWT_EMAIL_CLAIM_ENV_VAR: &str = "JWT_EMAIL_CLAIM";
     pub const REDIS_HOST_NAME_ENV_VAR: &str = "REDIS_HOST_NAME";
+    pub const TOTP_SKEW_STEPS_ENV_VAR: &str = "TOTP_SKEW_STEPS";
+    pub const TWO_FA_MAX_ATTEMPTS_ENV_VAR: &str = "TWO_FA_MAX_ATTEMPTS";
+    pub const WEBAUTHN_RP_ID_ENV_VAR: &str = "WEBAUTHN_RP_ID";
+    pub const WEBAUTHN_ORIGIN_ENV_VAR: &str = "WEBAUTHN_ORIGIN";
+    pub const OIDC_ISSUER_ENV_VAR: &str = "OIDC_ISSUER";
+    pub const PASSWORD_RESET_URL_ENV_VAR: &str = "PASSWORD_RESET_URL";
+    pub const EMAIL_VERIFICATION_URL_ENV_VAR: &str = "EMAIL_VERIFICATION_URL";
+    pub const EMAIL_CHANGE_CONFIRM_URL_ENV_VAR: &str = "EMAIL_CHANGE_CONFIRM_URL";
+    pub const EMAIL_CHANGE_CANCEL_URL_ENV_VAR: &str = "EMAIL_CHANGE_CANCEL_URL";
+    pub const ORG_INVITATION_URL_ENV_VAR: &str = "ORG_INVITATION_URL";
+    pub const UNVERIFIED_LOGIN_POLICY_ENV_VAR: &str = "UNVERIFIED_LOGIN_POLICY";
+    pub const ACCOUNT_DELETION_GRACE_HOURS_ENV_VAR: &str = "ACCOUNT_DELETION_GRACE_HOURS";
     pub const POSTMARK_AUTH_TOKEN_ENV_VAR: &str = "POSTMARK_AUTH_TOKEN"; 
+    pub const ADMIN_API_KEY_ENV_VAR: &str = "ADMIN_API_KEY";
+    pub const LOGIN_LOCKOUT_THRESHOLD_ENV_VAR: &str = "LOGIN_LOCKOUT_THRESHOLD";
+    pub const LOGIN_LOCKOUT_BASE_SECONDS_ENV_VAR: &str = "LOGIN_LOCKOUT_BASE_SECONDS";
+    pub const ACCOUNT_UNLOCK_URL_ENV_VAR: &str = "ACCOUNT_UNLOCK_URL";
+    pub const RATE_LIMITS_ENV_VAR: &str = "RATE_LIMITS";
+    pub const TRUSTED_PROXY_DEPTH_ENV_VAR: &str = "TRUSTED_PROXY_DEPTH";
 }
 
 pub mod prod {
@@ -70,4 +358,40 @@ pub mod test {
 }
 
 pub const JWT_COOKIE_NAME: &str = "jwt";
-pub const DEFAULT_REDIS_HOSTNAME: &str = "127.0.0.1";
\ No newline at end of file
+pub const REFRESH_COOKIE_NAME: &str = "refresh_token";
+pub const ADMIN_API_KEY_HEADER: &str = "x-admin-api-key";
+pub const DEFAULT_REDIS_HOSTNAME: &str = "127.0.0.1";
+pub const DEFAULT_JWT_SIGNING_ALGORITHM: Algorithm = Algorithm::RS256;
+pub const DEFAULT_JWT_EMAIL_CLAIM: bool = false;
+pub const SHARED_SECRET_KEY_ID: &str = "shared-secret";
+pub const DEFAULT_TOTP_SKEW_STEPS: u64 = 1;
+// Wrong guesses allowed against a single login attempt before its code is thrown away
+pub const DEFAULT_TWO_FA_MAX_ATTEMPTS: u32 = 5;
+pub const DEFAULT_WEBAUTHN_RP_ID: &str = "localhost";
+pub const DEFAULT_WEBAUTHN_ORIGIN: &str = "http://localhost:3000";
+// The public URL of the service, which OpenID Connect clients expect as the `iss` of ID tokens
+pub const DEFAULT_OIDC_ISSUER: &str = "http://localhost:3000";
+// Page the emailed reset link points at, the token is appended as a `token` query parameter
+pub const DEFAULT_PASSWORD_RESET_URL: &str = "http://localhost:8000/reset-password";
+pub const DEFAULT_EMAIL_VERIFICATION_URL: &str = "http://localhost:8000/verify-email";
+pub const DEFAULT_EMAIL_CHANGE_CONFIRM_URL: &str = "http://localhost:8000/change-email/confirm";
+pub const DEFAULT_EMAIL_CHANGE_CANCEL_URL: &str = "http://localhost:8000/change-email/cancel";
+pub const DEFAULT_ORG_INVITATION_URL: &str = "http://localhost:8000/invitations/accept";
+// New accounts get three days to confirm their address before login is refused
+pub const DEFAULT_UNVERIFIED_LOGIN_POLICY: UnverifiedLoginPolicy = UnverifiedLoginPolicy::GracePeriod(3 * 86_400);
+pub const DEFAULT_ACCOUNT_DELETION_GRACE_SECONDS: i64 = 0;
+// How often accounts whose deletion grace period is over get purged
+pub const ACCOUNT_PURGE_INTERVAL_SECONDS: u64 = 3600;
+// Wrong passwords in a row before an account is locked, the first lockout lasts a minute
+pub const DEFAULT_LOGIN_LOCKOUT_THRESHOLD: u32 = 5;
+pub const DEFAULT_LOGIN_LOCKOUT_BASE_SECONDS: i64 = 60;
+pub const MAX_LOGIN_LOCKOUT_SECONDS: i64 = 86_400;
+pub const DEFAULT_ACCOUNT_UNLOCK_URL: &str = "http://localhost:8000/unlock-account";
+// Applies to every route without a limit of its own
+pub const DEFAULT_RATE_LIMIT: RateLimit = RateLimit { requests: 300, window_seconds: 60 };
+// Proxies in front of the service that append to X-Forwarded-For. With none, the peer address is the client's.
+pub const DEFAULT_TRUSTED_PROXY_DEPTH: usize = 0;
+pub const DEFAULT_ADMIN_PAGE_SIZE: u64 = 20;
+pub const MAX_ADMIN_PAGE_SIZE: u64 = 100;
+// Shown to users by authenticator apps and passkey prompts
+pub const APP_NAME: &str = "Let's Get Rusty";
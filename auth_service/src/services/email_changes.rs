@@ -0,0 +1,110 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::user::Email;
+
+pub type EmailChangeStoreType = Arc<RwLock<dyn EmailChangeStore + Send + Sync>>;
+
+// This value determines how long the confirm and cancel links of an email change can be used for
+pub const EMAIL_CHANGE_TTL_SECONDS: i64 = 86_400; // 24 hours
+
+// Identifies a pending email change, carried in the `jti` claim of both its confirm and cancel tokens
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EmailChangeId(String);
+
+impl EmailChangeId {
+    pub fn parse(id: String) -> Result<Self, String> {
+        let valid_id = Uuid::parse_str(&id)
+            .map_err(|_| String::from("Invalid email change id format"))?;
+        Ok(Self(valid_id.to_string()))
+    }
+}
+
+impl Default for EmailChangeId {
+    fn default() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+}
+
+impl AsRef<str> for EmailChangeId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmailChange {
+    pub email: Email,
+    pub new_email: Email,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum EmailChangeStoreError {
+    ChangeNotFound,
+    UnexpectedError,
+}
+
+#[async_trait]
+pub trait EmailChangeStore {
+    async fn add_change(&mut self, id: EmailChangeId, change: EmailChange) -> Result<(), EmailChangeStoreError>;
+    // Confirming and cancelling both settle the change, so reading it also removes it
+    async fn take_change(&mut self, id: &EmailChangeId) -> Result<EmailChange, EmailChangeStoreError>;
+}
+
+#[derive(Default)]
+pub struct EmailChanges {
+    changes: HashMap<EmailChangeId, (EmailChange, i64)>,
+}
+
+#[async_trait]
+impl EmailChangeStore for EmailChanges {
+    async fn add_change(&mut self, id: EmailChangeId, change: EmailChange) -> Result<(), EmailChangeStoreError> {
+        let expires_at = Utc::now().timestamp() + EMAIL_CHANGE_TTL_SECONDS;
+        self.changes.insert(id, (change, expires_at));
+        Ok(())
+    }
+
+    async fn take_change(&mut self, id: &EmailChangeId) -> Result<EmailChange, EmailChangeStoreError> {
+        self.changes
+            .remove(id)
+            .filter(|(_, expires_at)| *expires_at > Utc::now().timestamp())
+            .map(|(change, _)| change)
+            .ok_or(EmailChangeStoreError::ChangeNotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change() -> EmailChange {
+        EmailChange {
+            email: Email::parse("old@example.com".to_owned()).unwrap(),
+            new_email: Email::parse("new@example.com".to_owned()).unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_changes_are_settled_once() {
+        let mut store = EmailChanges::default();
+        let id = EmailChangeId::default();
+
+        store.add_change(id.clone(), change()).await.unwrap();
+
+        assert_eq!(store.take_change(&id).await, Ok(change()));
+        assert_eq!(store.take_change(&id).await, Err(EmailChangeStoreError::ChangeNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_expired_change_is_not_found() {
+        let mut store = EmailChanges::default();
+        let id = EmailChangeId::default();
+        store.changes.insert(id.clone(), (change(), Utc::now().timestamp() - 1));
+
+        assert_eq!(store.take_change(&id).await, Err(EmailChangeStoreError::ChangeNotFound));
+    }
+}
@@ -0,0 +1,115 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::user::Email;
+
+use super::organizations::{OrgRole, OrganizationId};
+
+pub type InvitationStoreType = Arc<RwLock<dyn InvitationStore + Send + Sync>>;
+
+// This value determines how long an invitation link can be used for
+pub const INVITATION_TTL_SECONDS: i64 = 7 * 86_400; // 7 days
+
+// Identifies a pending invitation, carried in the `jti` claim of its signed token
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InvitationId(String);
+
+impl InvitationId {
+    pub fn parse(id: String) -> Result<Self, String> {
+        let valid_id = Uuid::parse_str(&id)
+            .map_err(|_| String::from("Invalid invitation id format"))?;
+        Ok(Self(valid_id.to_string()))
+    }
+}
+
+impl Default for InvitationId {
+    fn default() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+}
+
+impl AsRef<str> for InvitationId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invitation {
+    pub org_id: OrganizationId,
+    pub email: Email,
+    pub role: OrgRole,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum InvitationStoreError {
+    InvitationNotFound,
+    UnexpectedError,
+}
+
+#[async_trait]
+pub trait InvitationStore {
+    async fn add_invitation(&mut self, id: InvitationId, invitation: Invitation) -> Result<(), InvitationStoreError>;
+    // Invitations are single use, so reading one also removes it
+    async fn take_invitation(&mut self, id: &InvitationId) -> Result<Invitation, InvitationStoreError>;
+}
+
+#[derive(Default)]
+pub struct Invitations {
+    invitations: HashMap<InvitationId, (Invitation, i64)>,
+}
+
+#[async_trait]
+impl InvitationStore for Invitations {
+    async fn add_invitation(&mut self, id: InvitationId, invitation: Invitation) -> Result<(), InvitationStoreError> {
+        let expires_at = Utc::now().timestamp() + INVITATION_TTL_SECONDS;
+        self.invitations.insert(id, (invitation, expires_at));
+        Ok(())
+    }
+
+    async fn take_invitation(&mut self, id: &InvitationId) -> Result<Invitation, InvitationStoreError> {
+        self.invitations
+            .remove(id)
+            .filter(|(_, expires_at)| *expires_at > Utc::now().timestamp())
+            .map(|(invitation, _)| invitation)
+            .ok_or(InvitationStoreError::InvitationNotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invitation() -> Invitation {
+        Invitation {
+            org_id: OrganizationId::default(),
+            email: Email::parse("invitee@example.com".to_owned()).unwrap(),
+            role: OrgRole::Member,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invitations_are_single_use() {
+        let mut store = Invitations::default();
+        let id = InvitationId::default();
+        let invitation = invitation();
+
+        store.add_invitation(id.clone(), invitation.clone()).await.unwrap();
+
+        assert_eq!(store.take_invitation(&id).await, Ok(invitation));
+        assert_eq!(store.take_invitation(&id).await, Err(InvitationStoreError::InvitationNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_expired_invitation_is_not_found() {
+        let mut store = Invitations::default();
+        let id = InvitationId::default();
+        store.invitations.insert(id.clone(), (invitation(), Utc::now().timestamp() - 1));
+
+        assert_eq!(store.take_invitation(&id).await, Err(InvitationStoreError::InvitationNotFound));
+    }
+}
@@ -0,0 +1,422 @@
+use std::{path::Path, sync::Arc};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType
+    },
+    Algorithm, DecodingKey, EncodingKey
+};
+use chrono::Utc;
+use ring::{
+    digest::{digest, SHA256},
+    rsa::PublicKeyComponents,
+    signature::{Ed25519KeyPair, KeyPair, RsaKeyPair}
+};
+
+use tokio::sync::RwLock;
+
+use super::{
+    auth::{EMAIL_VERIFICATION_TTL_SECONDS, TOKEN_TTL_SECONDS},
+    constants::MAX_LOGIN_LOCKOUT_SECONDS,
+    email_changes::EMAIL_CHANGE_TTL_SECONDS,
+    invitations::INVITATION_TTL_SECONDS,
+    password_reset_tokens::PASSWORD_RESET_TTL_SECONDS
+};
+
+pub type KeyringType = Arc<RwLock<Keyring>>;
+
+#[derive(Debug, PartialEq)]
+pub enum KeyError {
+    UnsupportedAlgorithm,
+    InvalidKey,
+    UnreadableKeyFile,
+    KeyAlreadyActive,
+}
+
+// A key used to sign and verify JWT auth tokens, identified by the `kid` header it stamps on tokens
+pub struct SigningKey {
+    kid: String,
+    algorithm: Algorithm,
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    // Shared secrets have no public half and must never be published
+    jwk: Option<Jwk>,
+}
+
+impl SigningKey {
+    pub fn from_secret(kid: &str, secret: &[u8]) -> Self {
+        Self {
+            kid: kid.to_owned(),
+            algorithm: Algorithm::HS256,
+            encoding_key: EncodingKey::from_secret(secret),
+            decoding_key: DecodingKey::from_secret(secret),
+            jwk: None,
+        }
+    }
+
+    pub fn from_pem(kid: &str, algorithm: Algorithm, pem: &[u8]) -> Result<Self, KeyError> {
+        match algorithm {
+            Algorithm::RS256 => Self::from_rsa_pem(kid, pem),
+            Algorithm::EdDSA => Self::from_ed_pem(kid, pem),
+            _ => Err(KeyError::UnsupportedAlgorithm),
+        }
+    }
+
+    // Keys loaded from disk are named by their RFC 7638 thumbprint, so replacing the file always yields a new kid
+    pub fn from_pem_file(algorithm: Algorithm, path: impl AsRef<Path>) -> Result<Self, KeyError> {
+        let pem = std::fs::read(path).map_err(|_| KeyError::UnreadableKeyFile)?;
+        let key = Self::from_pem("", algorithm, &pem)?;
+        let kid = key.thumbprint().ok_or(KeyError::InvalidKey)?;
+        Ok(key.with_kid(kid))
+    }
+
+    // Accepts both PKCS#8 ("PRIVATE KEY") and PKCS#1 ("RSA PRIVATE KEY") encoded keys
+    fn from_rsa_pem(kid: &str, pem: &[u8]) -> Result<Self, KeyError> {
+        let der = pem::parse(pem).map_err(|_| KeyError::InvalidKey)?;
+
+        let key_pair = match der.tag() {
+            "PRIVATE KEY" => RsaKeyPair::from_pkcs8(der.contents()),
+            "RSA PRIVATE KEY" => RsaKeyPair::from_der(der.contents()),
+            _ => return Err(KeyError::InvalidKey),
+        }
+        .map_err(|_| KeyError::InvalidKey)?;
+
+        let components: PublicKeyComponents<Vec<u8>> = key_pair.public().into();
+        let n = URL_SAFE_NO_PAD.encode(&components.n);
+        let e = URL_SAFE_NO_PAD.encode(&components.e);
+
+        let encoding_key = EncodingKey::from_rsa_pem(pem).map_err(|_| KeyError::InvalidKey)?;
+        let decoding_key = DecodingKey::from_rsa_components(&n, &e).map_err(|_| KeyError::InvalidKey)?;
+
+        let jwk = Jwk {
+            common: public_key_parameters(kid, KeyAlgorithm::RS256),
+            algorithm: AlgorithmParameters::RSA(RSAKeyParameters {
+                key_type: RSAKeyType::RSA,
+                n,
+                e,
+            }),
+        };
+
+        Ok(Self {
+            kid: kid.to_owned(),
+            algorithm: Algorithm::RS256,
+            encoding_key,
+            decoding_key,
+            jwk: Some(jwk),
+        })
+    }
+
+    fn from_ed_pem(kid: &str, pem: &[u8]) -> Result<Self, KeyError> {
+        let der = pem::parse(pem).map_err(|_| KeyError::InvalidKey)?;
+
+        let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(der.contents())
+            .map_err(|_| KeyError::InvalidKey)?;
+        let x = URL_SAFE_NO_PAD.encode(key_pair.public_key().as_ref());
+
+        let encoding_key = EncodingKey::from_ed_pem(pem).map_err(|_| KeyError::InvalidKey)?;
+        let decoding_key = DecodingKey::from_ed_components(&x).map_err(|_| KeyError::InvalidKey)?;
+
+        let jwk = Jwk {
+            common: public_key_parameters(kid, KeyAlgorithm::EdDSA),
+            algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                key_type: OctetKeyPairType::OctetKeyPair,
+                curve: EllipticCurve::Ed25519,
+                x,
+            }),
+        };
+
+        Ok(Self {
+            kid: kid.to_owned(),
+            algorithm: Algorithm::EdDSA,
+            encoding_key,
+            decoding_key,
+            jwk: Some(jwk),
+        })
+    }
+
+    fn with_kid(mut self, kid: String) -> Self {
+        if let Some(jwk) = self.jwk.as_mut() {
+            jwk.common.key_id = Some(kid.clone());
+        }
+        self.kid = kid;
+        self
+    }
+
+    // SHA-256 over the required public members of the JWK, in lexicographic order
+    pub fn thumbprint(&self) -> Option<String> {
+        let members = match &self.jwk.as_ref()?.algorithm {
+            AlgorithmParameters::RSA(params) => {
+                format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#, params.e, params.n)
+            }
+            AlgorithmParameters::OctetKeyPair(params) => {
+                format!(r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#, params.x)
+            }
+            _ => return None,
+        };
+
+        Some(URL_SAFE_NO_PAD.encode(digest(&SHA256, members.as_bytes())))
+    }
+
+    pub fn kid(&self) -> &str {
+        &self.kid
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn jwk(&self) -> Option<&Jwk> {
+        self.jwk.as_ref()
+    }
+
+    pub fn encoding_key(&self) -> &EncodingKey {
+        &self.encoding_key
+    }
+
+    pub fn decoding_key(&self) -> &DecodingKey {
+        &self.decoding_key
+    }
+}
+
+fn public_key_parameters(kid: &str, algorithm: KeyAlgorithm) -> CommonParameters {
+    CommonParameters {
+        public_key_use: Some(PublicKeyUse::Signature),
+        key_algorithm: Some(algorithm),
+        key_id: Some(kid.to_owned()),
+        ..Default::default()
+    }
+}
+
+// The signing key also signs the tokens in emailed links, so a retired key has to verify them for as
+// long as the longest lived of those, not only for the lifetime of an auth token
+const RETIRED_KEY_TTL_SECONDS: i64 = max_of(&[
+    TOKEN_TTL_SECONDS,
+    PASSWORD_RESET_TTL_SECONDS,
+    EMAIL_VERIFICATION_TTL_SECONDS,
+    EMAIL_CHANGE_TTL_SECONDS,
+    INVITATION_TTL_SECONDS,
+    MAX_LOGIN_LOCKOUT_SECONDS,
+]);
+
+const fn max_of(values: &[i64]) -> i64 {
+    let mut max = 0;
+    let mut i = 0;
+    while i < values.len() {
+        if values[i] > max {
+            max = values[i];
+        }
+        i += 1;
+    }
+    max
+}
+
+// A key that no longer signs tokens, kept around until every token it signed has expired
+struct RetiredKey {
+    key: SigningKey,
+    retired_at: i64,
+}
+
+impl RetiredKey {
+    fn is_expired(&self, now: i64) -> bool {
+        self.retired_at + RETIRED_KEY_TTL_SECONDS <= now
+    }
+}
+
+// Holds the key new tokens are signed with, and resolves verification keys by `kid`
+pub struct Keyring {
+    active: SigningKey,
+    retired: Vec<RetiredKey>,
+}
+
+impl Keyring {
+    pub fn new(signing_key: SigningKey) -> Self {
+        Self { active: signing_key, retired: Vec::new() }
+    }
+
+    pub fn signing_key(&self) -> &SigningKey {
+        &self.active
+    }
+
+    pub fn verification_key(&self, kid: &str) -> Option<&SigningKey> {
+        if self.active.kid() == kid {
+            return Some(&self.active);
+        }
+
+        let now = Utc::now().timestamp();
+        self.retired
+            .iter()
+            .find(|retired| retired.key.kid() == kid && !retired.is_expired(now))
+            .map(|retired| &retired.key)
+    }
+
+    // Sign new tokens with `signing_key`. The previous key keeps verifying tokens until they expire.
+    pub fn rotate(&mut self, signing_key: SigningKey) -> Result<(), KeyError> {
+        if signing_key.kid() == self.active.kid() {
+            return Err(KeyError::KeyAlreadyActive);
+        }
+
+        let now = Utc::now().timestamp();
+        self.retired.retain(|retired| !retired.is_expired(now) && retired.key.kid() != signing_key.kid());
+
+        let previous = std::mem::replace(&mut self.active, signing_key);
+        self.retired.push(RetiredKey { key: previous, retired_at: now });
+
+        Ok(())
+    }
+
+    // Public keys for downstream services to verify tokens without calling /verify-token
+    pub fn jwks(&self) -> JwkSet {
+        let now = Utc::now().timestamp();
+        let retired = self
+            .retired
+            .iter()
+            .filter(|retired| !retired.is_expired(now))
+            .map(|retired| &retired.key);
+
+        JwkSet {
+            keys: std::iter::once(&self.active)
+                .chain(retired)
+                .filter_map(|key| key.jwk().cloned())
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        services::auth::{generate_email_verification_token, validate_email_verification_token},
+        user::Email
+    };
+
+    use super::*;
+
+    const RSA_PEM: &[u8] = include_bytes!("../../tests/fixtures/keys/rsa.pem");
+    const ED25519_PEM: &[u8] = include_bytes!("../../tests/fixtures/keys/ed25519.pem");
+
+    #[test]
+    fn test_rsa_key_from_pem() {
+        let key = SigningKey::from_pem("rsa", Algorithm::RS256, RSA_PEM).unwrap();
+        assert_eq!(key.kid(), "rsa");
+        assert_eq!(key.algorithm(), Algorithm::RS256);
+
+        let jwk = key.jwk().expect("RSA keys should be published");
+        assert_eq!(jwk.common.key_id.as_deref(), Some("rsa"));
+        assert!(matches!(jwk.algorithm, AlgorithmParameters::RSA(_)));
+    }
+
+    #[test]
+    fn test_ed25519_key_from_pem() {
+        let key = SigningKey::from_pem("ed", Algorithm::EdDSA, ED25519_PEM).unwrap();
+        assert_eq!(key.algorithm(), Algorithm::EdDSA);
+
+        let jwk = key.jwk().expect("Ed25519 keys should be published");
+        assert!(matches!(
+            &jwk.algorithm,
+            AlgorithmParameters::OctetKeyPair(params) if params.curve == EllipticCurve::Ed25519
+        ));
+    }
+
+    #[test]
+    fn test_key_from_pem_rejects_mismatched_input() {
+        assert_eq!(
+            SigningKey::from_pem("ed", Algorithm::EdDSA, RSA_PEM).err(),
+            Some(KeyError::InvalidKey)
+        );
+        assert_eq!(
+            SigningKey::from_pem("rsa", Algorithm::RS256, b"not a key").err(),
+            Some(KeyError::InvalidKey)
+        );
+        assert_eq!(
+            SigningKey::from_pem("es", Algorithm::ES256, ED25519_PEM).err(),
+            Some(KeyError::UnsupportedAlgorithm)
+        );
+    }
+
+    #[test]
+    fn test_keyring_does_not_publish_secrets() {
+        let keyring = Keyring::new(SigningKey::from_secret("secret", b"secret"));
+        assert_eq!(keyring.signing_key().kid(), "secret");
+        assert!(keyring.jwks().keys.is_empty());
+    }
+
+    #[test]
+    fn test_keyring_verification_key() {
+        let keyring = Keyring::new(SigningKey::from_pem("ed", Algorithm::EdDSA, ED25519_PEM).unwrap());
+        assert!(keyring.verification_key("ed").is_some());
+        assert!(keyring.verification_key("unknown").is_none());
+        assert!(keyring.jwks().find("ed").is_some());
+    }
+
+    #[test]
+    fn test_key_from_pem_file_is_named_by_thumbprint() {
+        let key = SigningKey::from_pem_file(Algorithm::EdDSA, "tests/fixtures/keys/ed25519.pem").unwrap();
+        assert_eq!(Some(key.kid().to_owned()), key.thumbprint());
+        assert_eq!(key.jwk().unwrap().common.key_id.as_deref(), Some(key.kid()));
+
+        assert_eq!(
+            SigningKey::from_pem_file(Algorithm::EdDSA, "tests/fixtures/keys/missing.pem").err(),
+            Some(KeyError::UnreadableKeyFile)
+        );
+    }
+
+    #[test]
+    fn test_keyring_rotation_keeps_retired_key_for_verification() {
+        let mut keyring = Keyring::new(SigningKey::from_pem("ed", Algorithm::EdDSA, ED25519_PEM).unwrap());
+        keyring.rotate(SigningKey::from_pem("rsa", Algorithm::RS256, RSA_PEM).unwrap()).unwrap();
+
+        assert_eq!(keyring.signing_key().kid(), "rsa");
+        assert!(keyring.verification_key("ed").is_some());
+        assert!(keyring.jwks().find("ed").is_some());
+        assert!(keyring.jwks().find("rsa").is_some());
+    }
+
+    #[test]
+    fn test_keyring_drops_retired_key_once_links_have_expired() {
+        let mut keyring = Keyring::new(SigningKey::from_pem("ed", Algorithm::EdDSA, ED25519_PEM).unwrap());
+        keyring.rotate(SigningKey::from_pem("rsa", Algorithm::RS256, RSA_PEM).unwrap()).unwrap();
+
+        // Links signed just before the rotation are still valid after auth tokens have expired,
+        // up until the longest lived of them has
+        keyring.retired[0].retired_at -= TOKEN_TTL_SECONDS;
+        assert!(keyring.verification_key("ed").is_some());
+
+        keyring.retired[0].retired_at -= RETIRED_KEY_TTL_SECONDS - TOKEN_TTL_SECONDS - 60;
+        assert!(keyring.verification_key("ed").is_some());
+
+        keyring.retired[0].retired_at -= 60;
+
+        assert!(keyring.verification_key("ed").is_none());
+        assert!(keyring.jwks().find("ed").is_none());
+
+        keyring.rotate(SigningKey::from_secret("secret", b"secret")).unwrap();
+        assert_eq!(keyring.retired.len(), 1);
+    }
+
+    #[test]
+    fn test_keyring_rejects_rotating_to_active_key() {
+        let mut keyring = Keyring::new(SigningKey::from_secret("secret", b"secret"));
+        assert_eq!(
+            keyring.rotate(SigningKey::from_secret("secret", b"other")).err(),
+            Some(KeyError::KeyAlreadyActive)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_links_signed_before_rotation_outlive_auth_tokens() {
+        let keyring: KeyringType = Arc::new(RwLock::new(Keyring::new(
+            SigningKey::from_pem("ed", Algorithm::EdDSA, ED25519_PEM).unwrap()
+        )));
+        let email = Email::parse(String::from("test@example.com")).unwrap();
+        let token = generate_email_verification_token(&email, keyring.clone()).await.unwrap();
+
+        keyring.write().await.rotate(SigningKey::from_pem("rsa", Algorithm::RS256, RSA_PEM).unwrap()).unwrap();
+
+        // Hours after the rotation, long after any auth token signed with the old key has expired
+        keyring.write().await.retired[0].retired_at -= EMAIL_VERIFICATION_TTL_SECONDS / 2;
+
+        assert_eq!(validate_email_verification_token(&token, keyring.clone()).await, Some(email));
+    }
+}
@@ -0,0 +1,155 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+
+use crate::user::Email;
+
+pub type LoginAttemptStoreType = Arc<RwLock<dyn LoginAttemptStore + Send + Sync>>;
+
+// Failed attempts and past lockouts are forgotten after this long without a failure
+pub const LOGIN_ATTEMPT_MEMORY_SECONDS: i64 = 86_400; // 24 hours
+
+// How many wrong passwords in a row lock an account, and for how long
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LockoutPolicy {
+    pub threshold: u32,
+    pub base_seconds: i64,
+    pub max_seconds: i64,
+}
+
+impl LockoutPolicy {
+    // Every lockout in a row lasts twice as long as the one before, up to the maximum
+    pub fn lockout_seconds(&self, lockouts: u32) -> i64 {
+        2i64.checked_pow(lockouts.saturating_sub(1))
+            .map_or(self.max_seconds, |factor| self.base_seconds.saturating_mul(factor))
+            .min(self.max_seconds)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LoginAttemptStoreError {
+    UnexpectedError,
+}
+
+#[async_trait]
+pub trait LoginAttemptStore {
+    // Unix timestamp the account stays locked until, `None` when it isn't locked
+    async fn get_locked_until(&self, email: &Email, now: i64) -> Result<Option<i64>, LoginAttemptStoreError>;
+    // Counts a wrong password against the account. Returns the end of the lockout when this failure starts one.
+    async fn record_failure(&mut self, email: &Email, policy: &LockoutPolicy, now: i64) -> Result<Option<i64>, LoginAttemptStoreError>;
+    // Forgets the failures and past lockouts of the account, and lifts a lockout in progress
+    async fn clear(&mut self, email: &Email) -> Result<(), LoginAttemptStoreError>;
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct AttemptRecord {
+    failures: u32,
+    lockouts: u32,
+    locked_until: Option<i64>,
+    last_failure_at: i64,
+}
+
+#[derive(Default)]
+pub struct LoginAttempts {
+    records: HashMap<Email, AttemptRecord>,
+}
+
+#[async_trait]
+impl LoginAttemptStore for LoginAttempts {
+    async fn get_locked_until(&self, email: &Email, now: i64) -> Result<Option<i64>, LoginAttemptStoreError> {
+        Ok(self.records
+            .get(email)
+            .and_then(|record| record.locked_until)
+            .filter(|locked_until| *locked_until > now))
+    }
+
+    async fn record_failure(&mut self, email: &Email, policy: &LockoutPolicy, now: i64) -> Result<Option<i64>, LoginAttemptStoreError> {
+        let record = self.records.entry(email.clone()).or_default();
+
+        if now - record.last_failure_at >= LOGIN_ATTEMPT_MEMORY_SECONDS {
+            *record = AttemptRecord::default();
+        }
+
+        record.failures += 1;
+        record.last_failure_at = now;
+
+        if record.failures < policy.threshold {
+            return Ok(None);
+        }
+
+        record.failures = 0;
+        record.lockouts += 1;
+
+        let locked_until = now + policy.lockout_seconds(record.lockouts);
+        record.locked_until = Some(locked_until);
+
+        Ok(Some(locked_until))
+    }
+
+    async fn clear(&mut self, email: &Email) -> Result<(), LoginAttemptStoreError> {
+        self.records.remove(email);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: LockoutPolicy = LockoutPolicy { threshold: 3, base_seconds: 60, max_seconds: 600 };
+
+    fn email() -> Email {
+        Email::parse("test@example.com".to_owned()).unwrap()
+    }
+
+    #[test]
+    fn test_lockout_seconds_back_off_exponentially() {
+        assert_eq!(POLICY.lockout_seconds(1), 60);
+        assert_eq!(POLICY.lockout_seconds(2), 120);
+        assert_eq!(POLICY.lockout_seconds(4), 480);
+        assert_eq!(POLICY.lockout_seconds(5), 600);
+        assert_eq!(POLICY.lockout_seconds(200), 600);
+    }
+
+    #[tokio::test]
+    async fn test_lock_after_threshold() {
+        let mut store = LoginAttempts::default();
+        let email = email();
+
+        assert_eq!(store.record_failure(&email, &POLICY, 1000).await, Ok(None));
+        assert_eq!(store.record_failure(&email, &POLICY, 1001).await, Ok(None));
+        assert_eq!(store.get_locked_until(&email, 1001).await, Ok(None));
+
+        assert_eq!(store.record_failure(&email, &POLICY, 1002).await, Ok(Some(1062)));
+        assert_eq!(store.get_locked_until(&email, 1002).await, Ok(Some(1062)));
+        assert_eq!(store.get_locked_until(&email, 1062).await, Ok(None));
+
+        // The next lockout in a row lasts twice as long
+        for now in 1100..1102 {
+            assert_eq!(store.record_failure(&email, &POLICY, now).await, Ok(None));
+        }
+        assert_eq!(store.record_failure(&email, &POLICY, 1102).await, Ok(Some(1222)));
+    }
+
+    #[tokio::test]
+    async fn test_failures_are_forgotten() {
+        let mut store = LoginAttempts::default();
+        let email = email();
+
+        for now in 0..3 {
+            store.record_failure(&email, &POLICY, now).await.unwrap();
+        }
+
+        // A day later the account starts over with the shortest lockout
+        let later = 2 + LOGIN_ATTEMPT_MEMORY_SECONDS;
+        for now in later..later + 2 {
+            assert_eq!(store.record_failure(&email, &POLICY, now).await, Ok(None));
+        }
+        assert_eq!(store.record_failure(&email, &POLICY, later + 2).await, Ok(Some(later + 62)));
+
+        store.clear(&email).await.unwrap();
+        assert_eq!(store.get_locked_until(&email, later + 2).await, Ok(None));
+        assert_eq!(store.record_failure(&email, &POLICY, later + 3).await, Ok(None));
+    }
+}
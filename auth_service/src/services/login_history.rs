@@ -0,0 +1,71 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+
+use crate::user::Email;
+
+use super::client_info::ClientInfo;
+
+pub type LoginHistoryStoreType = Arc<RwLock<dyn LoginHistoryStore + Send + Sync>>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoginEvent {
+    // Unix timestamp of the login
+    pub logged_in_at: i64,
+    pub client: ClientInfo,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LoginHistoryStoreError {
+    UserNotFound,
+    UnexpectedError,
+}
+
+#[async_trait]
+pub trait LoginHistoryStore {
+    async fn record_login(&mut self, email: &Email, event: LoginEvent) -> Result<(), LoginHistoryStoreError>;
+    // Oldest first
+    async fn get_logins(&self, email: &Email) -> Result<Vec<LoginEvent>, LoginHistoryStoreError>;
+}
+
+#[derive(Default)]
+pub struct LoginHistory {
+    events: HashMap<Email, Vec<LoginEvent>>,
+}
+
+#[async_trait]
+impl LoginHistoryStore for LoginHistory {
+    async fn record_login(&mut self, email: &Email, event: LoginEvent) -> Result<(), LoginHistoryStoreError> {
+        self.events.entry(email.clone()).or_default().push(event);
+        Ok(())
+    }
+
+    async fn get_logins(&self, email: &Email) -> Result<Vec<LoginEvent>, LoginHistoryStoreError> {
+        Ok(self.events.get(email).cloned().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_logins_are_kept_per_user_in_order() {
+        let mut store = LoginHistory::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let other = Email::parse("other@example.com".to_owned()).unwrap();
+
+        let first = LoginEvent { logged_in_at: 1, client: ClientInfo::default() };
+        let second = LoginEvent {
+            logged_in_at: 2,
+            client: ClientInfo { ip: Some("127.0.0.1".to_owned()), user_agent: None },
+        };
+
+        store.record_login(&email, first.clone()).await.unwrap();
+        store.record_login(&email, second.clone()).await.unwrap();
+
+        assert_eq!(store.get_logins(&email).await, Ok(vec![first, second]));
+        assert_eq!(store.get_logins(&other).await, Ok(vec![]));
+    }
+}
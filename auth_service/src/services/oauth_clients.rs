@@ -0,0 +1,259 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use rand::{distributions::Alphanumeric, Rng};
+use tokio::sync::RwLock;
+use url::{Host, Url};
+use uuid::Uuid;
+
+pub type OAuthClientStoreType = Arc<RwLock<dyn OAuthClientStore + Send + Sync>>;
+
+const CLIENT_SECRET_LENGTH: usize = 48;
+
+// The `client_id` a registered OAuth client identifies itself with
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OAuthClientId(String);
+
+impl OAuthClientId {
+    pub fn parse(id: String) -> Result<Self, String> {
+        let valid_id = Uuid::parse_str(&id)
+            .map_err(|_| String::from("Invalid client id format"))?;
+        Ok(Self(valid_id.to_string()))
+    }
+}
+
+impl Default for OAuthClientId {
+    fn default() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+}
+
+impl AsRef<str> for OAuthClientId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// Handed to a confidential client once when it's registered, only its hash is kept afterwards
+#[derive(Debug, Clone, PartialEq)]
+pub struct OAuthClientSecret(String);
+
+impl OAuthClientSecret {
+    pub fn parse(secret: String) -> Result<Self, String> {
+        if secret.len() == CLIENT_SECRET_LENGTH && secret.chars().all(|c| c.is_ascii_alphanumeric()) {
+            Ok(Self(secret))
+        } else {
+            Err(String::from("Invalid client secret format"))
+        }
+    }
+}
+
+impl Default for OAuthClientSecret {
+    fn default() -> Self {
+        let secret = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(CLIENT_SECRET_LENGTH)
+            .map(char::from)
+            .collect();
+        Self(secret)
+    }
+}
+
+impl AsRef<str> for OAuthClientSecret {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// Where authorization codes may be sent. Requests have to name one of the client's redirect URIs
+// exactly as it was registered, so it's kept the way it was written rather than normalized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedirectUri(String);
+
+impl RedirectUri {
+    // Codes only travel over TLS, except to loopback addresses, which is how native apps receive them
+    pub fn parse(uri: String) -> Result<Self, String> {
+        let url = Url::parse(&uri).map_err(|_| String::from("Redirect URI must be an absolute URL"))?;
+
+        if url.fragment().is_some() {
+            return Err(String::from("Redirect URI must not have a fragment"));
+        }
+
+        let loopback = match url.host() {
+            Some(Host::Domain(domain)) => domain == "localhost",
+            Some(Host::Ipv4(ip)) => ip.is_loopback(),
+            Some(Host::Ipv6(ip)) => ip.is_loopback(),
+            None => false,
+        };
+
+        match url.scheme() {
+            "https" => Ok(Self(uri)),
+            "http" if loopback => Ok(Self(uri)),
+            _ => Err(String::from("Redirect URI must use https, or http on a loopback address")),
+        }
+    }
+}
+
+impl AsRef<str> for RedirectUri {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OAuthClient {
+    pub id: OAuthClientId,
+    pub name: String,
+    pub redirect_uris: Vec<RedirectUri>,
+    // Confidential clients authenticate with their secret at /token. Public ones, like SPAs and
+    // native apps, can't keep a secret and rely on PKCE alone.
+    pub confidential: bool,
+    pub created_at: i64,
+}
+
+impl OAuthClient {
+    pub fn allows_redirect_uri(&self, uri: &str) -> bool {
+        self.redirect_uris.iter().any(|registered| registered.as_ref() == uri)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum OAuthClientStoreError {
+    ClientNotFound,
+    InvalidSecret,
+    UnexpectedError,
+}
+
+#[async_trait]
+pub trait OAuthClientStore {
+    // Confidential clients are added with their secret
+    async fn add_client(&mut self, client: OAuthClient, secret: Option<OAuthClientSecret>) -> Result<(), OAuthClientStoreError>;
+    async fn get_client(&self, id: &OAuthClientId) -> Result<OAuthClient, OAuthClientStoreError>;
+    // Oldest first
+    async fn list_clients(&self) -> Result<Vec<OAuthClient>, OAuthClientStoreError>;
+    // Also ends the sessions the client started for its users
+    async fn delete_client(&mut self, id: &OAuthClientId) -> Result<(), OAuthClientStoreError>;
+    async fn validate_secret(&self, id: &OAuthClientId, secret: &OAuthClientSecret) -> Result<(), OAuthClientStoreError>;
+}
+
+#[derive(Default)]
+pub struct OAuthClients {
+    clients: HashMap<OAuthClientId, (OAuthClient, Option<OAuthClientSecret>)>,
+}
+
+#[async_trait]
+impl OAuthClientStore for OAuthClients {
+    async fn add_client(&mut self, client: OAuthClient, secret: Option<OAuthClientSecret>) -> Result<(), OAuthClientStoreError> {
+        self.clients.insert(client.id.clone(), (client, secret));
+        Ok(())
+    }
+
+    async fn get_client(&self, id: &OAuthClientId) -> Result<OAuthClient, OAuthClientStoreError> {
+        self.clients
+            .get(id)
+            .map(|(client, _)| client.clone())
+            .ok_or(OAuthClientStoreError::ClientNotFound)
+    }
+
+    async fn list_clients(&self) -> Result<Vec<OAuthClient>, OAuthClientStoreError> {
+        let mut clients: Vec<OAuthClient> = self.clients.values().map(|(client, _)| client.clone()).collect();
+        clients.sort_by_key(|client| client.created_at);
+        Ok(clients)
+    }
+
+    async fn delete_client(&mut self, id: &OAuthClientId) -> Result<(), OAuthClientStoreError> {
+        self.clients
+            .remove(id)
+            .map(|_| ())
+            .ok_or(OAuthClientStoreError::ClientNotFound)
+    }
+
+    async fn validate_secret(&self, id: &OAuthClientId, secret: &OAuthClientSecret) -> Result<(), OAuthClientStoreError> {
+        match self.clients.get(id) {
+            Some((_, Some(stored))) if stored == secret => Ok(()),
+            Some(_) => Err(OAuthClientStoreError::InvalidSecret),
+            None => Err(OAuthClientStoreError::ClientNotFound),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redirect_uri_must_be_safe_to_send_codes_to() {
+        let valid = [
+            "https://app.example.com/callback",
+            "https://app.example.com/callback?tenant=acme",
+            "http://localhost:8080/callback",
+            "http://127.0.0.1:51004/",
+            "http://[::1]/callback",
+        ];
+        for uri in valid {
+            assert!(RedirectUri::parse(uri.to_owned()).is_ok(), "Refused {:?}", uri);
+        }
+
+        let invalid = [
+            "/callback",
+            "http://app.example.com/callback",
+            "https://app.example.com/callback#token",
+            "javascript:alert(1)",
+            "com.example.app:/callback",
+        ];
+        for uri in invalid {
+            assert!(RedirectUri::parse(uri.to_owned()).is_err(), "Accepted {:?}", uri);
+        }
+    }
+
+    #[test]
+    fn test_redirect_uris_match_exactly() {
+        let client = OAuthClient {
+            id: OAuthClientId::default(),
+            name: String::from("Example"),
+            redirect_uris: vec![RedirectUri::parse(String::from("https://app.example.com/callback")).unwrap()],
+            confidential: false,
+            created_at: 0,
+        };
+
+        assert!(client.allows_redirect_uri("https://app.example.com/callback"));
+
+        for uri in [
+            "https://app.example.com/callback/",
+            "https://app.example.com/callback?next=/",
+            "https://APP.example.com/callback",
+            "https://app.example.com/Callback",
+            "https://app.example.com",
+        ] {
+            assert!(!client.allows_redirect_uri(uri), "Allowed {:?}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate_secret() {
+        let mut store = OAuthClients::default();
+        let secret = OAuthClientSecret::default();
+        let confidential = OAuthClient {
+            id: OAuthClientId::default(),
+            name: String::from("Backend"),
+            redirect_uris: vec![],
+            confidential: true,
+            created_at: 0,
+        };
+        let public = OAuthClient { id: OAuthClientId::default(), confidential: false, ..confidential.clone() };
+
+        store.add_client(confidential.clone(), Some(secret.clone())).await.unwrap();
+        store.add_client(public.clone(), None).await.unwrap();
+
+        assert_eq!(store.validate_secret(&confidential.id, &secret).await, Ok(()));
+        assert_eq!(
+            store.validate_secret(&confidential.id, &OAuthClientSecret::default()).await,
+            Err(OAuthClientStoreError::InvalidSecret)
+        );
+        assert_eq!(store.validate_secret(&public.id, &secret).await, Err(OAuthClientStoreError::InvalidSecret));
+        assert_eq!(
+            store.validate_secret(&OAuthClientId::default(), &secret).await,
+            Err(OAuthClientStoreError::ClientNotFound)
+        );
+    }
+}
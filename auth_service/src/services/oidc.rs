@@ -0,0 +1,129 @@
+use serde::{Deserialize, Serialize};
+
+use crate::user::User;
+
+// The scopes a client can ask for at /authorize, any others it names are ignored.
+// There's no profile data beyond the email address, so `profile` is granted but releases no claims.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    OpenId,
+    Email,
+    Profile,
+}
+
+pub const SUPPORTED_SCOPES: [Scope; 3] = [Scope::OpenId, Scope::Email, Scope::Profile];
+
+impl Scope {
+    pub fn parse(scope: String) -> Result<Self, String> {
+        SUPPORTED_SCOPES
+            .into_iter()
+            .find(|supported| supported.as_str() == scope)
+            .ok_or(String::from("Unsupported scope"))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::OpenId => "openid",
+            Scope::Email => "email",
+            Scope::Profile => "profile",
+        }
+    }
+}
+
+// The supported scopes named in a space separated `scope` parameter, without duplicates
+pub fn parse_scopes(scope: &str) -> Vec<Scope> {
+    SUPPORTED_SCOPES
+        .into_iter()
+        .filter(|supported| scope.split(' ').any(|requested| requested == supported.as_str()))
+        .collect()
+}
+
+pub fn format_scopes(scopes: &[Scope]) -> String {
+    scopes.iter().map(Scope::as_str).collect::<Vec<_>>().join(" ")
+}
+
+// How a user proved who they are, named the way RFC 8176 names them in the `amr` claim
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMethod {
+    Password,
+    // An emailed 2FA code, a TOTP code or a recovery code
+    OneTimeCode,
+    // WebAuthn, whether as second factor or for a passwordless login
+    Passkey,
+}
+
+impl AuthMethod {
+    pub fn parse(method: String) -> Result<Self, String> {
+        match method.as_str() {
+            "pwd" => Ok(AuthMethod::Password),
+            "otp" => Ok(AuthMethod::OneTimeCode),
+            "hwk" => Ok(AuthMethod::Passkey),
+            _ => Err(String::from("Unknown authentication method")),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuthMethod::Password => "pwd",
+            AuthMethod::OneTimeCode => "otp",
+            AuthMethod::Passkey => "hwk",
+        }
+    }
+}
+
+// The `amr` claim of the methods, with `mfa` added when more than one factor was used
+pub fn amr(methods: &[AuthMethod]) -> Vec<String> {
+    let mut amr: Vec<String> = methods.iter().map(|method| method.as_str().to_owned()).collect();
+
+    if methods.len() > 1 {
+        amr.push(String::from("mfa"));
+    }
+
+    amr
+}
+
+// Claims about the user released to a client, as returned by /userinfo and included in ID tokens
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct UserInfo {
+    pub sub: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email_verified: Option<bool>,
+}
+
+impl UserInfo {
+    pub fn new(user: &User, scopes: &[Scope]) -> Self {
+        let email = scopes.contains(&Scope::Email);
+
+        Self {
+            sub: user.id.as_ref().to_owned(),
+            email: email.then(|| user.email.as_ref().to_owned()),
+            email_verified: email.then_some(user.email_verified),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_scopes_keeps_supported_scopes_once() {
+        assert_eq!(parse_scopes("openid email"), vec![Scope::OpenId, Scope::Email]);
+        assert_eq!(parse_scopes("email offline_access email openid"), vec![Scope::OpenId, Scope::Email]);
+        assert_eq!(parse_scopes("openidemail"), vec![]);
+        assert_eq!(format_scopes(&parse_scopes("profile email openid")), "openid email profile");
+    }
+
+    #[test]
+    fn test_amr_adds_mfa_for_several_factors() {
+        assert_eq!(amr(&[AuthMethod::Password]), vec!["pwd"]);
+        assert_eq!(amr(&[AuthMethod::Passkey]), vec!["hwk"]);
+        assert_eq!(amr(&[AuthMethod::Password, AuthMethod::OneTimeCode]), vec!["pwd", "otp", "mfa"]);
+
+        for method in [AuthMethod::Password, AuthMethod::OneTimeCode, AuthMethod::Passkey] {
+            assert_eq!(AuthMethod::parse(method.as_str().to_owned()), Ok(method));
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::user::UserId;
+
+pub type OrganizationStoreType = Arc<RwLock<dyn OrganizationStore + Send + Sync>>;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct OrganizationId(String);
+
+impl OrganizationId {
+    pub fn parse(id: String) -> Result<Self, String> {
+        let valid_id = Uuid::parse_str(&id)
+            .map_err(|_| String::from("Invalid organization id format"))?;
+        Ok(Self(valid_id.to_string()))
+    }
+}
+
+impl Default for OrganizationId {
+    fn default() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+}
+
+impl AsRef<str> for OrganizationId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Organization {
+    pub id: OrganizationId,
+    pub name: String,
+    // Unix timestamp of the creation
+    pub created_at: i64,
+}
+
+// What a member may do within one organization, persisted in the `organization_members.role` column
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrgRole {
+    Owner,
+    Admin,
+    Member,
+}
+
+impl OrgRole {
+    pub fn parse(role: &str) -> Result<Self, String> {
+        match role {
+            "owner" => Ok(Self::Owner),
+            "admin" => Ok(Self::Admin),
+            "member" => Ok(Self::Member),
+            _ => Err(format!("{} is not a valid organization role.", role)),
+        }
+    }
+
+    // Owners and admins can invite, but only owners can make someone else an owner
+    pub fn can_invite(&self, role: OrgRole) -> bool {
+        match self {
+            Self::Owner => true,
+            Self::Admin => role != Self::Owner,
+            Self::Member => false,
+        }
+    }
+}
+
+impl AsRef<str> for OrgRole {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Owner => "owner",
+            Self::Admin => "admin",
+            Self::Member => "member",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Membership {
+    pub organization: Organization,
+    pub role: OrgRole,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum OrganizationStoreError {
+    OrganizationNotFound,
+    MembershipNotFound,
+    UnexpectedError,
+}
+
+#[async_trait]
+pub trait OrganizationStore {
+    async fn add_organization(&mut self, organization: Organization) -> Result<(), OrganizationStoreError>;
+    // Joining an organization the user already belongs to keeps their current role
+    async fn add_member(&mut self, org_id: &OrganizationId, user_id: &UserId, role: OrgRole) -> Result<(), OrganizationStoreError>;
+    async fn get_membership(&self, org_id: &OrganizationId, user_id: &UserId) -> Result<Membership, OrganizationStoreError>;
+    // Sorted by organization name
+    async fn get_memberships(&self, user_id: &UserId) -> Result<Vec<Membership>, OrganizationStoreError>;
+    async fn set_active_organization(&mut self, user_id: &UserId, org_id: &OrganizationId) -> Result<(), OrganizationStoreError>;
+    // The membership tokens are issued for, if the user has picked an organization
+    async fn get_active_membership(&self, user_id: &UserId) -> Result<Option<Membership>, OrganizationStoreError>;
+}
+
+#[derive(Default)]
+pub struct Organizations {
+    organizations: HashMap<OrganizationId, Organization>,
+    members: HashMap<(OrganizationId, UserId), OrgRole>,
+    active: HashMap<UserId, OrganizationId>,
+}
+
+#[async_trait]
+impl OrganizationStore for Organizations {
+    async fn add_organization(&mut self, organization: Organization) -> Result<(), OrganizationStoreError> {
+        self.organizations.insert(organization.id.clone(), organization);
+        Ok(())
+    }
+
+    async fn add_member(&mut self, org_id: &OrganizationId, user_id: &UserId, role: OrgRole) -> Result<(), OrganizationStoreError> {
+        if !self.organizations.contains_key(org_id) {
+            return Err(OrganizationStoreError::OrganizationNotFound);
+        }
+
+        self.members.entry((org_id.clone(), user_id.clone())).or_insert(role);
+        Ok(())
+    }
+
+    async fn get_membership(&self, org_id: &OrganizationId, user_id: &UserId) -> Result<Membership, OrganizationStoreError> {
+        let role = self.members
+            .get(&(org_id.clone(), user_id.clone()))
+            .ok_or(OrganizationStoreError::MembershipNotFound)?;
+
+        let organization = self.organizations
+            .get(org_id)
+            .ok_or(OrganizationStoreError::OrganizationNotFound)?;
+
+        Ok(Membership { organization: organization.clone(), role: *role })
+    }
+
+    async fn get_memberships(&self, user_id: &UserId) -> Result<Vec<Membership>, OrganizationStoreError> {
+        let mut memberships: Vec<Membership> = self.members
+            .iter()
+            .filter(|((_, member), _)| member == user_id)
+            .filter_map(|((org_id, _), role)| {
+                self.organizations
+                    .get(org_id)
+                    .map(|organization| Membership { organization: organization.clone(), role: *role })
+            })
+            .collect();
+
+        memberships.sort_by(|a, b| a.organization.name.cmp(&b.organization.name));
+        Ok(memberships)
+    }
+
+    async fn set_active_organization(&mut self, user_id: &UserId, org_id: &OrganizationId) -> Result<(), OrganizationStoreError> {
+        if !self.members.contains_key(&(org_id.clone(), user_id.clone())) {
+            return Err(OrganizationStoreError::MembershipNotFound);
+        }
+
+        self.active.insert(user_id.clone(), org_id.clone());
+        Ok(())
+    }
+
+    async fn get_active_membership(&self, user_id: &UserId) -> Result<Option<Membership>, OrganizationStoreError> {
+        match self.active.get(user_id) {
+            Some(org_id) => match self.get_membership(org_id, user_id).await {
+                Ok(membership) => Ok(Some(membership)),
+                Err(OrganizationStoreError::MembershipNotFound) => Ok(None),
+                Err(e) => Err(e),
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn organization(name: &str) -> Organization {
+        Organization { id: OrganizationId::default(), name: name.to_owned(), created_at: 0 }
+    }
+
+    #[test]
+    fn test_can_invite() {
+        assert!(OrgRole::Owner.can_invite(OrgRole::Owner));
+        assert!(OrgRole::Admin.can_invite(OrgRole::Admin));
+        assert!(!OrgRole::Admin.can_invite(OrgRole::Owner));
+        assert!(!OrgRole::Member.can_invite(OrgRole::Member));
+    }
+
+    #[tokio::test]
+    async fn test_memberships() {
+        let mut store = Organizations::default();
+        let user_id = UserId::default();
+        let acme = organization("Acme");
+        let globex = organization("Globex");
+
+        assert_eq!(
+            store.add_member(&acme.id, &user_id, OrgRole::Owner).await,
+            Err(OrganizationStoreError::OrganizationNotFound)
+        );
+
+        store.add_organization(globex.clone()).await.unwrap();
+        store.add_organization(acme.clone()).await.unwrap();
+        store.add_member(&globex.id, &user_id, OrgRole::Member).await.unwrap();
+        store.add_member(&acme.id, &user_id, OrgRole::Owner).await.unwrap();
+
+        // Joining again doesn't change the role
+        store.add_member(&acme.id, &user_id, OrgRole::Member).await.unwrap();
+        assert_eq!(store.get_membership(&acme.id, &user_id).await.unwrap().role, OrgRole::Owner);
+
+        let memberships = store.get_memberships(&user_id).await.unwrap();
+        assert_eq!(
+            memberships.iter().map(|m| (m.organization.name.as_str(), m.role)).collect::<Vec<_>>(),
+            vec![("Acme", OrgRole::Owner), ("Globex", OrgRole::Member)]
+        );
+
+        assert_eq!(store.get_memberships(&UserId::default()).await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn test_active_organization() {
+        let mut store = Organizations::default();
+        let user_id = UserId::default();
+        let acme = organization("Acme");
+        let globex = organization("Globex");
+
+        store.add_organization(acme.clone()).await.unwrap();
+        store.add_organization(globex.clone()).await.unwrap();
+        store.add_member(&acme.id, &user_id, OrgRole::Admin).await.unwrap();
+
+        assert_eq!(store.get_active_membership(&user_id).await, Ok(None));
+
+        assert_eq!(
+            store.set_active_organization(&user_id, &globex.id).await,
+            Err(OrganizationStoreError::MembershipNotFound)
+        );
+
+        store.set_active_organization(&user_id, &acme.id).await.unwrap();
+        let active = store.get_active_membership(&user_id).await.unwrap().unwrap();
+        assert_eq!(active.organization, acme);
+        assert_eq!(active.role, OrgRole::Admin);
+    }
+}
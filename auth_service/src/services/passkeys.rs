@@ -0,0 +1,160 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::RngCore;
+use tokio::sync::RwLock;
+
+use crate::user::Email;
+
+pub type PasskeyStoreType = Arc<RwLock<dyn PasskeyStore + Send + Sync>>;
+
+// Opaque WebAuthn user.id, shared by all of a user's passkeys. It must not contain the email.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserHandle(String);
+
+impl UserHandle {
+    pub fn parse(handle: String) -> Result<Self, String> {
+        match URL_SAFE_NO_PAD.decode(&handle) {
+            Ok(bytes) if !bytes.is_empty() && bytes.len() <= 64 => Ok(Self(handle)),
+            _ => Err(String::from("Invalid user handle")),
+        }
+    }
+}
+
+impl Default for UserHandle {
+    fn default() -> Self {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        Self(URL_SAFE_NO_PAD.encode(bytes))
+    }
+}
+
+impl AsRef<str> for UserHandle {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Passkey {
+    // base64url encoded, as it appears in PublicKeyCredential.id
+    pub credential_id: String,
+    pub email: Email,
+    pub user_handle: UserHandle,
+    // COSE_Key encoded public key
+    pub public_key: Vec<u8>,
+    pub sign_count: u32,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PasskeyStoreError {
+    PasskeyNotFound,
+    PasskeyAlreadyExists,
+    UnexpectedError,
+}
+
+#[async_trait]
+pub trait PasskeyStore {
+    async fn add_passkey(&mut self, passkey: Passkey) -> Result<(), PasskeyStoreError>;
+    async fn get_passkey(&self, credential_id: &str) -> Result<Passkey, PasskeyStoreError>;
+    async fn get_passkeys(&self, email: &Email) -> Result<Vec<Passkey>, PasskeyStoreError>;
+    async fn update_sign_count(&mut self, credential_id: &str, sign_count: u32) -> Result<(), PasskeyStoreError>;
+}
+
+#[derive(Default)]
+pub struct Passkeys {
+    passkeys: HashMap<String, Passkey>,
+}
+
+#[async_trait]
+impl PasskeyStore for Passkeys {
+    async fn add_passkey(&mut self, passkey: Passkey) -> Result<(), PasskeyStoreError> {
+        if self.passkeys.contains_key(&passkey.credential_id) {
+            return Err(PasskeyStoreError::PasskeyAlreadyExists);
+        }
+
+        self.passkeys.insert(passkey.credential_id.clone(), passkey);
+        Ok(())
+    }
+
+    async fn get_passkey(&self, credential_id: &str) -> Result<Passkey, PasskeyStoreError> {
+        self.passkeys
+            .get(credential_id)
+            .cloned()
+            .ok_or(PasskeyStoreError::PasskeyNotFound)
+    }
+
+    async fn get_passkeys(&self, email: &Email) -> Result<Vec<Passkey>, PasskeyStoreError> {
+        Ok(self
+            .passkeys
+            .values()
+            .filter(|passkey| passkey.email == *email)
+            .cloned()
+            .collect())
+    }
+
+    async fn update_sign_count(&mut self, credential_id: &str, sign_count: u32) -> Result<(), PasskeyStoreError> {
+        let passkey = self
+            .passkeys
+            .get_mut(credential_id)
+            .ok_or(PasskeyStoreError::PasskeyNotFound)?;
+
+        passkey.sign_count = sign_count;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn passkey(credential_id: &str, email: &str) -> Passkey {
+        Passkey {
+            credential_id: credential_id.to_owned(),
+            email: Email::parse(email.to_owned()).unwrap(),
+            user_handle: UserHandle::default(),
+            public_key: vec![1, 2, 3],
+            sign_count: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_and_get_passkey() {
+        let mut store = Passkeys::default();
+        let key = passkey("cred", "test@example.com");
+
+        assert_eq!(store.add_passkey(key.clone()).await, Ok(()));
+        assert_eq!(store.add_passkey(key.clone()).await, Err(PasskeyStoreError::PasskeyAlreadyExists));
+        assert_eq!(store.get_passkey("cred").await, Ok(key));
+        assert_eq!(store.get_passkey("missing").await, Err(PasskeyStoreError::PasskeyNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_get_passkeys_for_user() {
+        let mut store = Passkeys::default();
+        store.add_passkey(passkey("one", "test@example.com")).await.unwrap();
+        store.add_passkey(passkey("two", "test@example.com")).await.unwrap();
+        store.add_passkey(passkey("three", "other@example.com")).await.unwrap();
+
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        assert_eq!(store.get_passkeys(&email).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_update_sign_count() {
+        let mut store = Passkeys::default();
+        store.add_passkey(passkey("cred", "test@example.com")).await.unwrap();
+
+        assert_eq!(store.update_sign_count("cred", 5).await, Ok(()));
+        assert_eq!(store.get_passkey("cred").await.unwrap().sign_count, 5);
+        assert_eq!(store.update_sign_count("missing", 5).await, Err(PasskeyStoreError::PasskeyNotFound));
+    }
+
+    #[test]
+    fn test_user_handle_parse() {
+        let handle = UserHandle::default();
+        assert_eq!(UserHandle::parse(handle.as_ref().to_owned()), Ok(handle));
+        assert!(UserHandle::parse(String::new()).is_err());
+    }
+}
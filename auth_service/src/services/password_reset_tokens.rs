@@ -0,0 +1,124 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::user::Email;
+
+pub type PasswordResetTokenStoreType = Arc<RwLock<dyn PasswordResetTokenStore + Send + Sync>>;
+
+// This value determines how long a password reset link can be used for
+pub const PASSWORD_RESET_TTL_SECONDS: i64 = 900; // 15 minutes
+
+// Identifies a single reset link, carried in the `jti` claim of the signed reset token
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PasswordResetId(String);
+
+impl PasswordResetId {
+    pub fn parse(id: String) -> Result<Self, String> {
+        let valid_id = Uuid::parse_str(&id)
+            .map_err(|_| String::from("Invalid password reset id format"))?;
+        Ok(Self(valid_id.to_string()))
+    }
+}
+
+impl Default for PasswordResetId {
+    fn default() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+}
+
+impl AsRef<str> for PasswordResetId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PasswordResetTokenStoreError {
+    TokenNotFound,
+    UnexpectedError,
+}
+
+#[async_trait]
+pub trait PasswordResetTokenStore {
+    async fn add_token(&mut self, id: PasswordResetId, email: Email) -> Result<(), PasswordResetTokenStoreError>;
+    // Reset links are single use, so reading one also removes it
+    async fn take_token(&mut self, id: &PasswordResetId) -> Result<Email, PasswordResetTokenStoreError>;
+    // Drops every pending reset link of the address, e.g. when the account moves to another one
+    async fn remove_user_tokens(&mut self, email: &Email) -> Result<(), PasswordResetTokenStoreError>;
+}
+
+#[derive(Default)]
+pub struct PasswordResetTokens {
+    tokens: HashMap<PasswordResetId, (Email, i64)>,
+}
+
+#[async_trait]
+impl PasswordResetTokenStore for PasswordResetTokens {
+    async fn add_token(&mut self, id: PasswordResetId, email: Email) -> Result<(), PasswordResetTokenStoreError> {
+        let expires_at = Utc::now().timestamp() + PASSWORD_RESET_TTL_SECONDS;
+        self.tokens.insert(id, (email, expires_at));
+        Ok(())
+    }
+
+    async fn take_token(&mut self, id: &PasswordResetId) -> Result<Email, PasswordResetTokenStoreError> {
+        self.tokens
+            .remove(id)
+            .filter(|(_, expires_at)| *expires_at > Utc::now().timestamp())
+            .map(|(email, _)| email)
+            .ok_or(PasswordResetTokenStoreError::TokenNotFound)
+    }
+
+    async fn remove_user_tokens(&mut self, email: &Email) -> Result<(), PasswordResetTokenStoreError> {
+        self.tokens.retain(|_, (token_email, _)| token_email != email);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_tokens_are_single_use() {
+        let mut store = PasswordResetTokens::default();
+        let id = PasswordResetId::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+
+        store.add_token(id.clone(), email.clone()).await.unwrap();
+
+        assert_eq!(store.take_token(&id).await, Ok(email));
+        assert_eq!(store.take_token(&id).await, Err(PasswordResetTokenStoreError::TokenNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_expired_token_is_not_found() {
+        let mut store = PasswordResetTokens::default();
+        let id = PasswordResetId::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        store.tokens.insert(id.clone(), (email, Utc::now().timestamp() - 1));
+
+        assert_eq!(store.take_token(&id).await, Err(PasswordResetTokenStoreError::TokenNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_remove_user_tokens() {
+        let mut store = PasswordResetTokens::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let other = Email::parse("other@example.com".to_owned()).unwrap();
+        let (first, second, others) = (PasswordResetId::default(), PasswordResetId::default(), PasswordResetId::default());
+
+        store.add_token(first.clone(), email.clone()).await.unwrap();
+        store.add_token(second.clone(), email.clone()).await.unwrap();
+        store.add_token(others.clone(), other.clone()).await.unwrap();
+
+        store.remove_user_tokens(&email).await.unwrap();
+
+        assert_eq!(store.take_token(&first).await, Err(PasswordResetTokenStoreError::TokenNotFound));
+        assert_eq!(store.take_token(&second).await, Err(PasswordResetTokenStoreError::TokenNotFound));
+        assert_eq!(store.take_token(&others).await, Ok(other));
+    }
+}
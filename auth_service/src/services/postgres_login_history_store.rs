@@ -0,0 +1,68 @@
+use sqlx::PgPool;
+
+use crate::user::Email;
+
+use super::{
+    client_info::ClientInfo,
+    login_history::{LoginEvent, LoginHistoryStore, LoginHistoryStoreError}
+};
+
+pub struct PostgresLoginHistoryStore {
+    pool: PgPool,
+}
+
+impl PostgresLoginHistoryStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl LoginHistoryStore for PostgresLoginHistoryStore {
+    #[tracing::instrument(name = "Recording login in PostgreSQL", skip_all)]
+    async fn record_login(&mut self, email: &Email, event: LoginEvent) -> Result<(), LoginHistoryStoreError> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO login_events (user_id, logged_in_at, ip, user_agent)
+            SELECT id, $2, $3, $4 FROM users WHERE email = $1
+            "#,
+            email.as_ref(),
+            event.logged_in_at,
+            event.client.ip,
+            event.client.user_agent
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| LoginHistoryStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(LoginHistoryStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Retrieving login history from PostgreSQL", skip_all)]
+    async fn get_logins(&self, email: &Email) -> Result<Vec<LoginEvent>, LoginHistoryStoreError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT e.logged_in_at, e.ip, e.user_agent
+            FROM login_events e
+            JOIN users u ON u.id = e.user_id
+            WHERE u.email = $1
+            ORDER BY e.id
+            "#,
+            email.as_ref()
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| LoginHistoryStoreError::UnexpectedError)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| LoginEvent {
+                logged_in_at: row.logged_in_at,
+                client: ClientInfo { ip: row.ip, user_agent: row.user_agent },
+            })
+            .collect())
+    }
+}
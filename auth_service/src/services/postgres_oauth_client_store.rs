@@ -0,0 +1,155 @@
+use sqlx::PgPool;
+
+use super::{
+    oauth_clients::{
+        OAuthClient, OAuthClientId, OAuthClientSecret, OAuthClientStore, OAuthClientStoreError, RedirectUri
+    },
+    postgres_user_store::{compute_password_hash, verify_password_hash}
+};
+
+pub struct PostgresOAuthClientStore {
+    pool: PgPool,
+}
+
+impl PostgresOAuthClientStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+struct OAuthClientRow {
+    id: String,
+    name: String,
+    redirect_uris: Vec<String>,
+    confidential: bool,
+    created_at: i64,
+}
+
+impl TryFrom<OAuthClientRow> for OAuthClient {
+    type Error = OAuthClientStoreError;
+
+    fn try_from(row: OAuthClientRow) -> Result<Self, Self::Error> {
+        Ok(OAuthClient {
+            id: OAuthClientId::parse(row.id).map_err(|_| OAuthClientStoreError::UnexpectedError)?,
+            name: row.name,
+            redirect_uris: row.redirect_uris
+                .into_iter()
+                .map(RedirectUri::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|_| OAuthClientStoreError::UnexpectedError)?,
+            confidential: row.confidential,
+            created_at: row.created_at,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl OAuthClientStore for PostgresOAuthClientStore {
+    #[tracing::instrument(name = "Adding OAuth client to PostgreSQL", skip_all)]
+    async fn add_client(&mut self, client: OAuthClient, secret: Option<OAuthClientSecret>) -> Result<(), OAuthClientStoreError> {
+        let secret_hash = match secret {
+            Some(secret) => Some(
+                compute_password_hash(secret.as_ref().to_owned())
+                    .await
+                    .map_err(|_| OAuthClientStoreError::UnexpectedError)?
+            ),
+            None => None,
+        };
+
+        let redirect_uris: Vec<String> = client.redirect_uris.iter().map(|uri| uri.as_ref().to_owned()).collect();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO oauth_clients (id, name, redirect_uris, secret_hash, created_at)
+            VALUES ($1::TEXT::UUID, $2, $3, $4, $5)
+            "#,
+            client.id.as_ref(),
+            client.name,
+            &redirect_uris,
+            secret_hash,
+            client.created_at
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| OAuthClientStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Retrieving OAuth client from PostgreSQL", skip_all)]
+    async fn get_client(&self, id: &OAuthClientId) -> Result<OAuthClient, OAuthClientStoreError> {
+        sqlx::query_as!(
+            OAuthClientRow,
+            r#"
+            SELECT id::TEXT AS "id!", name, redirect_uris, secret_hash IS NOT NULL AS "confidential!", created_at
+            FROM oauth_clients
+            WHERE id = $1::TEXT::UUID
+            "#,
+            id.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| OAuthClientStoreError::UnexpectedError)?
+        .ok_or(OAuthClientStoreError::ClientNotFound)?
+        .try_into()
+    }
+
+    #[tracing::instrument(name = "Listing OAuth clients from PostgreSQL", skip_all)]
+    async fn list_clients(&self) -> Result<Vec<OAuthClient>, OAuthClientStoreError> {
+        sqlx::query_as!(
+            OAuthClientRow,
+            r#"
+            SELECT id::TEXT AS "id!", name, redirect_uris, secret_hash IS NOT NULL AS "confidential!", created_at
+            FROM oauth_clients
+            ORDER BY created_at, id
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| OAuthClientStoreError::UnexpectedError)?
+        .into_iter()
+        .map(OAuthClient::try_from)
+        .collect()
+    }
+
+    #[tracing::instrument(name = "Deleting OAuth client from PostgreSQL", skip_all)]
+    async fn delete_client(&mut self, id: &OAuthClientId) -> Result<(), OAuthClientStoreError> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM oauth_clients
+            WHERE id = $1::TEXT::UUID
+            "#,
+            id.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| OAuthClientStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(OAuthClientStoreError::ClientNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Validating OAuth client secret in PostgreSQL", skip_all)]
+    async fn validate_secret(&self, id: &OAuthClientId, secret: &OAuthClientSecret) -> Result<(), OAuthClientStoreError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT secret_hash
+            FROM oauth_clients
+            WHERE id = $1::TEXT::UUID
+            "#,
+            id.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| OAuthClientStoreError::UnexpectedError)?
+        .ok_or(OAuthClientStoreError::ClientNotFound)?;
+
+        let secret_hash = row.secret_hash.ok_or(OAuthClientStoreError::InvalidSecret)?;
+
+        verify_password_hash(secret_hash, secret.as_ref().to_owned())
+            .await
+            .map_err(|_| OAuthClientStoreError::InvalidSecret)
+    }
+}
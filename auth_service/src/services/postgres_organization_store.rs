@@ -0,0 +1,171 @@
+use sqlx::PgPool;
+
+use crate::user::UserId;
+
+use super::organizations::{
+    Membership, OrgRole, Organization, OrganizationId, OrganizationStore, OrganizationStoreError
+};
+
+const FOREIGN_KEY_VIOLATION: &str = "23503";
+
+pub struct PostgresOrganizationStore {
+    pool: PgPool,
+}
+
+impl PostgresOrganizationStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+// A membership as selected by the lookups, joined with its organization
+struct MembershipRow {
+    id: String,
+    name: String,
+    created_at: i64,
+    role: String,
+}
+
+impl TryFrom<MembershipRow> for Membership {
+    type Error = OrganizationStoreError;
+
+    fn try_from(row: MembershipRow) -> Result<Self, Self::Error> {
+        Ok(Membership {
+            organization: Organization {
+                id: OrganizationId::parse(row.id).map_err(|_| OrganizationStoreError::UnexpectedError)?,
+                name: row.name,
+                created_at: row.created_at,
+            },
+            role: OrgRole::parse(&row.role).map_err(|_| OrganizationStoreError::UnexpectedError)?,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl OrganizationStore for PostgresOrganizationStore {
+    #[tracing::instrument(name = "Adding organization to PostgreSQL", skip_all)]
+    async fn add_organization(&mut self, organization: Organization) -> Result<(), OrganizationStoreError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO organizations (id, name, created_at)
+            VALUES ($1::TEXT::UUID, $2, $3)
+            "#,
+            organization.id.as_ref(),
+            organization.name,
+            organization.created_at
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| OrganizationStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Adding organization member in PostgreSQL", skip_all)]
+    async fn add_member(&mut self, org_id: &OrganizationId, user_id: &UserId, role: OrgRole) -> Result<(), OrganizationStoreError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO organization_members (org_id, user_id, role)
+            VALUES ($1::TEXT::UUID, $2::TEXT::UUID, $3)
+            ON CONFLICT DO NOTHING
+            "#,
+            org_id.as_ref(),
+            user_id.as_ref(),
+            role.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(e) if e.code().as_deref() == Some(FOREIGN_KEY_VIOLATION) => {
+                OrganizationStoreError::OrganizationNotFound
+            }
+            _ => OrganizationStoreError::UnexpectedError,
+        })?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Retrieving organization membership from PostgreSQL", skip_all)]
+    async fn get_membership(&self, org_id: &OrganizationId, user_id: &UserId) -> Result<Membership, OrganizationStoreError> {
+        sqlx::query_as!(
+            MembershipRow,
+            r#"
+            SELECT o.id::TEXT AS "id!", o.name, o.created_at, m.role
+            FROM organization_members m
+            JOIN organizations o ON o.id = m.org_id
+            WHERE m.org_id = $1::TEXT::UUID AND m.user_id = $2::TEXT::UUID
+            "#,
+            org_id.as_ref(),
+            user_id.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| OrganizationStoreError::UnexpectedError)?
+        .ok_or(OrganizationStoreError::MembershipNotFound)?
+        .try_into()
+    }
+
+    #[tracing::instrument(name = "Retrieving organization memberships from PostgreSQL", skip_all)]
+    async fn get_memberships(&self, user_id: &UserId) -> Result<Vec<Membership>, OrganizationStoreError> {
+        sqlx::query_as!(
+            MembershipRow,
+            r#"
+            SELECT o.id::TEXT AS "id!", o.name, o.created_at, m.role
+            FROM organization_members m
+            JOIN organizations o ON o.id = m.org_id
+            WHERE m.user_id = $1::TEXT::UUID
+            ORDER BY o.name
+            "#,
+            user_id.as_ref()
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| OrganizationStoreError::UnexpectedError)?
+        .into_iter()
+        .map(Membership::try_from)
+        .collect()
+    }
+
+    #[tracing::instrument(name = "Setting active organization in PostgreSQL", skip_all)]
+    async fn set_active_organization(&mut self, user_id: &UserId, org_id: &OrganizationId) -> Result<(), OrganizationStoreError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO active_organizations (user_id, org_id)
+            VALUES ($1::TEXT::UUID, $2::TEXT::UUID)
+            ON CONFLICT (user_id) DO UPDATE SET org_id = EXCLUDED.org_id
+            "#,
+            user_id.as_ref(),
+            org_id.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(e) if e.code().as_deref() == Some(FOREIGN_KEY_VIOLATION) => {
+                OrganizationStoreError::MembershipNotFound
+            }
+            _ => OrganizationStoreError::UnexpectedError,
+        })?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Retrieving active organization from PostgreSQL", skip_all)]
+    async fn get_active_membership(&self, user_id: &UserId) -> Result<Option<Membership>, OrganizationStoreError> {
+        sqlx::query_as!(
+            MembershipRow,
+            r#"
+            SELECT o.id::TEXT AS "id!", o.name, o.created_at, m.role
+            FROM active_organizations a
+            JOIN organization_members m ON m.org_id = a.org_id AND m.user_id = a.user_id
+            JOIN organizations o ON o.id = m.org_id
+            WHERE a.user_id = $1::TEXT::UUID
+            "#,
+            user_id.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| OrganizationStoreError::UnexpectedError)?
+        .map(Membership::try_from)
+        .transpose()
+    }
+}
@@ -0,0 +1,117 @@
+use sqlx::PgPool;
+
+use crate::user::Email;
+
+use super::passkeys::{Passkey, PasskeyStore, PasskeyStoreError, UserHandle};
+
+pub struct PostgresPasskeyStore {
+    pool: PgPool,
+}
+
+impl PostgresPasskeyStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl PasskeyStore for PostgresPasskeyStore {
+    #[tracing::instrument(name = "Adding passkey to PostgreSQL", skip_all)]
+    async fn add_passkey(&mut self, passkey: Passkey) -> Result<(), PasskeyStoreError> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO webauthn_credentials (credential_id, user_id, user_handle, public_key, sign_count)
+            SELECT $1, id, $3, $4, $5 FROM users WHERE email = $2
+            ON CONFLICT (credential_id) DO NOTHING
+            "#,
+            passkey.credential_id,
+            passkey.email.as_ref(),
+            passkey.user_handle.as_ref(),
+            passkey.public_key,
+            i64::from(passkey.sign_count)
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| PasskeyStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(PasskeyStoreError::PasskeyAlreadyExists),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Retrieving passkey from PostgreSQL", skip_all)]
+    async fn get_passkey(&self, credential_id: &str) -> Result<Passkey, PasskeyStoreError> {
+        sqlx::query!(
+            r#"
+            SELECT w.credential_id, u.email, w.user_handle, w.public_key, w.sign_count
+            FROM webauthn_credentials w
+            JOIN users u ON u.id = w.user_id
+            WHERE w.credential_id = $1
+            "#,
+            credential_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| PasskeyStoreError::UnexpectedError)?
+        .map(|row| passkey_from_row(row.credential_id, row.email, row.user_handle, row.public_key, row.sign_count))
+        .ok_or(PasskeyStoreError::PasskeyNotFound)?
+    }
+
+    #[tracing::instrument(name = "Retrieving user passkeys from PostgreSQL", skip_all)]
+    async fn get_passkeys(&self, email: &Email) -> Result<Vec<Passkey>, PasskeyStoreError> {
+        sqlx::query!(
+            r#"
+            SELECT w.credential_id, u.email, w.user_handle, w.public_key, w.sign_count
+            FROM webauthn_credentials w
+            JOIN users u ON u.id = w.user_id
+            WHERE u.email = $1
+            ORDER BY w.created_at
+            "#,
+            email.as_ref()
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| PasskeyStoreError::UnexpectedError)?
+        .into_iter()
+        .map(|row| passkey_from_row(row.credential_id, row.email, row.user_handle, row.public_key, row.sign_count))
+        .collect()
+    }
+
+    #[tracing::instrument(name = "Updating passkey sign count in PostgreSQL", skip_all)]
+    async fn update_sign_count(&mut self, credential_id: &str, sign_count: u32) -> Result<(), PasskeyStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE webauthn_credentials
+            SET sign_count = $2, last_used_at = NOW()
+            WHERE credential_id = $1
+            "#,
+            credential_id,
+            i64::from(sign_count)
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| PasskeyStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(PasskeyStoreError::PasskeyNotFound),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn passkey_from_row(
+    credential_id: String,
+    email: String,
+    user_handle: String,
+    public_key: Vec<u8>,
+    sign_count: i64
+) -> Result<Passkey, PasskeyStoreError> {
+    Ok(Passkey {
+        credential_id,
+        email: Email::parse(email).map_err(|_| PasskeyStoreError::UnexpectedError)?,
+        user_handle: UserHandle::parse(user_handle).map_err(|_| PasskeyStoreError::UnexpectedError)?,
+        public_key,
+        sign_count: sign_count.try_into().map_err(|_| PasskeyStoreError::UnexpectedError)?,
+    })
+}
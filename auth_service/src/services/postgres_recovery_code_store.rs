@@ -0,0 +1,133 @@
+use sqlx::PgPool;
+
+use crate::user::Email;
+
+use super::{
+    postgres_user_store::{compute_password_hash, verify_password_hash},
+    recovery_codes::{RecoveryCode, RecoveryCodeStore, RecoveryCodeStoreError}
+};
+
+pub struct PostgresRecoveryCodeStore {
+    pool: PgPool,
+}
+
+impl PostgresRecoveryCodeStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl RecoveryCodeStore for PostgresRecoveryCodeStore {
+    #[tracing::instrument(name = "Replacing recovery codes in PostgreSQL", skip_all)]
+    async fn set_codes(&mut self, email: &Email, codes: Vec<RecoveryCode>) -> Result<(), RecoveryCodeStoreError> {
+        // Hash the whole set in parallel, one Argon2 hash per code adds up otherwise
+        let tasks: Vec<_> = codes
+            .into_iter()
+            .map(|code| tokio::spawn(compute_password_hash(code.as_ref().to_owned())))
+            .collect();
+
+        let mut code_hashes = Vec::with_capacity(tasks.len());
+        for task in tasks {
+            let code_hash = task
+                .await
+                .map_err(|_| RecoveryCodeStoreError::UnexpectedError)?
+                .map_err(|_| RecoveryCodeStoreError::UnexpectedError)?;
+            code_hashes.push(code_hash);
+        }
+
+        let mut transaction = self.pool.begin().await.map_err(|_| RecoveryCodeStoreError::UnexpectedError)?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM recovery_codes
+            WHERE user_id = (SELECT id FROM users WHERE email = $1)
+            "#,
+            email.as_ref()
+        )
+        .execute(&mut transaction)
+        .await
+        .map_err(|_| RecoveryCodeStoreError::UnexpectedError)?;
+
+        for code_hash in code_hashes {
+            sqlx::query!(
+                r#"
+                INSERT INTO recovery_codes (user_id, code_hash)
+                SELECT id, $2 FROM users WHERE email = $1
+                "#,
+                email.as_ref(),
+                code_hash
+            )
+            .execute(&mut transaction)
+            .await
+            .map_err(|_| RecoveryCodeStoreError::UnexpectedError)?;
+        }
+
+        transaction.commit().await.map_err(|_| RecoveryCodeStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Using recovery code in PostgreSQL", skip_all)]
+    async fn use_code(&mut self, email: &Email, code: &RecoveryCode) -> Result<(), RecoveryCodeStoreError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, code_hash
+            FROM recovery_codes
+            WHERE user_id = (SELECT id FROM users WHERE email = $1)
+            "#,
+            email.as_ref()
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| RecoveryCodeStoreError::UnexpectedError)?;
+
+        // Salted hashes can't be looked up directly, so check the candidate against each one
+        let tasks: Vec<_> = rows
+            .into_iter()
+            .map(|row| (row.id, tokio::spawn(verify_password_hash(row.code_hash, code.as_ref().to_owned()))))
+            .collect();
+
+        let mut matching_id = None;
+        for (id, task) in tasks {
+            if let Ok(Ok(())) = task.await {
+                matching_id = Some(id);
+            }
+        }
+
+        let id = matching_id.ok_or(RecoveryCodeStoreError::CodeNotFound)?;
+
+        // Only the request that actually deletes the row gets to use the code
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM recovery_codes
+            WHERE id = $1
+            "#,
+            id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| RecoveryCodeStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(RecoveryCodeStoreError::CodeNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Counting recovery codes in PostgreSQL", skip_all)]
+    async fn count_codes(&self, email: &Email) -> Result<usize, RecoveryCodeStoreError> {
+        let count = sqlx::query!(
+            r#"
+            SELECT COUNT(*) AS "count!"
+            FROM recovery_codes
+            WHERE user_id = (SELECT id FROM users WHERE email = $1)
+            "#,
+            email.as_ref()
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|_| RecoveryCodeStoreError::UnexpectedError)?
+        .count;
+
+        count.try_into().map_err(|_| RecoveryCodeStoreError::UnexpectedError)
+    }
+}
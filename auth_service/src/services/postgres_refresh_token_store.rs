@@ -0,0 +1,140 @@
+use chrono::Utc;
+use sqlx::PgPool;
+
+use crate::user::Email;
+
+use super::refresh_tokens::{
+    RefreshToken, RefreshTokenFamily, RefreshTokenRecord, RefreshTokenStore, RefreshTokenStoreError
+};
+
+pub struct PostgresRefreshTokenStore {
+    pool: PgPool,
+}
+
+impl PostgresRefreshTokenStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl RefreshTokenStore for PostgresRefreshTokenStore {
+    #[tracing::instrument(name = "Adding refresh token to PostgreSQL", skip_all)]
+    async fn add_token(&mut self, token: RefreshToken, record: RefreshTokenRecord) -> Result<(), RefreshTokenStoreError> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO refresh_tokens (token, user_id, family_id, used, expires_at)
+            SELECT $1, id, $3, $4, $5 FROM users WHERE email = $2
+            "#,
+            token.as_ref(),
+            record.email.as_ref(),
+            record.family.as_ref(),
+            record.used,
+            record.expires_at
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        // Nothing is inserted for an address without an account, which must not pass for a token being issued
+        match result.rows_affected() {
+            0 => Err(RefreshTokenStoreError::UnexpectedError),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Retrieving refresh token from PostgreSQL", skip_all)]
+    async fn get_token(&self, token: &RefreshToken) -> Result<RefreshTokenRecord, RefreshTokenStoreError> {
+        sqlx::query!(
+            r#"
+            SELECT u.email, t.family_id, t.used, t.expires_at
+            FROM refresh_tokens t
+            JOIN users u ON u.id = t.user_id
+            WHERE t.token = $1 AND t.expires_at > $2
+            "#,
+            token.as_ref(),
+            Utc::now().timestamp()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| RefreshTokenStoreError::UnexpectedError)?
+        .map(|row| {
+            Ok(RefreshTokenRecord {
+                email: Email::parse(row.email).map_err(|_| RefreshTokenStoreError::UnexpectedError)?,
+                family: RefreshTokenFamily::parse(row.family_id)
+                    .map_err(|_| RefreshTokenStoreError::UnexpectedError)?,
+                used: row.used,
+                expires_at: row.expires_at,
+            })
+        })
+        .ok_or(RefreshTokenStoreError::TokenNotFound)?
+    }
+
+    #[tracing::instrument(name = "Marking refresh token as used in PostgreSQL", skip_all)]
+    async fn mark_used(&mut self, token: &RefreshToken) -> Result<(), RefreshTokenStoreError> {
+        // Conditional, so of two replicas rotating the same token at once only one succeeds
+        let result = sqlx::query!(
+            r#"
+            UPDATE refresh_tokens
+            SET used = TRUE
+            WHERE token = $1 AND used = FALSE
+            "#,
+            token.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        if result.rows_affected() > 0 {
+            return Ok(());
+        }
+
+        let exists = sqlx::query!(
+            r#"
+            SELECT EXISTS (SELECT 1 FROM refresh_tokens WHERE token = $1) AS "exists!"
+            "#,
+            token.as_ref()
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|_| RefreshTokenStoreError::UnexpectedError)?
+        .exists;
+
+        match exists {
+            true => Err(RefreshTokenStoreError::TokenAlreadyUsed),
+            false => Err(RefreshTokenStoreError::TokenNotFound),
+        }
+    }
+
+    #[tracing::instrument(name = "Revoking refresh token family in PostgreSQL", skip_all)]
+    async fn revoke_family(&mut self, family: &RefreshTokenFamily) -> Result<(), RefreshTokenStoreError> {
+        sqlx::query!(
+            r#"
+            DELETE FROM refresh_tokens
+            WHERE family_id = $1
+            "#,
+            family.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Revoking all refresh tokens of a user in PostgreSQL", skip_all)]
+    async fn revoke_all(&mut self, email: &Email) -> Result<(), RefreshTokenStoreError> {
+        sqlx::query!(
+            r#"
+            DELETE FROM refresh_tokens
+            WHERE user_id = (SELECT id FROM users WHERE email = $1)
+            "#,
+            email.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
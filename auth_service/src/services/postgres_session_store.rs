@@ -0,0 +1,195 @@
+use sqlx::PgPool;
+
+use crate::user::UserId;
+
+use super::{
+    client_info::ClientInfo,
+    oauth_clients::OAuthClientId,
+    oidc::{AuthMethod, Scope},
+    sessions::{Session, SessionId, SessionStore, SessionStoreError, SESSION_TTL_SECONDS}
+};
+
+pub struct PostgresSessionStore {
+    pool: PgPool,
+}
+
+impl PostgresSessionStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+struct SessionRow {
+    id: String,
+    user_id: String,
+    device: Option<String>,
+    ip: Option<String>,
+    user_agent: Option<String>,
+    created_at: i64,
+    last_seen_at: i64,
+    authenticated_at: i64,
+    auth_methods: Vec<String>,
+    oauth_client_id: Option<String>,
+    scopes: Vec<String>,
+}
+
+impl TryFrom<SessionRow> for Session {
+    type Error = SessionStoreError;
+
+    fn try_from(row: SessionRow) -> Result<Self, Self::Error> {
+        Ok(Session {
+            id: SessionId::parse(row.id).map_err(|_| SessionStoreError::UnexpectedError)?,
+            user_id: UserId::parse(row.user_id).map_err(|_| SessionStoreError::UnexpectedError)?,
+            device: row.device,
+            client: ClientInfo { ip: row.ip, user_agent: row.user_agent },
+            created_at: row.created_at,
+            last_seen_at: row.last_seen_at,
+            authenticated_at: row.authenticated_at,
+            auth_methods: row.auth_methods
+                .into_iter()
+                .map(AuthMethod::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|_| SessionStoreError::UnexpectedError)?,
+            oauth_client_id: row.oauth_client_id
+                .map(OAuthClientId::parse)
+                .transpose()
+                .map_err(|_| SessionStoreError::UnexpectedError)?,
+            scopes: row.scopes
+                .into_iter()
+                .map(Scope::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|_| SessionStoreError::UnexpectedError)?,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionStore for PostgresSessionStore {
+    #[tracing::instrument(name = "Adding session to PostgreSQL", skip_all)]
+    async fn add_session(&mut self, session: Session) -> Result<(), SessionStoreError> {
+        let auth_methods: Vec<String> = session.auth_methods.iter().map(|method| method.as_str().to_owned()).collect();
+        let scopes: Vec<String> = session.scopes.iter().map(|scope| scope.as_str().to_owned()).collect();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO sessions (
+                id, user_id, device, ip, user_agent, created_at, last_seen_at, authenticated_at, auth_methods,
+                oauth_client_id, scopes
+            )
+            VALUES ($1::TEXT::UUID, $2::TEXT::UUID, $3, $4, $5, $6, $7, $8, $9, $10::TEXT::UUID, $11)
+            "#,
+            session.id.as_ref(),
+            session.user_id.as_ref(),
+            session.device,
+            session.client.ip,
+            session.client.user_agent,
+            session.created_at,
+            session.last_seen_at,
+            session.authenticated_at,
+            &auth_methods,
+            session.oauth_client_id.as_ref().map(|id| id.as_ref()),
+            &scopes
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| SessionStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Retrieving session from PostgreSQL", skip_all)]
+    async fn get_session(&self, id: &SessionId, now: i64) -> Result<Session, SessionStoreError> {
+        sqlx::query_as!(
+            SessionRow,
+            r#"
+            SELECT id::TEXT AS "id!", user_id::TEXT AS "user_id!", device, ip, user_agent, created_at, last_seen_at,
+                authenticated_at, auth_methods, oauth_client_id::TEXT, scopes
+            FROM sessions
+            WHERE id = $1::TEXT::UUID AND last_seen_at > $2
+            "#,
+            id.as_ref(),
+            now - SESSION_TTL_SECONDS
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| SessionStoreError::UnexpectedError)?
+        .ok_or(SessionStoreError::SessionNotFound)?
+        .try_into()
+    }
+
+    #[tracing::instrument(name = "Listing sessions from PostgreSQL", skip_all)]
+    async fn list_sessions(&self, user_id: &UserId, now: i64) -> Result<Vec<Session>, SessionStoreError> {
+        sqlx::query_as!(
+            SessionRow,
+            r#"
+            SELECT id::TEXT AS "id!", user_id::TEXT AS "user_id!", device, ip, user_agent, created_at, last_seen_at,
+                authenticated_at, auth_methods, oauth_client_id::TEXT, scopes
+            FROM sessions
+            WHERE user_id = $1::TEXT::UUID AND last_seen_at > $2
+            ORDER BY last_seen_at DESC
+            "#,
+            user_id.as_ref(),
+            now - SESSION_TTL_SECONDS
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| SessionStoreError::UnexpectedError)?
+        .into_iter()
+        .map(Session::try_from)
+        .collect()
+    }
+
+    #[tracing::instrument(name = "Updating session last seen in PostgreSQL", skip_all)]
+    async fn touch_session(&mut self, id: &SessionId, now: i64) -> Result<(), SessionStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE sessions
+            SET last_seen_at = $2
+            WHERE id = $1::TEXT::UUID
+            "#,
+            id.as_ref(),
+            now
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| SessionStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(SessionStoreError::SessionNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Removing session from PostgreSQL", skip_all)]
+    async fn remove_session(&mut self, id: &SessionId) -> Result<(), SessionStoreError> {
+        sqlx::query!(
+            r#"
+            DELETE FROM sessions
+            WHERE id = $1::TEXT::UUID
+            "#,
+            id.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| SessionStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Removing sessions of a user from PostgreSQL", skip_all)]
+    async fn remove_user_sessions(&mut self, user_id: &UserId, keep: Option<&SessionId>) -> Result<(), SessionStoreError> {
+        sqlx::query!(
+            r#"
+            DELETE FROM sessions
+            WHERE user_id = $1::TEXT::UUID AND ($2::TEXT IS NULL OR id <> $2::TEXT::UUID)
+            "#,
+            user_id.as_ref(),
+            keep.map(|id| id.as_ref())
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| SessionStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+use sqlx::PgPool;
+
+use crate::user::Email;
+
+use super::totp::{TotpSecret, TotpStore, TotpStoreError};
+
+pub struct PostgresTotpStore {
+    pool: PgPool,
+}
+
+impl PostgresTotpStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl TotpStore for PostgresTotpStore {
+    #[tracing::instrument(name = "Adding pending TOTP secret to PostgreSQL", skip_all)]
+    async fn set_pending_secret(&mut self, email: Email, secret: TotpSecret) -> Result<(), TotpStoreError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO totp_secrets (user_id, pending_secret)
+            SELECT id, $2 FROM users WHERE email = $1
+            ON CONFLICT (user_id) DO UPDATE SET pending_secret = EXCLUDED.pending_secret
+            "#,
+            email.as_ref(),
+            secret.encode()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| TotpStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Retrieving pending TOTP secret from PostgreSQL", skip_all)]
+    async fn get_pending_secret(&self, email: &Email) -> Result<TotpSecret, TotpStoreError> {
+        let secret = sqlx::query!(
+            r#"
+            SELECT pending_secret
+            FROM totp_secrets
+            WHERE user_id = (SELECT id FROM users WHERE email = $1)
+            "#,
+            email.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| TotpStoreError::UnexpectedError)?
+        .and_then(|row| row.pending_secret)
+        .ok_or(TotpStoreError::SecretNotFound)?;
+
+        TotpSecret::parse(secret).map_err(|_| TotpStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Confirming TOTP secret in PostgreSQL", skip_all)]
+    async fn confirm_secret(&mut self, email: &Email, step: u64) -> Result<(), TotpStoreError> {
+        let step: i64 = step.try_into().map_err(|_| TotpStoreError::UnexpectedError)?;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE totp_secrets
+            SET secret = pending_secret, pending_secret = NULL, last_used_step = $2
+            WHERE user_id = (SELECT id FROM users WHERE email = $1) AND pending_secret IS NOT NULL
+            "#,
+            email.as_ref(),
+            step
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| TotpStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(TotpStoreError::SecretNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Retrieving TOTP secret from PostgreSQL", skip_all)]
+    async fn get_secret(&self, email: &Email) -> Result<TotpSecret, TotpStoreError> {
+        let secret = sqlx::query!(
+            r#"
+            SELECT secret
+            FROM totp_secrets
+            WHERE user_id = (SELECT id FROM users WHERE email = $1)
+            "#,
+            email.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| TotpStoreError::UnexpectedError)?
+        .and_then(|row| row.secret)
+        .ok_or(TotpStoreError::SecretNotFound)?;
+
+        TotpSecret::parse(secret).map_err(|_| TotpStoreError::UnexpectedError)
+    }
+
+    // The conditional update makes the replay check atomic across concurrent logins
+    #[tracing::instrument(name = "Recording used TOTP step in PostgreSQL", skip_all)]
+    async fn use_step(&mut self, email: &Email, step: u64) -> Result<(), TotpStoreError> {
+        let step: i64 = step.try_into().map_err(|_| TotpStoreError::UnexpectedError)?;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE totp_secrets
+            SET last_used_step = $2
+            WHERE user_id = (SELECT id FROM users WHERE email = $1)
+              AND secret IS NOT NULL
+              AND (last_used_step IS NULL OR last_used_step < $2)
+            "#,
+            email.as_ref(),
+            step
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| TotpStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(TotpStoreError::StepAlreadyUsed),
+            _ => Ok(()),
+        }
+    }
+}
@@ -7,7 +7,44 @@ use argon2::{
 
 use sqlx::PgPool;
 
-use crate::user::{store::{UserStore, UserStoreError}, Email, Password, User};
+use crate::user::{store::{UserPage, UserStore, UserStoreError}, Email, Password, Role, TwoFaMethod, User, UserId};
+
+const UNIQUE_VIOLATION: &str = "23505";
+const FOREIGN_KEY_VIOLATION: &str = "23503";
+
+// A `users` row as selected by the lookups, with the UUID cast to text
+struct UserRow {
+    id: String,
+    email: String,
+    password_hash: String,
+    two_fa_method: String,
+    email_verified: bool,
+    created_at: i64,
+    delete_after: Option<i64>,
+    locked: bool,
+    password_reset_required: bool,
+}
+
+impl TryFrom<UserRow> for User {
+    type Error = UserStoreError;
+
+    fn try_from(row: UserRow) -> Result<Self, Self::Error> {
+        Ok(User {
+            id: UserId::parse(row.id).map_err(|_| UserStoreError::UnexpectedError)?,
+            email: Email::parse(row.email).map_err(|_| UserStoreError::UnexpectedError)?,
+            password: Password::parse(row.password_hash)
+                .map_err(|_| UserStoreError::UnexpectedError)?,
+            two_fa_method: TwoFaMethod::parse(&row.two_fa_method)
+                .map_err(|_| UserStoreError::UnexpectedError)?,
+            email_verified: row.email_verified,
+            created_at: row.created_at,
+            delete_after: row.delete_after,
+            locked: row.locked,
+            password_reset_required: row.password_reset_required,
+        })
+    }
+}
+
 pub struct PostgresUserStore {
     pool: PgPool,
 }
@@ -28,12 +65,15 @@ impl UserStore for PostgresUserStore {
 
         sqlx::query!(
             r#"
-            INSERT INTO users (email, password_hash, requires_2fa)
-            VALUES ($1, $2, $3)
+            INSERT INTO users (id, email, password_hash, two_fa_method, email_verified, created_at)
+            VALUES ($1::TEXT::UUID, $2, $3, $4, $5, $6)
             "#,
+            user.id.as_ref(),
             user.email.as_ref(),
             &password_hash,
-            user.requires_2fa
+            user.two_fa_method.as_ref(),
+            user.email_verified,
+            user.created_at
         )
         .execute(&self.pool)
         .await
@@ -44,9 +84,11 @@ impl UserStore for PostgresUserStore {
 
     #[tracing::instrument(name = "Retrieving user from PostgreSQL", skip_all)]
     async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
-        sqlx::query!(
+        sqlx::query_as!(
+            UserRow,
             r#"
-            SELECT email, password_hash, requires_2fa
+            SELECT id::TEXT AS "id!", email, password_hash, two_fa_method, email_verified, created_at, delete_after,
+                locked, password_reset_required
             FROM users
             WHERE email = $1
             "#,
@@ -55,15 +97,27 @@ impl UserStore for PostgresUserStore {
         .fetch_optional(&self.pool)
         .await
         .map_err(|_| UserStoreError::UnexpectedError)?
-        .map(|row| {
-            Ok(User {
-                email: Email::parse(row.email).map_err(|_| UserStoreError::UnexpectedError)?,
-                password: Password::parse(row.password_hash)
-                    .map_err(|_| UserStoreError::UnexpectedError)?,
-                requires_2fa: row.requires_2fa,
-            })
-        })
         .ok_or(UserStoreError::UserNotFound)?
+        .try_into()
+    }
+
+    #[tracing::instrument(name = "Retrieving user by id from PostgreSQL", skip_all)]
+    async fn get_user_by_id(&self, id: &UserId) -> Result<User, UserStoreError> {
+        sqlx::query_as!(
+            UserRow,
+            r#"
+            SELECT id::TEXT AS "id!", email, password_hash, two_fa_method, email_verified, created_at, delete_after,
+                locked, password_reset_required
+            FROM users
+            WHERE id = $1::TEXT::UUID
+            "#,
+            id.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?
+        .ok_or(UserStoreError::UserNotFound)?
+        .try_into()
     }
 
     #[tracing::instrument(name = "Validating user credentials in PostgreSQL", skip_all)]
@@ -81,10 +135,333 @@ impl UserStore for PostgresUserStore {
         .await
         .map_err(|_| UserStoreError::InvalidCredentials)
     }
+
+    #[tracing::instrument(name = "Setting user 2FA method in PostgreSQL", skip_all)]
+    async fn set_two_fa_method(&mut self, email: &Email, method: TwoFaMethod) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET two_fa_method = $2
+            WHERE email = $1
+            "#,
+            email.as_ref(),
+            method.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Marking user email as verified in PostgreSQL", skip_all)]
+    async fn mark_email_verified(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET email_verified = TRUE
+            WHERE email = $1
+            "#,
+            email.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Updating user password in PostgreSQL", skip_all)]
+    async fn update_password(&mut self, email: &Email, password: Password) -> Result<(), UserStoreError> {
+        let password_hash = compute_password_hash(password.as_ref().to_owned())
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET password_hash = $2, password_reset_required = FALSE
+            WHERE email = $1
+            "#,
+            email.as_ref(),
+            &password_hash
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    // Every dependent table references the user id, so updating the row moves the whole account
+    #[tracing::instrument(name = "Updating user email in PostgreSQL", skip_all)]
+    async fn update_email(&mut self, email: &Email, new_email: Email) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET email = $2, email_verified = TRUE
+            WHERE email = $1
+            "#,
+            email.as_ref(),
+            new_email.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(e) if e.code().as_deref() == Some(UNIQUE_VIOLATION) => {
+                UserStoreError::UserAlreadyExists
+            }
+            _ => UserStoreError::UnexpectedError,
+        })?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Scheduling user deletion in PostgreSQL", skip_all)]
+    async fn set_delete_after(&mut self, email: &Email, delete_after: Option<i64>) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET delete_after = $2
+            WHERE email = $1
+            "#,
+            email.as_ref(),
+            delete_after
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    // Rows in the dependent tables go with the user through ON DELETE CASCADE
+    #[tracing::instrument(name = "Deleting user from PostgreSQL", skip_all)]
+    async fn delete_user(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM users
+            WHERE email = $1
+            "#,
+            email.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Purging scheduled user deletions from PostgreSQL", skip_all)]
+    async fn delete_scheduled_users(&mut self, now: i64) -> Result<Vec<Email>, UserStoreError> {
+        sqlx::query!(
+            r#"
+            DELETE FROM users
+            WHERE delete_after <= $1
+            RETURNING email
+            "#,
+            now
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?
+        .into_iter()
+        .map(|row| Email::parse(row.email).map_err(|_| UserStoreError::UnexpectedError))
+        .collect()
+    }
+
+    #[tracing::instrument(name = "Adding role to PostgreSQL", skip_all)]
+    async fn add_role(&mut self, role: Role) -> Result<(), UserStoreError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO roles (name, permissions)
+            VALUES ($1, $2)
+            ON CONFLICT (name) DO UPDATE SET permissions = EXCLUDED.permissions
+            "#,
+            role.name,
+            &role.permissions
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Assigning user role in PostgreSQL", skip_all)]
+    async fn assign_role(&mut self, email: &Email, role: &str) -> Result<(), UserStoreError> {
+        let user = self.get_user(email).await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO user_roles (user_id, role)
+            VALUES ($1::TEXT::UUID, $2)
+            ON CONFLICT DO NOTHING
+            "#,
+            user.id.as_ref(),
+            role
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(e) if e.code().as_deref() == Some(FOREIGN_KEY_VIOLATION) => {
+                UserStoreError::RoleNotFound
+            }
+            _ => UserStoreError::UnexpectedError,
+        })?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Revoking user role in PostgreSQL", skip_all)]
+    async fn revoke_role(&mut self, email: &Email, role: &str) -> Result<(), UserStoreError> {
+        let user = self.get_user(email).await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM user_roles
+            WHERE user_id = $1::TEXT::UUID AND role = $2
+            "#,
+            user.id.as_ref(),
+            role
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Retrieving user roles from PostgreSQL", skip_all)]
+    async fn get_roles(&self, email: &Email) -> Result<Vec<Role>, UserStoreError> {
+        let roles = sqlx::query!(
+            r#"
+            SELECT roles.name, roles.permissions
+            FROM user_roles
+            JOIN roles ON roles.name = user_roles.role
+            JOIN users ON users.id = user_roles.user_id
+            WHERE users.email = $1
+            ORDER BY roles.name
+            "#,
+            email.as_ref()
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?
+        .into_iter()
+        .map(|row| Role { name: row.name, permissions: row.permissions })
+        .collect();
+
+        Ok(roles)
+    }
+
+    #[tracing::instrument(name = "Listing users from PostgreSQL", skip_all)]
+    async fn list_users(&self, search: Option<&str>, offset: u64, limit: u64) -> Result<UserPage, UserStoreError> {
+        // LIKE wildcards in the search term are matched literally
+        let pattern = search.map(|search| {
+            let escaped = search.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+            format!("%{}%", escaped)
+        });
+
+        let total = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "total!"
+            FROM users
+            WHERE $1::TEXT IS NULL OR email ILIKE $1
+            "#,
+            pattern
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        let users = sqlx::query_as!(
+            UserRow,
+            r#"
+            SELECT id::TEXT AS "id!", email, password_hash, two_fa_method, email_verified, created_at, delete_after,
+                locked, password_reset_required
+            FROM users
+            WHERE $1::TEXT IS NULL OR email ILIKE $1
+            ORDER BY created_at, email
+            LIMIT $2 OFFSET $3
+            "#,
+            pattern,
+            limit as i64,
+            offset as i64
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?
+        .into_iter()
+        .map(User::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(UserPage { users, total: total as u64 })
+    }
+
+    #[tracing::instrument(name = "Setting user lock in PostgreSQL", skip_all)]
+    async fn set_locked(&mut self, email: &Email, locked: bool) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET locked = $2
+            WHERE email = $1
+            "#,
+            email.as_ref(),
+            locked
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Setting user password reset requirement in PostgreSQL", skip_all)]
+    async fn set_password_reset_required(&mut self, email: &Email, required: bool) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET password_reset_required = $2
+            WHERE email = $1
+            "#,
+            email.as_ref(),
+            required
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
 }
 
 #[tracing::instrument(name = "Verify password hash", skip_all)]
-async fn verify_password_hash(
+pub(crate) async fn verify_password_hash(
     expected_password_hash: String,
     password_candidate: String,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
@@ -105,7 +482,7 @@ async fn verify_password_hash(
 }
 
 #[tracing::instrument(name = "Computing password hash", skip_all)] 
-async fn compute_password_hash(password: String) -> Result<String, Box<dyn Error + Send + Sync>> {
+pub(crate) async fn compute_password_hash(password: String) -> Result<String, Box<dyn Error + Send + Sync>> {
     let current_span: tracing::Span = tracing::Span::current(); // New!
 
     let result = tokio::task::spawn_blocking(move || {
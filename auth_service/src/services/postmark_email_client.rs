@@ -2,7 +2,7 @@ use color_eyre::eyre::Result; // For improved error handling and reporting
 use reqwest::{Client, Url}; // For making HTTP requests
 use secrecy::{ExposeSecret, Secret}; // For securely handling sensitive data
 
-use crate::{app::email_client::{EmailClient, SendEmail}, user::Email};
+use crate::{app::email_client::EmailClient, user::Email};
 
 // Define the PostmarkEmailClient struct
 pub struct PostmarkEmailClient {
@@ -110,13 +110,13 @@ mod tests {
     }
 
     // Helper function to create a test email client
-    // fn email_client(base_url: String) -> PostmarkEmailClient {
-    //     let http_client = Client::builder()
-    //         .timeout(test::email_client::TIMEOUT)
-    //         .build()
-    //         .unwrap();
-    //     PostmarkEmailClient::new(base_url, email(), Secret::new(Faker.fake()), http_client)
-    // }
+    fn email_client(base_url: String) -> PostmarkEmailClient {
+        let http_client = Client::builder()
+            .timeout(test::email_client::TIMEOUT)
+            .build()
+            .unwrap();
+        PostmarkEmailClient::new(base_url, email(), Secret::new(Faker.fake()), http_client)
+    }
 
     // Custom matcher to validate the email request body
     struct SendEmailBodyMatcher;
@@ -138,70 +138,70 @@ mod tests {
     }
 
     // Test to ensure the email client sends the expected request
-    // #[tokio::test]
-    // async fn send_email_sends_the_expected_request() {
-    //     let mock_server = MockServer::start().await;
-    //     // let email_client = email_client(mock_server.uri());
-
-    //     // Set up the mock server to expect a specific request
-    //     Mock::given(header_exists(POSTMARK_AUTH_HEADER))
-    //         .and(header("Content-Type", "application/json"))
-    //         .and(path("/email"))
-    //         .and(method("POST"))
-    //         .and(SendEmailBodyMatcher)
-    //         .respond_with(ResponseTemplate::new(200))
-    //         .expect(1)
-    //         .mount(&mock_server)
-    //         .await;
-
-    //     // Execute the send_email function and check the outcome
-    //     let outcome = email_client
-    //         .send_email(&email(), &subject(), &content())
-    //         .await;
-
-    //     assert!(outcome.is_ok());
-    // }
+    #[tokio::test]
+    async fn send_email_sends_the_expected_request() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        // Set up the mock server to expect a specific request
+        Mock::given(header_exists(POSTMARK_AUTH_HEADER))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Execute the send_email function and check the outcome
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content())
+            .await;
+
+        assert!(outcome.is_ok());
+    }
 
     // Test to handle server error responses
-    // #[tokio::test]
-    // async fn send_email_fails_if_the_server_returns_500() {
-    //     let mock_server = MockServer::start().await;
-    //     let email_client = email_client(mock_server.uri());
-
-    //     // Set up the mock server to respond with a 500 error
-    //     Mock::given(any())
-    //         .respond_with(ResponseTemplate::new(500))
-    //         .expect(1)
-    //         .mount(&mock_server)
-    //         .await;
-
-    //     // Execute the send_email function and check the outcome
-    //     let outcome = email_client
-    //         .send_email(&email(), &subject(), &content())
-    //         .await;
-
-    //     assert!(outcome.is_err());
-    // }
-
-    // // Test to handle request timeouts
-    // #[tokio::test]
-    // async fn send_email_times_out_if_the_server_takes_too_long() {
-    //     let mock_server = MockServer::start().await;
-    //     let email_client = email_client(mock_server.uri());
-
-    //     // Set up the mock server to delay the response
-    //     let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180)); // 3 minutes delay
-    //     Mock::given(any())
-    //         .respond_with(response)
-    //         .expect(1)
-    //         .mount(&mock_server)
-    //         .await;
-
-    //     // Execute the send_email function and check the outcome
-    //     let outcome = email_client
-    //         .send_email(&email(), &subject(), &content())
-    //         .await;
-
-    //     assert!(outcome.is_err());
-    // }
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        // Set up the mock server to respond with a 500 error
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Execute the send_email function and check the outcome
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content())
+            .await;
+
+        assert!(outcome.is_err());
+    }
+
+    // Test to handle request timeouts
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        // Set up the mock server to delay the response
+        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180)); // 3 minutes delay
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Execute the send_email function and check the outcome
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content())
+            .await;
+
+        assert!(outcome.is_err());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,236 @@
+use std::{collections::HashMap, str::FromStr, sync::{Arc, Mutex}};
+
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+
+pub type RateLimitStoreType = Arc<RwLock<dyn RateLimitStore + Send + Sync>>;
+
+// At most `requests` per client in any `window_seconds` long stretch of time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub window_seconds: i64,
+}
+
+impl RateLimit {
+    // The sliding window is approximated from fixed windows: requests in the previous one
+    // count for the share of it the sliding window still overlaps
+    pub fn allows(&self, previous: u64, current: u64, elapsed: i64) -> bool {
+        earliest_allowed(self.requests, previous, current, self.window_seconds)
+            .is_some_and(|at| at <= elapsed)
+    }
+
+    // Seconds until `allows` lets another request through
+    pub fn retry_after(&self, previous: u64, current: u64, elapsed: i64) -> i64 {
+        let window = self.window_seconds;
+
+        if let Some(at) = earliest_allowed(self.requests, previous, current, window).filter(|at| *at < window) {
+            return (at - elapsed).max(1);
+        }
+
+        // The current window is full, so its requests become the previous ones of the next
+        let next = earliest_allowed(self.requests, current, 0, window)
+            .filter(|at| *at < window)
+            .unwrap_or(window);
+
+        window - elapsed + next
+    }
+}
+
+// Seconds into a window from which one more request fits, `None` when the current window alone is full
+fn earliest_allowed(requests: u32, previous: u64, current: u64, window: i64) -> Option<i64> {
+    let room = u64::from(requests).checked_sub(current + 1)?;
+
+    if previous == 0 {
+        return Some(0);
+    }
+
+    let overlap = room.saturating_mul(window as u64) / previous;
+
+    Some(window.saturating_sub(i64::try_from(overlap).unwrap_or(i64::MAX)).max(0))
+}
+
+// Written like `30/60`, i.e. 30 requests per 60 seconds
+impl FromStr for RateLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (requests, window_seconds) = s
+            .split_once('/')
+            .ok_or_else(|| format!("Rate limit {} is not written as requests/seconds", s))?;
+
+        let requests = requests
+            .trim()
+            .parse::<u32>()
+            .ok()
+            .filter(|requests| *requests > 0)
+            .ok_or_else(|| format!("Rate limit {} must allow a positive number of requests", s))?;
+
+        let window_seconds = window_seconds
+            .trim()
+            .parse::<i64>()
+            .ok()
+            .filter(|seconds| *seconds > 0)
+            .ok_or_else(|| format!("Rate limit {} must have a positive window", s))?;
+
+        Ok(Self { requests, window_seconds })
+    }
+}
+
+// The limits of each route, with a fallback for routes without their own
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RateLimits {
+    pub routes: HashMap<String, RateLimit>,
+    pub default: Option<RateLimit>,
+}
+
+impl RateLimits {
+    pub fn for_route(&self, path: &str) -> Option<&RateLimit> {
+        self.routes.get(path).or(self.default.as_ref())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RateLimitStoreError {
+    UnexpectedError,
+}
+
+#[async_trait]
+pub trait RateLimitStore {
+    // Counts a request against `key` unless it's over the limit.
+    // Returns how many seconds the client has to wait when it is.
+    // Takes `&self` so every request doesn't queue up behind a single write lock.
+    async fn hit(&self, key: &str, limit: &RateLimit, now: i64) -> Result<Option<i64>, RateLimitStoreError>;
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct WindowCounts {
+    window: i64,
+    previous: u64,
+    current: u64,
+    // Once the current window is no longer the previous one either, the counts are of no use
+    expires_at: i64,
+}
+
+// How often expired windows are swept out of the counters
+const SWEEP_INTERVAL_SECONDS: i64 = 60;
+
+#[derive(Default)]
+struct Counters {
+    counts: HashMap<String, WindowCounts>,
+    swept_at: i64,
+}
+
+// Counters only hold for a single instance, so this is meant for tests, local runs and as a
+// stand-in while Redis is unreachable
+#[derive(Default)]
+pub struct RateLimitCounters {
+    counters: Mutex<Counters>,
+}
+
+#[async_trait]
+impl RateLimitStore for RateLimitCounters {
+    async fn hit(&self, key: &str, limit: &RateLimit, now: i64) -> Result<Option<i64>, RateLimitStoreError> {
+        let window = now.div_euclid(limit.window_seconds);
+        let elapsed = now.rem_euclid(limit.window_seconds);
+        let mut counters = self.counters.lock().map_err(|_| RateLimitStoreError::UnexpectedError)?;
+
+        // Every client that ever called would stay in memory otherwise
+        if now - counters.swept_at >= SWEEP_INTERVAL_SECONDS {
+            counters.counts.retain(|_, counts| counts.expires_at > now);
+            counters.swept_at = now;
+        }
+
+        let counts = counters.counts.entry(key.to_owned()).or_default();
+
+        if counts.window != window {
+            let previous = if counts.window + 1 == window { counts.current } else { 0 };
+            let expires_at = (window + 2) * limit.window_seconds;
+            *counts = WindowCounts { window, previous, current: 0, expires_at };
+        }
+
+        if !limit.allows(counts.previous, counts.current, elapsed) {
+            return Ok(Some(limit.retry_after(counts.previous, counts.current, elapsed)));
+        }
+
+        counts.current += 1;
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMIT: RateLimit = RateLimit { requests: 3, window_seconds: 60 };
+
+    fn tracked_keys(store: &RateLimitCounters) -> usize {
+        store.counters.lock().unwrap().counts.len()
+    }
+
+    #[test]
+    fn test_parse_rate_limit() {
+        assert_eq!("30/60".parse::<RateLimit>(), Ok(RateLimit { requests: 30, window_seconds: 60 }));
+        assert!("30".parse::<RateLimit>().is_err());
+        assert!("0/60".parse::<RateLimit>().is_err());
+        assert!("30/0".parse::<RateLimit>().is_err());
+        assert!("a/60".parse::<RateLimit>().is_err());
+    }
+
+    #[test]
+    fn test_previous_window_counts_for_its_overlap() {
+        // A full previous window blocks until a third of the way in, where it only counts for 2
+        assert!(!LIMIT.allows(3, 0, 0));
+        assert!(!LIMIT.allows(3, 0, 19));
+        assert!(LIMIT.allows(3, 0, 20));
+        assert_eq!(LIMIT.retry_after(3, 0, 5), 15);
+
+        assert!(!LIMIT.allows(0, 3, 59));
+        assert!(LIMIT.allows(0, 2, 59));
+    }
+
+    #[test]
+    fn test_retry_after_full_window_waits_for_next_one() {
+        // Three requests now count for 2 a third of the way into the next window
+        assert_eq!(LIMIT.retry_after(0, 3, 10), 50 + 20);
+        assert_eq!(LIMIT.retry_after(3, 3, 59), 1 + 20);
+    }
+
+    #[tokio::test]
+    async fn test_counters_limit_each_key() {
+        let store = RateLimitCounters::default();
+
+        for now in 60..63 {
+            assert_eq!(store.hit("login:1.2.3.4", &LIMIT, now).await, Ok(None));
+        }
+
+        assert_eq!(store.hit("login:1.2.3.4", &LIMIT, 63).await, Ok(Some(57 + 20)));
+        assert_eq!(store.hit("login:5.6.7.8", &LIMIT, 63).await, Ok(None));
+
+        // Rejected requests aren't counted, so the client gets through once the window slides
+        assert_eq!(store.hit("login:1.2.3.4", &LIMIT, 139).await, Ok(Some(1)));
+        assert_eq!(store.hit("login:1.2.3.4", &LIMIT, 140).await, Ok(None));
+
+        // Two windows later nothing carries over
+        for now in 240..243 {
+            assert_eq!(store.hit("login:1.2.3.4", &LIMIT, now).await, Ok(None));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_counters_forget_expired_windows() {
+        let store = RateLimitCounters::default();
+
+        assert_eq!(store.hit("login:1.2.3.4", &LIMIT, 60).await, Ok(None));
+        assert_eq!(store.hit("login:5.6.7.8", &LIMIT, 100).await, Ok(None));
+        assert_eq!(tracked_keys(&store), 2);
+
+        // Still counts as the previous window
+        assert_eq!(store.hit("login:9.9.9.9", &LIMIT, 170).await, Ok(None));
+        assert_eq!(tracked_keys(&store), 3);
+
+        assert_eq!(store.hit("login:9.9.9.9", &LIMIT, 240).await, Ok(None));
+        assert_eq!(tracked_keys(&store), 1);
+    }
+}
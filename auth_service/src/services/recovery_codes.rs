@@ -0,0 +1,156 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use rand::Rng;
+use tokio::sync::RwLock;
+
+use crate::user::Email;
+
+pub type RecoveryCodeStoreType = Arc<RwLock<dyn RecoveryCodeStore + Send + Sync>>;
+
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+// Lowercase letters and digits without the easily confused 0/o, 1/l/i
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecoveryCode(String);
+
+impl RecoveryCode {
+    // Accepts codes the way users tend to type them back: any case, with or without the dash
+    pub fn parse(code: String) -> Result<Self, String> {
+        let normalized: String = code
+            .trim()
+            .chars()
+            .filter(|c| *c != '-')
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+
+        let valid = normalized.len() == RECOVERY_CODE_LENGTH
+            && normalized.bytes().all(|b| RECOVERY_CODE_ALPHABET.contains(&b));
+
+        if !valid {
+            return Err(String::from("Invalid recovery code format"));
+        }
+
+        Ok(Self(normalized))
+    }
+
+    // Fresh set handed to the user once, only hashes are kept afterwards
+    pub fn generate_set() -> Vec<Self> {
+        (0..RECOVERY_CODE_COUNT).map(|_| Self::default()).collect()
+    }
+
+    // Split in two halves so the code is easier to read off a printout
+    pub fn display(&self) -> String {
+        let (first, second) = self.0.split_at(RECOVERY_CODE_LENGTH / 2);
+        format!("{}-{}", first, second)
+    }
+}
+
+impl Default for RecoveryCode {
+    fn default() -> Self {
+        let mut rng = rand::thread_rng();
+        let code = (0..RECOVERY_CODE_LENGTH)
+            .map(|_| RECOVERY_CODE_ALPHABET[rng.gen_range(0..RECOVERY_CODE_ALPHABET.len())] as char)
+            .collect();
+
+        Self(code)
+    }
+}
+
+impl AsRef<str> for RecoveryCode {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RecoveryCodeStoreError {
+    CodeNotFound,
+    UnexpectedError,
+}
+
+#[async_trait]
+pub trait RecoveryCodeStore {
+    // Replaces any codes the user already has
+    async fn set_codes(&mut self, email: &Email, codes: Vec<RecoveryCode>) -> Result<(), RecoveryCodeStoreError>;
+    // Consumes the code so it cannot be used a second time
+    async fn use_code(&mut self, email: &Email, code: &RecoveryCode) -> Result<(), RecoveryCodeStoreError>;
+    async fn count_codes(&self, email: &Email) -> Result<usize, RecoveryCodeStoreError>;
+}
+
+#[derive(Default)]
+pub struct RecoveryCodes {
+    codes: HashMap<Email, Vec<RecoveryCode>>,
+}
+
+#[async_trait]
+impl RecoveryCodeStore for RecoveryCodes {
+    async fn set_codes(&mut self, email: &Email, codes: Vec<RecoveryCode>) -> Result<(), RecoveryCodeStoreError> {
+        self.codes.insert(email.clone(), codes);
+        Ok(())
+    }
+
+    async fn use_code(&mut self, email: &Email, code: &RecoveryCode) -> Result<(), RecoveryCodeStoreError> {
+        let codes = self.codes.get_mut(email).ok_or(RecoveryCodeStoreError::CodeNotFound)?;
+        let position = codes
+            .iter()
+            .position(|stored| stored.eq(code))
+            .ok_or(RecoveryCodeStoreError::CodeNotFound)?;
+
+        codes.swap_remove(position);
+        Ok(())
+    }
+
+    async fn count_codes(&self, email: &Email) -> Result<usize, RecoveryCodeStoreError> {
+        Ok(self.codes.get(email).map_or(0, Vec::len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_normalizes_display_form() {
+        let code = RecoveryCode::default();
+
+        assert_eq!(RecoveryCode::parse(code.display()), Ok(code.clone()));
+        assert_eq!(RecoveryCode::parse(code.display().to_uppercase()), Ok(code));
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_codes() {
+        for code in ["", "abcde-fghj", "abcde-fghjkm", "abcde-fghj0", "123456"] {
+            assert!(RecoveryCode::parse(code.to_owned()).is_err(), "Accepted {:?}", code);
+        }
+    }
+
+    #[test]
+    fn test_generate_set_is_unique() {
+        let codes = RecoveryCode::generate_set();
+
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        assert!(codes.iter().all(|code| codes.iter().filter(|other| other.eq(&code)).count() == 1));
+    }
+
+    #[tokio::test]
+    async fn test_codes_are_single_use_and_replaced() {
+        let mut store = RecoveryCodes::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let codes = RecoveryCode::generate_set();
+
+        assert_eq!(store.count_codes(&email).await, Ok(0));
+
+        store.set_codes(&email, codes.clone()).await.unwrap();
+        assert_eq!(store.use_code(&email, &codes[0]).await, Ok(()));
+        assert_eq!(store.use_code(&email, &codes[0]).await, Err(RecoveryCodeStoreError::CodeNotFound));
+        assert_eq!(store.count_codes(&email).await, Ok(RECOVERY_CODE_COUNT - 1));
+
+        store.set_codes(&email, RecoveryCode::generate_set()).await.unwrap();
+        assert_eq!(store.use_code(&email, &codes[1]).await, Err(RecoveryCodeStoreError::CodeNotFound));
+        assert_eq!(store.count_codes(&email).await, Ok(RECOVERY_CODE_COUNT));
+    }
+}
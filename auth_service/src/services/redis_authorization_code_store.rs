@@ -0,0 +1,109 @@
+use std::sync::Arc;
+
+use redis::{Commands, Connection};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::user::UserId;
+
+use super::{
+    authorization_codes::{
+        AuthorizationCode, AuthorizationCodeStore, AuthorizationCodeStoreError, AuthorizationGrant, CodeChallenge,
+        AUTHORIZATION_CODE_TTL_SECONDS
+    },
+    oauth_clients::OAuthClientId,
+    oidc::{AuthMethod, Scope}
+};
+
+pub struct RedisAuthorizationCodeStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl RedisAuthorizationCodeStore {
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl AuthorizationCodeStore for RedisAuthorizationCodeStore {
+    async fn add_code(&mut self, code: AuthorizationCode, grant: AuthorizationGrant) -> Result<(), AuthorizationCodeStoreError> {
+        let ttl: u64 = AUTHORIZATION_CODE_TTL_SECONDS
+            .try_into()
+            .map_err(|_| AuthorizationCodeStoreError::UnexpectedError)?;
+
+        let data = AuthorizationGrantData {
+            client_id: grant.client_id.as_ref().to_owned(),
+            redirect_uri: grant.redirect_uri,
+            user_id: grant.user_id.as_ref().to_owned(),
+            code_challenge: grant.code_challenge.as_ref().to_owned(),
+            scopes: grant.scopes.iter().map(|scope| scope.as_str().to_owned()).collect(),
+            nonce: grant.nonce,
+            authenticated_at: grant.authenticated_at,
+            auth_methods: grant.auth_methods.iter().map(|method| method.as_str().to_owned()).collect(),
+        };
+        let value = serde_json::to_string(&data).map_err(|_| AuthorizationCodeStoreError::UnexpectedError)?;
+
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .set_ex(get_key(&code), value, ttl)
+            .map_err(|_| AuthorizationCodeStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn take_code(&mut self, code: &AuthorizationCode) -> Result<AuthorizationGrant, AuthorizationCodeStoreError> {
+        // Reading and deleting in one command, so two exchanges racing each other can't both get the grant
+        let value: Option<String> = self
+            .conn
+            .write()
+            .await
+            .get_del(get_key(code))
+            .map_err(|_| AuthorizationCodeStoreError::UnexpectedError)?;
+
+        let value = value.ok_or(AuthorizationCodeStoreError::CodeNotFound)?;
+
+        let data: AuthorizationGrantData = serde_json::from_str(&value)
+            .map_err(|_| AuthorizationCodeStoreError::UnexpectedError)?;
+
+        Ok(AuthorizationGrant {
+            client_id: OAuthClientId::parse(data.client_id).map_err(|_| AuthorizationCodeStoreError::UnexpectedError)?,
+            redirect_uri: data.redirect_uri,
+            user_id: UserId::parse(data.user_id).map_err(|_| AuthorizationCodeStoreError::UnexpectedError)?,
+            code_challenge: CodeChallenge::parse(data.code_challenge)
+                .map_err(|_| AuthorizationCodeStoreError::UnexpectedError)?,
+            scopes: data.scopes
+                .into_iter()
+                .map(Scope::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|_| AuthorizationCodeStoreError::UnexpectedError)?,
+            nonce: data.nonce,
+            authenticated_at: data.authenticated_at,
+            auth_methods: data.auth_methods
+                .into_iter()
+                .map(AuthMethod::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|_| AuthorizationCodeStoreError::UnexpectedError)?,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct AuthorizationGrantData {
+    client_id: String,
+    redirect_uri: String,
+    user_id: String,
+    code_challenge: String,
+    scopes: Vec<String>,
+    nonce: Option<String>,
+    authenticated_at: i64,
+    auth_methods: Vec<String>,
+}
+
+const AUTHORIZATION_CODE_PREFIX: &str = "authorization_code:";
+
+fn get_key(code: &AuthorizationCode) -> String {
+    format!("{}{}", AUTHORIZATION_CODE_PREFIX, code.as_ref())
+}
@@ -3,6 +3,8 @@ use std::sync::Arc;
 use redis::{Commands, Connection};
 use tokio::sync::RwLock;
 
+use crate::user::UserId;
+
 use super::{auth::TOKEN_TTL_SECONDS, tokens::{BannedTokenStore, BannedTokenStoreError}};
 
 pub struct RedisBannedTokenStore {
@@ -48,11 +50,41 @@ impl BannedTokenStore for RedisBannedTokenStore {
 
         Ok(is_banned)
     }
+
+    async fn revoke_user_tokens(&mut self, user_id: &UserId, issued_before_ms: i64) -> Result<(), BannedTokenStoreError> {
+        // Every token issued before the cutoff has expired by the time the marker does
+        let ttl: u64 = TOKEN_TTL_SECONDS
+            .try_into()
+            .map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .set_ex(get_user_key(user_id), issued_before_ms, ttl)
+            .map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn user_tokens_revoked_before(&self, user_id: &UserId) -> Result<Option<i64>, BannedTokenStoreError> {
+        self.conn
+            .write()
+            .await
+            .get(get_user_key(user_id))
+            .map_err(|_| BannedTokenStoreError::UnexpectedError)
+    }
 }
 
 // We are using a key prefix to prevent collisions and organize data!
 const BANNED_TOKEN_KEY_PREFIX: &str = "banned_token:";
 
+const REVOKED_BEFORE_KEY_PREFIX: &str = "tokens_revoked_before:";
+
 fn get_key(token: &str) -> String {
     format!("{}{}", BANNED_TOKEN_KEY_PREFIX, token)
+}
+
+fn get_user_key(user_id: &UserId) -> String {
+    format!("{}{}", REVOKED_BEFORE_KEY_PREFIX, user_id.as_ref())
 }
\ No newline at end of file
@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use redis::{Commands, Connection};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::user::Email;
+
+use super::email_changes::{
+    EmailChange, EmailChangeId, EmailChangeStore, EmailChangeStoreError, EMAIL_CHANGE_TTL_SECONDS
+};
+
+pub struct RedisEmailChangeStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl RedisEmailChangeStore {
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailChangeStore for RedisEmailChangeStore {
+    async fn add_change(&mut self, id: EmailChangeId, change: EmailChange) -> Result<(), EmailChangeStoreError> {
+        let ttl: u64 = EMAIL_CHANGE_TTL_SECONDS
+            .try_into()
+            .map_err(|_| EmailChangeStoreError::UnexpectedError)?;
+
+        let data = EmailChangeData {
+            email: change.email.as_ref().to_owned(),
+            new_email: change.new_email.as_ref().to_owned(),
+        };
+        let value = serde_json::to_string(&data).map_err(|_| EmailChangeStoreError::UnexpectedError)?;
+
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .set_ex(get_key(&id), value, ttl)
+            .map_err(|_| EmailChangeStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn take_change(&mut self, id: &EmailChangeId) -> Result<EmailChange, EmailChangeStoreError> {
+        let value: Option<String> = self
+            .conn
+            .write()
+            .await
+            .get_del(get_key(id))
+            .map_err(|_| EmailChangeStoreError::UnexpectedError)?;
+
+        let value = value.ok_or(EmailChangeStoreError::ChangeNotFound)?;
+
+        let data: EmailChangeData = serde_json::from_str(&value)
+            .map_err(|_| EmailChangeStoreError::UnexpectedError)?;
+
+        Ok(EmailChange {
+            email: Email::parse(data.email).map_err(|_| EmailChangeStoreError::UnexpectedError)?,
+            new_email: Email::parse(data.new_email).map_err(|_| EmailChangeStoreError::UnexpectedError)?,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct EmailChangeData {
+    email: String,
+    new_email: String,
+}
+
+const EMAIL_CHANGE_PREFIX: &str = "email_change:";
+
+fn get_key(id: &EmailChangeId) -> String {
+    format!("{}{}", EMAIL_CHANGE_PREFIX, id.as_ref())
+}
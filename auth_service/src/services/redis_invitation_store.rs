@@ -0,0 +1,80 @@
+use std::sync::Arc;
+
+use redis::{Commands, Connection};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::user::Email;
+
+use super::{
+    invitations::{Invitation, InvitationId, InvitationStore, InvitationStoreError, INVITATION_TTL_SECONDS},
+    organizations::{OrgRole, OrganizationId}
+};
+
+pub struct RedisInvitationStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl RedisInvitationStore {
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl InvitationStore for RedisInvitationStore {
+    async fn add_invitation(&mut self, id: InvitationId, invitation: Invitation) -> Result<(), InvitationStoreError> {
+        let ttl: u64 = INVITATION_TTL_SECONDS
+            .try_into()
+            .map_err(|_| InvitationStoreError::UnexpectedError)?;
+
+        let data = InvitationData {
+            org_id: invitation.org_id.as_ref().to_owned(),
+            email: invitation.email.as_ref().to_owned(),
+            role: invitation.role,
+        };
+        let value = serde_json::to_string(&data).map_err(|_| InvitationStoreError::UnexpectedError)?;
+
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .set_ex(get_key(&id), value, ttl)
+            .map_err(|_| InvitationStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn take_invitation(&mut self, id: &InvitationId) -> Result<Invitation, InvitationStoreError> {
+        let value: Option<String> = self
+            .conn
+            .write()
+            .await
+            .get_del(get_key(id))
+            .map_err(|_| InvitationStoreError::UnexpectedError)?;
+
+        let value = value.ok_or(InvitationStoreError::InvitationNotFound)?;
+
+        let data: InvitationData = serde_json::from_str(&value)
+            .map_err(|_| InvitationStoreError::UnexpectedError)?;
+
+        Ok(Invitation {
+            org_id: OrganizationId::parse(data.org_id).map_err(|_| InvitationStoreError::UnexpectedError)?,
+            email: Email::parse(data.email).map_err(|_| InvitationStoreError::UnexpectedError)?,
+            role: data.role,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct InvitationData {
+    org_id: String,
+    email: String,
+    role: OrgRole,
+}
+
+const INVITATION_PREFIX: &str = "invitation:";
+
+fn get_key(id: &InvitationId) -> String {
+    format!("{}{}", INVITATION_PREFIX, id.as_ref())
+}
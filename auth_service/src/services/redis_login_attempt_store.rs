@@ -0,0 +1,100 @@
+use std::sync::Arc;
+
+use redis::{Commands, Connection};
+use tokio::sync::RwLock;
+
+use crate::user::Email;
+
+use super::login_attempts::{LockoutPolicy, LoginAttemptStore, LoginAttemptStoreError, LOGIN_ATTEMPT_MEMORY_SECONDS};
+
+pub struct RedisLoginAttemptStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl RedisLoginAttemptStore {
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl LoginAttemptStore for RedisLoginAttemptStore {
+    async fn get_locked_until(&self, email: &Email, now: i64) -> Result<Option<i64>, LoginAttemptStoreError> {
+        let locked_until: Option<i64> = self
+            .conn
+            .write()
+            .await
+            .get(get_key(LOCKED_UNTIL_PREFIX, email))
+            .map_err(|_| LoginAttemptStoreError::UnexpectedError)?;
+
+        Ok(locked_until.filter(|locked_until| *locked_until > now))
+    }
+
+    // INCR keeps the counts right even when several instances share the same Redis
+    async fn record_failure(&mut self, email: &Email, policy: &LockoutPolicy, now: i64) -> Result<Option<i64>, LoginAttemptStoreError> {
+        let failures_key = get_key(FAILURES_PREFIX, email);
+        let lockouts_key = get_key(LOCKOUTS_PREFIX, email);
+        let mut conn = self.conn.write().await;
+
+        let failures: u32 = conn
+            .incr(&failures_key, 1)
+            .map_err(|_| LoginAttemptStoreError::UnexpectedError)?;
+
+        let _: () = conn
+            .expire(&failures_key, LOGIN_ATTEMPT_MEMORY_SECONDS)
+            .map_err(|_| LoginAttemptStoreError::UnexpectedError)?;
+
+        if failures < policy.threshold {
+            return Ok(None);
+        }
+
+        let _: () = conn
+            .del(&failures_key)
+            .map_err(|_| LoginAttemptStoreError::UnexpectedError)?;
+
+        let lockouts: u32 = conn
+            .incr(&lockouts_key, 1)
+            .map_err(|_| LoginAttemptStoreError::UnexpectedError)?;
+
+        let _: () = conn
+            .expire(&lockouts_key, LOGIN_ATTEMPT_MEMORY_SECONDS)
+            .map_err(|_| LoginAttemptStoreError::UnexpectedError)?;
+
+        let lockout_seconds = policy.lockout_seconds(lockouts);
+        let locked_until = now + lockout_seconds;
+
+        let ttl: u64 = lockout_seconds
+            .try_into()
+            .map_err(|_| LoginAttemptStoreError::UnexpectedError)?;
+
+        let _: () = conn
+            .set_ex(get_key(LOCKED_UNTIL_PREFIX, email), locked_until, ttl)
+            .map_err(|_| LoginAttemptStoreError::UnexpectedError)?;
+
+        Ok(Some(locked_until))
+    }
+
+    async fn clear(&mut self, email: &Email) -> Result<(), LoginAttemptStoreError> {
+        let keys: Vec<String> = [FAILURES_PREFIX, LOCKOUTS_PREFIX, LOCKED_UNTIL_PREFIX]
+            .into_iter()
+            .map(|prefix| get_key(prefix, email))
+            .collect();
+
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .del(&keys)
+            .map_err(|_| LoginAttemptStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
+
+const FAILURES_PREFIX: &str = "login_failures:";
+const LOCKOUTS_PREFIX: &str = "login_lockouts:";
+const LOCKED_UNTIL_PREFIX: &str = "login_locked_until:";
+
+fn get_key(prefix: &str, email: &Email) -> String {
+    format!("{}{}", prefix, email.as_ref())
+}
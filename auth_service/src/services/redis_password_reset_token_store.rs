@@ -0,0 +1,90 @@
+use std::sync::Arc;
+
+use redis::{Commands, Connection};
+use tokio::sync::RwLock;
+
+use crate::user::Email;
+
+use super::password_reset_tokens::{
+    PasswordResetId, PasswordResetTokenStore, PasswordResetTokenStoreError, PASSWORD_RESET_TTL_SECONDS
+};
+
+pub struct RedisPasswordResetTokenStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl RedisPasswordResetTokenStore {
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl PasswordResetTokenStore for RedisPasswordResetTokenStore {
+    async fn add_token(&mut self, id: PasswordResetId, email: Email) -> Result<(), PasswordResetTokenStoreError> {
+        let ttl: u64 = PASSWORD_RESET_TTL_SECONDS
+            .try_into()
+            .map_err(|_| PasswordResetTokenStoreError::UnexpectedError)?;
+
+        let user_key = get_user_key(&email);
+        let mut conn = self.conn.write().await;
+
+        let _: () = conn
+            .set_ex(get_key(&id), email.as_ref(), ttl)
+            .map_err(|_| PasswordResetTokenStoreError::UnexpectedError)?;
+
+        // Index the link by address, so they can all be dropped without scanning for them
+        let _: () = conn
+            .sadd(&user_key, id.as_ref())
+            .map_err(|_| PasswordResetTokenStoreError::UnexpectedError)?;
+
+        let _: () = conn
+            .expire(&user_key, PASSWORD_RESET_TTL_SECONDS)
+            .map_err(|_| PasswordResetTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn take_token(&mut self, id: &PasswordResetId) -> Result<Email, PasswordResetTokenStoreError> {
+        let value: Option<String> = self
+            .conn
+            .write()
+            .await
+            .get_del(get_key(id))
+            .map_err(|_| PasswordResetTokenStoreError::UnexpectedError)?;
+
+        let value = value.ok_or(PasswordResetTokenStoreError::TokenNotFound)?;
+
+        Email::parse(value).map_err(|_| PasswordResetTokenStoreError::UnexpectedError)
+    }
+
+    // Ids of links that were already used are left in the index, deleting their keys again is harmless
+    async fn remove_user_tokens(&mut self, email: &Email) -> Result<(), PasswordResetTokenStoreError> {
+        let user_key = get_user_key(email);
+        let mut conn = self.conn.write().await;
+
+        let ids: Vec<String> = conn
+            .smembers(&user_key)
+            .map_err(|_| PasswordResetTokenStoreError::UnexpectedError)?;
+
+        let mut keys = vec![user_key];
+        keys.extend(ids.iter().map(|id| format!("{}{}", PASSWORD_RESET_PREFIX, id)));
+
+        let _: () = conn
+            .del(&keys)
+            .map_err(|_| PasswordResetTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
+
+const PASSWORD_RESET_PREFIX: &str = "password_reset:";
+const PASSWORD_RESET_USER_PREFIX: &str = "password_reset_user:";
+
+fn get_key(id: &PasswordResetId) -> String {
+    format!("{}{}", PASSWORD_RESET_PREFIX, id.as_ref())
+}
+
+fn get_user_key(email: &Email) -> String {
+    format!("{}{}", PASSWORD_RESET_USER_PREFIX, email.as_ref())
+}
@@ -0,0 +1,78 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use redis::{aio::MultiplexedConnection, AsyncCommands, RedisError};
+
+use super::rate_limits::{RateLimit, RateLimitCounters, RateLimitStore, RateLimitStoreError};
+
+// Uses its own async connection rather than the shared blocking one, since every request goes through here
+pub struct RedisRateLimitStore {
+    conn: MultiplexedConnection,
+    // Keeps limiting, per instance, while Redis can't be reached
+    fallback: RateLimitCounters,
+    failures: AtomicU64,
+}
+
+impl RedisRateLimitStore {
+    pub fn new(conn: MultiplexedConnection) -> Self {
+        Self {
+            conn,
+            fallback: RateLimitCounters::default(),
+            failures: AtomicU64::new(0),
+        }
+    }
+
+    // How many checks Redis failed since the start, each of them counted locally instead
+    pub fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    // Counting first with INCR keeps the limit exact when several instances share the same Redis
+    async fn hit_redis(&self, key: &str, limit: &RateLimit, now: i64) -> Result<Option<i64>, RedisError> {
+        let window = now.div_euclid(limit.window_seconds);
+        let elapsed = now.rem_euclid(limit.window_seconds);
+        let current_key = get_key(key, window);
+        let mut conn = self.conn.clone();
+
+        // Kept around while it still counts as the previous window
+        let (counted, previous): (u64, Option<u64>) = redis::pipe()
+            .incr(&current_key, 1)
+            .expire(&current_key, limit.window_seconds * 2)
+            .ignore()
+            .get(get_key(key, window - 1))
+            .query_async(&mut conn)
+            .await?;
+        let previous = previous.unwrap_or(0);
+
+        // The requests before this one
+        let current = counted - 1;
+
+        if limit.allows(previous, current, elapsed) {
+            return Ok(None);
+        }
+
+        // Rejected requests don't count
+        let _: () = conn.decr(&current_key, 1).await?;
+
+        Ok(Some(limit.retry_after(previous, current, elapsed)))
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for RedisRateLimitStore {
+    async fn hit(&self, key: &str, limit: &RateLimit, now: i64) -> Result<Option<i64>, RateLimitStoreError> {
+        match self.hit_redis(key, limit, now).await {
+            Ok(retry_after) => Ok(retry_after),
+            Err(e) => {
+                let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+                tracing::error!("Failed to count request in Redis, counting locally ({} failures so far): {:?}", failures, e);
+                self.fallback.hit(key, limit, now).await
+            }
+        }
+    }
+}
+
+const RATE_LIMIT_PREFIX: &str = "rate_limit:";
+
+fn get_key(key: &str, window: i64) -> String {
+    format!("{}{}:{}", RATE_LIMIT_PREFIX, key, window)
+}
@@ -0,0 +1,195 @@
+use std::sync::Arc;
+
+use chrono::Utc;
+use redis::{Commands, Connection};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::user::Email;
+
+use super::refresh_tokens::{
+    RefreshToken, RefreshTokenFamily, RefreshTokenRecord, RefreshTokenStore, RefreshTokenStoreError
+};
+
+pub struct RedisRefreshTokenStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl RedisRefreshTokenStore {
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl RefreshTokenStore for RedisRefreshTokenStore {
+    async fn add_token(&mut self, token: RefreshToken, record: RefreshTokenRecord) -> Result<(), RefreshTokenStoreError> {
+        let token_key = get_key(&token);
+        let family_key = get_family_key(&record.family);
+        let user_key = get_user_key(&record.email);
+        let ttl = get_ttl(&record)?;
+
+        let serialized_data = serde_json::to_string(&RefreshTokenData::from(&record))
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        let mut conn = self.conn.write().await;
+
+        let _: () = conn
+            .set_ex(&token_key, serialized_data, ttl)
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        // The family set has to outlive every token in it, and the newest token always expires last
+        let _: () = conn
+            .sadd(&family_key, token.as_ref())
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        let _: () = conn
+            .expire(&family_key, ttl as i64)
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        // Same goes for the set of families a user has, which revoke_all walks
+        let _: () = conn
+            .sadd(&user_key, record.family.as_ref())
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        let _: () = conn
+            .expire(&user_key, ttl as i64)
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn get_token(&self, token: &RefreshToken) -> Result<RefreshTokenRecord, RefreshTokenStoreError> {
+        let key = get_key(token);
+
+        match self.conn.write().await.get::<_, String>(&key) {
+            Ok(value) => {
+                let data: RefreshTokenData = serde_json::from_str(&value)
+                    .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+                data.try_into()
+            }
+            Err(_) => Err(RefreshTokenStoreError::TokenNotFound),
+        }
+    }
+
+    async fn mark_used(&mut self, token: &RefreshToken) -> Result<(), RefreshTokenStoreError> {
+        let mut record = self.get_token(token).await?;
+
+        if record.used {
+            return Err(RefreshTokenStoreError::TokenAlreadyUsed);
+        }
+
+        record.used = true;
+
+        let ttl = get_ttl(&record)?;
+        let serialized_data = serde_json::to_string(&RefreshTokenData::from(&record))
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .set_ex(get_key(token), serialized_data, ttl)
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn revoke_family(&mut self, family: &RefreshTokenFamily) -> Result<(), RefreshTokenStoreError> {
+        delete_family(&mut *self.conn.write().await, get_family_key(family))
+    }
+
+    async fn revoke_all(&mut self, email: &Email) -> Result<(), RefreshTokenStoreError> {
+        let user_key = get_user_key(email);
+        let mut conn = self.conn.write().await;
+
+        let families: Vec<String> = conn
+            .smembers(&user_key)
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        for family in families {
+            delete_family(&mut conn, format!("{}{}", REFRESH_TOKEN_FAMILY_KEY_PREFIX, family))?;
+        }
+
+        let _: () = conn
+            .del(&user_key)
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
+
+// Delete a family set along with every token in it
+fn delete_family(conn: &mut Connection, family_key: String) -> Result<(), RefreshTokenStoreError> {
+    let tokens: Vec<String> = conn
+        .smembers(&family_key)
+        .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+    let mut keys: Vec<String> = tokens
+        .iter()
+        .map(|token| format!("{}{}", REFRESH_TOKEN_KEY_PREFIX, token))
+        .collect();
+    keys.push(family_key);
+
+    let _: () = conn
+        .del(keys)
+        .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize)]
+struct RefreshTokenData {
+    email: String,
+    family: String,
+    used: bool,
+    expires_at: i64,
+}
+
+impl From<&RefreshTokenRecord> for RefreshTokenData {
+    fn from(record: &RefreshTokenRecord) -> Self {
+        Self {
+            email: record.email.as_ref().to_owned(),
+            family: record.family.as_ref().to_owned(),
+            used: record.used,
+            expires_at: record.expires_at,
+        }
+    }
+}
+
+impl TryFrom<RefreshTokenData> for RefreshTokenRecord {
+    type Error = RefreshTokenStoreError;
+
+    fn try_from(data: RefreshTokenData) -> Result<Self, Self::Error> {
+        Ok(Self {
+            email: Email::parse(data.email).map_err(|_| RefreshTokenStoreError::UnexpectedError)?,
+            family: RefreshTokenFamily::parse(data.family)
+                .map_err(|_| RefreshTokenStoreError::UnexpectedError)?,
+            used: data.used,
+            expires_at: data.expires_at,
+        })
+    }
+}
+
+fn get_ttl(record: &RefreshTokenRecord) -> Result<u64, RefreshTokenStoreError> {
+    (record.expires_at - Utc::now().timestamp())
+        .try_into()
+        .map_err(|_| RefreshTokenStoreError::UnexpectedError)
+}
+
+const REFRESH_TOKEN_KEY_PREFIX: &str = "refresh_token:";
+const REFRESH_TOKEN_FAMILY_KEY_PREFIX: &str = "refresh_token_family:";
+const REFRESH_TOKEN_USER_KEY_PREFIX: &str = "refresh_token_user:";
+
+fn get_key(token: &RefreshToken) -> String {
+    format!("{}{}", REFRESH_TOKEN_KEY_PREFIX, token.as_ref())
+}
+
+fn get_family_key(family: &RefreshTokenFamily) -> String {
+    format!("{}{}", REFRESH_TOKEN_FAMILY_KEY_PREFIX, family.as_ref())
+}
+
+fn get_user_key(email: &Email) -> String {
+    format!("{}{}", REFRESH_TOKEN_USER_KEY_PREFIX, email.as_ref())
+}
@@ -1,12 +1,17 @@
 use std::sync::Arc;
 
+use chrono::{DateTime, Utc};
 use redis::{Commands, Connection};
 use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 
 use crate::user::Email;
 
-use super::{api::TwoFaCodeError, two_fa::{LoginId, TwoFaCode, TwoFaCodes}};
+use super::{
+    api::TwoFaCodeError,
+    client_info::ClientInfo,
+    two_fa::{LoginId, TwoFaChallenge, TwoFaCode, TwoFaCodes, TWO_FA_CHALLENGE_TTL_SECONDS}
+};
 
 pub struct RedisTwoFACodeStore {
     conn: Arc<RwLock<Connection>>,
@@ -20,74 +25,204 @@ impl RedisTwoFACodeStore {
 
 #[async_trait::async_trait]
 impl TwoFaCodes for RedisTwoFACodeStore {
-    async fn add_code(
+    async fn add_challenge(
         &mut self,
-        email: Email,
-        login_attempt_id: LoginId,
-        code: TwoFaCode,
+        login_id: LoginId,
+        challenge: TwoFaChallenge,
     ) -> Result<(), TwoFaCodeError> {
-        let key = get_key(&email);
+        let key = get_key(&login_id);
+        let user_key = get_user_key(&challenge.email);
+        let ttl = ttl_seconds(&challenge);
+        let serialized_data = serialize_challenge(challenge)?;
 
-        let data = TwoFATuple(
-            login_attempt_id.as_ref().to_owned(),
-            code.as_ref().to_owned(),
-        );
-        let serialized_data =
-            serde_json::to_string(&data).map_err(|_| TwoFaCodeError::UnexpectedError)?;
+        let mut conn = self.conn.write().await;
 
-        let _: () = self
-            .conn
-            .write()
-            .await
-            .set_ex(&key, serialized_data, TEN_MINUTES_IN_SECONDS)
+        let _: () = conn
+            .set_ex(&key, serialized_data, ttl)
+            .map_err(|_| TwoFaCodeError::UnexpectedError)?;
+
+        // A fresh challenge gets a fresh set of tries
+        let _: () = conn
+            .del(get_attempts_key(&login_id))
+            .map_err(|_| TwoFaCodeError::UnexpectedError)?;
+
+        // Index the attempt by user, so they can all be dropped without scanning for them
+        let _: () = conn
+            .sadd(&user_key, login_id.as_ref())
+            .map_err(|_| TwoFaCodeError::UnexpectedError)?;
+
+        let _: () = conn
+            .expire(&user_key, TWO_FA_CHALLENGE_TTL_SECONDS)
             .map_err(|_| TwoFaCodeError::UnexpectedError)?;
 
         Ok(())
     }
 
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFaCodeError> {
-        let key = get_key(email);
+    async fn remove_challenge(&mut self, login_id: &LoginId) -> Result<(), TwoFaCodeError> {
+        let keys = [get_key(login_id), get_attempts_key(login_id)];
 
         let _: () = self
             .conn
             .write()
             .await
-            .del(&key)
+            .del(&keys)
             .map_err(|_| TwoFaCodeError::UnexpectedError)?;
 
         Ok(())
     }
 
-    async fn get_code(
+    async fn get_challenge(
         &self,
-        email: &Email,
-    ) -> Result<(LoginId, TwoFaCode), TwoFaCodeError> {
-        let key = get_key(email);
+        login_id: &LoginId,
+    ) -> Result<TwoFaChallenge, TwoFaCodeError> {
+        let key = get_key(login_id);
 
         match self.conn.write().await.get::<_, String>(&key) {
             Ok(value) => {
-                let data: TwoFATuple = serde_json::from_str(&value)
+                let data: TwoFaChallengeData = serde_json::from_str(&value)
                     .map_err(|_| TwoFaCodeError::UnexpectedError)?;
 
-                let login_attempt_id = LoginId::parse(data.0)
+                let email = Email::parse(data.email)
                     .map_err(|_| TwoFaCodeError::UnexpectedError)?;
 
                 let email_code =
-                    TwoFaCode::parse(data.1).map_err(|_| TwoFaCodeError::UnexpectedError)?;
-
-                Ok((login_attempt_id, email_code))
+                    TwoFaCode::parse(data.code).map_err(|_| TwoFaCodeError::UnexpectedError)?;
+
+                let expires_at = DateTime::<Utc>::from_timestamp(data.expires_at, 0)
+                    .ok_or(TwoFaCodeError::UnexpectedError)?;
+
+                let last_sent_at = DateTime::<Utc>::from_timestamp(data.last_sent_at, 0)
+                    .ok_or(TwoFaCodeError::UnexpectedError)?;
+
+                Ok(TwoFaChallenge {
+                    email,
+                    code: email_code,
+                    client: data.client,
+                    expires_at,
+                    last_sent_at,
+                    resend_count: data.resend_count,
+                })
             }
             Err(_) => Err(TwoFaCodeError::LoginAttemptIdNotFound),
         }
     }
+
+    async fn update_challenge(&mut self, login_id: &LoginId, challenge: TwoFaChallenge) -> Result<(), TwoFaCodeError> {
+        let user_key = get_user_key(&challenge.email);
+        let ttl = ttl_seconds(&challenge);
+        let serialized_data = serialize_challenge(challenge)?;
+
+        let mut conn = self.conn.write().await;
+
+        // XX only overwrites a challenge that hasn't expired or been used in the meantime
+        let updated: Option<String> = redis::cmd("SET")
+            .arg(get_key(login_id))
+            .arg(serialized_data)
+            .arg("EX")
+            .arg(ttl)
+            .arg("XX")
+            .query(&mut *conn)
+            .map_err(|_| TwoFaCodeError::UnexpectedError)?;
+
+        updated.ok_or(TwoFaCodeError::LoginAttemptIdNotFound)?;
+
+        // A resend can push the expiry out, the index has to live at least as long
+        let _: () = conn
+            .expire(&user_key, TWO_FA_CHALLENGE_TTL_SECONDS)
+            .map_err(|_| TwoFaCodeError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn record_failed_attempt(&mut self, login_id: &LoginId, max_attempts: u32) -> Result<(), TwoFaCodeError> {
+        let attempts_key = get_attempts_key(login_id);
+
+        // INCR keeps the count right even when several instances share the same Redis
+        let attempts: u32 = {
+            let mut conn = self.conn.write().await;
+
+            let attempts = conn
+                .incr(&attempts_key, 1)
+                .map_err(|_| TwoFaCodeError::UnexpectedError)?;
+
+            let _: () = conn
+                .expire(&attempts_key, TWO_FA_CHALLENGE_TTL_SECONDS)
+                .map_err(|_| TwoFaCodeError::UnexpectedError)?;
+
+            attempts
+        };
+
+        if attempts >= max_attempts {
+            self.remove_challenge(login_id).await?;
+            return Err(TwoFaCodeError::TooManyAttempts);
+        }
+
+        Ok(())
+    }
+
+    // Ids of attempts that already ended are left in the index, deleting their keys again is harmless
+    async fn remove_user_challenges(&mut self, email: &Email) -> Result<(), TwoFaCodeError> {
+        let user_key = get_user_key(email);
+        let mut conn = self.conn.write().await;
+
+        let login_ids: Vec<String> = conn
+            .smembers(&user_key)
+            .map_err(|_| TwoFaCodeError::UnexpectedError)?;
+
+        let mut keys = vec![user_key];
+        for login_id in login_ids {
+            keys.push(format!("{}{}", TWO_FA_CODE_PREFIX, login_id));
+            keys.push(format!("{}{}", TWO_FA_ATTEMPTS_PREFIX, login_id));
+        }
+
+        let _: () = conn
+            .del(&keys)
+            .map_err(|_| TwoFaCodeError::UnexpectedError)?;
+
+        Ok(())
+    }
 }
 
 #[derive(Serialize, Deserialize)]
-struct TwoFATuple(pub String, pub String);
+struct TwoFaChallengeData {
+    email: String,
+    code: String,
+    client: ClientInfo,
+    expires_at: i64,
+    last_sent_at: i64,
+    resend_count: u32,
+}
+
+// Let Redis drop the challenge when it expires
+fn ttl_seconds(challenge: &TwoFaChallenge) -> u64 {
+    (challenge.expires_at - Utc::now()).num_seconds().max(1) as u64
+}
+
+fn serialize_challenge(challenge: TwoFaChallenge) -> Result<String, TwoFaCodeError> {
+    let data = TwoFaChallengeData {
+        email: challenge.email.as_ref().to_owned(),
+        code: challenge.code.as_ref().to_owned(),
+        client: challenge.client,
+        expires_at: challenge.expires_at.timestamp(),
+        last_sent_at: challenge.last_sent_at.timestamp(),
+        resend_count: challenge.resend_count,
+    };
+
+    serde_json::to_string(&data).map_err(|_| TwoFaCodeError::UnexpectedError)
+}
 
-const TEN_MINUTES_IN_SECONDS: u64 = 600;
 const TWO_FA_CODE_PREFIX: &str = "two_fa_code:";
+const TWO_FA_ATTEMPTS_PREFIX: &str = "two_fa_attempts:";
+const TWO_FA_USER_PREFIX: &str = "two_fa_user:";
+
+fn get_key(login_id: &LoginId) -> String {
+    format!("{}{}", TWO_FA_CODE_PREFIX, login_id.as_ref())
+}
 
-fn get_key(email: &Email) -> String {
-    format!("{}{}", TWO_FA_CODE_PREFIX, email.as_ref())
-}
\ No newline at end of file
+fn get_attempts_key(login_id: &LoginId) -> String {
+    format!("{}{}", TWO_FA_ATTEMPTS_PREFIX, login_id.as_ref())
+}
+
+fn get_user_key(email: &Email) -> String {
+    format!("{}{}", TWO_FA_USER_PREFIX, email.as_ref())
+}
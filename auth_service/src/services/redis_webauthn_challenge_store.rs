@@ -0,0 +1,115 @@
+use std::sync::Arc;
+
+use redis::{Commands, Connection};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::user::Email;
+
+use super::{
+    passkeys::UserHandle,
+    two_fa::LoginId,
+    webauthn::{Challenge, CEREMONY_TIMEOUT_SECONDS},
+    webauthn_challenges::{Ceremony, WebauthnChallengeStore, WebauthnChallengeStoreError}
+};
+
+pub struct RedisWebauthnChallengeStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl RedisWebauthnChallengeStore {
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl WebauthnChallengeStore for RedisWebauthnChallengeStore {
+    async fn add_challenge(&mut self, challenge: Challenge, ceremony: Ceremony) -> Result<(), WebauthnChallengeStoreError> {
+        let serialized_data = serde_json::to_string(&CeremonyData::from(&ceremony))
+            .map_err(|_| WebauthnChallengeStoreError::UnexpectedError)?;
+
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .set_ex(get_key(&challenge), serialized_data, CEREMONY_TIMEOUT_SECONDS)
+            .map_err(|_| WebauthnChallengeStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn take_challenge(&mut self, challenge: &Challenge) -> Result<Ceremony, WebauthnChallengeStoreError> {
+        let value: Option<String> = self
+            .conn
+            .write()
+            .await
+            .get_del(get_key(challenge))
+            .map_err(|_| WebauthnChallengeStoreError::UnexpectedError)?;
+
+        let value = value.ok_or(WebauthnChallengeStoreError::ChallengeNotFound)?;
+        let data: CeremonyData = serde_json::from_str(&value)
+            .map_err(|_| WebauthnChallengeStoreError::UnexpectedError)?;
+
+        data.try_into()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+enum CeremonyData {
+    Registration {
+        email: String,
+        user_handle: String,
+    },
+    Authentication {
+        email: Option<String>,
+        login_id: Option<String>,
+    },
+}
+
+impl From<&Ceremony> for CeremonyData {
+    fn from(ceremony: &Ceremony) -> Self {
+        match ceremony {
+            Ceremony::Registration { email, user_handle } => Self::Registration {
+                email: email.as_ref().to_owned(),
+                user_handle: user_handle.as_ref().to_owned(),
+            },
+            Ceremony::Authentication { login_attempt } => Self::Authentication {
+                email: login_attempt.as_ref().map(|(email, _)| email.as_ref().to_owned()),
+                login_id: login_attempt.as_ref().map(|(_, login_id)| login_id.as_ref().to_owned()),
+            },
+        }
+    }
+}
+
+impl TryFrom<CeremonyData> for Ceremony {
+    type Error = WebauthnChallengeStoreError;
+
+    fn try_from(data: CeremonyData) -> Result<Self, Self::Error> {
+        let unexpected = |_| WebauthnChallengeStoreError::UnexpectedError;
+
+        match data {
+            CeremonyData::Registration { email, user_handle } => Ok(Self::Registration {
+                email: Email::parse(email).map_err(unexpected)?,
+                user_handle: UserHandle::parse(user_handle).map_err(unexpected)?,
+            }),
+            CeremonyData::Authentication { email: Some(email), login_id: Some(login_id) } => Ok(Self::Authentication {
+                login_attempt: Some((
+                    Email::parse(email).map_err(unexpected)?,
+                    LoginId::parse(login_id).map_err(unexpected)?,
+                )),
+            }),
+            CeremonyData::Authentication { email: None, login_id: None } => Ok(Self::Authentication {
+                login_attempt: None,
+            }),
+            CeremonyData::Authentication { .. } => Err(WebauthnChallengeStoreError::UnexpectedError),
+        }
+    }
+}
+
+const WEBAUTHN_CHALLENGE_PREFIX: &str = "webauthn_challenge:";
+
+fn get_key(challenge: &Challenge) -> String {
+    format!("{}{}", WEBAUTHN_CHALLENGE_PREFIX, challenge.as_ref())
+}
@@ -0,0 +1,252 @@
+use std::{collections::HashMap, sync::Arc};
+use async_trait::async_trait;
+use chrono::Utc;
+use rand::{distributions::Alphanumeric, Rng};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::user::Email;
+
+use super::sessions::SessionId;
+
+pub type RefreshTokenStoreType = Arc<RwLock<dyn RefreshTokenStore + Send + Sync>>;
+
+const REFRESH_TOKEN_LENGTH: usize = 64;
+
+// Opaque refresh token handed to the client in the refresh cookie
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RefreshToken(String);
+
+impl RefreshToken {
+    pub fn parse(token: String) -> Result<Self, String> {
+        if token.len() == REFRESH_TOKEN_LENGTH && token.chars().all(|c| c.is_ascii_alphanumeric()) {
+            Ok(Self(token))
+        } else {
+            Err(String::from("Invalid refresh token format"))
+        }
+    }
+}
+
+impl Default for RefreshToken {
+    fn default() -> Self {
+        let token = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(REFRESH_TOKEN_LENGTH)
+            .map(char::from)
+            .collect();
+        Self(token)
+    }
+}
+
+impl AsRef<str> for RefreshToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// Every token issued by rotating a refresh token shares the family of the token it replaced,
+// so a replayed token can take down the whole chain
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RefreshTokenFamily(String);
+
+impl RefreshTokenFamily {
+    pub fn parse(id: String) -> Result<Self, String> {
+        let valid_id = Uuid::parse_str(&id)
+            .map_err(|_| String::from("Invalid refresh token family format"))?;
+        Ok(Self(valid_id.to_string()))
+    }
+}
+
+impl Default for RefreshTokenFamily {
+    fn default() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+}
+
+// The refresh tokens of a session make up one family, so revoking the family ends the session
+impl From<&SessionId> for RefreshTokenFamily {
+    fn from(session: &SessionId) -> Self {
+        Self(session.as_ref().to_owned())
+    }
+}
+
+impl AsRef<str> for RefreshTokenFamily {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RefreshTokenRecord {
+    pub email: Email,
+    pub family: RefreshTokenFamily,
+    pub used: bool,
+    pub expires_at: i64,
+}
+
+impl RefreshTokenRecord {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now().timestamp()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RefreshTokenStoreError {
+    TokenNotFound,
+    TokenAlreadyUsed,
+    UnexpectedError,
+}
+
+#[async_trait]
+pub trait RefreshTokenStore {
+    async fn add_token(&mut self, token: RefreshToken, record: RefreshTokenRecord) -> Result<(), RefreshTokenStoreError>;
+    async fn get_token(&self, token: &RefreshToken) -> Result<RefreshTokenRecord, RefreshTokenStoreError>;
+    // Only one caller gets to mark a token used, any other finds it already was
+    async fn mark_used(&mut self, token: &RefreshToken) -> Result<(), RefreshTokenStoreError>;
+    async fn revoke_family(&mut self, family: &RefreshTokenFamily) -> Result<(), RefreshTokenStoreError>;
+    // Revoke every refresh token issued to the user, ending all of their sessions
+    async fn revoke_all(&mut self, email: &Email) -> Result<(), RefreshTokenStoreError>;
+}
+
+#[derive(Default)]
+pub struct RefreshTokens {
+    tokens: HashMap<RefreshToken, RefreshTokenRecord>,
+}
+
+#[async_trait]
+impl RefreshTokenStore for RefreshTokens {
+    async fn add_token(&mut self, token: RefreshToken, record: RefreshTokenRecord) -> Result<(), RefreshTokenStoreError> {
+        self.tokens.insert(token, record);
+        Ok(())
+    }
+
+    async fn get_token(&self, token: &RefreshToken) -> Result<RefreshTokenRecord, RefreshTokenStoreError> {
+        self.tokens.get(token)
+            .filter(|record| !record.is_expired())
+            .cloned()
+            .ok_or(RefreshTokenStoreError::TokenNotFound)
+    }
+
+    async fn mark_used(&mut self, token: &RefreshToken) -> Result<(), RefreshTokenStoreError> {
+        match self.tokens.get_mut(token) {
+            Some(record) if record.used => Err(RefreshTokenStoreError::TokenAlreadyUsed),
+            Some(record) => {
+                record.used = true;
+                Ok(())
+            }
+            None => Err(RefreshTokenStoreError::TokenNotFound),
+        }
+    }
+
+    async fn revoke_family(&mut self, family: &RefreshTokenFamily) -> Result<(), RefreshTokenStoreError> {
+        self.tokens.retain(|_, record| record.family != *family);
+        Ok(())
+    }
+
+    async fn revoke_all(&mut self, email: &Email) -> Result<(), RefreshTokenStoreError> {
+        self.tokens.retain(|_, record| record.email != *email);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(family: &RefreshTokenFamily) -> RefreshTokenRecord {
+        RefreshTokenRecord {
+            email: Email::parse("test@example.com".to_owned()).unwrap(),
+            family: family.clone(),
+            used: false,
+            expires_at: Utc::now().timestamp() + 60,
+        }
+    }
+
+    #[test]
+    fn test_parse_refresh_token() {
+        let token = RefreshToken::default();
+        assert_eq!(RefreshToken::parse(token.as_ref().to_owned()), Ok(token));
+        assert!(RefreshToken::parse(String::from("invalid_token")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_add_token() {
+        let mut store = RefreshTokens::default();
+        let token = RefreshToken::default();
+        let record = record(&RefreshTokenFamily::default());
+
+        let result = store.add_token(token.clone(), record.clone()).await;
+
+        assert!(result.is_ok());
+        assert_eq!(store.tokens.get(&token), Some(&record));
+    }
+
+    #[tokio::test]
+    async fn test_get_expired_token() {
+        let mut store = RefreshTokens::default();
+        let token = RefreshToken::default();
+        let mut record = record(&RefreshTokenFamily::default());
+        record.expires_at = Utc::now().timestamp() - 1;
+        store.tokens.insert(token.clone(), record);
+
+        let result = store.get_token(&token).await;
+
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_mark_used() {
+        let mut store = RefreshTokens::default();
+        let token = RefreshToken::default();
+        store.tokens.insert(token.clone(), record(&RefreshTokenFamily::default()));
+
+        let result = store.mark_used(&token).await;
+
+        assert!(result.is_ok());
+        assert!(store.get_token(&token).await.unwrap().used);
+
+        let result = store.mark_used(&token).await;
+
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenAlreadyUsed));
+    }
+
+    #[tokio::test]
+    async fn test_revoke_family() {
+        let mut store = RefreshTokens::default();
+        let family = RefreshTokenFamily::default();
+        let other_family = RefreshTokenFamily::default();
+        let first = RefreshToken::default();
+        let second = RefreshToken::default();
+        let other = RefreshToken::default();
+        store.tokens.insert(first.clone(), record(&family));
+        store.tokens.insert(second.clone(), record(&family));
+        store.tokens.insert(other.clone(), record(&other_family));
+
+        let result = store.revoke_family(&family).await;
+
+        assert!(result.is_ok());
+        assert!(!store.tokens.contains_key(&first));
+        assert!(!store.tokens.contains_key(&second));
+        assert!(store.tokens.contains_key(&other));
+    }
+
+    #[tokio::test]
+    async fn test_revoke_all() {
+        let mut store = RefreshTokens::default();
+        let first = RefreshToken::default();
+        let second = RefreshToken::default();
+        let other = RefreshToken::default();
+        let mut other_record = record(&RefreshTokenFamily::default());
+        other_record.email = Email::parse("other@example.com".to_owned()).unwrap();
+        store.tokens.insert(first.clone(), record(&RefreshTokenFamily::default()));
+        store.tokens.insert(second.clone(), record(&RefreshTokenFamily::default()));
+        store.tokens.insert(other.clone(), other_record);
+
+        let result = store.revoke_all(&Email::parse("test@example.com".to_owned()).unwrap()).await;
+
+        assert!(result.is_ok());
+        assert!(!store.tokens.contains_key(&first));
+        assert!(!store.tokens.contains_key(&second));
+        assert!(store.tokens.contains_key(&other));
+    }
+}
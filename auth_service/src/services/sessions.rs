@@ -0,0 +1,214 @@
+use std::{cmp::Reverse, collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::user::UserId;
+
+use super::{
+    client_info::ClientInfo,
+    oauth_clients::OAuthClientId,
+    oidc::{AuthMethod, Scope}
+};
+
+pub type SessionStoreType = Arc<RwLock<dyn SessionStore + Send + Sync>>;
+
+// A session ends this long after it was last seen, which matches the lifetime of the refresh token keeping it going
+pub const SESSION_TTL_SECONDS: i64 = 2_592_000; // 30 days
+
+// Names a session in the `sid` claim of its auth tokens, and is also the family of its refresh tokens
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SessionId(String);
+
+impl SessionId {
+    pub fn parse(id: String) -> Result<Self, String> {
+        let valid_id = Uuid::parse_str(&id)
+            .map_err(|_| String::from("Invalid session id format"))?;
+        Ok(Self(valid_id.to_string()))
+    }
+}
+
+impl Default for SessionId {
+    fn default() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+}
+
+impl AsRef<str> for SessionId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// One login on one device, live until it's revoked or goes unused for SESSION_TTL_SECONDS
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub id: SessionId,
+    pub user_id: UserId,
+    // Readable name of the browser and OS, when the user agent gives them away
+    pub device: Option<String>,
+    pub client: ClientInfo,
+    pub created_at: i64,
+    // Updated whenever the session gets a new auth token
+    pub last_seen_at: i64,
+    // When and how the user proved who they are, which for a session an OAuth client started
+    // is the login of the browser session they authorized the client from
+    pub authenticated_at: i64,
+    pub auth_methods: Vec<AuthMethod>,
+    // Set for sessions an OAuth client started at /token, only that client can refresh them
+    pub oauth_client_id: Option<OAuthClientId>,
+    // What the user authorized the OAuth client to do, empty for other sessions
+    pub scopes: Vec<Scope>,
+}
+
+impl Session {
+    pub fn new(user_id: UserId, client: ClientInfo, now: i64) -> Self {
+        Self {
+            id: SessionId::default(),
+            user_id,
+            device: client.device(),
+            client,
+            created_at: now,
+            last_seen_at: now,
+            authenticated_at: now,
+            auth_methods: Vec::new(),
+            oauth_client_id: None,
+            scopes: Vec::new(),
+        }
+    }
+
+    pub fn authenticated_with(mut self, methods: Vec<AuthMethod>, at: i64) -> Self {
+        self.auth_methods = methods;
+        self.authenticated_at = at;
+        self
+    }
+
+    pub fn for_oauth_client(mut self, client_id: OAuthClientId, scopes: Vec<Scope>) -> Self {
+        self.oauth_client_id = Some(client_id);
+        self.scopes = scopes;
+        self
+    }
+
+    pub fn is_live(&self, now: i64) -> bool {
+        self.last_seen_at + SESSION_TTL_SECONDS > now
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SessionStoreError {
+    SessionNotFound,
+    UnexpectedError,
+}
+
+#[async_trait]
+pub trait SessionStore {
+    async fn add_session(&mut self, session: Session) -> Result<(), SessionStoreError>;
+    // Sessions that are no longer live aren't found
+    async fn get_session(&self, id: &SessionId, now: i64) -> Result<Session, SessionStoreError>;
+    // The live sessions of the user, most recently seen first
+    async fn list_sessions(&self, user_id: &UserId, now: i64) -> Result<Vec<Session>, SessionStoreError>;
+    async fn touch_session(&mut self, id: &SessionId, now: i64) -> Result<(), SessionStoreError>;
+    async fn remove_session(&mut self, id: &SessionId) -> Result<(), SessionStoreError>;
+    // Ends every session of the user, except `keep` when given
+    async fn remove_user_sessions(&mut self, user_id: &UserId, keep: Option<&SessionId>) -> Result<(), SessionStoreError>;
+}
+
+#[derive(Default)]
+pub struct Sessions {
+    sessions: HashMap<SessionId, Session>,
+}
+
+#[async_trait]
+impl SessionStore for Sessions {
+    async fn add_session(&mut self, session: Session) -> Result<(), SessionStoreError> {
+        self.sessions.insert(session.id.clone(), session);
+        Ok(())
+    }
+
+    async fn get_session(&self, id: &SessionId, now: i64) -> Result<Session, SessionStoreError> {
+        self.sessions
+            .get(id)
+            .filter(|session| session.is_live(now))
+            .cloned()
+            .ok_or(SessionStoreError::SessionNotFound)
+    }
+
+    async fn list_sessions(&self, user_id: &UserId, now: i64) -> Result<Vec<Session>, SessionStoreError> {
+        let mut sessions: Vec<Session> = self.sessions
+            .values()
+            .filter(|session| session.user_id == *user_id && session.is_live(now))
+            .cloned()
+            .collect();
+
+        sessions.sort_by_key(|session| Reverse(session.last_seen_at));
+
+        Ok(sessions)
+    }
+
+    async fn touch_session(&mut self, id: &SessionId, now: i64) -> Result<(), SessionStoreError> {
+        let session = self.sessions.get_mut(id).ok_or(SessionStoreError::SessionNotFound)?;
+        session.last_seen_at = now;
+        Ok(())
+    }
+
+    async fn remove_session(&mut self, id: &SessionId) -> Result<(), SessionStoreError> {
+        self.sessions.remove(id);
+        Ok(())
+    }
+
+    async fn remove_user_sessions(&mut self, user_id: &UserId, keep: Option<&SessionId>) -> Result<(), SessionStoreError> {
+        self.sessions.retain(|id, session| session.user_id != *user_id || Some(id) == keep);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(user_id: &UserId, now: i64) -> Session {
+        Session::new(user_id.clone(), ClientInfo::default(), now)
+    }
+
+    #[tokio::test]
+    async fn test_sessions_expire_when_unused() {
+        let mut store = Sessions::default();
+        let user_id = UserId::default();
+        let session = session(&user_id, 1000);
+
+        store.add_session(session.clone()).await.unwrap();
+        assert_eq!(store.get_session(&session.id, 1000).await, Ok(session.clone()));
+
+        let expiry = 1000 + SESSION_TTL_SECONDS;
+        assert_eq!(store.get_session(&session.id, expiry).await, Err(SessionStoreError::SessionNotFound));
+
+        // Being seen again keeps the session going
+        store.touch_session(&session.id, 2000).await.unwrap();
+        assert!(store.get_session(&session.id, expiry).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_list_and_remove_user_sessions() {
+        let mut store = Sessions::default();
+        let user_id = UserId::default();
+        let older = session(&user_id, 1000);
+        let newer = session(&user_id, 2000);
+        let other = session(&UserId::default(), 3000);
+
+        for session in [older.clone(), newer.clone(), other.clone()] {
+            store.add_session(session).await.unwrap();
+        }
+
+        assert_eq!(store.list_sessions(&user_id, 3000).await, Ok(vec![newer.clone(), older.clone()]));
+
+        store.remove_user_sessions(&user_id, Some(&newer.id)).await.unwrap();
+        assert_eq!(store.list_sessions(&user_id, 3000).await, Ok(vec![newer.clone()]));
+
+        store.remove_session(&newer.id).await.unwrap();
+        assert_eq!(store.list_sessions(&user_id, 3000).await, Ok(vec![]));
+
+        // Other users keep their sessions
+        assert!(store.get_session(&other.id, 3000).await.is_ok());
+    }
+}
@@ -1,12 +1,15 @@
-use std::{collections::HashSet, sync::Arc};
+use std::{collections::{HashMap, HashSet}, sync::Arc};
 use async_trait::async_trait;
 use tokio::sync::RwLock;
 
+use crate::user::UserId;
+
 pub type BannedTokenStoreType = Arc<RwLock<dyn BannedTokenStore + Send + Sync>>;
 
 #[derive(Default)]
 pub struct BannedTokens {
     tokens: HashSet<String>,
+    revoked_before: HashMap<UserId, i64>,
 }
 
 
@@ -19,6 +22,9 @@ pub enum BannedTokenStoreError {
 pub trait BannedTokenStore {
     async fn add_token(&mut self, token: String) -> Result<(), BannedTokenStoreError>;
     async fn contains_token(&self, token: &str) -> Result<bool, BannedTokenStoreError>;
+    // Ban every token issued to the user before the given Unix time in milliseconds, e.g. after their password changes
+    async fn revoke_user_tokens(&mut self, user_id: &UserId, issued_before_ms: i64) -> Result<(), BannedTokenStoreError>;
+    async fn user_tokens_revoked_before(&self, user_id: &UserId) -> Result<Option<i64>, BannedTokenStoreError>;
 }
 
 
@@ -32,6 +38,15 @@ impl BannedTokenStore for BannedTokens {
     async fn contains_token(&self, token: &str) -> Result<bool, BannedTokenStoreError> {
         Ok(self.tokens.contains(token))
     }
+
+    async fn revoke_user_tokens(&mut self, user_id: &UserId, issued_before_ms: i64) -> Result<(), BannedTokenStoreError> {
+        self.revoked_before.insert(user_id.clone(), issued_before_ms);
+        Ok(())
+    }
+
+    async fn user_tokens_revoked_before(&self, user_id: &UserId) -> Result<Option<i64>, BannedTokenStoreError> {
+        Ok(self.revoked_before.get(user_id).copied())
+    }
 }
 
 #[cfg(test)]
@@ -58,4 +73,17 @@ mod tests {
 
         assert!(result.unwrap());
     }
+
+    #[tokio::test]
+    async fn test_revoke_user_tokens() {
+        let mut store = BannedTokens::default();
+        let user_id = UserId::default();
+        let other = UserId::default();
+
+        let result = store.revoke_user_tokens(&user_id, 100).await;
+
+        assert!(result.is_ok());
+        assert_eq!(store.user_tokens_revoked_before(&user_id).await.unwrap(), Some(100));
+        assert_eq!(store.user_tokens_revoked_before(&other).await.unwrap(), None);
+    }
 }
@@ -0,0 +1,291 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use data_encoding::BASE32_NOPAD;
+use qrcode::{render::svg, QrCode};
+use rand::RngCore;
+use reqwest::Url;
+use ring::{constant_time, hmac};
+use tokio::sync::RwLock;
+
+use crate::user::Email;
+
+pub type TotpStoreType = Arc<RwLock<dyn TotpStore + Send + Sync>>;
+
+// RFC 6238 defaults, which is what every mainstream authenticator app assumes
+const TOTP_STEP_SECONDS: i64 = 30;
+const TOTP_DIGITS: u32 = 6;
+const TOTP_SECRET_BYTES: usize = 20;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TotpSecret(Vec<u8>);
+
+impl TotpSecret {
+    // Parses the base32 form handed to authenticator apps and stored at rest
+    pub fn parse(secret: String) -> Result<Self, String> {
+        let bytes = BASE32_NOPAD
+            .decode(secret.trim_end_matches('=').as_bytes())
+            .map_err(|_| String::from("Invalid TOTP secret"))?;
+
+        if bytes.is_empty() {
+            return Err(String::from("Invalid TOTP secret"));
+        }
+
+        Ok(Self(bytes))
+    }
+
+    pub fn encode(&self) -> String {
+        BASE32_NOPAD.encode(&self.0)
+    }
+
+    // Generate the code for a given time step (RFC 4226 HOTP with HMAC-SHA1)
+    fn code_at(&self, step: u64) -> TotpCode {
+        let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, &self.0);
+        let tag = hmac::sign(&key, &step.to_be_bytes());
+        let digest = tag.as_ref();
+
+        let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+        let binary = u32::from_be_bytes([
+            digest[offset] & 0x7f,
+            digest[offset + 1],
+            digest[offset + 2],
+            digest[offset + 3],
+        ]);
+
+        let code = binary % 10u32.pow(TOTP_DIGITS);
+        TotpCode(format!("{:0width$}", code, width = TOTP_DIGITS as usize))
+    }
+
+    // Returns the time step the code was generated for, allowing `skew` steps of clock drift either way
+    pub fn verify(&self, code: &TotpCode, unix_time: i64, skew: u64) -> Option<u64> {
+        let current = (unix_time / TOTP_STEP_SECONDS) as u64;
+
+        (current.saturating_sub(skew)..=current + skew).find(|step| self.code_at(*step).eq(code))
+    }
+
+    pub fn generate(&self, unix_time: i64) -> TotpCode {
+        self.code_at((unix_time / TOTP_STEP_SECONDS) as u64)
+    }
+
+    pub fn verify_now(&self, code: &TotpCode, skew: u64) -> Option<u64> {
+        self.verify(code, Utc::now().timestamp(), skew)
+    }
+
+    // Key URI understood by authenticator apps, see https://github.com/google/google-authenticator/wiki/Key-Uri-Format
+    pub fn otpauth_uri(&self, issuer: &str, email: &Email) -> String {
+        let mut uri = Url::parse("otpauth://totp/").expect("otpauth base URI is valid");
+        uri.set_path(&format!("{}:{}", issuer, email.as_ref()));
+        uri.query_pairs_mut()
+            .append_pair("secret", &self.encode())
+            .append_pair("issuer", issuer)
+            .append_pair("algorithm", "SHA1")
+            .append_pair("digits", &TOTP_DIGITS.to_string())
+            .append_pair("period", &TOTP_STEP_SECONDS.to_string());
+
+        uri.to_string()
+    }
+}
+
+impl Default for TotpSecret {
+    fn default() -> Self {
+        let mut bytes = vec![0u8; TOTP_SECRET_BYTES];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        Self(bytes)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TotpCode(String);
+
+impl PartialEq for TotpCode {
+    fn eq(&self, other: &Self) -> bool {
+        constant_time::verify_slices_are_equal(self.0.as_bytes(), other.0.as_bytes()).is_ok()
+    }
+}
+
+impl TotpCode {
+    // Unlike emailed codes, TOTP codes may start with zeros
+    pub fn parse(code: String) -> Result<Self, String> {
+        if code.len() == TOTP_DIGITS as usize && code.chars().all(|c| c.is_ascii_digit()) {
+            Ok(Self(code))
+        } else {
+            Err(String::from("Invalid code format"))
+        }
+    }
+}
+
+impl AsRef<str> for TotpCode {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+pub fn qr_code_svg(uri: &str) -> Result<String, String> {
+    let code = QrCode::new(uri.as_bytes()).map_err(|e| e.to_string())?;
+
+    Ok(code
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .build())
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TotpStoreError {
+    SecretNotFound,
+    StepAlreadyUsed,
+    UnexpectedError,
+}
+
+#[async_trait]
+pub trait TotpStore {
+    // Replaces any unconfirmed secret, leaving a confirmed one in place until the new one is confirmed
+    async fn set_pending_secret(&mut self, email: Email, secret: TotpSecret) -> Result<(), TotpStoreError>;
+    async fn get_pending_secret(&self, email: &Email) -> Result<TotpSecret, TotpStoreError>;
+    // Promotes the pending secret, recording the step of the code that confirmed it
+    async fn confirm_secret(&mut self, email: &Email, step: u64) -> Result<(), TotpStoreError>;
+    async fn get_secret(&self, email: &Email) -> Result<TotpSecret, TotpStoreError>;
+    // Fails with `StepAlreadyUsed` unless `step` is newer than the last accepted code
+    async fn use_step(&mut self, email: &Email, step: u64) -> Result<(), TotpStoreError>;
+}
+
+#[derive(Default)]
+struct TotpRecord {
+    secret: Option<TotpSecret>,
+    pending_secret: Option<TotpSecret>,
+    last_used_step: Option<u64>,
+}
+
+#[derive(Default)]
+pub struct TotpSecrets {
+    records: HashMap<Email, TotpRecord>,
+}
+
+#[async_trait]
+impl TotpStore for TotpSecrets {
+    async fn set_pending_secret(&mut self, email: Email, secret: TotpSecret) -> Result<(), TotpStoreError> {
+        self.records.entry(email).or_default().pending_secret = Some(secret);
+        Ok(())
+    }
+
+    async fn get_pending_secret(&self, email: &Email) -> Result<TotpSecret, TotpStoreError> {
+        self.records
+            .get(email)
+            .and_then(|record| record.pending_secret.clone())
+            .ok_or(TotpStoreError::SecretNotFound)
+    }
+
+    async fn confirm_secret(&mut self, email: &Email, step: u64) -> Result<(), TotpStoreError> {
+        let record = self.records.get_mut(email).ok_or(TotpStoreError::SecretNotFound)?;
+        let secret = record.pending_secret.take().ok_or(TotpStoreError::SecretNotFound)?;
+
+        record.secret = Some(secret);
+        record.last_used_step = Some(step);
+        Ok(())
+    }
+
+    async fn get_secret(&self, email: &Email) -> Result<TotpSecret, TotpStoreError> {
+        self.records
+            .get(email)
+            .and_then(|record| record.secret.clone())
+            .ok_or(TotpStoreError::SecretNotFound)
+    }
+
+    async fn use_step(&mut self, email: &Email, step: u64) -> Result<(), TotpStoreError> {
+        let record = self.records.get_mut(email).ok_or(TotpStoreError::SecretNotFound)?;
+
+        if record.last_used_step.is_some_and(|last| last >= step) {
+            return Err(TotpStoreError::StepAlreadyUsed);
+        }
+
+        record.last_used_step = Some(step);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test vectors from RFC 6238 Appendix B, truncated to six digits
+    fn rfc_secret() -> TotpSecret {
+        TotpSecret(b"12345678901234567890".to_vec())
+    }
+
+    #[test]
+    fn test_code_matches_rfc_6238_vectors() {
+        let secret = rfc_secret();
+        for (time, expected) in [(59, "287082"), (1111111109, "081804"), (1234567890, "005924"), (2000000000, "279037")] {
+            assert_eq!(secret.generate(time).as_ref(), expected);
+        }
+    }
+
+    #[test]
+    fn test_verify_allows_configured_skew() {
+        let secret = rfc_secret();
+        let code = TotpCode::parse("081804".to_owned()).unwrap();
+        let step = 1111111109 / TOTP_STEP_SECONDS;
+
+        assert_eq!(secret.verify(&code, 1111111109, 0), Some(step as u64));
+        assert_eq!(secret.verify(&code, 1111111109 + TOTP_STEP_SECONDS, 1), Some(step as u64));
+        assert_eq!(secret.verify(&code, 1111111109 + TOTP_STEP_SECONDS, 0), None);
+        assert_eq!(secret.verify(&code, 1111111109 + 2 * TOTP_STEP_SECONDS, 1), None);
+    }
+
+    #[test]
+    fn test_code_parse() {
+        assert!(TotpCode::parse("012345".to_owned()).is_ok());
+        assert!(TotpCode::parse("12345".to_owned()).is_err());
+        assert!(TotpCode::parse("1234567".to_owned()).is_err());
+        assert!(TotpCode::parse("12345a".to_owned()).is_err());
+    }
+
+    #[test]
+    fn test_secret_round_trips_through_base32() {
+        let secret = TotpSecret::default();
+        assert_eq!(TotpSecret::parse(secret.encode()), Ok(secret));
+        assert!(TotpSecret::parse("not base32!".to_owned()).is_err());
+    }
+
+    #[test]
+    fn test_otpauth_uri() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let uri = rfc_secret().otpauth_uri("Auth Service", &email);
+
+        assert!(uri.starts_with("otpauth://totp/Auth%20Service:test@example.com?"));
+        assert!(uri.contains("secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ"));
+        assert!(uri.contains("issuer=Auth+Service"));
+        assert!(qr_code_svg(&uri).unwrap().starts_with("<?xml"));
+    }
+
+    #[tokio::test]
+    async fn test_store_confirms_pending_secret() {
+        let mut store = TotpSecrets::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let secret = TotpSecret::default();
+
+        assert_eq!(store.get_pending_secret(&email).await, Err(TotpStoreError::SecretNotFound));
+
+        store.set_pending_secret(email.clone(), secret.clone()).await.unwrap();
+        assert_eq!(store.get_pending_secret(&email).await, Ok(secret.clone()));
+        assert_eq!(store.get_secret(&email).await, Err(TotpStoreError::SecretNotFound));
+
+        store.confirm_secret(&email, 10).await.unwrap();
+        assert_eq!(store.get_secret(&email).await, Ok(secret));
+        assert_eq!(store.get_pending_secret(&email).await, Err(TotpStoreError::SecretNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_store_rejects_replayed_steps() {
+        let mut store = TotpSecrets::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+
+        store.set_pending_secret(email.clone(), TotpSecret::default()).await.unwrap();
+        store.confirm_secret(&email, 10).await.unwrap();
+
+        assert_eq!(store.use_step(&email, 10).await, Err(TotpStoreError::StepAlreadyUsed));
+        assert_eq!(store.use_step(&email, 9).await, Err(TotpStoreError::StepAlreadyUsed));
+        assert_eq!(store.use_step(&email, 11).await, Ok(()));
+        assert_eq!(store.use_step(&email, 11).await, Err(TotpStoreError::StepAlreadyUsed));
+    }
+}
@@ -1,11 +1,19 @@
 use std::collections::HashMap;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use rand::Rng;
+use ring::constant_time;
 use uuid::Uuid;
-use crate::{services::api::TwoFaCodeError, user::Email};
+use crate::{services::{api::TwoFaCodeError, client_info::ClientInfo}, user::Email};
 
+// How long a pending login attempt can be completed for
+pub const TWO_FA_CHALLENGE_TTL_SECONDS: i64 = 600;
+// How often, and how many times, an emailed code can be sent again for one login attempt
+pub const TWO_FA_RESEND_COOLDOWN_SECONDS: i64 = 30;
+pub const TWO_FA_MAX_RESENDS: u32 = 3;
 
-#[derive(Debug, Clone, PartialEq)]
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct LoginId(String);
 
 
@@ -30,9 +38,16 @@ impl AsRef<str> for LoginId {
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug)]
 pub struct TwoFaCode(String);
 
+// Compare in constant time so response timing doesn't reveal how many leading digits were right
+impl PartialEq for TwoFaCode {
+    fn eq(&self, other: &Self) -> bool {
+        constant_time::verify_slices_are_equal(self.0.as_bytes(), other.0.as_bytes()).is_ok()
+    }
+}
+
 impl TwoFaCode {
     pub fn parse(code: String) -> Result<Self, String> {
         let err_invalid_format = String::from("Invalid code format");
@@ -59,98 +74,321 @@ impl AsRef<str> for TwoFaCode {
     }
 }
 
+// A pending second factor for one login attempt. A user can have several at once, one per device logging in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TwoFaChallenge {
+    pub email: Email,
+    pub code: TwoFaCode,
+    pub client: ClientInfo,
+    pub expires_at: DateTime<Utc>,
+    pub last_sent_at: DateTime<Utc>,
+    pub resend_count: u32,
+}
+
+impl TwoFaChallenge {
+    pub fn new(email: Email, code: TwoFaCode, client: ClientInfo) -> Self {
+        let now = Utc::now();
+
+        Self {
+            email,
+            code,
+            client,
+            expires_at: now + challenge_ttl(),
+            last_sent_at: now,
+            resend_count: 0,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now()
+    }
+
+    // Seconds until the code may be sent again, zero once the cooldown has passed
+    pub fn resend_cooldown_remaining(&self) -> i64 {
+        let elapsed = (Utc::now() - self.last_sent_at).num_seconds();
+        (TWO_FA_RESEND_COOLDOWN_SECONDS - elapsed).max(0)
+    }
+
+    pub fn resends_remaining(&self) -> u32 {
+        TWO_FA_MAX_RESENDS.saturating_sub(self.resend_count)
+    }
+
+    // Sending the same code again keeps any earlier email valid, unless the code would run out
+    // before the user gets to it. In that case a fresh code with a fresh expiry goes out instead.
+    pub fn prepare_resend(&mut self) {
+        let now = Utc::now();
+
+        if self.expires_at - now < challenge_ttl() / 2 {
+            self.code = TwoFaCode::default();
+            self.expires_at = now + challenge_ttl();
+        }
+
+        self.last_sent_at = now;
+        self.resend_count += 1;
+    }
+}
+
+fn challenge_ttl() -> chrono::Duration {
+    chrono::Duration::try_seconds(TWO_FA_CHALLENGE_TTL_SECONDS).expect("valid duration")
+}
+
 #[derive(Default)]
 pub struct TwoFaCodeStore {
-    codes: HashMap<Email, (LoginId, TwoFaCode)>,
+    challenges: HashMap<LoginId, TwoFaChallenge>,
+    failed_attempts: HashMap<LoginId, u32>,
 }
 
 #[async_trait]
 pub trait TwoFaCodes {
-    async fn add_code(&mut self, email: Email, login_id: LoginId, code: TwoFaCode) -> Result<(), TwoFaCodeError>;
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFaCodeError>;
-    async fn get_code(&self, email: &Email) -> Result<(LoginId, TwoFaCode), TwoFaCodeError>;
+    async fn add_challenge(&mut self, login_id: LoginId, challenge: TwoFaChallenge) -> Result<(), TwoFaCodeError>;
+    async fn remove_challenge(&mut self, login_id: &LoginId) -> Result<(), TwoFaCodeError>;
+    async fn get_challenge(&self, login_id: &LoginId) -> Result<TwoFaChallenge, TwoFaCodeError>;
+    // Saves changes to an existing challenge without resetting its failed attempts
+    async fn update_challenge(&mut self, login_id: &LoginId, challenge: TwoFaChallenge) -> Result<(), TwoFaCodeError>;
+    // Counts a wrong guess against the challenge and throws it away once `max_attempts` is reached
+    async fn record_failed_attempt(&mut self, login_id: &LoginId, max_attempts: u32) -> Result<(), TwoFaCodeError>;
+    // Drops every pending login attempt of the user, e.g. when their account goes away
+    async fn remove_user_challenges(&mut self, email: &Email) -> Result<(), TwoFaCodeError>;
 }
 
 #[async_trait]
 impl TwoFaCodes for TwoFaCodeStore {
-    async fn add_code(&mut self, email: Email, login_id: LoginId, code: TwoFaCode) -> Result<(), TwoFaCodeError> {
-        let _ = self.codes.insert(email, (login_id, code))
-            .map(|_| ())
-            .ok_or(TwoFaCodeError::UnexpectedError); // TODO
+    async fn add_challenge(&mut self, login_id: LoginId, challenge: TwoFaChallenge) -> Result<(), TwoFaCodeError> {
+        self.failed_attempts.remove(&login_id);
+        self.challenges.insert(login_id, challenge);
         Ok(())
     }
 
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFaCodeError> {
-        self.codes.remove(email)
+    async fn remove_challenge(&mut self, login_id: &LoginId) -> Result<(), TwoFaCodeError> {
+        self.failed_attempts.remove(login_id);
+        self.challenges.remove(login_id)
             .map(|_| ())
-            .ok_or(TwoFaCodeError::UnexpectedError)
+            .ok_or(TwoFaCodeError::LoginAttemptIdNotFound)
     }
 
-    async fn get_code(&self, email: &Email) -> Result<(LoginId, TwoFaCode), TwoFaCodeError> {
-        self.codes.get(email)
-            .map(|tup| tup.clone())
+    async fn get_challenge(&self, login_id: &LoginId) -> Result<TwoFaChallenge, TwoFaCodeError> {
+        self.challenges.get(login_id)
+            .filter(|challenge| !challenge.is_expired())
+            .cloned()
             .ok_or(TwoFaCodeError::LoginAttemptIdNotFound)
     }
+
+    async fn update_challenge(&mut self, login_id: &LoginId, challenge: TwoFaChallenge) -> Result<(), TwoFaCodeError> {
+        let stored = self.challenges
+            .get_mut(login_id)
+            .filter(|stored| !stored.is_expired())
+            .ok_or(TwoFaCodeError::LoginAttemptIdNotFound)?;
+
+        *stored = challenge;
+        Ok(())
+    }
+
+    async fn record_failed_attempt(&mut self, login_id: &LoginId, max_attempts: u32) -> Result<(), TwoFaCodeError> {
+        if !self.challenges.contains_key(login_id) {
+            return Err(TwoFaCodeError::LoginAttemptIdNotFound);
+        }
+
+        let attempts = self.failed_attempts.entry(login_id.clone()).or_default();
+        *attempts += 1;
+
+        if *attempts >= max_attempts {
+            self.remove_challenge(login_id).await?;
+            return Err(TwoFaCodeError::TooManyAttempts);
+        }
+
+        Ok(())
+    }
+
+    async fn remove_user_challenges(&mut self, email: &Email) -> Result<(), TwoFaCodeError> {
+        let login_ids: Vec<LoginId> = self.challenges
+            .iter()
+            .filter(|(_, challenge)| challenge.email == *email)
+            .map(|(login_id, _)| login_id.clone())
+            .collect();
+
+        for login_id in login_ids {
+            self.remove_challenge(&login_id).await?;
+        }
+
+        Ok(())
+    }
 }
 
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    fn challenge() -> TwoFaChallenge {
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        TwoFaChallenge::new(email, TwoFaCode::default(), ClientInfo::default())
+    }
+
     #[tokio::test]
-    async fn test_add_code() {
+    async fn test_add_challenge() {
         let mut store = TwoFaCodeStore::default();
-        let email = Email::parse("test@example.com".to_string()).unwrap();
         let login_id = LoginId::default();
-        let code = TwoFaCode::default();
+        let challenge = challenge();
 
         let result = store
-            .add_code(email.clone(), login_id.clone(), code.clone())
+            .add_challenge(login_id.clone(), challenge.clone())
             .await;
 
         assert!(result.is_ok());
-        assert_eq!(store.codes.get(&email), Some(&(login_id, code)));
+        assert_eq!(store.challenges.get(&login_id), Some(&challenge));
     }
 
     #[tokio::test]
-    async fn test_remove_code() {
+    async fn test_remove_challenge() {
         let mut store = TwoFaCodeStore::default();
-        let email = Email::parse("test@example.com".to_string()).unwrap();
-        let login_attempt_id = LoginId::default();
-        let code = TwoFaCode::default();
+        let login_id = LoginId::default();
 
         store
-            .codes
-            .insert(email.clone(), (login_attempt_id.clone(), code.clone()));
+            .challenges
+            .insert(login_id.clone(), challenge());
 
-        let result = store.remove_code(&email).await;
+        let result = store.remove_challenge(&login_id).await;
 
         assert!(result.is_ok());
-        assert_eq!(store.codes.get(&email), None);
+        assert_eq!(store.challenges.get(&login_id), None);
     }
 
     #[tokio::test]
-    async fn test_get_code() {
+    async fn test_get_challenge() {
         let mut store = TwoFaCodeStore::default();
-        let email = Email::parse("test@example.com".to_string()).unwrap();
-        let login_attempt_id = LoginId::default();
-        let code = TwoFaCode::default();
+        let login_id = LoginId::default();
+        let challenge = challenge();
         store
-            .codes
-            .insert(email.clone(), (login_attempt_id.clone(), code.clone()));
+            .challenges
+            .insert(login_id.clone(), challenge.clone());
 
-        let result = store.get_code(&email).await;
+        let result = store.get_challenge(&login_id).await;
 
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), (login_attempt_id, code));
+        assert_eq!(result.unwrap(), challenge);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_challenges_for_one_user() {
+        let mut store = TwoFaCodeStore::default();
+        let (laptop, phone) = (LoginId::default(), LoginId::default());
+        let (laptop_challenge, phone_challenge) = (challenge(), challenge());
+
+        store.add_challenge(laptop.clone(), laptop_challenge.clone()).await.unwrap();
+        store.add_challenge(phone.clone(), phone_challenge.clone()).await.unwrap();
+
+        assert_eq!(store.get_challenge(&laptop).await, Ok(laptop_challenge));
+        assert_eq!(store.get_challenge(&phone).await, Ok(phone_challenge.clone()));
+
+        store.remove_challenge(&laptop).await.unwrap();
+        assert_eq!(store.get_challenge(&phone).await, Ok(phone_challenge));
+    }
+
+    #[tokio::test]
+    async fn test_expired_challenge_not_found() {
+        let mut store = TwoFaCodeStore::default();
+        let login_id = LoginId::default();
+        let mut challenge = challenge();
+        challenge.expires_at = Utc::now() - chrono::Duration::try_seconds(1).unwrap();
+
+        store.add_challenge(login_id.clone(), challenge).await.unwrap();
+
+        assert_eq!(store.get_challenge(&login_id).await, Err(TwoFaCodeError::LoginAttemptIdNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_record_failed_attempt_invalidates_challenge_at_limit() {
+        let mut store = TwoFaCodeStore::default();
+        let login_id = LoginId::default();
+
+        store.add_challenge(login_id.clone(), challenge()).await.unwrap();
+
+        assert_eq!(store.record_failed_attempt(&login_id, 3).await, Ok(()));
+        assert_eq!(store.record_failed_attempt(&login_id, 3).await, Ok(()));
+        assert_eq!(store.record_failed_attempt(&login_id, 3).await, Err(TwoFaCodeError::TooManyAttempts));
+        assert_eq!(store.get_challenge(&login_id).await, Err(TwoFaCodeError::LoginAttemptIdNotFound));
+        assert_eq!(store.record_failed_attempt(&login_id, 3).await, Err(TwoFaCodeError::LoginAttemptIdNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_failed_attempts_are_counted_per_challenge() {
+        let mut store = TwoFaCodeStore::default();
+        let (first, second) = (LoginId::default(), LoginId::default());
+
+        store.add_challenge(first.clone(), challenge()).await.unwrap();
+        store.add_challenge(second.clone(), challenge()).await.unwrap();
+
+        assert_eq!(store.record_failed_attempt(&first, 2).await, Ok(()));
+        assert_eq!(store.record_failed_attempt(&second, 2).await, Ok(()));
+        assert_eq!(store.record_failed_attempt(&first, 2).await, Err(TwoFaCodeError::TooManyAttempts));
+        assert!(store.get_challenge(&second).await.is_ok());
     }
 
     #[tokio::test]
-    async fn test_get_code_not_found() {
+    async fn test_update_challenge_keeps_failed_attempts() {
+        let mut store = TwoFaCodeStore::default();
+        let login_id = LoginId::default();
+        let mut challenge = challenge();
+
+        assert_eq!(
+            store.update_challenge(&login_id, challenge.clone()).await,
+            Err(TwoFaCodeError::LoginAttemptIdNotFound)
+        );
+
+        store.add_challenge(login_id.clone(), challenge.clone()).await.unwrap();
+        assert_eq!(store.record_failed_attempt(&login_id, 2).await, Ok(()));
+
+        challenge.prepare_resend();
+        store.update_challenge(&login_id, challenge.clone()).await.unwrap();
+
+        assert_eq!(store.get_challenge(&login_id).await, Ok(challenge));
+        assert_eq!(store.record_failed_attempt(&login_id, 2).await, Err(TwoFaCodeError::TooManyAttempts));
+    }
+
+    #[test]
+    fn test_resend_cooldown() {
+        let mut challenge = challenge();
+
+        assert!(challenge.resend_cooldown_remaining() > TWO_FA_RESEND_COOLDOWN_SECONDS - 5);
+
+        challenge.last_sent_at = Utc::now() - chrono::Duration::try_seconds(TWO_FA_RESEND_COOLDOWN_SECONDS).unwrap();
+        assert_eq!(challenge.resend_cooldown_remaining(), 0);
+
+        challenge.prepare_resend();
+        assert!(challenge.resend_cooldown_remaining() > 0);
+        assert_eq!(challenge.resends_remaining(), TWO_FA_MAX_RESENDS - 1);
+    }
+
+    #[test]
+    fn test_resend_issues_fresh_code_only_when_close_to_expiry() {
+        let mut challenge = challenge();
+        let code = challenge.code.clone();
+
+        challenge.prepare_resend();
+        assert_eq!(challenge.code, code);
+
+        challenge.expires_at = Utc::now() + chrono::Duration::try_seconds(60).unwrap();
+        challenge.code = TwoFaCode::parse("123456".to_owned()).unwrap();
+        challenge.prepare_resend();
+
+        assert!(challenge.expires_at > Utc::now() + chrono::Duration::try_seconds(TWO_FA_CHALLENGE_TTL_SECONDS - 5).unwrap());
+        assert_eq!(challenge.resend_count, 2);
+    }
+
+    #[test]
+    fn test_code_comparison() {
+        let code = TwoFaCode::parse("123456".to_owned()).unwrap();
+
+        assert_eq!(code, TwoFaCode::parse("123456".to_owned()).unwrap());
+        assert_ne!(code, TwoFaCode::parse("123457".to_owned()).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_get_challenge_not_found() {
         let store = TwoFaCodeStore::default();
-        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let login_id = LoginId::default();
 
-        let result = store.get_code(&email).await;
+        let result = store.get_challenge(&login_id).await;
 
         assert!(result.is_err());
         assert_eq!(
@@ -158,4 +396,29 @@ mod tests {
             TwoFaCodeError::LoginAttemptIdNotFound
         );
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_remove_user_challenges() {
+        let mut store = TwoFaCodeStore::default();
+        let first_id = LoginId::default();
+        let second_id = LoginId::default();
+        let other_id = LoginId::default();
+        let other_email = Email::parse("other@example.com".to_string()).unwrap();
+
+        store.add_challenge(first_id.clone(), challenge()).await.unwrap();
+        store.add_challenge(second_id.clone(), challenge()).await.unwrap();
+        store
+            .add_challenge(other_id.clone(), TwoFaChallenge::new(other_email, TwoFaCode::default(), ClientInfo::default()))
+            .await
+            .unwrap();
+        store.record_failed_attempt(&first_id, 5).await.unwrap();
+
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        store.remove_user_challenges(&email).await.unwrap();
+
+        assert!(store.get_challenge(&first_id).await.is_err());
+        assert!(store.get_challenge(&second_id).await.is_err());
+        assert!(store.failed_attempts.is_empty());
+        assert!(store.get_challenge(&other_id).await.is_ok());
+    }
+}
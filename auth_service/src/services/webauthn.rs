@@ -0,0 +1,552 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ciborium::value::{Integer, Value};
+use rand::RngCore;
+use ring::{
+    digest::{digest, SHA256},
+    signature::{self, RsaPublicKeyComponents, UnparsedPublicKey}
+};
+use serde::{Deserialize, Serialize};
+
+use crate::user::Email;
+
+use super::{
+    constants::{APP_NAME, WEBAUTHN_ORIGIN, WEBAUTHN_RP_ID},
+    passkeys::{Passkey, UserHandle}
+};
+
+// How long a browser has to complete a ceremony, also used as the lifetime of the stored challenge
+pub const CEREMONY_TIMEOUT_SECONDS: u64 = 300;
+
+// COSE algorithm identifiers we can verify, in order of preference
+const COSE_ALG_ES256: i64 = -7;
+const COSE_ALG_EDDSA: i64 = -8;
+const COSE_ALG_RS256: i64 = -257;
+
+// Authenticator data flags, see https://www.w3.org/TR/webauthn-2/#authenticator-data
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+#[derive(Debug, PartialEq)]
+pub enum WebauthnError {
+    InvalidClientData,
+    InvalidAuthenticatorData,
+    InvalidAttestation,
+    RelyingPartyMismatch,
+    UserNotPresent,
+    UserNotVerified,
+    UnsupportedAlgorithm,
+    InvalidSignature,
+    // The authenticator's signature counter went backwards, so the credential has likely been cloned
+    CounterRegression,
+}
+
+pub struct RelyingParty<'a> {
+    pub id: &'a str,
+    pub name: &'a str,
+    pub origin: &'a str,
+}
+
+impl RelyingParty<'static> {
+    pub fn configured() -> Self {
+        Self {
+            id: &WEBAUTHN_RP_ID,
+            name: APP_NAME,
+            origin: &WEBAUTHN_ORIGIN,
+        }
+    }
+}
+
+// Random bytes the authenticator signs over, base64url encoded as it appears in clientDataJSON
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Challenge(String);
+
+impl Challenge {
+    pub fn parse(challenge: String) -> Result<Self, String> {
+        match URL_SAFE_NO_PAD.decode(&challenge) {
+            Ok(bytes) if bytes.len() >= 16 => Ok(Self(challenge)),
+            _ => Err(String::from("Invalid challenge")),
+        }
+    }
+}
+
+impl Default for Challenge {
+    fn default() -> Self {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        Self(URL_SAFE_NO_PAD.encode(bytes))
+    }
+}
+
+impl AsRef<str> for Challenge {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelyingPartyEntity {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserEntity {
+    pub id: String,
+    pub name: String,
+    pub display_name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialParameters {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub alg: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialDescriptor {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticatorSelection {
+    pub resident_key: String,
+    pub user_verification: String,
+}
+
+// Options for navigator.credentials.create(), with binary fields base64url encoded
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreationOptions {
+    pub rp: RelyingPartyEntity,
+    pub user: UserEntity,
+    pub challenge: String,
+    pub pub_key_cred_params: Vec<CredentialParameters>,
+    pub timeout: u64,
+    pub exclude_credentials: Vec<CredentialDescriptor>,
+    pub authenticator_selection: AuthenticatorSelection,
+    pub attestation: String,
+}
+
+// Options for navigator.credentials.get(), with binary fields base64url encoded
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestOptions {
+    pub challenge: String,
+    pub rp_id: String,
+    pub timeout: u64,
+    pub allow_credentials: Vec<CredentialDescriptor>,
+    pub user_verification: String,
+}
+
+// PublicKeyCredential.toJSON() of a newly created credential
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistrationCredential {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub response: AttestationResponse,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "attestationObject")]
+    pub attestation_object: String,
+}
+
+// PublicKeyCredential.toJSON() of an assertion
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticationCredential {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub response: AssertionResponse,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "authenticatorData")]
+    pub authenticator_data: String,
+    pub signature: String,
+    #[serde(rename = "userHandle")]
+    pub user_handle: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CollectedClientData {
+    #[serde(rename = "type")]
+    type_: String,
+    challenge: String,
+    origin: String,
+}
+
+// The parts of a verified registration worth keeping
+#[derive(Debug, PartialEq)]
+pub struct RegisteredCredential {
+    pub credential_id: String,
+    pub public_key: Vec<u8>,
+    pub sign_count: u32,
+}
+
+struct AuthenticatorData {
+    rp_id_hash: Vec<u8>,
+    flags: u8,
+    sign_count: u32,
+    // Credential ID and COSE public key, only present during registration
+    attested_credential: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+pub fn creation_options(
+    rp: &RelyingParty,
+    email: &Email,
+    user_handle: &UserHandle,
+    challenge: &Challenge,
+    existing: &[Passkey]
+) -> CreationOptions {
+    CreationOptions {
+        rp: RelyingPartyEntity {
+            id: rp.id.to_owned(),
+            name: rp.name.to_owned(),
+        },
+        user: UserEntity {
+            id: user_handle.as_ref().to_owned(),
+            name: email.as_ref().to_owned(),
+            display_name: email.as_ref().to_owned(),
+        },
+        challenge: challenge.as_ref().to_owned(),
+        pub_key_cred_params: [COSE_ALG_ES256, COSE_ALG_EDDSA, COSE_ALG_RS256]
+            .into_iter()
+            .map(|alg| CredentialParameters { type_: "public-key".to_owned(), alg })
+            .collect(),
+        timeout: CEREMONY_TIMEOUT_SECONDS * 1000,
+        exclude_credentials: descriptors(existing),
+        // Discoverable credentials are what let a passkey sign in without typing an email
+        authenticator_selection: AuthenticatorSelection {
+            resident_key: "preferred".to_owned(),
+            user_verification: "preferred".to_owned(),
+        },
+        attestation: "none".to_owned(),
+    }
+}
+
+pub fn request_options(
+    rp: &RelyingParty,
+    challenge: &Challenge,
+    allowed: &[Passkey],
+    require_user_verification: bool
+) -> RequestOptions {
+    RequestOptions {
+        challenge: challenge.as_ref().to_owned(),
+        rp_id: rp.id.to_owned(),
+        timeout: CEREMONY_TIMEOUT_SECONDS * 1000,
+        allow_credentials: descriptors(allowed),
+        user_verification: if require_user_verification { "required" } else { "preferred" }.to_owned(),
+    }
+}
+
+fn descriptors(passkeys: &[Passkey]) -> Vec<CredentialDescriptor> {
+    passkeys
+        .iter()
+        .map(|passkey| CredentialDescriptor {
+            type_: "public-key".to_owned(),
+            id: passkey.credential_id.clone(),
+        })
+        .collect()
+}
+
+// Read the challenge out of clientDataJSON so the matching ceremony can be looked up.
+// Nothing in it is trusted until the full verification below has passed.
+pub fn client_data_challenge(client_data_json: &str) -> Result<Challenge, WebauthnError> {
+    let client_data: CollectedClientData = decode_json(client_data_json)?;
+    Challenge::parse(client_data.challenge).map_err(|_| WebauthnError::InvalidClientData)
+}
+
+// Attestation statements are not verified since we request "none" conveyance and don't
+// restrict which authenticator models may be used
+pub fn verify_registration(
+    rp: &RelyingParty,
+    challenge: &Challenge,
+    credential: &RegistrationCredential,
+    require_user_verification: bool
+) -> Result<RegisteredCredential, WebauthnError> {
+    verify_client_data(rp, challenge, "webauthn.create", &credential.response.client_data_json)?;
+
+    let attestation_object = decode_base64(&credential.response.attestation_object)?;
+    let attestation: Value = ciborium::de::from_reader(attestation_object.as_slice())
+        .map_err(|_| WebauthnError::InvalidAttestation)?;
+
+    let auth_data = map_get(&attestation, &Value::Text("authData".to_owned()))
+        .and_then(Value::as_bytes)
+        .ok_or(WebauthnError::InvalidAttestation)?;
+
+    let auth_data = parse_authenticator_data(auth_data)?;
+    verify_authenticator_data(rp, &auth_data, require_user_verification)?;
+
+    let (credential_id, public_key) = auth_data
+        .attested_credential
+        .ok_or(WebauthnError::InvalidAuthenticatorData)?;
+
+    let credential_id = URL_SAFE_NO_PAD.encode(credential_id);
+    if credential_id != credential.id {
+        return Err(WebauthnError::InvalidAttestation);
+    }
+
+    // Reject keys we would be unable to verify assertions with later
+    CosePublicKey::parse(&public_key)?;
+
+    Ok(RegisteredCredential {
+        credential_id,
+        public_key,
+        sign_count: auth_data.sign_count,
+    })
+}
+
+// Returns the authenticator's new signature counter, to be stored for the next login
+pub fn verify_assertion(
+    rp: &RelyingParty,
+    challenge: &Challenge,
+    credential: &AuthenticationCredential,
+    passkey: &Passkey,
+    require_user_verification: bool
+) -> Result<u32, WebauthnError> {
+    verify_client_data(rp, challenge, "webauthn.get", &credential.response.client_data_json)?;
+
+    if let Some(user_handle) = &credential.response.user_handle {
+        if user_handle != passkey.user_handle.as_ref() {
+            return Err(WebauthnError::InvalidClientData);
+        }
+    }
+
+    let raw_auth_data = decode_base64(&credential.response.authenticator_data)?;
+    let auth_data = parse_authenticator_data(&raw_auth_data)?;
+    verify_authenticator_data(rp, &auth_data, require_user_verification)?;
+
+    let client_data_hash = digest(&SHA256, &decode_base64(&credential.response.client_data_json)?);
+    let signed_data = [raw_auth_data.as_slice(), client_data_hash.as_ref()].concat();
+    let signature = decode_base64(&credential.response.signature)?;
+
+    CosePublicKey::parse(&passkey.public_key)?.verify(&signed_data, &signature)?;
+
+    // Authenticators that don't implement a counter always report zero
+    if (auth_data.sign_count != 0 || passkey.sign_count != 0) && auth_data.sign_count <= passkey.sign_count {
+        return Err(WebauthnError::CounterRegression);
+    }
+
+    Ok(auth_data.sign_count)
+}
+
+fn verify_client_data(
+    rp: &RelyingParty,
+    challenge: &Challenge,
+    ceremony_type: &str,
+    client_data_json: &str
+) -> Result<(), WebauthnError> {
+    let client_data: CollectedClientData = decode_json(client_data_json)?;
+
+    if client_data.type_ != ceremony_type || client_data.challenge != challenge.as_ref() {
+        return Err(WebauthnError::InvalidClientData);
+    }
+
+    if client_data.origin != rp.origin {
+        return Err(WebauthnError::RelyingPartyMismatch);
+    }
+
+    Ok(())
+}
+
+fn verify_authenticator_data(
+    rp: &RelyingParty,
+    auth_data: &AuthenticatorData,
+    require_user_verification: bool
+) -> Result<(), WebauthnError> {
+    if auth_data.rp_id_hash != digest(&SHA256, rp.id.as_bytes()).as_ref() {
+        return Err(WebauthnError::RelyingPartyMismatch);
+    }
+
+    if auth_data.flags & FLAG_USER_PRESENT == 0 {
+        return Err(WebauthnError::UserNotPresent);
+    }
+
+    if require_user_verification && auth_data.flags & FLAG_USER_VERIFIED == 0 {
+        return Err(WebauthnError::UserNotVerified);
+    }
+
+    Ok(())
+}
+
+fn parse_authenticator_data(data: &[u8]) -> Result<AuthenticatorData, WebauthnError> {
+    if data.len() < 37 {
+        return Err(WebauthnError::InvalidAuthenticatorData);
+    }
+
+    let flags = data[32];
+    let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+
+    let attested_credential = match flags & FLAG_ATTESTED_CREDENTIAL_DATA {
+        0 => None,
+        // 16 byte AAGUID, then a big-endian credential ID length, the ID and a COSE key
+        _ => {
+            let id_length = data
+                .get(53..55)
+                .map(|length| u16::from_be_bytes([length[0], length[1]]) as usize)
+                .ok_or(WebauthnError::InvalidAuthenticatorData)?;
+
+            let id_end = 55 + id_length;
+            let credential_id = data
+                .get(55..id_end)
+                .ok_or(WebauthnError::InvalidAuthenticatorData)?
+                .to_vec();
+
+            // The COSE key may be followed by extensions, so measure how much of the input it used
+            let key_start = data.get(id_end..).ok_or(WebauthnError::InvalidAuthenticatorData)?;
+            let mut remaining = key_start;
+            let _: Value = ciborium::de::from_reader(&mut remaining)
+                .map_err(|_| WebauthnError::InvalidAuthenticatorData)?;
+            let public_key = key_start[..key_start.len() - remaining.len()].to_vec();
+
+            Some((credential_id, public_key))
+        }
+    };
+
+    Ok(AuthenticatorData {
+        rp_id_hash: data[..32].to_vec(),
+        flags,
+        sign_count,
+        attested_credential,
+    })
+}
+
+enum CosePublicKey {
+    Es256 { x: Vec<u8>, y: Vec<u8> },
+    EdDsa { x: Vec<u8> },
+    Rs256 { n: Vec<u8>, e: Vec<u8> },
+}
+
+impl CosePublicKey {
+    fn parse(bytes: &[u8]) -> Result<Self, WebauthnError> {
+        let key: Value = ciborium::de::from_reader(bytes).map_err(|_| WebauthnError::InvalidAuthenticatorData)?;
+
+        let param = |label: i64| map_get(&key, &Value::Integer(Integer::from(label)));
+        let bytes_param = |label: i64| {
+            param(label)
+                .and_then(Value::as_bytes)
+                .cloned()
+                .ok_or(WebauthnError::InvalidAuthenticatorData)
+        };
+
+        let alg = param(3)
+            .and_then(Value::as_integer)
+            .and_then(|alg| i64::try_from(alg).ok())
+            .ok_or(WebauthnError::InvalidAuthenticatorData)?;
+
+        match alg {
+            COSE_ALG_ES256 => Ok(Self::Es256 { x: bytes_param(-2)?, y: bytes_param(-3)? }),
+            COSE_ALG_EDDSA => Ok(Self::EdDsa { x: bytes_param(-2)? }),
+            COSE_ALG_RS256 => Ok(Self::Rs256 { n: bytes_param(-1)?, e: bytes_param(-2)? }),
+            _ => Err(WebauthnError::UnsupportedAlgorithm),
+        }
+    }
+
+    fn verify(&self, message: &[u8], sig: &[u8]) -> Result<(), WebauthnError> {
+        let result = match self {
+            Self::Es256 { x, y } => {
+                let point = [&[0x04], x.as_slice(), y.as_slice()].concat();
+                UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_ASN1, point).verify(message, sig)
+            }
+            Self::EdDsa { x } => UnparsedPublicKey::new(&signature::ED25519, x).verify(message, sig),
+            Self::Rs256 { n, e } => RsaPublicKeyComponents { n, e }
+                .verify(&signature::RSA_PKCS1_2048_8192_SHA256, message, sig),
+        };
+
+        result.map_err(|_| WebauthnError::InvalidSignature)
+    }
+}
+
+fn map_get<'a>(map: &'a Value, key: &Value) -> Option<&'a Value> {
+    map.as_map()?
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v)
+}
+
+fn decode_base64(value: &str) -> Result<Vec<u8>, WebauthnError> {
+    URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|_| WebauthnError::InvalidClientData)
+}
+
+fn decode_json<T: serde::de::DeserializeOwned>(value: &str) -> Result<T, WebauthnError> {
+    serde_json::from_slice(&decode_base64(value)?).map_err(|_| WebauthnError::InvalidClientData)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_challenge_parse() {
+        let challenge = Challenge::default();
+        assert_eq!(Challenge::parse(challenge.as_ref().to_owned()), Ok(challenge));
+        assert!(Challenge::parse("c2hvcnQ".to_owned()).is_err());
+        assert!(Challenge::parse("not base64!".to_owned()).is_err());
+    }
+
+    #[test]
+    fn test_parse_authenticator_data_rejects_truncated_input() {
+        assert!(parse_authenticator_data(&[0u8; 36]).is_err());
+
+        // Claims attested credential data but ends before the credential ID
+        let mut data = vec![0u8; 55];
+        data[32] = FLAG_USER_PRESENT | FLAG_ATTESTED_CREDENTIAL_DATA;
+        data[54] = 16;
+        assert!(parse_authenticator_data(&data).is_err());
+    }
+
+    #[test]
+    fn test_parse_authenticator_data_measures_cose_key() {
+        let cose_key = Value::Map(vec![
+            (Value::Integer(1.into()), Value::Integer(1.into())),
+            (Value::Integer(3.into()), Value::Integer(COSE_ALG_EDDSA.into())),
+            (Value::Integer((-2).into()), Value::Bytes(vec![7u8; 32])),
+        ]);
+        let mut key_bytes = Vec::new();
+        ciborium::ser::into_writer(&cose_key, &mut key_bytes).unwrap();
+
+        let mut data = vec![0u8; 37];
+        data[32] = FLAG_USER_PRESENT | FLAG_ATTESTED_CREDENTIAL_DATA;
+        data.extend_from_slice(&[0u8; 16]);
+        data.extend_from_slice(&[0, 2, 0xAB, 0xCD]);
+        data.extend_from_slice(&key_bytes);
+        // Trailing extension data must not end up in the stored key
+        data.extend_from_slice(&[0xA0]);
+
+        let auth_data = parse_authenticator_data(&data).unwrap();
+        let (credential_id, public_key) = auth_data.attested_credential.unwrap();
+        assert_eq!(credential_id, vec![0xAB, 0xCD]);
+        assert_eq!(public_key, key_bytes);
+        assert!(matches!(CosePublicKey::parse(&public_key), Ok(CosePublicKey::EdDsa { .. })));
+    }
+
+    #[test]
+    fn test_cose_key_rejects_unsupported_algorithm() {
+        let cose_key = Value::Map(vec![(Value::Integer(3.into()), Value::Integer((-35).into()))]);
+        let mut key_bytes = Vec::new();
+        ciborium::ser::into_writer(&cose_key, &mut key_bytes).unwrap();
+
+        assert!(matches!(CosePublicKey::parse(&key_bytes), Err(WebauthnError::UnsupportedAlgorithm)));
+    }
+}
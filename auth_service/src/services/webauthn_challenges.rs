@@ -0,0 +1,76 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+
+use crate::user::Email;
+
+use super::{passkeys::UserHandle, two_fa::LoginId, webauthn::Challenge};
+
+pub type WebauthnChallengeStoreType = Arc<RwLock<dyn WebauthnChallengeStore + Send + Sync>>;
+
+// What a challenge was issued for, so the finishing request can't be replayed against another ceremony
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ceremony {
+    Registration {
+        email: Email,
+        user_handle: UserHandle,
+    },
+    // A passkey used after a password carries the login attempt it completes.
+    // Without one it is a passwordless login, which requires user verification.
+    Authentication {
+        login_attempt: Option<(Email, LoginId)>,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum WebauthnChallengeStoreError {
+    ChallengeNotFound,
+    UnexpectedError,
+}
+
+#[async_trait]
+pub trait WebauthnChallengeStore {
+    async fn add_challenge(&mut self, challenge: Challenge, ceremony: Ceremony) -> Result<(), WebauthnChallengeStoreError>;
+    // Challenges are single use, so reading one also removes it
+    async fn take_challenge(&mut self, challenge: &Challenge) -> Result<Ceremony, WebauthnChallengeStoreError>;
+}
+
+#[derive(Default)]
+pub struct WebauthnChallenges {
+    challenges: HashMap<Challenge, Ceremony>,
+}
+
+#[async_trait]
+impl WebauthnChallengeStore for WebauthnChallenges {
+    async fn add_challenge(&mut self, challenge: Challenge, ceremony: Ceremony) -> Result<(), WebauthnChallengeStoreError> {
+        self.challenges.insert(challenge, ceremony);
+        Ok(())
+    }
+
+    async fn take_challenge(&mut self, challenge: &Challenge) -> Result<Ceremony, WebauthnChallengeStoreError> {
+        self.challenges
+            .remove(challenge)
+            .ok_or(WebauthnChallengeStoreError::ChallengeNotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_challenges_are_single_use() {
+        let mut store = WebauthnChallenges::default();
+        let challenge = Challenge::default();
+        let ceremony = Ceremony::Authentication { login_attempt: None };
+
+        store.add_challenge(challenge.clone(), ceremony.clone()).await.unwrap();
+
+        assert_eq!(store.take_challenge(&challenge).await, Ok(ceremony));
+        assert_eq!(
+            store.take_challenge(&challenge).await,
+            Err(WebauthnChallengeStoreError::ChallengeNotFound)
+        );
+    }
+}
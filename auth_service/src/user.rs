@@ -1,12 +1,168 @@
 pub mod credentials;
+pub mod roles;
 pub mod store;
 
 pub use credentials::{Email, Password};
+pub use roles::Role;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
+// Stable identifier of an account, unlike the email address it survives an address change
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UserId(String);
+
+impl UserId {
+    pub fn parse(id: String) -> Result<Self, String> {
+        let valid_id = Uuid::parse_str(&id)
+            .map_err(|_| String::from("Invalid user id format"))?;
+        Ok(Self(valid_id.to_string()))
+    }
+}
+
+impl Default for UserId {
+    fn default() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+}
+
+impl AsRef<str> for UserId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct User {
-    pub requires_2fa: bool,
+    pub id: UserId,
+    pub two_fa_method: TwoFaMethod,
     pub email: Email,
     pub password: Password,
+    // Set once the user follows the confirmation link sent to their address
+    pub email_verified: bool,
+    // Unix timestamp of the signup
+    pub created_at: i64,
+    // Unix timestamp after which a deleted account is purged, unset unless deletion is pending
+    pub delete_after: Option<i64>,
+    // Set by an admin, a locked account can't log in until it is unlocked
+    pub locked: bool,
+    // Set by an admin to make the user pick a new password through the reset flow before logging in again
+    pub password_reset_required: bool,
+}
+
+impl User {
+    pub fn requires_2fa(&self) -> bool {
+        self.two_fa_method != TwoFaMethod::None
+    }
+}
+
+// What login does with accounts whose email address hasn't been verified yet
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnverifiedLoginPolicy {
+    Allow,
+    Deny,
+    // Unverified accounts can log in for this many seconds after signing up
+    GracePeriod(i64),
+}
+
+impl UnverifiedLoginPolicy {
+    // Accepts `allow`, `deny` or `grace:<hours>`
+    pub fn parse(policy: &str) -> Result<Self, String> {
+        match policy {
+            "allow" => Ok(Self::Allow),
+            "deny" => Ok(Self::Deny),
+            _ => policy
+                .strip_prefix("grace:")
+                .and_then(|hours| hours.parse::<i64>().ok())
+                .filter(|hours| *hours >= 0)
+                .map(|hours| Self::GracePeriod(hours * 3600))
+                .ok_or(format!("{} is not a valid unverified login policy.", policy)),
+        }
+    }
+
+    pub fn allows_login(&self, user: &User, now: i64) -> bool {
+        if user.email_verified {
+            return true;
+        }
+
+        match self {
+            Self::Allow => true,
+            Self::Deny => false,
+            Self::GracePeriod(seconds) => now < user.created_at + seconds,
+        }
+    }
+}
+
+// The second factor a user has enrolled, persisted in the `users.two_fa_method` column
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TwoFaMethod {
+    #[default]
+    None,
+    Email,
+    Totp,
+    Passkey,
+}
+
+impl TwoFaMethod {
+    pub fn parse(method: &str) -> Result<Self, String> {
+        match method {
+            "none" => Ok(Self::None),
+            "email" => Ok(Self::Email),
+            "totp" => Ok(Self::Totp),
+            "passkey" => Ok(Self::Passkey),
+            _ => Err(format!("{} is not a valid 2FA method.", method)),
+        }
+    }
+}
+
+impl AsRef<str> for TwoFaMethod {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::None => "none",
+            Self::Email => "email",
+            Self::Totp => "totp",
+            Self::Passkey => "passkey",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(email_verified: bool, created_at: i64) -> User {
+        User {
+            id: UserId::default(),
+            email: Email::parse("test@example.com".to_owned()).unwrap(),
+            password: Password::parse("password123".to_owned()).unwrap(),
+            two_fa_method: TwoFaMethod::None,
+            email_verified,
+            created_at,
+            delete_after: None,
+            locked: false,
+            password_reset_required: false,
+        }
+    }
+
+    #[test]
+    fn test_parse_unverified_login_policy() {
+        assert_eq!(UnverifiedLoginPolicy::parse("allow"), Ok(UnverifiedLoginPolicy::Allow));
+        assert_eq!(UnverifiedLoginPolicy::parse("deny"), Ok(UnverifiedLoginPolicy::Deny));
+        assert_eq!(UnverifiedLoginPolicy::parse("grace:24"), Ok(UnverifiedLoginPolicy::GracePeriod(86_400)));
+        assert!(UnverifiedLoginPolicy::parse("grace:-1").is_err());
+        assert!(UnverifiedLoginPolicy::parse("sometimes").is_err());
+    }
+
+    #[test]
+    fn test_unverified_login_policy() {
+        let now = 1_000_000;
+
+        assert!(UnverifiedLoginPolicy::Deny.allows_login(&user(true, 0), now));
+        assert!(!UnverifiedLoginPolicy::Deny.allows_login(&user(false, now), now));
+        assert!(UnverifiedLoginPolicy::Allow.allows_login(&user(false, 0), now));
+
+        let grace = UnverifiedLoginPolicy::GracePeriod(3600);
+        assert!(grace.allows_login(&user(false, now - 3599), now));
+        assert!(!grace.allows_login(&user(false, now - 3600), now));
+    }
 }
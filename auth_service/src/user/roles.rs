@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+
+// Seeded by the roles migration, and by the in-memory store so both backends start out alike
+pub const ADMIN_ROLE: &str = "admin";
+
+pub const USERS_READ_PERMISSION: &str = "users:read";
+pub const USERS_WRITE_PERMISSION: &str = "users:write";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Role {
+    pub name: String,
+    pub permissions: Vec<String>,
+}
+
+impl Role {
+    pub fn new(name: &str, permissions: &[&str]) -> Self {
+        Self {
+            name: name.to_owned(),
+            permissions: permissions.iter().map(|permission| (*permission).to_owned()).collect(),
+        }
+    }
+}
+
+pub fn builtin_roles() -> Vec<Role> {
+    vec![Role::new(ADMIN_ROLE, &[USERS_READ_PERMISSION, USERS_WRITE_PERMISSION])]
+}
+
+// Every permission granted by any of the roles, without duplicates
+pub fn permissions_of(roles: &[Role]) -> Vec<String> {
+    let mut permissions: Vec<String> = roles
+        .iter()
+        .flat_map(|role| role.permissions.iter().cloned())
+        .collect();
+
+    permissions.sort();
+    permissions.dedup();
+    permissions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permissions_of() {
+        let roles = vec![
+            Role::new("support", &["users:read", "tickets:write"]),
+            Role::new("auditor", &["users:read", "logs:read"]),
+        ];
+
+        assert_eq!(permissions_of(&roles), vec!["logs:read", "tickets:write", "users:read"]);
+        assert!(permissions_of(&[]).is_empty());
+    }
+}
@@ -1,10 +1,10 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::{BTreeSet, HashMap}, sync::Arc};
 
 use async_trait::async_trait;
 
 use tokio::sync::RwLock;
 
-use super::{Email, Password, User};
+use super::{roles::builtin_roles, Email, Password, Role, TwoFaMethod, User, UserId};
 
 pub type UserStoreType = Arc<RwLock<dyn UserStore + Send + Sync>>;
 
@@ -12,18 +12,59 @@ pub type UserStoreType = Arc<RwLock<dyn UserStore + Send + Sync>>;
 pub trait UserStore {
     async fn add_user(&mut self, user: User) -> Result<(), UserStoreError>;
     async fn get_user(&self, email: &Email) -> Result<User, UserStoreError>;
+    async fn get_user_by_id(&self, id: &UserId) -> Result<User, UserStoreError>;
     async fn validate_user(&self, email: &Email, password: &Password) -> Result<(), UserStoreError>;
+    async fn set_two_fa_method(&mut self, email: &Email, method: TwoFaMethod) -> Result<(), UserStoreError>;
+    async fn update_password(&mut self, email: &Email, password: Password) -> Result<(), UserStoreError>;
+    async fn mark_email_verified(&mut self, email: &Email) -> Result<(), UserStoreError>;
+    // Moves the account to a confirmed new address, which counts as verified
+    async fn update_email(&mut self, email: &Email, new_email: Email) -> Result<(), UserStoreError>;
+    // Schedules the account for deletion at the given Unix timestamp, or cancels a pending deletion with `None`
+    async fn set_delete_after(&mut self, email: &Email, delete_after: Option<i64>) -> Result<(), UserStoreError>;
+    async fn delete_user(&mut self, email: &Email) -> Result<(), UserStoreError>;
+    // Purges the accounts whose deletion grace period is over, returning their addresses
+    async fn delete_scheduled_users(&mut self, now: i64) -> Result<Vec<Email>, UserStoreError>;
+    // Creates the role, or replaces the permissions of an existing one
+    async fn add_role(&mut self, role: Role) -> Result<(), UserStoreError>;
+    async fn assign_role(&mut self, email: &Email, role: &str) -> Result<(), UserStoreError>;
+    async fn revoke_role(&mut self, email: &Email, role: &str) -> Result<(), UserStoreError>;
+    // The roles of the user sorted by name, empty for unknown users
+    async fn get_roles(&self, email: &Email) -> Result<Vec<Role>, UserStoreError>;
+    // Users ordered by signup time, optionally only those whose email contains `search` ignoring case
+    async fn list_users(&self, search: Option<&str>, offset: u64, limit: u64) -> Result<UserPage, UserStoreError>;
+    async fn set_locked(&mut self, email: &Email, locked: bool) -> Result<(), UserStoreError>;
+    // Cleared again by `update_password`
+    async fn set_password_reset_required(&mut self, email: &Email, required: bool) -> Result<(), UserStoreError>;
+}
+
+// One page of a user listing, with the number of matching users across all pages
+#[derive(Debug, PartialEq)]
+pub struct UserPage {
+    pub users: Vec<User>,
+    pub total: u64,
 }
 
-#[derive(Default)]
 pub struct Users {
-    users: HashMap<Email, User>
+    users: HashMap<Email, User>,
+    roles: HashMap<String, Role>,
+    user_roles: HashMap<UserId, BTreeSet<String>>,
+}
+
+impl Default for Users {
+    fn default() -> Self {
+        Self {
+            users: HashMap::new(),
+            roles: builtin_roles().into_iter().map(|role| (role.name.clone(), role)).collect(),
+            user_roles: HashMap::new(),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub enum UserStoreError {
     UserAlreadyExists,
     UserNotFound,
+    RoleNotFound,
     InvalidCredentials,
     UnexpectedError
 }
@@ -46,6 +87,14 @@ impl UserStore for Users {
         }
     }
 
+    async fn get_user_by_id(&self, id: &UserId) -> Result<User, UserStoreError> {
+        self.users
+            .values()
+            .find(|user| &user.id == id)
+            .cloned()
+            .ok_or(UserStoreError::UserNotFound)
+    }
+
     async fn validate_user(&self, email: &Email, password: &Password) -> Result<(), UserStoreError> {
         match self.users.get(email) {
             Some(user) => {
@@ -59,19 +108,181 @@ impl UserStore for Users {
         }
         
     }
+
+    async fn set_two_fa_method(&mut self, email: &Email, method: TwoFaMethod) -> Result<(), UserStoreError> {
+        match self.users.get_mut(email) {
+            Some(user) => {
+                user.two_fa_method = method;
+                Ok(())
+            }
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn update_password(&mut self, email: &Email, password: Password) -> Result<(), UserStoreError> {
+        match self.users.get_mut(email) {
+            Some(user) => {
+                user.password = password;
+                user.password_reset_required = false;
+                Ok(())
+            }
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn mark_email_verified(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        match self.users.get_mut(email) {
+            Some(user) => {
+                user.email_verified = true;
+                Ok(())
+            }
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn update_email(&mut self, email: &Email, new_email: Email) -> Result<(), UserStoreError> {
+        if self.users.contains_key(&new_email) {
+            return Err(UserStoreError::UserAlreadyExists);
+        }
+
+        let mut user = self.users.remove(email).ok_or(UserStoreError::UserNotFound)?;
+        user.email = new_email.clone();
+        user.email_verified = true;
+        self.users.insert(new_email, user);
+        Ok(())
+    }
+
+    async fn set_delete_after(&mut self, email: &Email, delete_after: Option<i64>) -> Result<(), UserStoreError> {
+        match self.users.get_mut(email) {
+            Some(user) => {
+                user.delete_after = delete_after;
+                Ok(())
+            }
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn delete_user(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        let user = self.users.remove(email).ok_or(UserStoreError::UserNotFound)?;
+        self.user_roles.remove(&user.id);
+        Ok(())
+    }
+
+    async fn delete_scheduled_users(&mut self, now: i64) -> Result<Vec<Email>, UserStoreError> {
+        let due: Vec<Email> = self.users
+            .values()
+            .filter(|user| user.delete_after.is_some_and(|delete_after| delete_after <= now))
+            .map(|user| user.email.clone())
+            .collect();
+
+        for email in &due {
+            if let Some(user) = self.users.remove(email) {
+                self.user_roles.remove(&user.id);
+            }
+        }
+
+        Ok(due)
+    }
+
+    async fn add_role(&mut self, role: Role) -> Result<(), UserStoreError> {
+        self.roles.insert(role.name.clone(), role);
+        Ok(())
+    }
+
+    async fn assign_role(&mut self, email: &Email, role: &str) -> Result<(), UserStoreError> {
+        let user = self.users.get(email).ok_or(UserStoreError::UserNotFound)?;
+
+        if !self.roles.contains_key(role) {
+            return Err(UserStoreError::RoleNotFound);
+        }
+
+        self.user_roles.entry(user.id.clone()).or_default().insert(role.to_owned());
+        Ok(())
+    }
+
+    async fn revoke_role(&mut self, email: &Email, role: &str) -> Result<(), UserStoreError> {
+        let user = self.users.get(email).ok_or(UserStoreError::UserNotFound)?;
+
+        if let Some(roles) = self.user_roles.get_mut(&user.id) {
+            roles.remove(role);
+        }
+
+        Ok(())
+    }
+
+    async fn get_roles(&self, email: &Email) -> Result<Vec<Role>, UserStoreError> {
+        let names = self.users
+            .get(email)
+            .and_then(|user| self.user_roles.get(&user.id));
+
+        Ok(names
+            .into_iter()
+            .flatten()
+            .filter_map(|name| self.roles.get(name).cloned())
+            .collect())
+    }
+
+    async fn list_users(&self, search: Option<&str>, offset: u64, limit: u64) -> Result<UserPage, UserStoreError> {
+        let search = search.map(str::to_lowercase);
+
+        let mut users: Vec<&User> = self.users
+            .values()
+            .filter(|user| search.as_ref().map_or(true, |search| user.email.as_ref().to_lowercase().contains(search)))
+            .collect();
+
+        users.sort_by(|a, b| (a.created_at, a.email.as_ref()).cmp(&(b.created_at, b.email.as_ref())));
+
+        Ok(UserPage {
+            total: users.len() as u64,
+            users: users
+                .into_iter()
+                .skip(offset as usize)
+                .take(limit as usize)
+                .cloned()
+                .collect(),
+        })
+    }
+
+    async fn set_locked(&mut self, email: &Email, locked: bool) -> Result<(), UserStoreError> {
+        match self.users.get_mut(email) {
+            Some(user) => {
+                user.locked = locked;
+                Ok(())
+            }
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn set_password_reset_required(&mut self, email: &Email, required: bool) -> Result<(), UserStoreError> {
+        match self.users.get_mut(email) {
+            Some(user) => {
+                user.password_reset_required = required;
+                Ok(())
+            }
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::user::roles::ADMIN_ROLE;
+
     use super::*;
 
     #[tokio::test]
     async fn test_add_user() {
         let mut store = Users::default();
         let user = User {
+            id: UserId::default(),
             email: Email::parse(String::from("johndoe@mail.com")).unwrap(), 
             password: Password::parse(String::from("plsdonthackme")).unwrap(), 
-            requires_2fa: false
+            two_fa_method: TwoFaMethod::None,
+            email_verified: false,
+            created_at: 0,
+            delete_after: None,
+            locked: false,
+            password_reset_required: false
         };
         // Test adding a new user
         let result = store.add_user(user.clone()).await;
@@ -90,9 +301,15 @@ mod tests {
         let password = Password::parse(String::from("plsdonthackme")).unwrap();
 
         let user = User {
+            id: UserId::default(),
             email: email.clone(),
             password: password.clone(),
-            requires_2fa: false
+            two_fa_method: TwoFaMethod::None,
+            email_verified: false,
+            created_at: 0,
+            delete_after: None,
+            locked: false,
+            password_reset_required: false
         };
 
         // Test getting a user that exists
@@ -108,6 +325,35 @@ mod tests {
         assert_eq!(result, Err(UserStoreError::UserNotFound));
     }
 
+    #[tokio::test]
+    async fn test_get_user_by_id() {
+        let mut store = Users::default();
+        let email = Email::parse(String::from("johndoe@mail.com")).unwrap();
+
+        let user = User {
+            id: UserId::default(),
+            email: email.clone(),
+            password: Password::parse(String::from("plsdonthackme")).unwrap(),
+            two_fa_method: TwoFaMethod::None,
+            email_verified: false,
+            created_at: 0,
+            delete_after: None,
+            locked: false,
+            password_reset_required: false
+        };
+
+        store.users.insert(email.clone(), user.clone());
+        assert_eq!(store.get_user_by_id(&user.id).await, Ok(user.clone()));
+
+        // The id stays with the account when its address changes
+        let new_email = Email::parse(String::from("janedoe@mail.com")).unwrap();
+        store.update_email(&email, new_email.clone()).await.unwrap();
+        assert_eq!(store.get_user_by_id(&user.id).await.unwrap().email, new_email);
+
+        let result = store.get_user_by_id(&UserId::default()).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
     #[tokio::test]
     async fn test_validate_user() {
         let mut store = Users::default();
@@ -115,9 +361,15 @@ mod tests {
         let password = Password::parse(String::from("plsdonthackme")).unwrap();
 
         let user = User {
+            id: UserId::default(),
             email: email.clone(),
             password: password.clone(),
-            requires_2fa: false
+            two_fa_method: TwoFaMethod::None,
+            email_verified: false,
+            created_at: 0,
+            delete_after: None,
+            locked: false,
+            password_reset_required: false
         };
 
         // Test validating a user that exists with correct password
@@ -140,4 +392,281 @@ mod tests {
 
         assert_eq!(result, Err(UserStoreError::UserNotFound));
     }
+
+    #[tokio::test]
+    async fn test_set_two_fa_method() {
+        let mut store = Users::default();
+        let email = Email::parse(String::from("johndoe@mail.com")).unwrap();
+
+        let user = User {
+            id: UserId::default(),
+            email: email.clone(),
+            password: Password::parse(String::from("plsdonthackme")).unwrap(),
+            two_fa_method: TwoFaMethod::None,
+            email_verified: false,
+            created_at: 0,
+            delete_after: None,
+            locked: false,
+            password_reset_required: false
+        };
+
+        store.users.insert(email.clone(), user);
+        let result = store.set_two_fa_method(&email, TwoFaMethod::Totp).await;
+        assert_eq!(result, Ok(()));
+        assert_eq!(store.get_user(&email).await.unwrap().two_fa_method, TwoFaMethod::Totp);
+
+        let result = store
+            .set_two_fa_method(&Email::parse("nonexistent@mail.com".to_owned()).unwrap(), TwoFaMethod::Email)
+            .await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_update_password() {
+        let mut store = Users::default();
+        let email = Email::parse(String::from("johndoe@mail.com")).unwrap();
+        let old_password = Password::parse(String::from("plsdonthackme")).unwrap();
+        let new_password = Password::parse(String::from("newpassword")).unwrap();
+
+        let user = User {
+            id: UserId::default(),
+            email: email.clone(),
+            password: old_password.clone(),
+            two_fa_method: TwoFaMethod::None,
+            email_verified: false,
+            created_at: 0,
+            delete_after: None,
+            locked: false,
+            password_reset_required: false
+        };
+
+        store.users.insert(email.clone(), user);
+        let result = store.update_password(&email, new_password.clone()).await;
+        assert_eq!(result, Ok(()));
+        assert_eq!(store.validate_user(&email, &new_password).await, Ok(()));
+        assert_eq!(store.validate_user(&email, &old_password).await, Err(UserStoreError::InvalidCredentials));
+
+        let result = store
+            .update_password(&Email::parse("nonexistent@mail.com".to_owned()).unwrap(), new_password)
+            .await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_mark_email_verified() {
+        let mut store = Users::default();
+        let email = Email::parse(String::from("johndoe@mail.com")).unwrap();
+
+        let user = User {
+            id: UserId::default(),
+            email: email.clone(),
+            password: Password::parse(String::from("plsdonthackme")).unwrap(),
+            two_fa_method: TwoFaMethod::None,
+            email_verified: false,
+            created_at: 0,
+            delete_after: None,
+            locked: false,
+            password_reset_required: false
+        };
+
+        store.users.insert(email.clone(), user);
+        let result = store.mark_email_verified(&email).await;
+        assert_eq!(result, Ok(()));
+        assert!(store.get_user(&email).await.unwrap().email_verified);
+
+        let result = store
+            .mark_email_verified(&Email::parse("nonexistent@mail.com".to_owned()).unwrap())
+            .await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_update_email() {
+        let mut store = Users::default();
+        let email = Email::parse(String::from("johndoe@mail.com")).unwrap();
+        let new_email = Email::parse(String::from("janedoe@mail.com")).unwrap();
+        let taken_email = Email::parse(String::from("taken@mail.com")).unwrap();
+
+        for address in [&email, &taken_email] {
+            store.users.insert(address.clone(), User {
+                id: UserId::default(),
+                email: address.clone(),
+                password: Password::parse(String::from("plsdonthackme")).unwrap(),
+                two_fa_method: TwoFaMethod::None,
+                email_verified: false,
+                created_at: 0,
+            delete_after: None,
+            locked: false,
+            password_reset_required: false
+            });
+        }
+
+        let result = store.update_email(&email, taken_email.clone()).await;
+        assert_eq!(result, Err(UserStoreError::UserAlreadyExists));
+
+        let result = store.update_email(&email, new_email.clone()).await;
+        assert_eq!(result, Ok(()));
+        assert_eq!(store.get_user(&email).await, Err(UserStoreError::UserNotFound));
+
+        let user = store.get_user(&new_email).await.unwrap();
+        assert_eq!(user.email, new_email);
+        assert!(user.email_verified);
+
+        let result = store.update_email(&email, Email::parse("other@mail.com".to_owned()).unwrap()).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_delete_scheduled_users() {
+        let mut store = Users::default();
+        let emails: Vec<Email> = ["due@mail.com", "pending@mail.com", "kept@mail.com"]
+            .into_iter()
+            .map(|email| Email::parse(email.to_owned()).unwrap())
+            .collect();
+
+        for email in &emails {
+            store.users.insert(email.clone(), User {
+                id: UserId::default(),
+                email: email.clone(),
+                password: Password::parse(String::from("plsdonthackme")).unwrap(),
+                two_fa_method: TwoFaMethod::None,
+                email_verified: true,
+                created_at: 0,
+                delete_after: None,
+                locked: false,
+                password_reset_required: false
+            });
+        }
+
+        store.set_delete_after(&emails[0], Some(100)).await.unwrap();
+        store.set_delete_after(&emails[1], Some(200)).await.unwrap();
+
+        assert_eq!(store.delete_scheduled_users(150).await, Ok(vec![emails[0].clone()]));
+        assert_eq!(store.get_user(&emails[0]).await, Err(UserStoreError::UserNotFound));
+
+        // Cancelling a pending deletion keeps the account around
+        store.set_delete_after(&emails[1], None).await.unwrap();
+        assert_eq!(store.delete_scheduled_users(300).await, Ok(vec![]));
+        assert!(store.get_user(&emails[1]).await.is_ok());
+
+        assert_eq!(store.delete_user(&emails[2]).await, Ok(()));
+        assert_eq!(store.delete_user(&emails[2]).await, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_assign_and_revoke_roles() {
+        let mut store = Users::default();
+        let email = Email::parse(String::from("johndoe@mail.com")).unwrap();
+
+        store.users.insert(email.clone(), User {
+            id: UserId::default(),
+            email: email.clone(),
+            password: Password::parse(String::from("plsdonthackme")).unwrap(),
+            two_fa_method: TwoFaMethod::None,
+            email_verified: true,
+            created_at: 0,
+            delete_after: None,
+            locked: false,
+            password_reset_required: false
+        });
+
+        let support = Role::new("support", &["users:read"]);
+        store.add_role(support.clone()).await.unwrap();
+
+        assert_eq!(store.assign_role(&email, "unknown").await, Err(UserStoreError::RoleNotFound));
+        let nobody = Email::parse(String::from("nobody@mail.com")).unwrap();
+        assert_eq!(store.assign_role(&nobody, "support").await, Err(UserStoreError::UserNotFound));
+
+        store.assign_role(&email, "support").await.unwrap();
+        store.assign_role(&email, ADMIN_ROLE).await.unwrap();
+        // Assigning a role twice is a no-op
+        store.assign_role(&email, "support").await.unwrap();
+
+        let roles = store.get_roles(&email).await.unwrap();
+        assert_eq!(roles.iter().map(|role| role.name.as_str()).collect::<Vec<_>>(), vec![ADMIN_ROLE, "support"]);
+
+        // Roles follow the account to a new address
+        let new_email = Email::parse(String::from("janedoe@mail.com")).unwrap();
+        store.update_email(&email, new_email.clone()).await.unwrap();
+
+        store.revoke_role(&new_email, ADMIN_ROLE).await.unwrap();
+        assert_eq!(store.get_roles(&new_email).await, Ok(vec![support]));
+        assert_eq!(store.get_roles(&email).await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn test_list_users() {
+        let mut store = Users::default();
+
+        for (created_at, email) in ["carol@example.com", "alice@mail.com", "Bob@Mail.com"].into_iter().enumerate() {
+            let email = Email::parse(email.to_owned()).unwrap();
+            store.users.insert(email.clone(), User {
+                id: UserId::default(),
+                email,
+                password: Password::parse(String::from("plsdonthackme")).unwrap(),
+                two_fa_method: TwoFaMethod::None,
+                email_verified: true,
+                created_at: created_at as i64,
+                delete_after: None,
+                locked: false,
+                password_reset_required: false
+            });
+        }
+
+        let emails = |page: UserPage| page.users.into_iter().map(|user| user.email.as_ref().to_owned()).collect::<Vec<_>>();
+
+        let page = store.list_users(None, 0, 2).await.unwrap();
+        assert_eq!(page.total, 3);
+        assert_eq!(emails(page), vec!["carol@example.com", "alice@mail.com"]);
+
+        let page = store.list_users(None, 2, 2).await.unwrap();
+        assert_eq!(emails(page), vec!["Bob@Mail.com"]);
+
+        // Search ignores case
+        let page = store.list_users(Some("MAIL.com"), 0, 10).await.unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(emails(page), vec!["alice@mail.com", "Bob@Mail.com"]);
+
+        let page = store.list_users(Some("nobody"), 0, 10).await.unwrap();
+        assert_eq!(page, UserPage { users: vec![], total: 0 });
+    }
+
+    #[tokio::test]
+    async fn test_lock_and_require_password_reset() {
+        let mut store = Users::default();
+        let email = Email::parse(String::from("johndoe@mail.com")).unwrap();
+
+        store.users.insert(email.clone(), User {
+            id: UserId::default(),
+            email: email.clone(),
+            password: Password::parse(String::from("plsdonthackme")).unwrap(),
+            two_fa_method: TwoFaMethod::None,
+            email_verified: true,
+            created_at: 0,
+            delete_after: None,
+            locked: false,
+            password_reset_required: false
+        });
+
+        store.set_locked(&email, true).await.unwrap();
+        store.set_password_reset_required(&email, true).await.unwrap();
+
+        let user = store.get_user(&email).await.unwrap();
+        assert!(user.locked);
+        assert!(user.password_reset_required);
+
+        // Picking a new password satisfies the reset, but doesn't unlock the account
+        store.update_password(&email, Password::parse(String::from("newpassword")).unwrap()).await.unwrap();
+
+        let user = store.get_user(&email).await.unwrap();
+        assert!(user.locked);
+        assert!(!user.password_reset_required);
+
+        store.set_locked(&email, false).await.unwrap();
+        assert!(!store.get_user(&email).await.unwrap().locked);
+
+        let nobody = Email::parse(String::from("nobody@mail.com")).unwrap();
+        assert_eq!(store.set_locked(&nobody, true).await, Err(UserStoreError::UserNotFound));
+        assert_eq!(store.set_password_reset_required(&nobody, true).await, Err(UserStoreError::UserNotFound));
+    }
 }
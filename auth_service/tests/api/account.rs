@@ -0,0 +1,186 @@
+use auth_service::{
+    route_handlers::{AccountExport, DeleteAccountResponse},
+    services::{
+        client_info::ClientInfo,
+        constants::JWT_COOKIE_NAME,
+        two_fa::{LoginId, TwoFaChallenge, TwoFaCode, TwoFaCodes}
+    },
+    user::{roles::ADMIN_ROLE, store::UserStoreError, Email}
+};
+use chrono::Utc;
+use reqwest::header::CONTENT_DISPOSITION;
+
+use crate::utils::{get_random_email, TestApp};
+
+async fn signup_and_login(app: &TestApp, email: &str) -> String {
+    let response = app.post_signup(&serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_login(&serde_json::json!({
+        "email": email,
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+
+    auth_cookie.value().to_owned()
+}
+
+#[tokio::test]
+async fn should_export_everything_stored_about_the_user() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_account_export().await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    let random_email = get_random_email();
+    signup_and_login(&app, &random_email).await;
+
+    app.user_store
+        .write()
+        .await
+        .assign_role(&Email::parse(random_email.clone()).unwrap(), ADMIN_ROLE)
+        .await
+        .unwrap();
+
+    let response = app.post_organizations(&serde_json::json!({ "name": "Acme" })).await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.get_account_export().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .headers()
+        .get(CONTENT_DISPOSITION)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("attachment")));
+
+    let export = response
+        .json::<AccountExport>()
+        .await
+        .expect("Could not deserialize response body to AccountExport");
+
+    assert_eq!(export.user.email, random_email);
+    assert!(!export.user.email_verified);
+    assert_eq!(export.user.delete_after, None);
+    assert_eq!(export.roles.len(), 1);
+    assert_eq!(export.roles[0].name, ADMIN_ROLE);
+    assert_eq!(export.organizations.len(), 1);
+    assert_eq!(export.organizations[0].name, "Acme");
+    assert_eq!(export.sessions.len(), 1);
+    assert!(export.sessions[0].ip.is_some());
+    assert_eq!(export.login_history.len(), 1);
+    assert!(export.login_history[0].ip.is_some());
+    assert!(!export.factors.totp);
+    assert!(export.factors.passkeys.is_empty());
+    assert_eq!(export.factors.recovery_codes_remaining, 0);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_delete_account_and_everything_attached_to_it() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    let token = signup_and_login(&app, &random_email).await;
+
+    // A login attempt left pending on another device
+    let email = Email::parse(random_email.clone()).unwrap();
+    let login_id = LoginId::default();
+    app.two_fa_code
+        .write()
+        .await
+        .add_challenge(login_id.clone(), TwoFaChallenge::new(email.clone(), TwoFaCode::default(), ClientInfo::default()))
+        .await
+        .unwrap();
+
+    let response = app.delete_account(&serde_json::json!({ "password": "wrongpassword" })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.delete_account(&serde_json::json!({ "password": "password123" })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = response
+        .json::<DeleteAccountResponse>()
+        .await
+        .expect("Could not deserialize response body to DeleteAccountResponse");
+
+    assert_eq!(body.delete_after, None);
+
+    assert_eq!(app.user_store.read().await.get_user(&email).await, Err(UserStoreError::UserNotFound));
+    assert!(app.two_fa_code.read().await.get_challenge(&login_id).await.is_err());
+
+    let response = app.post_verify_token(&serde_json::json!({ "token": token })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_refresh().await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app.post_login(&serde_json::json!({
+        "email": random_email,
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_purge_accounts_once_their_grace_period_is_over() {
+    let mut app = TestApp::new().await;
+
+    let due_email = get_random_email();
+    let restored_email = get_random_email();
+    signup_and_login(&app, &due_email).await;
+    signup_and_login(&app, &restored_email).await;
+
+    let due = Email::parse(due_email).unwrap();
+    let restored = Email::parse(restored_email.clone()).unwrap();
+    let now = Utc::now().timestamp();
+
+    {
+        let mut user_store = app.user_store.write().await;
+        user_store.set_delete_after(&due, Some(now - 1)).await.unwrap();
+        user_store.set_delete_after(&restored, Some(now + 3600)).await.unwrap();
+    }
+
+    // Logging back in during the grace period keeps the account
+    let response = app.post_login(&serde_json::json!({
+        "email": restored_email,
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let user = app.user_store.read().await.get_user(&restored).await.unwrap();
+    assert_eq!(user.delete_after, None);
+
+    let purged = app.user_store.write().await.delete_scheduled_users(now + 7200).await.unwrap();
+
+    assert_eq!(purged, vec![due.clone()]);
+    assert_eq!(app.user_store.read().await.get_user(&due).await, Err(UserStoreError::UserNotFound));
+
+    app.clean_up().await;
+}
@@ -0,0 +1,395 @@
+use auth_service::{
+    route_handlers::{AdminUser, AdminUserDetails, AdminUsersResponse},
+    services::{api::ErrorResponse, constants::{ADMIN_API_KEY_HEADER, JWT_COOKIE_NAME, LOGIN_LOCKOUT_POLICY}},
+    user::{roles::ADMIN_ROLE, Email}
+};
+use wiremock::{matchers::{method, path}, Mock, ResponseTemplate};
+
+use crate::utils::{get_random_email, TestApp};
+
+async fn signup(app: &TestApp, email: &str) -> String {
+    let response = app.post_signup(&serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    app.user_store
+        .read()
+        .await
+        .get_user(&Email::parse(email.to_owned()).unwrap())
+        .await
+        .unwrap()
+        .id
+        .as_ref()
+        .to_owned()
+}
+
+async fn login(app: &TestApp, email: &str) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "email": email,
+        "password": "password123"
+    }))
+    .await
+}
+
+// Logs in as `email` and returns the auth token of the new session
+async fn login_token(app: &TestApp, email: &str) -> String {
+    let response = login(app, email).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+
+    auth_cookie.value().to_owned()
+}
+
+// Signs up an admin and leaves the test client logged in as them
+async fn login_admin(app: &TestApp) -> String {
+    let email = get_random_email();
+    signup(app, &email).await;
+
+    app.user_store
+        .write()
+        .await
+        .assign_role(&Email::parse(email.clone()).unwrap(), ADMIN_ROLE)
+        .await
+        .unwrap();
+
+    login_token(app, &email).await;
+    email
+}
+
+async fn error_message(response: reqwest::Response) -> String {
+    response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse")
+        .error
+}
+
+#[tokio::test]
+async fn should_only_allow_admins() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_admin_users(&[]).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    let email = get_random_email();
+    signup(&app, &email).await;
+    login_token(&app, &email).await;
+
+    let response = app.get_admin_users(&[]).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+
+    // No API key is configured, so none is accepted, and a bad key doesn't fall back to the cookie
+    login_admin(&app).await;
+
+    let response = app.client
+        .get(format!("{}/admin/users", &app.addr))
+        .header(ADMIN_API_KEY_HEADER, "guess")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_list_search_and_show_users() {
+    let mut app = TestApp::new().await;
+
+    let mut emails = Vec::new();
+    for name in ["alice", "bob", "alina"] {
+        let email = format!("{}-{}", name, get_random_email());
+        signup(&app, &email).await;
+        emails.push(email);
+    }
+
+    let admin_email = login_admin(&app).await;
+
+    let response = app.get_admin_users(&[("page", "1"), ("perPage", "2")]).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let listing = response
+        .json::<AdminUsersResponse>()
+        .await
+        .expect("Could not deserialize response body to AdminUsersResponse");
+
+    assert_eq!(listing.total, 4);
+    assert_eq!((listing.page, listing.per_page), (1, 2));
+    assert_eq!(listing.users.len(), 2);
+
+    let first_page: Vec<_> = listing.users.into_iter().map(|user| user.email).collect();
+
+    let listing = app
+        .get_admin_users(&[("page", "2"), ("perPage", "2")])
+        .await
+        .json::<AdminUsersResponse>()
+        .await
+        .expect("Could not deserialize response body to AdminUsersResponse");
+
+    // Signups within the same second tie on creation time, so only check the pages split everyone between them
+    let mut listed: Vec<_> = first_page.into_iter().chain(listing.users.into_iter().map(|user| user.email)).collect();
+    let mut expected: Vec<_> = emails.iter().cloned().chain([admin_email.clone()]).collect();
+    listed.sort();
+    expected.sort();
+
+    assert_eq!(listed, expected);
+
+    let listing = app
+        .get_admin_users(&[("search", "ALI")])
+        .await
+        .json::<AdminUsersResponse>()
+        .await
+        .expect("Could not deserialize response body to AdminUsersResponse");
+
+    let found: Vec<_> = listing.users.iter().map(|user| user.email.as_str()).collect();
+    assert_eq!(found, vec![emails[0].as_str(), emails[2].as_str()]);
+
+    let response = app.get_admin_user_details(&listing.users[0].id).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let details = response
+        .json::<AdminUserDetails>()
+        .await
+        .expect("Could not deserialize response body to AdminUserDetails");
+
+    assert_eq!(details.user.email, emails[0]);
+    assert!(details.roles.is_empty());
+
+    let admin_id = app.user_store.read().await.get_user(&Email::parse(admin_email).unwrap()).await.unwrap().id;
+
+    let details = app
+        .get_admin_user_details(admin_id.as_ref())
+        .await
+        .json::<AdminUserDetails>()
+        .await
+        .expect("Could not deserialize response body to AdminUserDetails");
+
+    assert_eq!(details.roles, vec![ADMIN_ROLE]);
+
+    let response = app.get_admin_user_details("not-a-uuid").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app.get_admin_user_details(&uuid::Uuid::new_v4().to_string()).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_lock_and_unlock_accounts() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    let user_id = signup(&app, &email).await;
+    let token = login_token(&app, &email).await;
+
+    login_admin(&app).await;
+
+    let response = app.post_admin_user_lock(&serde_json::json!({ "userId": user_id })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let user = response
+        .json::<AdminUser>()
+        .await
+        .expect("Could not deserialize response body to AdminUser");
+
+    assert!(user.locked);
+
+    // The session the user already had ends with the lock
+    let response = app.post_verify_token(&serde_json::json!({ "token": token })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = login(&app, &email).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(error_message(response).await, "Account locked");
+
+    login_admin(&app).await;
+
+    let response = app.post_admin_user_unlock(&serde_json::json!({ "userId": user_id })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    login_token(&app, &email).await;
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_lift_failed_login_lockout_on_unlock() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    let user_id = signup(&app, &email).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    for _ in 0..LOGIN_LOCKOUT_POLICY.threshold {
+        app.post_login(&serde_json::json!({
+            "email": email,
+            "password": "wrongpassword"
+        }))
+        .await;
+    }
+
+    let response = login(&app, &email).await;
+
+    assert_eq!(response.status().as_u16(), 429);
+
+    login_admin(&app).await;
+
+    let response = app.post_admin_user_unlock(&serde_json::json!({ "userId": user_id })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    login_token(&app, &email).await;
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_force_password_reset() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    let user_id = signup(&app, &email).await;
+
+    login_admin(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_admin_user_password_reset(&serde_json::json!({ "userId": user_id })).await;
+
+    assert_eq!(response.status().as_u16(), 202);
+
+    // The old password still checks out, but isn't enough to log in any more
+    let response = login(&app, &email).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(error_message(response).await, "Password reset required");
+
+    let token = app.token_from_email("Reset your password").await;
+
+    let response = app.post_password_reset_confirm(&serde_json::json!({
+        "token": token,
+        "newPassword": "newpassword123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_login(&serde_json::json!({
+        "email": email,
+        "password": "newpassword123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_toggle_2fa() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    let user_id = signup(&app, &email).await;
+
+    login_admin(&app).await;
+
+    let response = app.post_admin_user_2fa(&serde_json::json!({ "userId": user_id, "requires2FA": true })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let user = response
+        .json::<AdminUser>()
+        .await
+        .expect("Could not deserialize response body to AdminUser");
+
+    assert!(user.requires_2fa);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = login(&app, &email).await;
+
+    assert_eq!(response.status().as_u16(), 206);
+
+    let response = app.post_admin_user_2fa(&serde_json::json!({ "userId": user_id, "requires2FA": false })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    login_token(&app, &email).await;
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_revoke_sessions_and_delete_users() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    let user_id = signup(&app, &email).await;
+    let token = login_token(&app, &email).await;
+
+    login_admin(&app).await;
+
+    let response = app.post_admin_user_revoke_sessions(&serde_json::json!({ "userId": user_id })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_verify_token(&serde_json::json!({ "token": token })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.delete_admin_users(&serde_json::json!({ "userId": user_id })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.get_admin_user_details(&user_id).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+
+    let response = app.delete_admin_users(&serde_json::json!({ "userId": user_id })).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+
+    let response = login(&app, &email).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
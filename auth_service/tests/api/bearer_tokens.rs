@@ -0,0 +1,243 @@
+use auth_service::{
+    route_handlers::{OrganizationResponse, TokenResponse, TwoFactorAuthResponse},
+    services::{auth::TOKEN_TTL_SECONDS, two_fa::{LoginId, TwoFaCodes}}
+};
+use reqwest::{header::SET_COOKIE, Client};
+use serde::Serialize;
+use wiremock::{matchers::{method, path}, Mock, ResponseTemplate};
+
+use crate::utils::{get_random_email, TestApp};
+
+// A client without a cookie jar, like a CLI tool or a mobile app
+struct CliClient {
+    client: Client,
+    addr: String,
+}
+
+impl CliClient {
+    fn new(app: &TestApp) -> Self {
+        Self { client: Client::new(), addr: app.addr.clone() }
+    }
+
+    async fn post<Body: Serialize>(&self, route: &str, body: &Body) -> reqwest::Response {
+        self.client
+            .post(format!("{}{}", &self.addr, route))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    async fn post_with_token<Body: Serialize>(&self, route: &str, token: &str, body: &Body) -> reqwest::Response {
+        self.client
+            .post(format!("{}{}", &self.addr, route))
+            .bearer_auth(token)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    async fn get_with_token(&self, route: &str, token: &str) -> reqwest::Response {
+        self.client
+            .get(format!("{}{}", &self.addr, route))
+            .bearer_auth(token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+}
+
+async fn signup(app: &TestApp, requires_2fa: bool) -> String {
+    let email = get_random_email();
+
+    let response = app.post_signup(&serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": requires_2fa
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    email
+}
+
+async fn login_for_tokens(cli: &CliClient, email: &str) -> TokenResponse {
+    let response = cli.post("/login", &serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "responseMode": "token"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers().get(SET_COOKIE).is_none());
+
+    response
+        .json::<TokenResponse>()
+        .await
+        .expect("Could not deserialize response body to TokenResponse")
+}
+
+#[tokio::test]
+async fn should_return_tokens_in_body_in_token_mode() {
+    let mut app = TestApp::new().await;
+    let cli = CliClient::new(&app);
+    let email = signup(&app, false).await;
+
+    let tokens = login_for_tokens(&cli, &email).await;
+
+    assert_eq!(tokens.token_type, "Bearer");
+    assert_eq!(tokens.expires_in, TOKEN_TTL_SECONDS);
+    assert!(tokens.refresh_token.is_some());
+
+    // Every authenticated route takes the token as a bearer token
+    let response = cli.get_with_token("/sessions", &tokens.access_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = cli.post("/verify-token", &serde_json::json!({ "token": tokens.access_token })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = cli.get_with_token("/sessions", "not-a-jwt").await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_tokens_in_body_after_2fa_in_token_mode() {
+    let mut app = TestApp::new().await;
+    let cli = CliClient::new(&app);
+    let email = signup(&app, true).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = cli.post("/login", &serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "responseMode": "token"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 206);
+
+    let login_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_id;
+
+    let challenge = app
+        .two_fa_code
+        .read()
+        .await
+        .get_challenge(&LoginId::parse(login_id.clone()).unwrap())
+        .await
+        .unwrap();
+
+    let response = cli.post("/verify-2fa", &serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_id,
+        "2FACode": challenge.code.as_ref(),
+        "responseMode": "token"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers().get(SET_COOKIE).is_none());
+
+    let tokens = response
+        .json::<TokenResponse>()
+        .await
+        .expect("Could not deserialize response body to TokenResponse");
+
+    let response = cli.get_with_token("/sessions", &tokens.access_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_refresh_with_refresh_token_in_body() {
+    let mut app = TestApp::new().await;
+    let cli = CliClient::new(&app);
+    let email = signup(&app, false).await;
+
+    let tokens = login_for_tokens(&cli, &email).await;
+    let refresh_token = tokens.refresh_token.unwrap();
+
+    let response = cli.post("/refresh", &serde_json::json!({ "refreshToken": refresh_token })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers().get(SET_COOKIE).is_none());
+
+    let refreshed = response
+        .json::<TokenResponse>()
+        .await
+        .expect("Could not deserialize response body to TokenResponse");
+
+    assert_ne!(refreshed.refresh_token.as_deref(), Some(refresh_token.as_str()));
+
+    let response = cli.get_with_token("/sessions", &refreshed.access_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Rotated refresh tokens can't be used again
+    let response = cli.post("/refresh", &serde_json::json!({ "refreshToken": refresh_token })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_logout_with_bearer_token() {
+    let mut app = TestApp::new().await;
+    let cli = CliClient::new(&app);
+    let email = signup(&app, false).await;
+
+    let tokens = login_for_tokens(&cli, &email).await;
+
+    let response = cli.post_with_token("/logout", &tokens.access_token, &serde_json::json!({})).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = cli.get_with_token("/sessions", &tokens.access_token).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The session's refresh token ended with it
+    let response = cli.post("/refresh", &serde_json::json!({ "refreshToken": tokens.refresh_token })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reissue_tokens_in_body_for_bearer_clients() {
+    let mut app = TestApp::new().await;
+    let cli = CliClient::new(&app);
+    let email = signup(&app, false).await;
+
+    let tokens = login_for_tokens(&cli, &email).await;
+
+    let response = cli.post_with_token("/orgs", &tokens.access_token, &serde_json::json!({ "name": "Acme" })).await;
+
+    assert_eq!(response.status().as_u16(), 201);
+    assert!(response.headers().get(SET_COOKIE).is_none());
+
+    let organization = response
+        .json::<OrganizationResponse>()
+        .await
+        .expect("Could not deserialize response body to OrganizationResponse");
+
+    // Only the auth token changes, the session keeps its refresh token
+    let reissued = organization.tokens.expect("No tokens in the response body");
+    assert!(reissued.refresh_token.is_none());
+
+    let response = cli.get_with_token("/orgs", &reissued.access_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
@@ -0,0 +1,336 @@
+use auth_service::{
+    route_handlers::ChangeEmailResponse,
+    services::{
+        api::ErrorResponse,
+        client_info::ClientInfo,
+        constants::{JWT_COOKIE_NAME, REFRESH_COOKIE_NAME},
+        refresh_tokens::{RefreshToken, RefreshTokenStoreError},
+        totp::TotpSecret,
+        two_fa::{LoginId, TwoFaChallenge, TwoFaCode, TwoFaCodes}
+    },
+    user::Email
+};
+use reqwest::cookie::CookieStore;
+use wiremock::{matchers::{body_partial_json, method, path}, Mock, ResponseTemplate};
+
+use crate::utils::{get_random_email, TestApp};
+
+const CONFIRM_SUBJECT: &str = "Confirm your new email address";
+const NOTICE_SUBJECT: &str = "Your email address is being changed";
+const RESET_SUBJECT: &str = "Reset your password";
+
+async fn signup_and_login(app: &TestApp, email: &str) -> String {
+    let response = app.post_signup(&serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_login(&serde_json::json!({
+        "email": email,
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+
+    auth_cookie.value().to_owned()
+}
+
+async fn mount_change_email_mocks(app: &TestApp) {
+    for subject in [CONFIRM_SUBJECT, NOTICE_SUBJECT] {
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .and(body_partial_json(serde_json::json!({ "Subject": subject })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&app.email_server)
+            .await;
+    }
+}
+
+#[tokio::test]
+async fn should_move_account_to_new_email_once_confirmed() {
+    let mut app = TestApp::new().await;
+
+    let old_email = get_random_email();
+    let new_email = get_random_email();
+    let old_token = signup_and_login(&app, &old_email).await;
+
+    // Enroll an authenticator directly, so there is a dependent record that has to follow the account
+    let secret = TotpSecret::parse("JBSWY3DPEHPK3PXP".to_owned()).unwrap();
+    {
+        let mut totp_store = app.totp_store.write().await;
+        let email = Email::parse(old_email.clone()).unwrap();
+        totp_store.set_pending_secret(email.clone(), secret.clone()).await.unwrap();
+        totp_store.confirm_secret(&email, 1).await.unwrap();
+    }
+
+    mount_change_email_mocks(&app).await;
+
+    let response = app.post_change_email(&serde_json::json!({
+        "newEmail": new_email,
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 202);
+
+    response
+        .json::<ChangeEmailResponse>()
+        .await
+        .expect("Could not deserialize response body to ChangeEmailResponse");
+
+    // Nothing changes until the new address is confirmed
+    let response = app.post_verify_token(&serde_json::json!({ "token": old_token })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let confirm_token = app.token_from_email(CONFIRM_SUBJECT).await;
+    let cancel_token = app.token_from_email(NOTICE_SUBJECT).await;
+
+    let cookies = app.cookie_jar.cookies(&app.addr.parse().unwrap()).expect("No cookies found");
+    let refresh_token = cookies
+        .to_str()
+        .unwrap()
+        .split("; ")
+        .find_map(|cookie| cookie.strip_prefix(&format!("{}=", REFRESH_COOKIE_NAME)))
+        .map(|token| RefreshToken::parse(token.to_owned()).unwrap())
+        .expect("No refresh cookie found");
+
+    let response = app.post_change_email_confirm(&serde_json::json!({ "token": confirm_token })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Sessions of the old address are over, and the account now answers to the new one
+    let response = app.post_verify_token(&serde_json::json!({ "token": old_token })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    let result = app.refresh_tokens.read().await.get_token(&refresh_token).await;
+
+    assert_eq!(result, Err(RefreshTokenStoreError::TokenNotFound));
+
+    let response = app.post_refresh().await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_login(&serde_json::json!({
+        "email": old_email,
+        "password": "password123"
+    }))
+    .await;
+
+    assert_ne!(response.status().as_u16(), 200);
+
+    let response = app.post_login(&serde_json::json!({
+        "email": new_email,
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let stored_secret = app
+        .totp_store
+        .read()
+        .await
+        .get_secret(&Email::parse(new_email).unwrap())
+        .await
+        .expect("TOTP secret did not follow the account");
+
+    assert_eq!(stored_secret.encode(), secret.encode());
+
+    // The change is settled, so neither link works again
+    let response = app.post_change_email_confirm(&serde_json::json!({ "token": confirm_token })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_change_email_cancel(&serde_json::json!({ "token": cancel_token })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_drop_pending_state_of_old_email_once_confirmed() {
+    let mut app = TestApp::new().await;
+
+    let old_email = get_random_email();
+    let new_email = get_random_email();
+    signup_and_login(&app, &old_email).await;
+
+    mount_change_email_mocks(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_partial_json(serde_json::json!({ "Subject": RESET_SUBJECT })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // A reset link and a login waiting for its second factor, both for the old address
+    let response = app.post_password_reset_request(&serde_json::json!({ "email": old_email })).await;
+
+    assert_eq!(response.status().as_u16(), 202);
+
+    let reset_token = app.token_from_email(RESET_SUBJECT).await;
+
+    let login_id = LoginId::default();
+    app.two_fa_code
+        .write()
+        .await
+        .add_challenge(
+            login_id.clone(),
+            TwoFaChallenge::new(Email::parse(old_email.clone()).unwrap(), TwoFaCode::default(), ClientInfo::default())
+        )
+        .await
+        .unwrap();
+
+    let response = app.post_change_email(&serde_json::json!({
+        "newEmail": new_email,
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 202);
+
+    let confirm_token = app.token_from_email(CONFIRM_SUBJECT).await;
+
+    let response = app.post_change_email_confirm(&serde_json::json!({ "token": confirm_token })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Whoever signs up with the old address next must not inherit either of them
+    let response = app.post_password_reset_confirm(&serde_json::json!({
+        "token": reset_token,
+        "newPassword": "newpassword123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    assert!(app.two_fa_code.read().await.get_challenge(&login_id).await.is_err());
+
+    let response = app.post_login(&serde_json::json!({
+        "email": new_email,
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_keep_old_email_when_change_is_cancelled() {
+    let mut app = TestApp::new().await;
+
+    let old_email = get_random_email();
+    let new_email = get_random_email();
+    signup_and_login(&app, &old_email).await;
+
+    mount_change_email_mocks(&app).await;
+
+    let response = app.post_change_email(&serde_json::json!({
+        "newEmail": new_email,
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 202);
+
+    let confirm_token = app.token_from_email(CONFIRM_SUBJECT).await;
+    let cancel_token = app.token_from_email(NOTICE_SUBJECT).await;
+
+    // Each link only works for its own action
+    let response = app.post_change_email_cancel(&serde_json::json!({ "token": confirm_token })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_change_email_cancel(&serde_json::json!({ "token": cancel_token })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_change_email_confirm(&serde_json::json!({ "token": confirm_token })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_login(&serde_json::json!({
+        "email": old_email,
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reject_invalid_requests() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_change_email(&serde_json::json!({
+        "newEmail": get_random_email(),
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    let email = get_random_email();
+    let taken_email = get_random_email();
+    signup_and_login(&app, &taken_email).await;
+    signup_and_login(&app, &email).await;
+
+    let response = app.post_change_email(&serde_json::json!({
+        "newEmail": get_random_email(),
+        "password": "wrongpassword"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_change_email(&serde_json::json!({
+        "newEmail": email,
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app.post_change_email(&serde_json::json!({
+        "newEmail": taken_email,
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 409);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "User already exists".to_owned()
+    );
+
+    let response = app.post_change_email_confirm(&serde_json::json!({ "token": "not-a-token" })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
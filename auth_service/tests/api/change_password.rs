@@ -0,0 +1,156 @@
+use auth_service::services::{api::ErrorResponse, constants::JWT_COOKIE_NAME};
+use wiremock::{matchers::{body_partial_json, method, path}, Mock, ResponseTemplate};
+
+use crate::utils::{get_random_email, TestApp};
+
+async fn signup_and_login(app: &TestApp, email: &str) -> String {
+    let response = app.post_signup(&serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_login(&serde_json::json!({
+        "email": email,
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+
+    auth_cookie.value().to_owned()
+}
+
+#[tokio::test]
+async fn should_change_password_and_keep_only_the_current_session() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    let old_token = signup_and_login(&app, &random_email).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_partial_json(serde_json::json!({ "Subject": "Your password was changed" })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_change_password(&serde_json::json!({
+        "currentPassword": "password123",
+        "newPassword": "newpassword123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let new_token = {
+        let auth_cookie = response
+            .cookies()
+            .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+            .expect("No auth cookie found");
+
+        auth_cookie.value().to_owned()
+    };
+
+    // Tokens issued before the change are revoked, the ones handed back keep this session going
+    let response = app.post_verify_token(&serde_json::json!({ "token": old_token })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_verify_token(&serde_json::json!({ "token": new_token })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_refresh().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_login(&serde_json::json!({
+        "email": random_email,
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_login(&serde_json::json!({
+        "email": random_email,
+        "password": "newpassword123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_current_password_is_wrong() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    signup_and_login(&app, &random_email).await;
+
+    let response = app.post_change_password(&serde_json::json!({
+        "currentPassword": "wrongpassword",
+        "newPassword": "newpassword123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Incorrect credentials".to_owned()
+    );
+
+    let response = app.post_login(&serde_json::json!({
+        "email": random_email,
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reject_invalid_requests() {
+    let mut app = TestApp::new().await;
+
+    let body = serde_json::json!({
+        "currentPassword": "password123",
+        "newPassword": "newpassword123"
+    });
+
+    let response = app.post_change_password(&body).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    let random_email = get_random_email();
+    signup_and_login(&app, &random_email).await;
+
+    let response = app.post_change_password(&serde_json::json!({
+        "currentPassword": "password123",
+        "newPassword": "short"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
@@ -0,0 +1,174 @@
+use auth_service::{
+    services::{constants::JWT_COOKIE_NAME, keys::SigningKey},
+    user::Email
+};
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+
+use crate::utils::{get_random_email, TestApp, TEST_KEY_ID};
+
+#[derive(Deserialize)]
+struct Claims {
+    sub: String,
+    email: Option<String>,
+}
+
+#[tokio::test]
+async fn should_return_public_signing_keys() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_jwks().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let jwks = response
+        .json::<JwkSet>()
+        .await
+        .expect("Could not deserialize response body to JwkSet");
+
+    assert_eq!(jwks.keys.len(), 1);
+    assert!(jwks.find(TEST_KEY_ID).is_some());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_verify_auth_token_with_published_key() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&signup_body).await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+
+    let token = auth_cookie.value().to_owned();
+
+    let jwks = app
+        .get_jwks()
+        .await
+        .json::<JwkSet>()
+        .await
+        .expect("Could not deserialize response body to JwkSet");
+
+    let header = decode_header(&token).expect("Failed to decode token header");
+    let kid = header.kid.expect("Token has no kid header");
+    let jwk = jwks.find(&kid).expect("Token was signed with an unpublished key");
+
+    let claims = decode::<Claims>(
+        &token,
+        &DecodingKey::from_jwk(jwk).expect("Failed to build decoding key from JWK"),
+        &Validation::new(header.alg),
+    )
+    .expect("Failed to verify token with published key")
+    .claims;
+
+    let user = app.user_store
+        .read()
+        .await
+        .get_user(&Email::parse(random_email).unwrap())
+        .await
+        .unwrap();
+
+    // The subject is the stable user id, and the address stays out of the token unless configured
+    assert_eq!(claims.sub, user.id.as_ref());
+    assert_eq!(claims.email, None);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_accept_tokens_signed_with_retired_key_after_rotation() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&signup_body).await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let old_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    let new_key = SigningKey::from_pem(
+        "test-rsa",
+        Algorithm::RS256,
+        include_bytes!("../fixtures/keys/rsa.pem"),
+    )
+    .expect("Failed to load RSA key");
+
+    app.keyring
+        .write()
+        .await
+        .rotate(new_key)
+        .expect("Failed to rotate keyring");
+
+    let response = app.post_verify_token(&serde_json::json!({ "token": old_token })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_login(&login_body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let new_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    let header = decode_header(&new_token).expect("Failed to decode token header");
+    assert_eq!(header.kid.as_deref(), Some("test-rsa"));
+
+    let jwks = app
+        .get_jwks()
+        .await
+        .json::<JwkSet>()
+        .await
+        .expect("Could not deserialize response body to JwkSet");
+
+    assert!(jwks.find(TEST_KEY_ID).is_some());
+    assert!(jwks.find("test-rsa").is_some());
+
+    app.clean_up().await;
+}
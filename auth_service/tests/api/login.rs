@@ -3,7 +3,7 @@ use auth_service::{
     services::{
         api::ErrorResponse, 
         constants::JWT_COOKIE_NAME,
-        two_fa::TwoFaCodes
+        two_fa::{LoginId, TwoFaCodes}
     }, 
     user::Email
 };
@@ -14,7 +14,7 @@ use crate::utils::{get_random_email, TestApp};
 
 #[tokio::test]
 async fn should_return_422_if_malformed_credentials() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
 
     let random_email = get_random_email();
 
@@ -48,12 +48,14 @@ async fn should_return_422_if_malformed_credentials() {
             test_case
         );
     }
+
+    app.clean_up().await;
 }
 
 
 #[tokio::test]
 async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
 
     let random_email = get_random_email();
 
@@ -92,17 +94,22 @@ async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
 
     let two_fa_code_store = app.two_fa_code.read().await;
 
-    let code_tuple = two_fa_code_store
-        .get_code(&Email::parse(random_email).unwrap())
+    let challenge = two_fa_code_store
+        .get_challenge(&LoginId::parse(json_body.login_id).unwrap())
         .await
-        .expect("Failed to get 2FA code");
+        .expect("Failed to get 2FA challenge");
+
+    assert_eq!(challenge.email, Email::parse(random_email).unwrap());
+    assert_eq!(challenge.client.ip.as_deref(), Some("127.0.0.1"));
+
+    drop(two_fa_code_store);
 
-    assert_eq!(code_tuple.0.as_ref(), json_body.login_id);
+    app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_400_if_invalid_input() {
-    let test_app = TestApp::new().await;
+    let mut test_app = TestApp::new().await;
 
     let random_email = get_random_email();
 
@@ -138,11 +145,13 @@ async fn should_return_400_if_invalid_input() {
             String::from("Invalid credentials")
         );
     }
+
+    test_app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_401_if_incorrect_credentials() {
-    let test_app = TestApp::new().await;
+    let mut test_app = TestApp::new().await;
 
     let random_email = get_random_email();
 
@@ -155,10 +164,12 @@ async fn should_return_401_if_incorrect_credentials() {
     
     assert_eq!(response.status().as_u16(), 201);
 
+    // Unknown addresses count towards a lockout as well, so each run uses fresh ones
+    let unknown_email = get_random_email();
     let test_case = vec![
         (random_email.as_str(), "wrong-password"),
-        ("wrong@email.com", "password123"),
-        ("wrong@email.com", "wrong-password")
+        (unknown_email.as_str(), "password123"),
+        (unknown_email.as_str(), "wrong-password")
     ];
 
     for (email, password) in test_case {
@@ -178,11 +189,12 @@ async fn should_return_401_if_incorrect_credentials() {
         );
     }
 
+    test_app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_200_if_valid_credentials_and_2fa_disabled() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
 
     let random_email = get_random_email();
 
@@ -211,4 +223,6 @@ async fn should_return_200_if_valid_credentials_and_2fa_disabled() {
         .expect("No auth cookie found");
 
     assert!(!auth_cookie.value().is_empty());
+
+    app.clean_up().await;
 }
\ No newline at end of file
@@ -1,7 +1,26 @@
 mod utils;
+mod account;
+mod admin;
+mod bearer_tokens;
+mod change_email;
+mod change_password;
+mod jwks;
 mod login;
 mod logout;
+mod oauth;
+mod oidc;
+mod organizations;
+mod password_reset;
+mod rate_limit;
+mod recovery_codes;
+mod refresh;
+mod resend_2fa;
 mod root;
+mod sessions;
 mod signup;
+mod totp;
+mod unlock_account;
 mod verify_2fa;
+mod verify_email;
 mod verify_token;
+mod webauthn;
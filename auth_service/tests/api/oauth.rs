@@ -0,0 +1,584 @@
+use auth_service::{
+    route_handlers::{OAuthClientResponse, OAuthClientsResponse, OAuthTokenResponse, SessionsResponse, TwoFactorAuthResponse},
+    services::{api::OAuthErrorResponse, auth::TOKEN_TTL_SECONDS, two_fa::{LoginId, TwoFaCodes}},
+    user::{roles::ADMIN_ROLE, Email}
+};
+use reqwest::{header::{CACHE_CONTROL, LOCATION}, Url};
+use wiremock::{matchers::{method, path}, Mock, ResponseTemplate};
+
+use crate::utils::{get_random_email, TestApp};
+
+pub(crate) const REDIRECT_URI: &str = "https://app.example.com/callback";
+
+// The example from RFC 7636 appendix B
+const CODE_VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+const CODE_CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+
+pub(crate) async fn signup(app: &TestApp, email: &str, requires_2fa: bool) {
+    let response = app.post_signup(&serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": requires_2fa
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+pub(crate) async fn login(app: &TestApp, email: &str) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "email": email,
+        "password": "password123"
+    }))
+    .await
+}
+
+// Signs up a user who doesn't need a second factor and logs the test client in as them
+pub(crate) async fn login_user(app: &TestApp) -> String {
+    let email = get_random_email();
+    signup(app, &email, false).await;
+
+    assert_eq!(login(app, &email).await.status().as_u16(), 200);
+    email
+}
+
+// Registers a client as a freshly signed up admin, who is logged out again afterwards
+pub(crate) async fn register_client(app: &TestApp, confidential: bool) -> OAuthClientResponse {
+    let email = get_random_email();
+    signup(app, &email, false).await;
+
+    app.user_store
+        .write()
+        .await
+        .assign_role(&Email::parse(email.clone()).unwrap(), ADMIN_ROLE)
+        .await
+        .unwrap();
+
+    assert_eq!(login(app, &email).await.status().as_u16(), 200);
+
+    let response = app.post_admin_oauth_clients(&serde_json::json!({
+        "name": "Example app",
+        "redirectUris": [REDIRECT_URI, "http://127.0.0.1:8123/callback"],
+        "confidential": confidential
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let client = response
+        .json::<OAuthClientResponse>()
+        .await
+        .expect("Could not deserialize response body to OAuthClientResponse");
+
+    assert_eq!(app.post_logout().await.status().as_u16(), 200);
+    client
+}
+
+pub(crate) fn authorize_query(client_id: &str) -> Vec<(&str, &str)> {
+    vec![
+        ("response_type", "code"),
+        ("client_id", client_id),
+        ("redirect_uri", REDIRECT_URI),
+        ("state", "af0ifjsldkj"),
+        ("code_challenge", CODE_CHALLENGE),
+        ("code_challenge_method", "S256"),
+    ]
+}
+
+pub(crate) fn location(app: &TestApp, response: &reqwest::Response) -> Url {
+    assert_eq!(response.status().as_u16(), 303);
+
+    let location = response.headers()[LOCATION].to_str().unwrap();
+    Url::parse(&app.addr).unwrap().join(location).unwrap()
+}
+
+pub(crate) fn query_param(url: &Url, name: &str) -> Option<String> {
+    url.query_pairs().find(|(key, _)| key == name).map(|(_, value)| value.into_owned())
+}
+
+// Runs /authorize for the logged-in user and returns the code it redirected back with
+async fn authorize(app: &TestApp, client_id: &str) -> String {
+    let response = app.get_authorize(&authorize_query(client_id)).await;
+    let redirect = location(app, &response);
+
+    assert!(redirect.as_str().starts_with(REDIRECT_URI), "Redirected to {}", redirect);
+    assert_eq!(query_param(&redirect, "state").as_deref(), Some("af0ifjsldkj"));
+
+    query_param(&redirect, "code").expect("Redirect has no code")
+}
+
+pub(crate) async fn exchange_code(app: &TestApp, client_id: &str, code: &str) -> reqwest::Response {
+    app.post_token(&[
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", REDIRECT_URI),
+        ("code_verifier", CODE_VERIFIER),
+        ("client_id", client_id),
+    ])
+    .await
+}
+
+pub(crate) async fn oauth_error(response: reqwest::Response) -> String {
+    response
+        .json::<OAuthErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to OAuthErrorResponse")
+        .error
+}
+
+pub(crate) async fn tokens(response: reqwest::Response) -> OAuthTokenResponse {
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()[CACHE_CONTROL], "no-store");
+
+    response
+        .json::<OAuthTokenResponse>()
+        .await
+        .expect("Could not deserialize response body to OAuthTokenResponse")
+}
+
+#[tokio::test]
+async fn should_let_admins_register_clients() {
+    let mut app = TestApp::new().await;
+
+    let confidential = register_client(&app, true).await;
+    let public = register_client(&app, false).await;
+
+    assert!(confidential.confidential);
+    assert_eq!(confidential.client_secret.as_ref().map(String::len), Some(48));
+    assert!(!public.confidential);
+    assert_eq!(public.client_secret, None);
+
+    // Not an admin
+    login_user(&app).await;
+    let response = app.post_admin_oauth_clients(&serde_json::json!({
+        "name": "Sneaky app",
+        "redirectUris": [REDIRECT_URI]
+    }))
+    .await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    let admin = get_random_email();
+    signup(&app, &admin, false).await;
+    app.user_store.write().await.assign_role(&Email::parse(admin.clone()).unwrap(), ADMIN_ROLE).await.unwrap();
+    login(&app, &admin).await;
+
+    let invalid_redirect_uris = [
+        serde_json::json!([]),
+        serde_json::json!(["http://app.example.com/callback"]),
+        serde_json::json!(["https://app.example.com/callback#fragment"]),
+        serde_json::json!(["/callback"]),
+    ];
+
+    for redirect_uris in invalid_redirect_uris {
+        let response = app.post_admin_oauth_clients(&serde_json::json!({
+            "name": "Example app",
+            "redirectUris": redirect_uris
+        }))
+        .await;
+
+        assert_eq!(response.status().as_u16(), 400, "Registered with {}", redirect_uris);
+    }
+
+    // Secrets are only shown when the client is registered
+    let clients = app.get_admin_oauth_clients().await.json::<OAuthClientsResponse>().await.unwrap().clients;
+    assert_eq!(clients.len(), 2);
+    assert!(clients.iter().all(|client| client.client_secret.is_none()));
+    assert_eq!(clients[0].redirect_uris, vec![REDIRECT_URI, "http://127.0.0.1:8123/callback"]);
+
+    let response = app.delete_admin_oauth_clients(&serde_json::json!({ "clientId": public.client_id })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.delete_admin_oauth_clients(&serde_json::json!({ "clientId": public.client_id })).await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_send_users_through_login_and_2fa_before_authorizing() {
+    let mut app = TestApp::new().await;
+    let client = register_client(&app, false).await;
+
+    let email = get_random_email();
+    signup(&app, &email, true).await;
+
+    let response = app.get_authorize(&authorize_query(&client.client_id)).await;
+    let login_page = location(&app, &response);
+
+    assert_eq!(login_page.path(), "/");
+    let return_to = query_param(&login_page, "returnTo").expect("Login page has nowhere to return to");
+    assert!(return_to.starts_with("/authorize?"));
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // The login page goes through the usual flow, then back to where /authorize sent it from
+    let response = login(&app, &email).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let login_id = response.json::<TwoFactorAuthResponse>().await.unwrap().login_id;
+    let challenge = app.two_fa_code.read().await.get_challenge(&LoginId::parse(login_id.clone()).unwrap()).await.unwrap();
+
+    let response = app.post_verify_2fa(&serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_id,
+        "2FACode": challenge.code.as_ref()
+    }))
+    .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let return_to = Url::parse(&app.addr).unwrap().join(&return_to).unwrap();
+    let query: Vec<(String, String)> = return_to.query_pairs().into_owned().collect();
+    let query: Vec<(&str, &str)> = query.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect();
+    assert_eq!(query, authorize_query(&client.client_id));
+
+    let response = app.get_authorize(&query).await;
+    let redirect = location(&app, &response);
+    assert!(redirect.as_str().starts_with(REDIRECT_URI), "Redirected to {}", redirect);
+
+    let code = query_param(&redirect, "code").expect("Redirect has no code");
+    let tokens = tokens(exchange_code(&app, &client.client_id, &code).await).await;
+
+    assert_eq!(tokens.token_type, "Bearer");
+    assert_eq!(tokens.expires_in, TOKEN_TTL_SECONDS);
+
+    // Valid, but the client wasn't granted the `openid` scope /userinfo asks for
+    let response = app.get_userinfo(&tokens.access_token).await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    // The client's session is one more of the user's devices
+    let sessions = app.get_sessions().await.json::<SessionsResponse>().await.unwrap().sessions;
+    assert_eq!(sessions.len(), 2);
+    assert!(sessions.iter().any(|session| session.oauth_client_id.as_ref() == Some(&client.client_id)));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_keep_client_access_tokens_off_first_party_routes() {
+    let mut app = TestApp::new().await;
+    let client = register_client(&app, false).await;
+    let email = login_user(&app).await;
+
+    // An admin, whose own auth tokens would get through every route below
+    app.user_store.write().await.assign_role(&Email::parse(email).unwrap(), ADMIN_ROLE).await.unwrap();
+
+    let code = authorize(&app, &client.client_id).await;
+    let issued = tokens(exchange_code(&app, &client.client_id, &code).await).await;
+
+    let response = app.post_verify_token(&serde_json::json!({ "token": issued.access_token })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Sent the way the client would, without the browser's cookies
+    let client = reqwest::Client::new();
+    let get = |route: &str| client.get(format!("{}{}", &app.addr, route)).bearer_auth(&issued.access_token).send();
+    let post = |route: &str| {
+        client
+            .post(format!("{}{}", &app.addr, route))
+            .bearer_auth(&issued.access_token)
+            .json(&serde_json::json!({ "password": "password123" }))
+            .send()
+    };
+
+    for response in [
+        get("/sessions").await.unwrap(),
+        get("/account/export").await.unwrap(),
+        get("/admin/users").await.unwrap(),
+        post("/2fa/totp/enroll").await.unwrap(),
+        post("/webauthn/register/start").await.unwrap(),
+    ] {
+        assert_eq!(response.status().as_u16(), 401, "{} let the client in", response.url());
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_redirect_to_unregistered_uris() {
+    let mut app = TestApp::new().await;
+    let client = register_client(&app, false).await;
+    login_user(&app).await;
+
+    let unknown_client = uuid::Uuid::new_v4().to_string();
+    let redirect_uris = [
+        "https://app.example.com/callback/",
+        "https://app.example.com/callback?next=/",
+        "https://evil.example.com/callback",
+        "http://127.0.0.1:9999/callback",
+    ];
+
+    let mut queries: Vec<Vec<(&str, &str)>> = redirect_uris
+        .iter()
+        .map(|redirect_uri| {
+            authorize_query(&client.client_id)
+                .into_iter()
+                .map(|(key, value)| if key == "redirect_uri" { (key, *redirect_uri) } else { (key, value) })
+                .collect()
+        })
+        .collect();
+    queries.push(authorize_query(&client.client_id).into_iter().filter(|(key, _)| *key != "redirect_uri").collect());
+    queries.push(authorize_query(&unknown_client));
+    queries.push(authorize_query("not-a-client"));
+
+    for query in queries {
+        let response = app.get_authorize(&query).await;
+
+        assert_eq!(response.status().as_u16(), 400, "Failed for query: {:?}", query);
+        assert!(response.headers().get(LOCATION).is_none());
+        assert_eq!(oauth_error(response).await, "invalid_request");
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_redirect_request_errors_back_to_client() {
+    let mut app = TestApp::new().await;
+    let client = register_client(&app, false).await;
+    login_user(&app).await;
+
+    let without = |name: &str| -> Vec<(&str, &str)> {
+        authorize_query(&client.client_id).into_iter().filter(|(key, _)| *key != name).collect()
+    };
+    let with = |name: &'static str, value: &'static str| -> Vec<(&str, &str)> {
+        authorize_query(&client.client_id)
+            .into_iter()
+            .map(|(key, old)| if key == name { (key, value) } else { (key, old) })
+            .collect()
+    };
+
+    let test_cases = [
+        (with("response_type", "token"), "unsupported_response_type"),
+        (without("response_type"), "unsupported_response_type"),
+        (without("code_challenge"), "invalid_request"),
+        (with("code_challenge", "not+base64url"), "invalid_request"),
+        (without("code_challenge_method"), "invalid_request"),
+        (with("code_challenge_method", "plain"), "invalid_request"),
+    ];
+
+    for (query, error) in test_cases {
+        let response = app.get_authorize(&query).await;
+        let redirect = location(&app, &response);
+
+        assert!(redirect.as_str().starts_with(REDIRECT_URI), "Redirected to {}", redirect);
+        assert_eq!(query_param(&redirect, "error").as_deref(), Some(error), "Failed for query: {:?}", query);
+        assert_eq!(query_param(&redirect, "state").as_deref(), Some("af0ifjsldkj"));
+        assert_eq!(query_param(&redirect, "code"), None);
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_exchange_codes_only_once() {
+    let mut app = TestApp::new().await;
+    let client = register_client(&app, false).await;
+    login_user(&app).await;
+
+    let code = authorize(&app, &client.client_id).await;
+
+    tokens(exchange_code(&app, &client.client_id, &code).await).await;
+
+    let response = exchange_code(&app, &client.client_id, &code).await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(oauth_error(response).await, "invalid_grant");
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_check_pkce_verifier_redirect_uri_and_client() {
+    let mut app = TestApp::new().await;
+    let client = register_client(&app, false).await;
+    let other_client = register_client(&app, false).await;
+    login_user(&app).await;
+
+    let wrong_verifier = CODE_VERIFIER.replace('d', "e");
+    let test_cases = [
+        ("code_verifier", wrong_verifier.as_str()),
+        ("redirect_uri", "http://127.0.0.1:8123/callback"),
+        ("client_id", other_client.client_id.as_str()),
+    ];
+
+    for (name, value) in test_cases {
+        let code = authorize(&app, &client.client_id).await;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code.as_str()),
+            ("redirect_uri", REDIRECT_URI),
+            ("code_verifier", CODE_VERIFIER),
+            ("client_id", client.client_id.as_str()),
+        ];
+        form.iter_mut().filter(|(key, _)| *key == name).for_each(|param| param.1 = value);
+
+        let response = app.post_token(&form).await;
+        assert_eq!(response.status().as_u16(), 400, "Failed for wrong {}", name);
+        assert_eq!(oauth_error(response).await, "invalid_grant");
+
+        // A failed exchange uses the code up too
+        let response = exchange_code(&app, &client.client_id, &code).await;
+        assert_eq!(response.status().as_u16(), 400, "Code still usable after wrong {}", name);
+    }
+
+    let code = authorize(&app, &client.client_id).await;
+    let response = app.post_token(&[
+        ("grant_type", "authorization_code"),
+        ("code", code.as_str()),
+        ("redirect_uri", REDIRECT_URI),
+        ("client_id", client.client_id.as_str()),
+    ])
+    .await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(oauth_error(response).await, "invalid_request");
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_authenticate_confidential_clients() {
+    let mut app = TestApp::new().await;
+    let client = register_client(&app, true).await;
+    let secret = client.client_secret.clone().unwrap();
+    login_user(&app).await;
+
+    let code = authorize(&app, &client.client_id).await;
+    let form = [
+        ("grant_type", "authorization_code"),
+        ("code", code.as_str()),
+        ("redirect_uri", REDIRECT_URI),
+        ("code_verifier", CODE_VERIFIER),
+    ];
+
+    let wrong_secret = "x".repeat(48);
+    let attempts = [
+        (client.client_id.as_str(), None),
+        (client.client_id.as_str(), Some(wrong_secret.as_str())),
+        ("not-a-client", Some(secret.as_str())),
+    ];
+
+    for (client_id, client_secret) in attempts {
+        let response = app.client
+            .post(format!("{}/token", &app.addr))
+            .basic_auth(client_id, client_secret)
+            .form(&form)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status().as_u16(), 401, "Failed for secret {:?}", client_secret);
+        assert_eq!(oauth_error(response).await, "invalid_client");
+    }
+
+    // Refused clients don't use up the code
+    let response = app.client
+        .post(format!("{}/token", &app.addr))
+        .basic_auth(&client.client_id, Some(&secret))
+        .form(&form)
+        .send()
+        .await
+        .unwrap();
+    tokens(response).await;
+
+    // The secret can also be sent in the form
+    let code = authorize(&app, &client.client_id).await;
+    let response = app.post_token(&[
+        ("grant_type", "authorization_code"),
+        ("code", code.as_str()),
+        ("redirect_uri", REDIRECT_URI),
+        ("code_verifier", CODE_VERIFIER),
+        ("client_id", client.client_id.as_str()),
+        ("client_secret", secret.as_str()),
+    ])
+    .await;
+    tokens(response).await;
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_refresh_tokens_only_for_the_client_they_were_issued_to() {
+    let mut app = TestApp::new().await;
+    let client = register_client(&app, false).await;
+    let other_client = register_client(&app, false).await;
+    login_user(&app).await;
+
+    let code = authorize(&app, &client.client_id).await;
+    let issued = tokens(exchange_code(&app, &client.client_id, &code).await).await;
+
+    let refresh = |client_id: &str, refresh_token: &str| {
+        let form = [
+            ("grant_type", "refresh_token".to_owned()),
+            ("refresh_token", refresh_token.to_owned()),
+            ("client_id", client_id.to_owned()),
+        ];
+        app.client.post(format!("{}/token", &app.addr)).form(&form).send()
+    };
+
+    // Neither another client nor /refresh, where no client authenticates, can use the token
+    let response = refresh(&other_client.client_id, &issued.refresh_token).await.unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(oauth_error(response).await, "invalid_grant");
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/refresh", &app.addr))
+        .json(&serde_json::json!({ "refreshToken": issued.refresh_token }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    let refreshed = tokens(refresh(&client.client_id, &issued.refresh_token).await.unwrap()).await;
+    assert_ne!(refreshed.refresh_token, issued.refresh_token);
+
+    let response = app.get_userinfo(&refreshed.access_token).await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    // Presenting the rotated token again ends the session
+    let response = refresh(&client.client_id, &issued.refresh_token).await.unwrap();
+    assert_eq!(oauth_error(response).await, "invalid_grant");
+
+    let response = refresh(&client.client_id, &refreshed.refresh_token).await.unwrap();
+    assert_eq!(oauth_error(response).await, "invalid_grant");
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_end_client_sessions_when_client_is_deleted() {
+    let mut app = TestApp::new().await;
+    let client = register_client(&app, false).await;
+    let email = login_user(&app).await;
+
+    let code = authorize(&app, &client.client_id).await;
+    let issued = tokens(exchange_code(&app, &client.client_id, &code).await).await;
+
+    let admin = get_random_email();
+    signup(&app, &admin, false).await;
+    app.user_store.write().await.assign_role(&Email::parse(admin.clone()).unwrap(), ADMIN_ROLE).await.unwrap();
+    login(&app, &admin).await;
+
+    let response = app.delete_admin_oauth_clients(&serde_json::json!({ "clientId": client.client_id })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.get_userinfo(&issued.access_token).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The user's own login is unaffected
+    assert_eq!(login(&app, &email).await.status().as_u16(), 200);
+
+    let response = app.post_token(&[
+        ("grant_type", "refresh_token"),
+        ("refresh_token", issued.refresh_token.as_str()),
+        ("client_id", client.client_id.as_str()),
+    ])
+    .await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(oauth_error(response).await, "invalid_client");
+
+    app.clean_up().await;
+}
@@ -0,0 +1,280 @@
+use auth_service::{
+    route_handlers::{OpenIdConfiguration, OAuthTokenResponse, TwoFactorAuthResponse},
+    services::{
+        auth::{decode_id_token_hint, IdTokenClaims},
+        constants::{JWT_COOKIE_NAME, OIDC_ISSUER},
+        oidc::UserInfo,
+        two_fa::{LoginId, TwoFaCodes}
+    }
+};
+use reqwest::header::{CACHE_CONTROL, LOCATION, WWW_AUTHENTICATE};
+use wiremock::{matchers::{method, path}, Mock, ResponseTemplate};
+
+use crate::{
+    oauth::{
+        authorize_query, exchange_code, location, login, login_user, oauth_error, query_param, register_client,
+        signup, tokens, REDIRECT_URI
+    },
+    utils::{get_random_email, TestApp}
+};
+
+const NONCE: &str = "n-0S6_WzA2Mj";
+
+// Runs /authorize for the logged-in user with the given scopes and exchanges the code for tokens
+async fn authorize_with_scope(app: &TestApp, client_id: &str, scope: &str) -> OAuthTokenResponse {
+    let mut query = authorize_query(client_id);
+    query.extend([("scope", scope), ("nonce", NONCE)]);
+
+    let redirect = location(app, &app.get_authorize(&query).await);
+    assert!(redirect.as_str().starts_with(REDIRECT_URI), "Redirected to {}", redirect);
+
+    let code = query_param(&redirect, "code").expect("Redirect has no code");
+    tokens(exchange_code(app, client_id, &code).await).await
+}
+
+async fn id_token_claims(app: &TestApp, tokens: &OAuthTokenResponse) -> IdTokenClaims {
+    let id_token = tokens.id_token.as_ref().expect("No ID token was issued");
+
+    decode_id_token_hint(id_token, app.keyring.clone())
+        .await
+        .expect("Could not decode the ID token")
+}
+
+#[tokio::test]
+async fn should_describe_the_provider_at_discovery_endpoint() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_openid_configuration().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let configuration = response
+        .json::<OpenIdConfiguration>()
+        .await
+        .expect("Could not deserialize response body to OpenIdConfiguration");
+
+    assert_eq!(configuration.issuer, *OIDC_ISSUER);
+    assert_eq!(configuration.authorization_endpoint, format!("{}/authorize", *OIDC_ISSUER));
+    assert_eq!(configuration.token_endpoint, format!("{}/token", *OIDC_ISSUER));
+    assert_eq!(configuration.userinfo_endpoint, format!("{}/userinfo", *OIDC_ISSUER));
+    assert_eq!(configuration.jwks_uri, format!("{}/.well-known/jwks.json", *OIDC_ISSUER));
+    assert_eq!(configuration.end_session_endpoint, format!("{}/end-session", *OIDC_ISSUER));
+    assert_eq!(configuration.scopes_supported, vec!["openid", "email", "profile"]);
+    assert_eq!(configuration.response_types_supported, vec!["code"]);
+    assert_eq!(configuration.code_challenge_methods_supported, vec!["S256"]);
+
+    // ID tokens are signed with the key the JWKS publishes
+    let algorithm = app.keyring.read().await.signing_key().algorithm();
+    assert_eq!(configuration.id_token_signing_alg_values_supported, vec![algorithm]);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_issue_id_tokens_for_openid_scope() {
+    let mut app = TestApp::new().await;
+    let client = register_client(&app, false).await;
+    let email = login_user(&app).await;
+
+    let issued = authorize_with_scope(&app, &client.client_id, "openid email offline_access").await;
+    assert_eq!(issued.scope.as_deref(), Some("openid email"));
+
+    let claims = id_token_claims(&app, &issued).await;
+
+    assert_eq!(claims.iss, *OIDC_ISSUER);
+    assert_eq!(claims.aud, client.client_id);
+    assert_eq!(claims.nonce.as_deref(), Some(NONCE));
+    assert_eq!(claims.amr, vec!["pwd"]);
+    assert!(claims.auth_time <= claims.iat);
+    assert_eq!(claims.user_info.email.as_deref(), Some(email.as_str()));
+    assert_eq!(claims.user_info.email_verified, Some(false));
+
+    // The email claims need the email scope
+    let issued = authorize_with_scope(&app, &client.client_id, "openid").await;
+    let claims = id_token_claims(&app, &issued).await;
+
+    assert_eq!(claims.user_info.email, None);
+    assert_eq!(claims.user_info.email_verified, None);
+
+    // Plain OAuth clients don't get an ID token
+    let redirect = location(&app, &app.get_authorize(&authorize_query(&client.client_id)).await);
+    let code = query_param(&redirect, "code").unwrap();
+    let issued = tokens(exchange_code(&app, &client.client_id, &code).await).await;
+
+    assert_eq!(issued.id_token, None);
+    assert_eq!(issued.scope, None);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_report_second_factor_in_amr() {
+    let mut app = TestApp::new().await;
+    let client = register_client(&app, false).await;
+
+    let email = get_random_email();
+    signup(&app, &email, true).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = login(&app, &email).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let login_id = response.json::<TwoFactorAuthResponse>().await.unwrap().login_id;
+    let challenge = app.two_fa_code.read().await.get_challenge(&LoginId::parse(login_id.clone()).unwrap()).await.unwrap();
+
+    let response = app.post_verify_2fa(&serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_id,
+        "2FACode": challenge.code.as_ref()
+    }))
+    .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let issued = authorize_with_scope(&app, &client.client_id, "openid").await;
+    let claims = id_token_claims(&app, &issued).await;
+
+    assert_eq!(claims.amr, vec!["pwd", "otp", "mfa"]);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_userinfo_for_openid_access_tokens() {
+    let mut app = TestApp::new().await;
+    let client = register_client(&app, false).await;
+    let email = login_user(&app).await;
+
+    let issued = authorize_with_scope(&app, &client.client_id, "openid email").await;
+
+    let response = app.get_userinfo(&issued.access_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()[CACHE_CONTROL], "no-store");
+
+    let user_info = response.json::<UserInfo>().await.expect("Could not deserialize response body to UserInfo");
+    let claims = id_token_claims(&app, &issued).await;
+
+    assert_eq!(user_info.sub, claims.user_info.sub);
+    assert_eq!(user_info.email.as_deref(), Some(email.as_str()));
+
+    // Access tokens issued without the openid scope can't read it
+    let redirect = location(&app, &app.get_authorize(&authorize_query(&client.client_id)).await);
+    let code = query_param(&redirect, "code").unwrap();
+    let issued = tokens(exchange_code(&app, &client.client_id, &code).await).await;
+
+    let response = app.get_userinfo(&issued.access_token).await;
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(response.headers()[WWW_AUTHENTICATE], "Bearer error=\"insufficient_scope\"");
+
+    let response = app.get_userinfo("not-a-token").await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(oauth_error(response).await, "invalid_token");
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_issue_new_id_token_on_refresh() {
+    let mut app = TestApp::new().await;
+    let client = register_client(&app, false).await;
+    login_user(&app).await;
+
+    let issued = authorize_with_scope(&app, &client.client_id, "openid").await;
+    let first = id_token_claims(&app, &issued).await;
+
+    let refreshed = tokens(
+        app.post_token(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", issued.refresh_token.as_str()),
+            ("client_id", client.client_id.as_str()),
+        ])
+        .await
+    )
+    .await;
+
+    let claims = id_token_claims(&app, &refreshed).await;
+
+    // The nonce only belongs in the ID token from the authorization
+    assert_eq!(claims.nonce, None);
+    assert_eq!(claims.sid, first.sid);
+    assert_eq!(claims.auth_time, first.auth_time);
+    assert_eq!(refreshed.scope.as_deref(), Some("openid"));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_report_login_required_for_prompt_none() {
+    let mut app = TestApp::new().await;
+    let client = register_client(&app, false).await;
+
+    let mut query = authorize_query(&client.client_id);
+    query.extend([("scope", "openid"), ("prompt", "none")]);
+
+    let redirect = location(&app, &app.get_authorize(&query).await);
+
+    assert!(redirect.as_str().starts_with(REDIRECT_URI), "Redirected to {}", redirect);
+    assert_eq!(query_param(&redirect, "error").as_deref(), Some("login_required"));
+    assert_eq!(query_param(&redirect, "state").as_deref(), Some("af0ifjsldkj"));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_log_out_from_client_initiated_logout() {
+    let mut app = TestApp::new().await;
+    let client = register_client(&app, false).await;
+
+    let email = get_random_email();
+    signup(&app, &email, false).await;
+
+    let response = login(&app, &email).await;
+    let browser_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    let issued = authorize_with_scope(&app, &client.client_id, "openid").await;
+    let id_token = issued.id_token.clone().unwrap();
+
+    let response = app.get_end_session(&[("post_logout_redirect_uri", REDIRECT_URI)]).await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(oauth_error(response).await, "invalid_request");
+
+    let response = app.get_end_session(&[
+        ("id_token_hint", id_token.as_str()),
+        ("post_logout_redirect_uri", "https://evil.example.com/callback"),
+    ])
+    .await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response.headers().get(LOCATION).is_none());
+
+    let response = app.get_end_session(&[
+        ("id_token_hint", id_token.as_str()),
+        ("client_id", client.client_id.as_str()),
+        ("post_logout_redirect_uri", REDIRECT_URI),
+        ("state", "logged-out"),
+    ])
+    .await;
+
+    let redirect = location(&app, &response);
+    assert!(redirect.as_str().starts_with(REDIRECT_URI), "Redirected to {}", redirect);
+    assert_eq!(query_param(&redirect, "state").as_deref(), Some("logged-out"));
+
+    // Both the client's session and the browser's login are over
+    let response = app.get_userinfo(&issued.access_token).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_verify_token(&serde_json::json!({ "token": browser_token })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    assert!(app.banned_tokens.read().await.contains_token(&browser_token).await.unwrap());
+
+    app.clean_up().await;
+}
@@ -0,0 +1,278 @@
+use auth_service::{
+    route_handlers::{InvitationResponse, OrganizationResponse, OrganizationsResponse},
+    services::constants::JWT_COOKIE_NAME
+};
+use jsonwebtoken::{decode, DecodingKey, Validation};
+use serde::Deserialize;
+use wiremock::{matchers::{body_partial_json, method, path}, Mock, ResponseTemplate};
+
+use crate::utils::{get_random_email, TestApp};
+
+#[derive(Deserialize)]
+struct OrgClaims {
+    org_id: Option<String>,
+    org_role: Option<String>,
+}
+
+fn org_claims(response: &reqwest::Response) -> OrgClaims {
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+
+    // Only the claims matter here, signatures are covered by the jwks tests
+    let mut validation = Validation::default();
+    validation.insecure_disable_signature_validation();
+
+    decode::<OrgClaims>(auth_cookie.value(), &DecodingKey::from_secret(&[]), &validation)
+        .expect("Failed to decode auth token")
+        .claims
+}
+
+async fn signup_and_login(app: &TestApp, email: &str) -> reqwest::Response {
+    let response = app.post_signup(&serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_login(&serde_json::json!({
+        "email": email,
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    response
+}
+
+async fn create_organization(app: &TestApp, name: &str) -> OrganizationResponse {
+    let response = app.post_organizations(&serde_json::json!({ "name": name })).await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    response
+        .json::<OrganizationResponse>()
+        .await
+        .expect("Could not deserialize response body to OrganizationResponse")
+}
+
+async fn invite(app: &TestApp, org: &OrganizationResponse, email: &str, role: &str) -> String {
+    let subject = format!("You've been invited to join {}", org.name);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_partial_json(serde_json::json!({ "Subject": subject })))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_organization_invitations(&serde_json::json!({
+        "orgId": org.id,
+        "email": email,
+        "role": role
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 202);
+
+    app.token_from_email(&subject).await
+}
+
+#[tokio::test]
+async fn should_create_organizations_and_switch_between_them() {
+    let mut app = TestApp::new().await;
+
+    let response = signup_and_login(&app, &get_random_email()).await;
+
+    assert!(org_claims(&response).org_id.is_none());
+
+    let response = app.post_organizations(&serde_json::json!({ "name": "Acme" })).await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let claims = org_claims(&response);
+    let acme = response
+        .json::<OrganizationResponse>()
+        .await
+        .expect("Could not deserialize response body to OrganizationResponse");
+
+    assert_eq!(claims.org_id.as_deref(), Some(acme.id.as_str()));
+    assert_eq!(claims.org_role.as_deref(), Some("owner"));
+    assert!(acme.active);
+
+    // The newest organization becomes the active one
+    let globex = create_organization(&app, "Globex").await;
+
+    let organizations = app
+        .get_organizations()
+        .await
+        .json::<OrganizationsResponse>()
+        .await
+        .expect("Could not deserialize response body to OrganizationsResponse")
+        .organizations;
+
+    let summary: Vec<_> = organizations.iter().map(|org| (org.name.as_str(), org.active)).collect();
+    assert_eq!(summary, vec![("Acme", false), ("Globex", true)]);
+
+    let response = app.post_organization_switch(&serde_json::json!({ "orgId": acme.id })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(org_claims(&response).org_id.as_deref(), Some(acme.id.as_str()));
+
+    // The active organization sticks to the account across logins
+    let response = signup_and_login(&app, &get_random_email()).await;
+
+    assert!(org_claims(&response).org_id.is_none());
+
+    let response = app.post_organization_switch(&serde_json::json!({ "orgId": globex.id })).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_sign_up_invitee_into_organization() {
+    let mut app = TestApp::new().await;
+
+    signup_and_login(&app, &get_random_email()).await;
+    let org = create_organization(&app, "Acme").await;
+
+    let invitee = get_random_email();
+    let token = invite(&app, &org, &invitee, "member").await;
+
+    // Without an account a password is needed, and the link stays usable until one is given
+    let response = app.post_organization_invitations_accept(&serde_json::json!({ "token": token })).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app.post_organization_invitations_accept(&serde_json::json!({
+        "token": token,
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let accepted = response
+        .json::<InvitationResponse>()
+        .await
+        .expect("Could not deserialize response body to InvitationResponse");
+
+    assert_eq!(accepted.org_id.as_deref(), Some(org.id.as_str()));
+
+    let response = app.post_organization_invitations_accept(&serde_json::json!({
+        "token": token,
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The invitation proved the address, so the new account can log in straight into the organization
+    let response = app.post_login(&serde_json::json!({
+        "email": invitee,
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let claims = org_claims(&response);
+    assert_eq!(claims.org_id.as_deref(), Some(org.id.as_str()));
+    assert_eq!(claims.org_role.as_deref(), Some("member"));
+
+    // Members can't invite anyone
+    let response = app.post_organization_invitations(&serde_json::json!({
+        "orgId": org.id,
+        "email": get_random_email(),
+        "role": "member"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 403);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_add_existing_user_to_organization() {
+    let mut app = TestApp::new().await;
+
+    let invitee = get_random_email();
+    signup_and_login(&app, &invitee).await;
+
+    let owner = get_random_email();
+    signup_and_login(&app, &owner).await;
+    let org = create_organization(&app, "Acme").await;
+
+    let token = invite(&app, &org, &invitee, "admin").await;
+
+    let response = app.post_organization_invitations_accept(&serde_json::json!({ "token": token })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Already a member
+    let response = app.post_organization_invitations(&serde_json::json!({
+        "orgId": org.id,
+        "email": invitee,
+        "role": "member"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 409);
+
+    // Admins can invite, but not hand out ownership
+    let response = app.post_login(&serde_json::json!({
+        "email": invitee,
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(org_claims(&response).org_role.as_deref(), Some("admin"));
+
+    let response = app.post_organization_invitations(&serde_json::json!({
+        "orgId": org.id,
+        "email": get_random_email(),
+        "role": "owner"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 403);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reject_invalid_organization_requests() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_organizations(&serde_json::json!({ "name": "Acme" })).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    signup_and_login(&app, &get_random_email()).await;
+
+    let response = app.post_organizations(&serde_json::json!({ "name": "   " })).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app.post_organization_switch(&serde_json::json!({ "orgId": "not-a-uuid" })).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app.post_organization_invitations_accept(&serde_json::json!({
+        "token": "invalid",
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
@@ -0,0 +1,172 @@
+use std::time::Duration;
+
+use auth_service::{
+    route_handlers::PasswordResetResponse,
+    services::{api::ErrorResponse, constants::JWT_COOKIE_NAME}
+};
+use wiremock::{matchers::{method, path}, Mock, ResponseTemplate};
+
+use crate::utils::{get_random_email, TestApp};
+
+async fn signup_and_login(app: &TestApp, email: &str) -> String {
+    let response = app.post_signup(&serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_login(&serde_json::json!({
+        "email": email,
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+
+    auth_cookie.value().to_owned()
+}
+
+async fn request_reset(app: &TestApp, email: &str) -> String {
+    let response = app.post_password_reset_request(&serde_json::json!({ "email": email })).await;
+
+    assert_eq!(response.status().as_u16(), 202);
+
+    response
+        .json::<PasswordResetResponse>()
+        .await
+        .expect("Could not deserialize response body to PasswordResetResponse")
+        .message
+}
+
+#[tokio::test]
+async fn should_reset_password_and_end_existing_sessions() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    let old_token = signup_and_login(&app, &random_email).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    request_reset(&app, &random_email).await;
+    let reset_token = app.token_from_email("Reset your password").await;
+
+    let response = app.post_password_reset_confirm(&serde_json::json!({
+        "token": reset_token,
+        "newPassword": "newpassword123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The session started with the old password is over
+    let response = app.post_verify_token(&serde_json::json!({ "token": old_token })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_refresh().await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_login(&serde_json::json!({
+        "email": random_email,
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_login(&serde_json::json!({
+        "email": random_email,
+        "password": "newpassword123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The link only works once
+    let response = app.post_password_reset_confirm(&serde_json::json!({
+        "token": reset_token,
+        "newPassword": "anotherpassword123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_respond_the_same_for_unknown_accounts() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    signup_and_login(&app, &random_email).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let known = request_reset(&app, &random_email).await;
+    app.token_from_email("Reset your password").await;
+
+    let unknown = request_reset(&app, &get_random_email()).await;
+
+    assert_eq!(known, unknown);
+
+    // Give a stray email for the unknown account time to show up before the mock checks its count
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reject_invalid_reset_requests() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_password_reset_request(&serde_json::json!({ "email": "not-an-email" })).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app.post_password_reset_confirm(&serde_json::json!({
+        "token": "invalid",
+        "newPassword": "newpassword123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Invalid auth token".to_owned()
+    );
+
+    let response = app.post_password_reset_confirm(&serde_json::json!({
+        "token": "invalid",
+        "newPassword": "short"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
@@ -0,0 +1,110 @@
+use auth_service::services::{
+    api::ErrorResponse,
+    constants::RATE_LIMITS,
+    rate_limits::{RateLimit, RateLimitStore},
+    redis_rate_limit_store::RedisRateLimitStore
+};
+use uuid::Uuid;
+
+use crate::utils::{configure_async_redis, TestApp};
+
+// Rejected before the password is hashed, so it's cheap to send many
+fn invalid_signup() -> serde_json::Value {
+    serde_json::json!({
+        "email": "invalidemail",
+        "password": "password123",
+        "requires2FA": false
+    })
+}
+
+#[tokio::test]
+async fn should_return_429_once_over_the_route_limit() {
+    let mut app = TestApp::new().await;
+
+    let limit = RATE_LIMITS.for_route("/signup").expect("No rate limit for /signup");
+
+    for _ in 0..limit.requests {
+        let response = app.post_signup(&invalid_signup()).await;
+
+        assert_eq!(response.status().as_u16(), 400);
+    }
+
+    let response = app.post_signup(&invalid_signup()).await;
+
+    assert_eq!(response.status().as_u16(), 429);
+
+    let retry_after = response
+        .headers()
+        .get("retry-after")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<i64>().ok())
+        .expect("No Retry-After header");
+
+    assert!(retry_after > 0 && retry_after <= 2 * limit.window_seconds);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Too many requests"
+    );
+
+    // Other routes have limits of their own
+    let response = app.post_login(&serde_json::json!({
+        "email": "invalidemail",
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_ignore_forwarded_for_without_trusted_proxies() {
+    let mut app = TestApp::new().await;
+
+    let limit = RATE_LIMITS.for_route("/signup").expect("No rate limit for /signup");
+
+    // Otherwise a client could pick a new address for every request
+    for i in 0..=limit.requests {
+        let response = app.client
+            .post(format!("{}/signup", &app.addr))
+            .header("X-Forwarded-For", format!("10.0.0.{}", i))
+            .json(&invalid_signup())
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        let expected = if i < limit.requests { 400 } else { 429 };
+        assert_eq!(response.status().as_u16(), expected);
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_share_counters_through_redis() {
+    // Two instances of the service pointed at the same Redis
+    let first = RedisRateLimitStore::new(configure_async_redis().await);
+    let second = RedisRateLimitStore::new(configure_async_redis().await);
+
+    let limit = RateLimit { requests: 2, window_seconds: 60 };
+    let key = format!("/login:{}", Uuid::new_v4());
+    // The start of a window, so the previous one is empty
+    let now = chrono::Utc::now().timestamp() / 60 * 60;
+
+    assert_eq!(first.hit(&key, &limit, now).await, Ok(None));
+    assert_eq!(second.hit(&key, &limit, now).await, Ok(None));
+    // Waits until the two requests only count for one, half way into the next window
+    assert_eq!(first.hit(&key, &limit, now).await, Ok(Some(90)));
+    // Rejected requests don't count, or that wait would keep growing
+    assert_eq!(second.hit(&key, &limit, now + 60).await, Ok(Some(30)));
+    assert_eq!(second.hit(&key, &limit, now + 90).await, Ok(None));
+
+    assert_eq!(first.failures(), 0);
+    assert_eq!(second.failures(), 0);
+}
@@ -0,0 +1,191 @@
+use auth_service::{
+    route_handlers::{RecoveryCodeCountResponse, RecoveryCodesResponse, SignupResponse, TwoFactorAuthResponse},
+    services::{api::ErrorResponse, constants::JWT_COOKIE_NAME},
+    user::Email
+};
+use wiremock::{matchers::{method, path}, Mock, ResponseTemplate};
+
+use crate::utils::{get_random_email, TestApp};
+
+// Sign up a user with email 2FA and return their email along with the recovery codes issued at signup
+async fn signup_with_2fa(app: &TestApp) -> (String, Vec<String>) {
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": true
+    });
+
+    let response = app.post_signup(&signup_body).await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let recovery_codes = response
+        .json::<SignupResponse>()
+        .await
+        .expect("Could not deserialize response body to SignupResponse")
+        .recovery_codes
+        .expect("No recovery codes issued at signup");
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    (random_email, recovery_codes)
+}
+
+async fn login_with_recovery_code(app: &TestApp, email: &str, recovery_code: &str) -> reqwest::Response {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123"
+    });
+
+    let response = app.post_login(&login_body).await;
+
+    assert_eq!(response.status().as_u16(), 206);
+
+    let login_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_id;
+
+    app.post_verify_2fa(&serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_id,
+        "2FACode": recovery_code
+    }))
+    .await
+}
+
+async fn remaining_codes(app: &TestApp) -> usize {
+    let response = app.get_recovery_codes().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    response
+        .json::<RecoveryCodeCountResponse>()
+        .await
+        .expect("Could not deserialize response body to RecoveryCodeCountResponse")
+        .remaining
+}
+
+#[tokio::test]
+async fn should_accept_each_recovery_code_once() {
+    let mut app = TestApp::new().await;
+
+    let (email, recovery_codes) = signup_with_2fa(&app).await;
+
+    let response = login_with_recovery_code(&app, &email, &recovery_codes[0]).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+
+    assert!(!auth_cookie.value().is_empty());
+
+    assert_eq!(remaining_codes(&app).await, 9);
+
+    let response = login_with_recovery_code(&app, &email, &recovery_codes[0]).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Codes are accepted however the user types them back
+    let response = login_with_recovery_code(&app, &email, &recovery_codes[1].to_uppercase().replace('-', "")).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(remaining_codes(&app).await, 8);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_invalidate_old_codes_when_regenerating() {
+    let mut app = TestApp::new().await;
+
+    let (email, old_codes) = signup_with_2fa(&app).await;
+
+    let response = login_with_recovery_code(&app, &email, &old_codes[0]).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_recovery_codes_regenerate().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let new_codes = response
+        .json::<RecoveryCodesResponse>()
+        .await
+        .expect("Could not deserialize response body to RecoveryCodesResponse")
+        .recovery_codes
+        .expect("No recovery codes in response");
+
+    assert_eq!(new_codes.len(), 10);
+    assert_eq!(remaining_codes(&app).await, 10);
+
+    let response = login_with_recovery_code(&app, &email, &old_codes[1]).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = login_with_recovery_code(&app, &email, &new_codes[0]).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_regenerating_without_2fa() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_recovery_codes_regenerate().await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Missing auth token".to_owned()
+    );
+
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&signup_body).await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_login(&serde_json::json!({
+        "email": random_email,
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_recovery_codes_regenerate().await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    let email = Email::parse(random_email).expect("Failed to parse email");
+    let count = app.recovery_code_store.read().await.count_codes(&email).await;
+
+    assert_eq!(count, Ok(0));
+
+    app.clean_up().await;
+}
@@ -0,0 +1,216 @@
+use auth_service::{
+    services::{
+        api::ErrorResponse,
+        constants::{JWT_COOKIE_NAME, REFRESH_COOKIE_NAME},
+        refresh_tokens::{RefreshToken, RefreshTokenFamily, RefreshTokenRecord, RefreshTokenStoreError}
+    },
+    user::Email
+};
+use chrono::Utc;
+use reqwest::Url;
+
+use crate::utils::{get_random_email, TestApp};
+
+async fn signup_and_login(app: &TestApp) -> String {
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&signup_body).await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let refresh_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == REFRESH_COOKIE_NAME)
+        .expect("No refresh cookie found");
+
+    refresh_cookie.value().to_owned()
+}
+
+fn set_refresh_cookie(app: &TestApp, token: &str) {
+    app.cookie_jar.add_cookie_str(
+        &format!(
+            "{}={}; HttpOnly; SameSite=Lax; Secure; Path=/",
+            REFRESH_COOKIE_NAME, token
+        ),
+        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+    );
+}
+
+#[tokio::test]
+async fn should_return_400_if_refresh_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_refresh().await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Missing auth token".to_owned()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_invalid_refresh_token() {
+    let mut app = TestApp::new().await;
+
+    let unknown_token = RefreshToken::default();
+
+    for token in ["invalid", unknown_token.as_ref()] {
+        set_refresh_cookie(&app, token);
+
+        let response = app.post_refresh().await;
+
+        assert_eq!(response.status().as_u16(), 401, "Failed for token: {:?}", token);
+
+        assert_eq!(
+            response
+                .json::<ErrorResponse>()
+                .await
+                .expect("Could not deserialize response body to ErrorResponse")
+                .error,
+            "Invalid auth token".to_owned()
+        );
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_200_and_rotate_refresh_token() {
+    let mut app = TestApp::new().await;
+
+    let refresh_token = signup_and_login(&app).await;
+
+    let response = app.post_refresh().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+
+    assert!(!auth_cookie.value().is_empty());
+
+    let refresh_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == REFRESH_COOKIE_NAME)
+        .expect("No refresh cookie found");
+
+    assert_ne!(refresh_cookie.value(), refresh_token);
+
+    let record = app
+        .refresh_tokens
+        .read()
+        .await
+        .get_token(&RefreshToken::parse(refresh_token).unwrap())
+        .await
+        .expect("Failed to get refresh token");
+
+    assert!(record.used);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_and_revoke_family_if_refresh_token_reused() {
+    let mut app = TestApp::new().await;
+
+    let first_token = signup_and_login(&app).await;
+
+    let response = app.post_refresh().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let second_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == REFRESH_COOKIE_NAME)
+        .expect("No refresh cookie found")
+        .value()
+        .to_owned();
+
+    // Replay the token that was already rotated
+    set_refresh_cookie(&app, &first_token);
+
+    let response = app.post_refresh().await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The legitimate successor is revoked along with the rest of the family
+    set_refresh_cookie(&app, &second_token);
+
+    let response = app.post_refresh().await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_refresh_after_logout() {
+    let mut app = TestApp::new().await;
+
+    let refresh_token = signup_and_login(&app).await;
+
+    let response = app.post_logout().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    set_refresh_cookie(&app, &refresh_token);
+
+    let response = app.post_refresh().await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_mark_a_refresh_token_used_only_once() {
+    let mut app = TestApp::new().await;
+
+    let token = RefreshToken::parse(signup_and_login(&app).await).unwrap();
+    let mut store = app.refresh_tokens.write().await;
+
+    // Two replicas rotating the same token both read it as unused, only one of them gets to mark it
+    let record = store.get_token(&token).await.unwrap();
+    assert!(!record.used);
+
+    assert_eq!(store.mark_used(&token).await, Ok(()));
+    assert_eq!(store.mark_used(&token).await, Err(RefreshTokenStoreError::TokenAlreadyUsed));
+    assert_eq!(store.mark_used(&RefreshToken::default()).await, Err(RefreshTokenStoreError::TokenNotFound));
+
+    // A token can't be issued to an address without an account
+    let record = RefreshTokenRecord {
+        email: Email::parse(get_random_email()).unwrap(),
+        family: RefreshTokenFamily::default(),
+        used: false,
+        expires_at: Utc::now().timestamp() + 60,
+    };
+    assert!(store.add_token(RefreshToken::default(), record).await.is_err());
+
+    drop(store);
+    app.clean_up().await;
+}
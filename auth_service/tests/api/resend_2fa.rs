@@ -0,0 +1,172 @@
+use auth_service::{
+    route_handlers::{Resend2FAResponse, TwoFactorAuthResponse},
+    services::{
+        api::ErrorResponse,
+        two_fa::{LoginId, TwoFaCodes, TWO_FA_MAX_RESENDS, TWO_FA_RESEND_COOLDOWN_SECONDS}
+    }
+};
+use chrono::Utc;
+use wiremock::{matchers::{method, path}, Mock, ResponseTemplate};
+
+use crate::utils::{get_random_email, TestApp};
+
+// Sign up a user with email 2FA and start a login, expecting `emails` codes to be sent in total
+async fn start_2fa_login(app: &TestApp, emails: u64) -> (String, String) {
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": true
+    });
+
+    let response = app.post_signup(&signup_body).await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(emails)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_login(&serde_json::json!({
+        "email": random_email,
+        "password": "password123"
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 206);
+
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_id;
+
+    (random_email, login_attempt_id)
+}
+
+// Pretend the last code went out long enough ago for the cooldown to have passed
+async fn skip_cooldown(app: &TestApp, login_attempt_id: &str) {
+    let login_id = LoginId::parse(login_attempt_id.to_owned()).unwrap();
+    let mut two_fa_code_store = app.two_fa_code.write().await;
+
+    let mut challenge = two_fa_code_store.get_challenge(&login_id).await.unwrap();
+    challenge.last_sent_at = Utc::now() - chrono::Duration::try_seconds(TWO_FA_RESEND_COOLDOWN_SECONDS).unwrap();
+
+    two_fa_code_store.update_challenge(&login_id, challenge).await.unwrap();
+}
+
+#[tokio::test]
+async fn should_return_429_with_remaining_cooldown() {
+    let mut app = TestApp::new().await;
+
+    let (_, login_attempt_id) = start_2fa_login(&app, 1).await;
+
+    let response = app.post_resend_2fa(&serde_json::json!({ "loginAttemptId": login_attempt_id })).await;
+
+    assert_eq!(response.status().as_u16(), 429);
+    assert!(response.headers().contains_key("retry-after"));
+
+    let body = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse");
+
+    assert_eq!(body.error, "Resend cooldown".to_owned());
+    assert!(body.retry_after.is_some_and(|seconds| seconds > 0 && seconds <= TWO_FA_RESEND_COOLDOWN_SECONDS));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_resend_code_after_cooldown() {
+    let mut app = TestApp::new().await;
+
+    let (random_email, login_attempt_id) = start_2fa_login(&app, 2).await;
+
+    skip_cooldown(&app, &login_attempt_id).await;
+
+    let response = app.post_resend_2fa(&serde_json::json!({ "loginAttemptId": login_attempt_id })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = response
+        .json::<Resend2FAResponse>()
+        .await
+        .expect("Could not deserialize response body to Resend2FAResponse");
+
+    assert_eq!(body.cooldown_seconds, TWO_FA_RESEND_COOLDOWN_SECONDS);
+    assert_eq!(body.resends_remaining, TWO_FA_MAX_RESENDS - 1);
+
+    let challenge = app
+        .two_fa_code
+        .read()
+        .await
+        .get_challenge(&LoginId::parse(login_attempt_id.clone()).unwrap())
+        .await
+        .unwrap();
+
+    let response = app.post_verify_2fa(&serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": challenge.code.as_ref()
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_429_after_too_many_resends() {
+    let mut app = TestApp::new().await;
+
+    let (_, login_attempt_id) = start_2fa_login(&app, 1 + TWO_FA_MAX_RESENDS as u64).await;
+
+    for _ in 0..TWO_FA_MAX_RESENDS {
+        skip_cooldown(&app, &login_attempt_id).await;
+
+        let response = app.post_resend_2fa(&serde_json::json!({ "loginAttemptId": login_attempt_id })).await;
+
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    skip_cooldown(&app, &login_attempt_id).await;
+
+    let response = app.post_resend_2fa(&serde_json::json!({ "loginAttemptId": login_attempt_id })).await;
+
+    assert_eq!(response.status().as_u16(), 429);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Too many resends".to_owned()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reject_unknown_login_attempts() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_resend_2fa(&serde_json::json!({ "loginAttemptId": "invalid" })).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app.post_resend_2fa(&serde_json::json!({
+        "loginAttemptId": LoginId::default().as_ref()
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
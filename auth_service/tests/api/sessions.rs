@@ -0,0 +1,252 @@
+use auth_service::{
+    route_handlers::{SessionResponse, SessionsResponse},
+    services::constants::REFRESH_COOKIE_NAME
+};
+use reqwest::Client;
+use uuid::Uuid;
+
+use crate::utils::{get_random_email, TestApp};
+
+const FIREFOX_ON_LINUX: &str = "Mozilla/5.0 (X11; Linux x86_64; rv:131.0) Gecko/20100101 Firefox/131.0";
+
+// Another device of the same user, with cookies of its own
+struct Device {
+    client: Client,
+    addr: String,
+}
+
+impl Device {
+    fn new(app: &TestApp, user_agent: &str) -> Self {
+        let client = Client::builder()
+            .cookie_store(true)
+            .user_agent(user_agent)
+            .build()
+            .unwrap();
+
+        Self { client, addr: app.addr.clone() }
+    }
+
+    async fn login(&self, email: &str) {
+        let response = self.client
+            .post(format!("{}/login", &self.addr))
+            .json(&login_body(email))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    async fn get_sessions(&self) -> reqwest::Response {
+        self.client
+            .get(format!("{}/sessions", &self.addr))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    async fn refresh(&self) -> reqwest::Response {
+        self.client
+            .post(format!("{}/refresh", &self.addr))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+}
+
+fn login_body(email: &str) -> serde_json::Value {
+    serde_json::json!({
+        "email": email,
+        "password": "password123"
+    })
+}
+
+async fn signup_and_login(app: &TestApp) -> String {
+    let email = get_random_email();
+
+    let response = app.post_signup(&serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_login(&login_body(&email)).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    email
+}
+
+async fn sessions(response: reqwest::Response) -> Vec<SessionResponse> {
+    assert_eq!(response.status().as_u16(), 200);
+
+    response
+        .json::<SessionsResponse>()
+        .await
+        .expect("Could not deserialize response body to SessionsResponse")
+        .sessions
+}
+
+#[tokio::test]
+async fn should_list_sessions_of_every_device() {
+    let mut app = TestApp::new().await;
+
+    let email = signup_and_login(&app).await;
+    let laptop = Device::new(&app, FIREFOX_ON_LINUX);
+    laptop.login(&email).await;
+
+    let listed = sessions(app.get_sessions().await).await;
+
+    assert_eq!(listed.len(), 2);
+
+    let other = listed.iter().find(|session| !session.current).expect("No session of the other device");
+    let current = listed.iter().find(|session| session.current).expect("No current session");
+
+    assert_ne!(other.id, current.id);
+    assert_eq!(current.device, None);
+    assert_eq!(other.device.as_deref(), Some("Firefox on Linux"));
+    assert_eq!(other.user_agent.as_deref(), Some(FIREFOX_ON_LINUX));
+    assert_eq!(other.ip.as_deref(), Some("127.0.0.1"));
+    assert!(other.created_at <= other.last_seen_at);
+
+    // Each device sees its own session as the current one
+    let listed = sessions(laptop.get_sessions().await).await;
+
+    assert!(listed.iter().any(|session| session.current && session.id == other.id));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_revoke_another_session() {
+    let mut app = TestApp::new().await;
+
+    let email = signup_and_login(&app).await;
+    let laptop = Device::new(&app, FIREFOX_ON_LINUX);
+    laptop.login(&email).await;
+
+    let listed = sessions(app.get_sessions().await).await;
+    let laptop_session = listed.iter().find(|session| !session.current).unwrap();
+
+    let response = app.post_sessions_revoke(&serde_json::json!({ "sessionId": laptop_session.id })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The laptop's auth token stops working right away, and it can't get a new one
+    assert_eq!(laptop.get_sessions().await.status().as_u16(), 401);
+    assert_eq!(laptop.refresh().await.status().as_u16(), 401);
+
+    let listed = sessions(app.get_sessions().await).await;
+
+    assert_eq!(listed.len(), 1);
+    assert!(listed[0].current);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_revoke_current_session_like_logout() {
+    let mut app = TestApp::new().await;
+
+    signup_and_login(&app).await;
+
+    let listed = sessions(app.get_sessions().await).await;
+
+    let response = app.post_sessions_revoke(&serde_json::json!({ "sessionId": listed[0].id })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.cookies().any(|cookie| cookie.name() == REFRESH_COOKIE_NAME && cookie.value().is_empty()));
+
+    assert_eq!(app.get_sessions().await.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_revoke_all_other_sessions() {
+    let mut app = TestApp::new().await;
+
+    let email = signup_and_login(&app).await;
+    let laptop = Device::new(&app, FIREFOX_ON_LINUX);
+    let phone = Device::new(&app, "Mozilla/5.0 (Linux; Android 14) Chrome/130.0.0.0 Mobile Safari/537.36");
+    laptop.login(&email).await;
+    phone.login(&email).await;
+
+    assert_eq!(sessions(app.get_sessions().await).await.len(), 3);
+
+    let response = app.post_sessions_revoke_others().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(laptop.get_sessions().await.status().as_u16(), 401);
+    assert_eq!(phone.refresh().await.status().as_u16(), 401);
+
+    let listed = sessions(app.get_sessions().await).await;
+
+    assert_eq!(listed.len(), 1);
+    assert!(listed[0].current);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_end_session_on_logout() {
+    let mut app = TestApp::new().await;
+
+    let email = signup_and_login(&app).await;
+    let laptop = Device::new(&app, FIREFOX_ON_LINUX);
+    laptop.login(&email).await;
+
+    let response = app.post_logout().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let listed = sessions(laptop.get_sessions().await).await;
+
+    assert_eq!(listed.len(), 1);
+    assert!(listed[0].current);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_only_revoke_own_sessions() {
+    let mut app = TestApp::new().await;
+
+    let other_email = signup_and_login(&app).await;
+    let other_device = Device::new(&app, FIREFOX_ON_LINUX);
+    other_device.login(&other_email).await;
+    let other_session = sessions(other_device.get_sessions().await).await.remove(0);
+
+    // The test client is now logged in as someone else
+    signup_and_login(&app).await;
+
+    let response = app.post_sessions_revoke(&serde_json::json!({ "sessionId": other_session.id })).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+
+    let response = app.post_sessions_revoke(&serde_json::json!({ "sessionId": Uuid::new_v4().to_string() })).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+
+    let response = app.post_sessions_revoke(&serde_json::json!({ "sessionId": "not-a-session" })).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    assert_eq!(other_device.get_sessions().await.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_not_logged_in() {
+    let mut app = TestApp::new().await;
+
+    assert_eq!(app.get_sessions().await.status().as_u16(), 400);
+    assert_eq!(app.post_sessions_revoke_others().await.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
@@ -7,7 +7,7 @@ use serde_json::json;
 
 #[tokio::test]
 async fn should_return_422_if_malformed_input() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
 
     let random_email = get_random_email();
 
@@ -42,11 +42,13 @@ async fn should_return_422_if_malformed_input() {
             test_case
         );
     }
+
+    app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_201_if_valid_input() {
-    let test_app = TestApp::new().await;
+    let mut test_app = TestApp::new().await;
     let body = serde_json::json!(
         {
             "email": "test_user@email.com",
@@ -58,22 +60,22 @@ async fn should_return_201_if_valid_input() {
 
     assert_eq!(response.status().as_u16(), 201);
 
-    let expected_response = SignupResponse {
-        message: "User created successfully!".to_owned(),
-    };
+    let json_body = response
+        .json::<SignupResponse>()
+        .await
+        .expect("Could not deserialize response body to UserBody");
 
-    assert_eq!(
-        response
-            .json::<SignupResponse>()
-            .await
-            .expect("Could not deserialize response body to UserBody"),
-        expected_response
-    );
+    assert_eq!(json_body.message, "User created successfully!".to_owned());
+
+    // Signing up with 2FA enrolls it, so the user gets their recovery codes straight away
+    assert_eq!(json_body.recovery_codes.map(|codes| codes.len()), Some(10));
+
+    test_app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_400_if_invalid_input() {
-    let test_app = TestApp::new().await;
+    let mut test_app = TestApp::new().await;
 
     let json_invalid_email_empty = json!({
             "email": "",
@@ -117,11 +119,13 @@ async fn should_return_400_if_invalid_input() {
         .error,
     "Invalid credentials".to_owned())
     }
+
+    test_app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_409_if_email_already_exists() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
 
     let random_email = get_random_email();
 
@@ -147,4 +151,6 @@ async fn should_return_409_if_email_already_exists() {
             .error,
         "User already exists".to_owned()
     );
+
+    app.clean_up().await;
 }
\ No newline at end of file
@@ -0,0 +1,204 @@
+use auth_service::{
+    route_handlers::{TotpEnrollResponse, TwoFactorAuthResponse},
+    services::{api::ErrorResponse, constants::JWT_COOKIE_NAME, totp::TotpSecret},
+    user::{Email, TwoFaMethod}
+};
+use chrono::Utc;
+
+use crate::utils::{get_random_email, TestApp};
+
+// Sign up and log in a user without 2FA, leaving the auth cookie in the client's jar
+async fn signup_and_login(app: &TestApp) -> serde_json::Value {
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&signup_body).await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    login_body
+}
+
+async fn enroll(app: &TestApp) -> TotpSecret {
+    let response = app.post_totp_enroll(&serde_json::json!({ "password": "password123" })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = response
+        .json::<TotpEnrollResponse>()
+        .await
+        .expect("Could not deserialize response body to TotpEnrollResponse");
+
+    assert!(body.otpauth_uri.starts_with("otpauth://totp/"));
+    assert!(body.otpauth_uri.contains(&body.secret));
+    assert!(body.qr_code_svg.contains("<svg"));
+
+    TotpSecret::parse(body.secret).expect("Failed to parse TOTP secret")
+}
+
+#[tokio::test]
+async fn should_return_400_if_enrolling_without_auth_cookie() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_totp_enroll(&serde_json::json!({ "password": "password123" })).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Missing auth token".to_owned()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_require_current_password_to_enroll() {
+    let mut app = TestApp::new().await;
+
+    signup_and_login(&app).await;
+
+    let response = app.post_totp_enroll(&serde_json::json!({ "password": "wrongpassword" })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Nothing is pending, so there's no secret to confirm
+    let response = app.post_totp_confirm(&serde_json::json!({ "code": "123456", "password": "password123" })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reject_invalid_confirmation_codes() {
+    let mut app = TestApp::new().await;
+
+    signup_and_login(&app).await;
+    let secret = enroll(&app).await;
+
+    let response = app.post_totp_confirm(&serde_json::json!({ "code": "12345", "password": "password123" })).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    // A code from well outside the skew window
+    let stale_code = secret.generate(Utc::now().timestamp() - 600);
+    let response = app.post_totp_confirm(&serde_json::json!({ "code": stale_code.as_ref(), "password": "password123" })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_require_totp_at_login_after_confirming_enrollment() {
+    let mut app = TestApp::new().await;
+
+    let login_body = signup_and_login(&app).await;
+    let secret = enroll(&app).await;
+
+    let now = Utc::now().timestamp();
+    let code = secret.generate(now);
+
+    // The code alone isn't enough, the password is asked for again
+    let response = app.post_totp_confirm(&serde_json::json!({ "code": code.as_ref(), "password": "wrongpassword" })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_totp_confirm(&serde_json::json!({ "code": code.as_ref(), "password": "password123" })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email = Email::parse(login_body["email"].as_str().unwrap().to_owned()).unwrap();
+    let stored_secret = app
+        .totp_store
+        .read()
+        .await
+        .get_secret(&email)
+        .await
+        .expect("TOTP secret was not confirmed");
+
+    assert_eq!(stored_secret, secret);
+
+    let response = app.post_login(&login_body).await;
+
+    assert_eq!(response.status().as_u16(), 206);
+
+    let json_body = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+
+    assert_eq!(json_body.two_fa_method, TwoFaMethod::Totp);
+
+    // The code that confirmed enrollment has already been used
+    let response = app.post_verify_2fa(&serde_json::json!({
+        "email": login_body["email"],
+        "loginAttemptId": json_body.login_id,
+        "2FACode": code.as_ref()
+    })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    let json_body = app
+        .post_login(&login_body)
+        .await
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+
+    // The next code is still inside the default skew window
+    let next_code = secret.generate(now + 30);
+    let verify_body = serde_json::json!({
+        "email": login_body["email"],
+        "loginAttemptId": json_body.login_id,
+        "2FACode": next_code.as_ref()
+    });
+
+    let response = app.post_verify_2fa(&verify_body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+
+    assert!(!auth_cookie.value().is_empty());
+
+    // Replaying the same code on a fresh login attempt is rejected
+    let json_body = app
+        .post_login(&login_body)
+        .await
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+
+    let response = app.post_verify_2fa(&serde_json::json!({
+        "email": login_body["email"],
+        "loginAttemptId": json_body.login_id,
+        "2FACode": next_code.as_ref()
+    })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
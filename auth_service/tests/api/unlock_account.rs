@@ -0,0 +1,199 @@
+use auth_service::{
+    route_handlers::UnlockAccountResponse,
+    services::{api::ErrorResponse, constants::LOGIN_LOCKOUT_POLICY}
+};
+use wiremock::{matchers::{method, path}, Mock, ResponseTemplate};
+
+use crate::utils::{get_random_email, TestApp};
+
+async fn login(app: &TestApp, email: &str, password: &str) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "email": email,
+        "password": password
+    }))
+    .await
+}
+
+// Fails enough logins in a row to lock the account and returns the response to the last one
+async fn lock_account(app: &TestApp, email: &str) -> reqwest::Response {
+    for _ in 1..LOGIN_LOCKOUT_POLICY.threshold {
+        let response = login(app, email, "wrongpassword").await;
+
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    login(app, email, "wrongpassword").await
+}
+
+async fn signup(app: &TestApp, email: &str) {
+    let response = app.post_signup(&serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+#[tokio::test]
+async fn should_lock_account_after_repeated_failed_logins() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    signup(&app, &email).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = lock_account(&app, &email).await;
+
+    assert_eq!(response.status().as_u16(), 429);
+
+    let retry_after = response
+        .headers()
+        .get("retry-after")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<i64>().ok())
+        .expect("No Retry-After header");
+
+    assert!(retry_after > 0 && retry_after <= LOGIN_LOCKOUT_POLICY.base_seconds);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Account temporarily locked"
+    );
+
+    // The right password doesn't get through the lockout either
+    let response = login(&app, &email, "password123").await;
+
+    assert_eq!(response.status().as_u16(), 429);
+
+    app.token_from_email("Your account has been locked").await;
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_unlock_account_with_emailed_link() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    signup(&app, &email).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = lock_account(&app, &email).await;
+
+    assert_eq!(response.status().as_u16(), 429);
+
+    let token = app.token_from_email("Your account has been locked").await;
+
+    let response = app.post_unlock_account(&serde_json::json!({ "token": token })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(
+        response
+            .json::<UnlockAccountResponse>()
+            .await
+            .expect("Could not deserialize response body to UnlockAccountResponse")
+            .message,
+        "Account unlocked"
+    );
+
+    let response = login(&app, &email, "password123").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reset_failed_attempts_after_successful_login() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    signup(&app, &email).await;
+
+    for _ in 1..LOGIN_LOCKOUT_POLICY.threshold {
+        let response = login(&app, &email, "wrongpassword").await;
+
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    let response = login(&app, &email, "password123").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Only failures in a row count towards a lockout
+    let response = login(&app, &email, "wrongpassword").await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_lock_unknown_email_like_an_account() {
+    let mut app = TestApp::new().await;
+
+    // No account to notify, so any email sent would fail the test
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = lock_account(&app, &get_random_email()).await;
+
+    assert_eq!(response.status().as_u16(), 429);
+
+    assert!(response.headers().contains_key("retry-after"));
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Account temporarily locked"
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_invalid_unlock_token() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_unlock_account(&serde_json::json!({ "token": "invalid" })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_422_if_malformed_input() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_unlock_account(&serde_json::json!({ "tokn": "invalid" })).await;
+
+    assert_eq!(response.status().as_u16(), 422);
+
+    app.clean_up().await;
+}
@@ -1,14 +1,13 @@
-use std::{str::FromStr, sync::Arc};
+use std::{str::FromStr, sync::Arc, time::Duration};
 
-use auth_service::{get_postgres_pool, get_redis_client, services::{constants::{test, DATABASE_URL, DEFAULT_REDIS_HOSTNAME}, postgres_user_store::PostgresUserStore, postmark_email_client::PostmarkEmailClient, redis_banned_token_store::RedisBannedTokenStore, redis_two_fa_code_store::RedisTwoFACodeStore, two_fa::TwoFaCodeStore, MockEmailClient}, user::Email};
+use auth_service::{get_postgres_pool, get_redis_client, services::{constants::{test, DATABASE_URL, DEFAULT_REDIS_HOSTNAME}, keys::{Keyring, KeyringType, SigningKey}, postgres_refresh_token_store::PostgresRefreshTokenStore, postgres_totp_store::PostgresTotpStore, postgres_passkey_store::PostgresPasskeyStore, passkeys::PasskeyStoreType, postgres_recovery_code_store::PostgresRecoveryCodeStore, postgres_login_history_store::PostgresLoginHistoryStore, recovery_codes::RecoveryCodeStoreType, redis_webauthn_challenge_store::RedisWebauthnChallengeStore, redis_password_reset_token_store::RedisPasswordResetTokenStore, redis_email_change_store::RedisEmailChangeStore, postgres_organization_store::PostgresOrganizationStore, postgres_session_store::PostgresSessionStore, redis_invitation_store::RedisInvitationStore, redis_login_attempt_store::RedisLoginAttemptStore, redis_authorization_code_store::RedisAuthorizationCodeStore, postgres_oauth_client_store::PostgresOAuthClientStore, rate_limits::RateLimitCounters, totp::TotpStoreType, postgres_user_store::PostgresUserStore, refresh_tokens::RefreshTokenStoreType, postmark_email_client::PostmarkEmailClient, redis_banned_token_store::RedisBannedTokenStore, redis_two_fa_code_store::RedisTwoFACodeStore}, user::Email};
 #[allow(dead_code, unused)]
-
 use auth_service::{
     app::{state::AppState, App}, 
     services::tokens::{BannedTokenStoreError, BannedTokenStoreType, BannedTokens}, 
-    user::store::Users
+    user::store::{UserStoreType, Users}
 };
-use reqwest::{cookie::Jar, Client, Response};
+use reqwest::{cookie::Jar, redirect::Policy, Client, Response};
 use secrecy::Secret;
 use serde::Serialize;
 use sqlx::{postgres::{PgConnectOptions, PgPoolOptions}, Connection, Executor, PgConnection, PgPool};
@@ -20,13 +19,20 @@ pub struct TestApp {
     pub addr: String,
     pub client: Client,
     pub cookie_jar: Arc<Jar>,
+    pub user_store: UserStoreType,
     pub banned_tokens: BannedTokenStoreType,
+    pub refresh_tokens: RefreshTokenStoreType,
+    pub keyring: KeyringType,
+    pub totp_store: TotpStoreType,
+    pub recovery_code_store: RecoveryCodeStoreType,
+    pub passkey_store: PasskeyStoreType,
     pub two_fa_code: Arc<RwLock<RedisTwoFACodeStore>>,
     pub db_name: String,
     pub email_server: MockServer, 
     pub clean_up_called: bool
 }
 
+#[allow(dead_code)]
 impl TestApp {
     pub async fn new() -> Self {
         let email_server = MockServer::start().await;
@@ -36,17 +42,50 @@ impl TestApp {
         let redis_connection = Arc::new(RwLock::new(configure_redis()));
         let db_name = Uuid::new_v4().to_string();
         let pg_pool = configure_postgresql(&db_name).await;
-        let user_store = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool)));
+        let user_store: UserStoreType = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool.clone())));
+        let refresh_token_store: RefreshTokenStoreType = Arc::new(RwLock::new(PostgresRefreshTokenStore::new(pg_pool.clone())));
+        let totp_store: TotpStoreType = Arc::new(RwLock::new(PostgresTotpStore::new(pg_pool.clone())));
+        let recovery_code_store: RecoveryCodeStoreType = Arc::new(RwLock::new(PostgresRecoveryCodeStore::new(pg_pool.clone())));
+        let passkey_store: PasskeyStoreType = Arc::new(RwLock::new(PostgresPasskeyStore::new(pg_pool.clone())));
+        let login_history_store = Arc::new(RwLock::new(PostgresLoginHistoryStore::new(pg_pool.clone())));
+        let organization_store = Arc::new(RwLock::new(PostgresOrganizationStore::new(pg_pool.clone())));
+        let session_store = Arc::new(RwLock::new(PostgresSessionStore::new(pg_pool.clone())));
+        let oauth_client_store = Arc::new(RwLock::new(PostgresOAuthClientStore::new(pg_pool)));
         let banned_token_store = Arc::new(RwLock::new(RedisBannedTokenStore::new(
             redis_connection.clone(),
         )));
-        let two_fa_code = Arc::new(RwLock::new(RedisTwoFACodeStore::new(redis_connection)));
+        let two_fa_code = Arc::new(RwLock::new(RedisTwoFACodeStore::new(redis_connection.clone())));
+        let webauthn_challenge_store = Arc::new(RwLock::new(RedisWebauthnChallengeStore::new(redis_connection.clone())));
+        let password_reset_token_store = Arc::new(RwLock::new(RedisPasswordResetTokenStore::new(redis_connection.clone())));
+        let email_change_store = Arc::new(RwLock::new(RedisEmailChangeStore::new(redis_connection.clone())));
+        let invitation_store = Arc::new(RwLock::new(RedisInvitationStore::new(redis_connection.clone())));
+        let login_attempt_store = Arc::new(RwLock::new(RedisLoginAttemptStore::new(redis_connection.clone())));
+        let authorization_code_store = Arc::new(RwLock::new(RedisAuthorizationCodeStore::new(redis_connection)));
+        // Every test app calls from 127.0.0.1, so counters shared through Redis would make tests limit each other
+        let rate_limit_store = Arc::new(RwLock::new(RateLimitCounters::default()));
+        let keyring: KeyringType = Arc::new(RwLock::new(configure_keyring()));
 
         let app_state = AppState {
             banned_token_store: banned_token_store.clone(),
+            refresh_token_store: refresh_token_store.clone(),
+            session_store,
             two_fa_code: two_fa_code.clone(),
+            totp_store: totp_store.clone(),
+            recovery_code_store: recovery_code_store.clone(),
+            passkey_store: passkey_store.clone(),
+            webauthn_challenge_store,
+            password_reset_token_store,
+            email_change_store,
+            login_history_store,
+            organization_store,
+            invitation_store,
+            login_attempt_store,
+            rate_limit_store,
+            oauth_client_store,
+            authorization_code_store,
+            keyring: keyring.clone(),
             email_client,
-            user_store
+            user_store: user_store.clone()
         };
 
         let app = App::build(app_state, "127.0.0.1:0")
@@ -68,7 +107,13 @@ impl TestApp {
             addr, 
             client, 
             cookie_jar, 
+            user_store,
             banned_tokens: banned_token_store,
+            refresh_tokens: refresh_token_store,
+            keyring,
+            totp_store,
+            recovery_code_store,
+            passkey_store,
             two_fa_code,
             db_name,
             email_server,
@@ -76,16 +121,50 @@ impl TestApp {
         }
     }
 
+    // Emails with links are sent in the background, so wait for one with the given subject
+    // and pull the token out of its link
+    pub async fn token_from_email(&self, subject: &str) -> String {
+        for _ in 0..50 {
+            let requests = self.email_server.received_requests().await.unwrap_or_default();
+
+            let email = requests
+                .iter()
+                .rev()
+                .filter_map(|request| serde_json::from_slice::<serde_json::Value>(&request.body).ok())
+                .find(|body| body["Subject"] == subject);
+
+            if let Some(body) = email {
+                return body["TextBody"]
+                    .as_str()
+                    .and_then(|text| text.split("token=").nth(1))
+                    .expect("Email does not contain a link with a token")
+                    .to_owned();
+            }
+
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        panic!("No email with subject {} was sent", subject);
+    }
+
     pub async fn get_root(&self) -> Response {
         self.client
-            .get(&format!("{}/", &self.addr))
+            .get(format!("{}/", &self.addr))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_jwks(&self) -> Response {
+        self.client
+            .get(format!("{}/.well-known/jwks.json", &self.addr))
             .send()
             .await
             .expect("Failed to execute request")
     }
 
     pub async fn post_login<Body: serde::Serialize>(&self, body: &Body) -> Response {
-        self.client.post(&format!("{}/login", &self.addr))
+        self.client.post(format!("{}/login", &self.addr))
         .json(body)
         .send().await
         .expect("Failed to execute request")
@@ -93,35 +172,35 @@ impl TestApp {
 
     pub async fn get_signup(&self) -> Response {
         self.client
-            .post(&format!("{}/signup", &self.addr))
+            .post(format!("{}/signup", &self.addr))
             .send()
             .await
             .expect("Failed to execute request")
     }
     pub async fn get_login(&self) -> Response {
         self.client
-            .post(&format!("{}/login", &self.addr))
+            .post(format!("{}/login", &self.addr))
             .send()
             .await
             .expect("Failed to execute request")
     }
     pub async fn get_logout(&self) -> Response {
         self.client
-            .post(&format!("{}/logout", &self.addr))
+            .post(format!("{}/logout", &self.addr))
             .send()
             .await
             .expect(" -> StringFailed to execute request")
     }
     pub async fn get_verify_2fa(&self) -> Response {
         self.client
-            .post(&format!("{}/verify-2fa", &self.addr))
+            .post(format!("{}/verify-2fa", &self.addr))
             .send()
             .await
             .expect("Failed to execute request")
     }
     pub async fn get_verify_token(&self) -> Response {
         self.client
-            .post(&format!("{}/verify-token", &self.addr))
+            .post(format!("{}/verify-token", &self.addr))
             .send()
             .await
             .expect("Failed to execute request")
@@ -131,7 +210,7 @@ impl TestApp {
     where
         Body: serde::Serialize {
         self.client
-            .post(&format!("{}/signup", &self.addr))
+            .post(format!("{}/signup", &self.addr))
             .json(body)
             .send()
             .await
@@ -146,6 +225,14 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_refresh(&self) -> reqwest::Response {
+        self.client
+            .post(format!("{}/refresh", &self.addr))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_verify_2fa<Body>(&self, body: &Body) -> reqwest::Response
     where Body: Serialize {
         self.client
@@ -156,6 +243,124 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_resend_2fa<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: Serialize {
+        self.client
+            .post(format!("{}/resend-2fa", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: Serialize {
+        self.client
+            .post(format!("{}/change-password", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_account_export(&self) -> reqwest::Response {
+        self.client
+            .get(format!("{}/account/export", &self.addr))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_account<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: Serialize {
+        self.client
+            .delete(format!("{}/account", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_change_email<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: Serialize {
+        self.client
+            .post(format!("{}/change-email", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_change_email_confirm<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: Serialize {
+        self.client
+            .post(format!("{}/change-email/confirm", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_change_email_cancel<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: Serialize {
+        self.client
+            .post(format!("{}/change-email/cancel", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_verify_email<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: Serialize {
+        self.client
+            .post(format!("{}/verify-email", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_verify_email_resend<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: Serialize {
+        self.client
+            .post(format!("{}/verify-email/resend", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_unlock_account<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: Serialize {
+        self.client
+            .post(format!("{}/unlock-account", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_password_reset_request<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: Serialize {
+        self.client
+            .post(format!("{}/password-reset/request", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_password_reset_confirm<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: Serialize {
+        self.client
+            .post(format!("{}/password-reset/confirm", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_verify_token<Body>(&self, body: &Body) -> reqwest::Response
     where Body: serde::Serialize {
         self.client
@@ -166,6 +371,316 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_totp_enroll<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: serde::Serialize {
+        self.client
+            .post(format!("{}/2fa/totp/enroll", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_totp_confirm<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: serde::Serialize {
+        self.client
+            .post(format!("{}/2fa/totp/confirm", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_recovery_codes(&self) -> reqwest::Response {
+        self.client
+            .get(format!("{}/2fa/recovery-codes", &self.addr))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_recovery_codes_regenerate(&self) -> reqwest::Response {
+        self.client
+            .post(format!("{}/2fa/recovery-codes/regenerate", &self.addr))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_webauthn_register_start<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: serde::Serialize {
+        self.client
+            .post(format!("{}/webauthn/register/start", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_webauthn_register_finish<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: serde::Serialize {
+        self.client
+            .post(format!("{}/webauthn/register/finish", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_webauthn_login_start<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: serde::Serialize {
+        self.client
+            .post(format!("{}/webauthn/login/start", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_webauthn_login_finish<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: serde::Serialize {
+        self.client
+            .post(format!("{}/webauthn/login/finish", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_organizations<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: serde::Serialize {
+        self.client
+            .post(format!("{}/orgs", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_sessions(&self) -> reqwest::Response {
+        self.client
+            .get(format!("{}/sessions", &self.addr))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_sessions_revoke<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: serde::Serialize {
+        self.client
+            .post(format!("{}/sessions/revoke", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_sessions_revoke_others(&self) -> reqwest::Response {
+        self.client
+            .post(format!("{}/sessions/revoke-others", &self.addr))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_organizations(&self) -> reqwest::Response {
+        self.client
+            .get(format!("{}/orgs", &self.addr))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_organization_invitations<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: serde::Serialize {
+        self.client
+            .post(format!("{}/orgs/invitations", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_organization_invitations_accept<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: serde::Serialize {
+        self.client
+            .post(format!("{}/orgs/invitations/accept", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_organization_switch<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: serde::Serialize {
+        self.client
+            .post(format!("{}/orgs/switch", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_users(&self, query: &[(&str, &str)]) -> reqwest::Response {
+        self.client
+            .get(format!("{}/admin/users", &self.addr))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_user_details(&self, user_id: &str) -> reqwest::Response {
+        self.client
+            .get(format!("{}/admin/users/details", &self.addr))
+            .query(&[("userId", user_id)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_admin_users<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: serde::Serialize {
+        self.client
+            .delete(format!("{}/admin/users", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_admin_user_2fa<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: serde::Serialize {
+        self.client
+            .post(format!("{}/admin/users/2fa", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_admin_user_lock<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: serde::Serialize {
+        self.client
+            .post(format!("{}/admin/users/lock", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_admin_user_unlock<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: serde::Serialize {
+        self.client
+            .post(format!("{}/admin/users/unlock", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_admin_user_password_reset<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: serde::Serialize {
+        self.client
+            .post(format!("{}/admin/users/password-reset", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_admin_user_revoke_sessions<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: serde::Serialize {
+        self.client
+            .post(format!("{}/admin/users/revoke-sessions", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_oauth_clients(&self) -> reqwest::Response {
+        self.client
+            .get(format!("{}/admin/oauth-clients", &self.addr))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_admin_oauth_clients<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: serde::Serialize {
+        self.client
+            .post(format!("{}/admin/oauth-clients", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_admin_oauth_clients<Body>(&self, body: &Body) -> reqwest::Response
+    where Body: serde::Serialize {
+        self.client
+            .delete(format!("{}/admin/oauth-clients", &self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // Redirects aren't followed, so tests can look at where /authorize sends the browser
+    pub async fn get_authorize(&self, query: &[(&str, &str)]) -> reqwest::Response {
+        Client::builder()
+            .cookie_provider(self.cookie_jar.clone())
+            .redirect(Policy::none())
+            .build()
+            .unwrap()
+            .get(format!("{}/authorize", &self.addr))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_token(&self, form: &[(&str, &str)]) -> reqwest::Response {
+        self.client
+            .post(format!("{}/token", &self.addr))
+            .form(form)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_openid_configuration(&self) -> reqwest::Response {
+        self.client
+            .get(format!("{}/.well-known/openid-configuration", &self.addr))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_userinfo(&self, token: &str) -> reqwest::Response {
+        self.client
+            .get(format!("{}/userinfo", &self.addr))
+            .bearer_auth(token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // Like /authorize, redirects aren't followed so tests can see where the client is sent back to
+    pub async fn get_end_session(&self, query: &[(&str, &str)]) -> reqwest::Response {
+        Client::builder()
+            .cookie_provider(self.cookie_jar.clone())
+            .redirect(Policy::none())
+            .build()
+            .unwrap()
+            .get(format!("{}/end-session", &self.addr))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn clean_up(&mut self) {
         if self.clean_up_called {
             return;
@@ -185,6 +700,8 @@ impl Drop for TestApp {
     }
 }
 
+pub const TEST_KEY_ID: &str = "test-ed25519";
+
 pub fn get_random_email() -> String {
     format!("{}@example.com", Uuid::new_v4())
 }
@@ -194,7 +711,7 @@ async fn configure_postgresql(db_name: &str) -> PgPool {
 
     // We are creating a new database for each test case, and we need to ensure each database has a unique name!
 
-    configure_database(&postgresql_conn_url, &db_name).await;
+    configure_database(&postgresql_conn_url, db_name).await;
 
     let postgresql_conn_url_with_db = format!("{}/{}", postgresql_conn_url, db_name);
 
@@ -269,7 +786,7 @@ async fn delete_database(db_name: &str) {
 }
 
 
-fn configure_redis() -> redis::Connection {
+pub fn configure_redis() -> redis::Connection {
     let redis_hostname = DEFAULT_REDIS_HOSTNAME.to_owned();
 
     get_redis_client(redis_hostname)
@@ -278,6 +795,27 @@ fn configure_redis() -> redis::Connection {
         .expect("Failed to get Redis connection")
 }
 
+pub async fn configure_async_redis() -> redis::aio::MultiplexedConnection {
+    let redis_hostname = DEFAULT_REDIS_HOSTNAME.to_owned();
+
+    get_redis_client(redis_hostname)
+        .expect("Failed to get Redis client")
+        .get_multiplexed_tokio_connection()
+        .await
+        .expect("Failed to get Redis connection")
+}
+
+fn configure_keyring() -> Keyring {
+    let signing_key = SigningKey::from_pem(
+        TEST_KEY_ID,
+        jsonwebtoken::Algorithm::EdDSA,
+        include_bytes!("../fixtures/keys/ed25519.pem"),
+    )
+    .expect("Failed to load test signing key");
+
+    Keyring::new(signing_key)
+}
+
 fn configure_postmark_email_client(base_url: String) -> PostmarkEmailClient {
     let postmark_auth_token = Secret::new("auth_token".to_owned());
 
@@ -1,10 +1,12 @@
-use auth_service::{route_handlers::TwoFactorAuthResponse, services::{api::ErrorResponse, constants::JWT_COOKIE_NAME, two_fa::{LoginId, TwoFaCode, TwoFaCodes}}, user::Email};
+use auth_service::{route_handlers::TwoFactorAuthResponse, services::{api::ErrorResponse, constants::{DEFAULT_TWO_FA_MAX_ATTEMPTS, JWT_COOKIE_NAME}, two_fa::{LoginId, TwoFaCode, TwoFaCodes}}, user::Email};
+
+use wiremock::{matchers::{method, path}, Mock, ResponseTemplate};
 
 use crate::utils::{get_random_email, TestApp};
 
 #[tokio::test]
 async fn should_return_422_if_malformed_input() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
 
     let random_email = get_random_email();
     let login_attempt_id = LoginId::default().as_ref().to_owned();
@@ -44,12 +46,14 @@ async fn should_return_422_if_malformed_input() {
             test_case
         );
     }
+
+    app.clean_up().await;
 }
 
 
 #[tokio::test]
 async fn should_return_400_if_invalid_input() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
 
     let random_email = get_random_email();
     let login_attempt_id = LoginId::default().as_ref().to_owned();
@@ -77,7 +81,7 @@ async fn should_return_400_if_invalid_input() {
     for (email, login_attempt_id, code) in test_cases {
         let request_body = serde_json::json!({
             "email": email,
-            "LoginId": login_attempt_id,
+            "loginAttemptId": login_attempt_id,
             "2FACode": code
         });
 
@@ -99,11 +103,13 @@ async fn should_return_400_if_invalid_input() {
             "Invalid credentials".to_owned()
         );
     }
+
+    app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_401_if_incorrect_credentials() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
 
     let random_email = get_random_email();
 
@@ -119,6 +125,12 @@ async fn should_return_401_if_incorrect_credentials() {
 
     // --------------------------
 
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
     let login_body = serde_json::json!({
         "email": random_email,
         "password": "password123"
@@ -138,15 +150,15 @@ async fn should_return_401_if_incorrect_credentials() {
 
     let login_attempt_id = response_body.login_id;
 
-    let code_tuple = app
+    let challenge = app
         .two_fa_code
         .read()
         .await
-        .get_code(&Email::parse(random_email.clone()).unwrap())
+        .get_challenge(&LoginId::parse(login_attempt_id.clone()).unwrap())
         .await
         .unwrap();
 
-    let two_fa_code = code_tuple.1.as_ref();
+    let two_fa_code = challenge.code.as_ref();
 
     // --------------------------
 
@@ -175,7 +187,7 @@ async fn should_return_401_if_incorrect_credentials() {
     for (email, login_attempt_id, code) in test_cases {
         let request_body = serde_json::json!({
             "email": email,
-            "LoginId": login_attempt_id,
+            "loginAttemptId": login_attempt_id,
             "2FACode": code
         });
 
@@ -197,10 +209,12 @@ async fn should_return_401_if_incorrect_credentials() {
             "Incorrect credentials".to_owned()
         );
     }
+
+    app.clean_up().await;
 }
 #[tokio::test]
-async fn should_return_401_if_old_code() {
-    let app = TestApp::new().await;
+async fn should_keep_concurrent_login_attempts_separate() {
+    let mut app = TestApp::new().await;
 
     let random_email = get_random_email();
 
@@ -216,6 +230,12 @@ async fn should_return_401_if_old_code() {
 
     // First login call
 
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
     let login_body = serde_json::json!({
         "email": random_email,
         "password": "password123"
@@ -235,23 +255,52 @@ async fn should_return_401_if_old_code() {
 
     let login_attempt_id = response_body.login_id;
 
-    let code_tuple = app
+    let challenge = app
         .two_fa_code
         .read()
         .await
-        .get_code(&Email::parse(random_email.clone()).unwrap())
+        .get_challenge(&LoginId::parse(login_attempt_id.clone()).unwrap())
         .await
         .unwrap();
 
-    let code = code_tuple.1.as_ref();
+    let code = challenge.code.as_ref();
 
-    // Second login call
+    // Second login call, e.g. from another device
 
     let response = app.post_login(&login_body).await;
 
     assert_eq!(response.status().as_u16(), 206);
 
-    // 2FA attempt with old login_attempt_id and code
+    let second_login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_id;
+
+    assert_ne!(second_login_attempt_id, login_attempt_id);
+
+    let second_challenge = app
+        .two_fa_code
+        .read()
+        .await
+        .get_challenge(&LoginId::parse(second_login_attempt_id.clone()).unwrap())
+        .await
+        .unwrap();
+
+    // A code only completes the login attempt it was sent for
+
+    if second_challenge.code.as_ref() != code {
+        let response = app.post_verify_2fa(&serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": second_login_attempt_id,
+            "2FACode": code
+        }))
+        .await;
+
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // The first login attempt wasn't overwritten by the second
 
     let request_body = serde_json::json!({
         "email": random_email,
@@ -261,13 +310,24 @@ async fn should_return_401_if_old_code() {
 
     let response = app.post_verify_2fa(&request_body).await;
 
-    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_verify_2fa(&serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": second_login_attempt_id,
+        "2FACode": second_challenge.code.as_ref()
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
 }
 
 
 #[tokio::test]
 async fn should_return_200_if_correct_code() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
 
     let random_email = get_random_email();
 
@@ -281,6 +341,12 @@ async fn should_return_200_if_correct_code() {
 
     assert_eq!(response.status().as_u16(), 201);
 
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
     let login_body = serde_json::json!({
         "email": random_email,
         "password": "password123"
@@ -300,15 +366,15 @@ async fn should_return_200_if_correct_code() {
 
     let login_attempt_id = response_body.login_id;
 
-    let code_tuple = app
+    let challenge = app
         .two_fa_code
         .read()
         .await
-        .get_code(&Email::parse(random_email.clone()).unwrap())
+        .get_challenge(&LoginId::parse(login_attempt_id.clone()).unwrap())
         .await
         .unwrap();
 
-    let code = code_tuple.1.as_ref();
+    let code = challenge.code.as_ref();
 
     let request_body = serde_json::json!({
         "email": random_email,
@@ -326,11 +392,13 @@ async fn should_return_200_if_correct_code() {
         .expect("No auth cookie found");
 
     assert!(!auth_cookie.value().is_empty());
+
+    app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_401_if_same_code_twice() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
 
     let random_email = get_random_email();
 
@@ -344,6 +412,12 @@ async fn should_return_401_if_same_code_twice() {
 
     assert_eq!(response.status().as_u16(), 201);
 
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
     let login_body = serde_json::json!({
         "email": random_email,
         "password": "password123"
@@ -363,15 +437,15 @@ async fn should_return_401_if_same_code_twice() {
 
     let login_attempt_id = response_body.login_id;
 
-    let code_tuple = app
+    let challenge = app
         .two_fa_code
         .read()
         .await
-        .get_code(&Email::parse(random_email.clone()).unwrap())
+        .get_challenge(&LoginId::parse(login_attempt_id.clone()).unwrap())
         .await
         .unwrap();
 
-    let code = code_tuple.1.as_ref();
+    let code = challenge.code.as_ref();
 
     let request_body = serde_json::json!({
         "email": random_email,
@@ -393,5 +467,130 @@ async fn should_return_401_if_same_code_twice() {
     let response = app.post_verify_2fa(&request_body).await;
 
     assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+
+// Sign up a user with email 2FA, start a login and return the login attempt ID with the emailed code
+async fn start_2fa_login(app: &TestApp, email: &str) -> (String, String) {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": true
+    });
+
+    let response = app.post_signup(&signup_body).await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123"
+    });
+
+    let response = app.post_login(&login_body).await;
+
+    assert_eq!(response.status().as_u16(), 206);
+
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_id;
+
+    let challenge = app
+        .two_fa_code
+        .read()
+        .await
+        .get_challenge(&LoginId::parse(login_attempt_id.clone()).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(challenge.email, Email::parse(email.to_owned()).unwrap());
+
+    (login_attempt_id, challenge.code.as_ref().to_owned())
+}
+
+fn wrong_code(code: &str) -> &'static str {
+    if code == "123456" { "654321" } else { "123456" }
+}
+
+#[tokio::test]
+async fn should_accept_correct_code_after_a_typo() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    let (login_attempt_id, code) = start_2fa_login(&app, &random_email).await;
+
+    let response = app.post_verify_2fa(&serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": wrong_code(&code)
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_verify_2fa(&serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
 }
 
+#[tokio::test]
+async fn should_return_429_and_invalidate_code_after_too_many_attempts() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    let (login_attempt_id, code) = start_2fa_login(&app, &random_email).await;
+
+    let wrong_guess = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": wrong_code(&code)
+    });
+
+    for _ in 1..DEFAULT_TWO_FA_MAX_ATTEMPTS {
+        let response = app.post_verify_2fa(&wrong_guess).await;
+
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    let response = app.post_verify_2fa(&wrong_guess).await;
+
+    assert_eq!(response.status().as_u16(), 429);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Too many attempts".to_owned()
+    );
+
+    // The right code is no good once the login attempt has been burned
+    let response = app.post_verify_2fa(&serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
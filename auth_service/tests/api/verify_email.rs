@@ -0,0 +1,137 @@
+use std::time::Duration;
+
+use auth_service::{
+    route_handlers::VerifyEmailResponse,
+    services::api::ErrorResponse,
+    user::Email
+};
+use wiremock::{matchers::{method, path}, Mock, ResponseTemplate};
+
+use crate::utils::{get_random_email, TestApp};
+
+async fn is_verified(app: &TestApp, email: &str) -> bool {
+    let email = Email::parse(email.to_owned()).expect("Failed to parse email");
+
+    app.user_store
+        .read()
+        .await
+        .get_user(&email)
+        .await
+        .expect("Failed to get user")
+        .email_verified
+}
+
+#[tokio::test]
+async fn should_verify_email_with_link_sent_at_signup() {
+    let mut app = TestApp::new().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let random_email = get_random_email();
+
+    let response = app.post_signup(&serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+    assert!(!is_verified(&app, &random_email).await);
+
+    let token = app.token_from_email("Confirm your email address").await;
+
+    let response = app.post_verify_email(&serde_json::json!({ "token": token })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(is_verified(&app, &random_email).await);
+
+    // Following the link again is harmless
+    let response = app.post_verify_email(&serde_json::json!({ "token": token })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_invalid_token() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_verify_email(&serde_json::json!({ "token": "invalid" })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Invalid auth token".to_owned()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_only_resend_to_unverified_accounts() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+
+    let response = app.post_signup(&serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let resend = |email: String| {
+        let app = &app;
+        async move {
+            let response = app.post_verify_email_resend(&serde_json::json!({ "email": email })).await;
+
+            assert_eq!(response.status().as_u16(), 202);
+
+            response
+                .json::<VerifyEmailResponse>()
+                .await
+                .expect("Could not deserialize response body to VerifyEmailResponse")
+                .message
+        }
+    };
+
+    let unverified = resend(random_email.clone()).await;
+    let token = app.token_from_email("Confirm your email address").await;
+
+    let response = app.post_verify_email(&serde_json::json!({ "token": token })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Neither verified nor unknown accounts get another email, but the response gives nothing away
+    let verified = resend(random_email).await;
+    let unknown = resend(get_random_email()).await;
+
+    assert_eq!(unverified, verified);
+    assert_eq!(unverified, unknown);
+
+    // Give stray emails time to show up before the mock checks its count
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    app.clean_up().await;
+}
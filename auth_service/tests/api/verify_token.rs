@@ -1,10 +1,13 @@
-use auth_service::services::{api::ErrorResponse, constants::JWT_COOKIE_NAME};
+use auth_service::{
+    services::{api::ErrorResponse, constants::JWT_COOKIE_NAME},
+    user::{roles::{ADMIN_ROLE, USERS_WRITE_PERMISSION}, Email}
+};
 
 use crate::utils::{get_random_email, TestApp};
 
 #[tokio::test]
 async fn should_return_422_if_malformed_input() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
 
     let test_cases = vec![
         serde_json::json!({
@@ -17,11 +20,13 @@ async fn should_return_422_if_malformed_input() {
         let response = app.post_verify_token(&test_case).await;
         assert_eq!(response.status().as_u16(), 422);
     }
+
+    app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_200_valid_token() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
 
     let random_email = get_random_email();
 
@@ -60,11 +65,13 @@ async fn should_return_200_valid_token() {
     let response = app.post_verify_token(&verify_token_body).await;
 
     assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_401_if_invalid_token() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
 
     let test_cases = vec!["", "invalid_token"];
 
@@ -86,12 +93,14 @@ async fn should_return_401_if_invalid_token() {
             "Invalid auth token".to_owned()
         );
     }
+
+    app.clean_up().await;
 }
 
 
 #[tokio::test]
 async fn should_return_401_if_banned_token() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
 
     let random_email = get_random_email();
 
@@ -145,5 +154,71 @@ async fn should_return_401_if_banned_token() {
             .error,
         "Invalid auth token".to_owned()
     );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_check_required_role_and_permission() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    assert_eq!(app.post_signup(&signup_body).await.status().as_u16(), 201);
+
+    let token = login_token(&app, &random_email).await;
+
+    let response = app.post_verify_token(&serde_json::json!({
+        "token": token,
+        "requiredRole": ADMIN_ROLE,
+    })).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+
+    // Roles are read when a token is minted, so the new role shows up after logging in again
+    app.user_store
+        .write()
+        .await
+        .assign_role(&Email::parse(random_email.clone()).unwrap(), ADMIN_ROLE)
+        .await
+        .unwrap();
+
+    let token = login_token(&app, &random_email).await;
+
+    let test_cases = [
+        (serde_json::json!({ "token": token, "requiredRole": ADMIN_ROLE }), 200),
+        (serde_json::json!({ "token": token, "requiredPermission": USERS_WRITE_PERMISSION }), 200),
+        (serde_json::json!({ "token": token, "requiredRole": "support" }), 403),
+        (serde_json::json!({ "token": token, "requiredPermission": "billing:write" }), 403),
+    ];
+
+    for (body, status) in test_cases {
+        let response = app.post_verify_token(&body).await;
+        assert_eq!(response.status().as_u16(), status, "Failed for input: {:?}", body);
+    }
+
+    app.clean_up().await;
 }
 
+async fn login_token(app: &TestApp, email: &str) -> String {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+
+    auth_cookie.value().to_owned()
+}
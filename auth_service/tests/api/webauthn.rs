@@ -0,0 +1,377 @@
+use auth_service::{
+    route_handlers::{TwoFactorAuthResponse, WebauthnLoginStartResponse, WebauthnRegisterStartResponse},
+    services::{
+        api::ErrorResponse,
+        constants::{JWT_COOKIE_NAME, WEBAUTHN_ORIGIN},
+        webauthn::{
+            AssertionResponse, AttestationResponse, AuthenticationCredential, CreationOptions,
+            RegistrationCredential, RequestOptions
+        }
+    },
+    user::{Email, TwoFaMethod}
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ciborium::value::Value;
+use reqwest::Response;
+use ring::{
+    digest::{digest, SHA256},
+    rand::{SecureRandom, SystemRandom},
+    signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING}
+};
+
+use crate::utils::{get_random_email, TestApp};
+
+// A P-256 passkey held in memory, standing in for a platform authenticator or security key
+struct SoftAuthenticator {
+    key_pair: EcdsaKeyPair,
+    credential_id: Vec<u8>,
+    user_handle: Option<String>,
+    sign_count: u32,
+    rng: SystemRandom,
+}
+
+impl SoftAuthenticator {
+    fn new() -> Self {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)
+            .expect("Failed to generate key");
+        let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng)
+            .expect("Failed to load key");
+
+        let mut credential_id = vec![0u8; 16];
+        rng.fill(&mut credential_id).expect("Failed to generate credential ID");
+
+        Self { key_pair, credential_id, user_handle: None, sign_count: 0, rng }
+    }
+
+    fn id(&self) -> String {
+        URL_SAFE_NO_PAD.encode(&self.credential_id)
+    }
+
+    fn register(&mut self, options: &CreationOptions) -> RegistrationCredential {
+        self.user_handle = Some(options.user.id.clone());
+
+        let client_data = client_data("webauthn.create", &options.challenge);
+
+        // Uncompressed SEC1 point: 0x04 || x || y
+        let point = self.key_pair.public_key().as_ref();
+        let cose_key = Value::Map(vec![
+            (Value::Integer(1.into()), Value::Integer(2.into())),
+            (Value::Integer(3.into()), Value::Integer((-7).into())),
+            (Value::Integer((-1).into()), Value::Integer(1.into())),
+            (Value::Integer((-2).into()), Value::Bytes(point[1..33].to_vec())),
+            (Value::Integer((-3).into()), Value::Bytes(point[33..65].to_vec())),
+        ]);
+
+        let mut auth_data = self.authenticator_data(&options.rp.id, 0x01 | 0x04 | 0x40);
+        auth_data.extend_from_slice(&[0u8; 16]);
+        auth_data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+        auth_data.extend_from_slice(&self.credential_id);
+        ciborium::ser::into_writer(&cose_key, &mut auth_data).expect("Failed to encode COSE key");
+
+        let attestation = Value::Map(vec![
+            (Value::Text("fmt".to_owned()), Value::Text("none".to_owned())),
+            (Value::Text("attStmt".to_owned()), Value::Map(vec![])),
+            (Value::Text("authData".to_owned()), Value::Bytes(auth_data)),
+        ]);
+        let mut attestation_object = Vec::new();
+        ciborium::ser::into_writer(&attestation, &mut attestation_object).expect("Failed to encode attestation");
+
+        RegistrationCredential {
+            id: self.id(),
+            type_: "public-key".to_owned(),
+            response: AttestationResponse {
+                client_data_json: URL_SAFE_NO_PAD.encode(client_data),
+                attestation_object: URL_SAFE_NO_PAD.encode(attestation_object),
+            },
+        }
+    }
+
+    fn authenticate(&mut self, options: &RequestOptions, user_verified: bool) -> AuthenticationCredential {
+        self.sign_count += 1;
+
+        let client_data = client_data("webauthn.get", &options.challenge);
+        let flags = if user_verified { 0x01 | 0x04 } else { 0x01 };
+        let auth_data = self.authenticator_data(&options.rp_id, flags);
+
+        let signed_data = [auth_data.as_slice(), digest(&SHA256, &client_data).as_ref()].concat();
+        let signature = self.key_pair.sign(&self.rng, &signed_data).expect("Failed to sign assertion");
+
+        AuthenticationCredential {
+            id: self.id(),
+            type_: "public-key".to_owned(),
+            response: AssertionResponse {
+                client_data_json: URL_SAFE_NO_PAD.encode(client_data),
+                authenticator_data: URL_SAFE_NO_PAD.encode(auth_data),
+                signature: URL_SAFE_NO_PAD.encode(signature.as_ref()),
+                user_handle: self.user_handle.clone(),
+            },
+        }
+    }
+
+    fn authenticator_data(&self, rp_id: &str, flags: u8) -> Vec<u8> {
+        let mut data = digest(&SHA256, rp_id.as_bytes()).as_ref().to_vec();
+        data.push(flags);
+        data.extend_from_slice(&self.sign_count.to_be_bytes());
+        data
+    }
+}
+
+fn client_data(ceremony_type: &str, challenge: &str) -> Vec<u8> {
+    serde_json::to_vec(&serde_json::json!({
+        "type": ceremony_type,
+        "challenge": challenge,
+        "origin": *WEBAUTHN_ORIGIN,
+        "crossOrigin": false
+    }))
+    .expect("Failed to serialize client data")
+}
+
+async fn signup_and_login(app: &TestApp) -> serde_json::Value {
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&signup_body).await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    login_body
+}
+
+async fn register(app: &TestApp) -> SoftAuthenticator {
+    let response = app.post_webauthn_register_start(&serde_json::json!({ "password": "password123" })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let options = response
+        .json::<WebauthnRegisterStartResponse>()
+        .await
+        .expect("Could not deserialize response body to WebauthnRegisterStartResponse")
+        .public_key;
+
+    let mut authenticator = SoftAuthenticator::new();
+    let credential = authenticator.register(&options);
+
+    let response = app.post_webauthn_register_finish(&credential).await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    authenticator
+}
+
+async fn login_options(app: &TestApp, body: &serde_json::Value) -> RequestOptions {
+    let response = app.post_webauthn_login_start(body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    response
+        .json::<WebauthnLoginStartResponse>()
+        .await
+        .expect("Could not deserialize response body to WebauthnLoginStartResponse")
+        .public_key
+}
+
+fn auth_cookie(response: &Response) -> Option<String> {
+    response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .map(|cookie| cookie.value().to_owned())
+}
+
+#[tokio::test]
+async fn should_return_400_if_registering_without_auth_cookie() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_webauthn_register_start(&serde_json::json!({ "password": "password123" })).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Missing auth token".to_owned()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_use_passkey_as_second_factor_after_registration() {
+    let mut app = TestApp::new().await;
+
+    let login_body = signup_and_login(&app).await;
+    let mut authenticator = register(&app).await;
+
+    let response = app.post_login(&login_body).await;
+
+    assert_eq!(response.status().as_u16(), 206);
+
+    let json_body = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+
+    assert_eq!(json_body.two_fa_method, TwoFaMethod::Passkey);
+
+    let options = login_options(&app, &serde_json::json!({
+        "email": login_body["email"],
+        "loginAttemptId": json_body.login_id
+    }))
+    .await;
+
+    assert_eq!(options.allow_credentials.len(), 1);
+    assert_eq!(options.allow_credentials[0].id, authenticator.id());
+
+    // A security key without a PIN is fine as a second factor
+    let credential = authenticator.authenticate(&options, false);
+    let response = app.post_webauthn_login_finish(&credential).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(auth_cookie(&response).is_some_and(|token| !token.is_empty()));
+
+    // The assertion can't be replayed, its challenge has been used up
+    let response = app.post_webauthn_login_finish(&credential).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_require_current_password_to_register() {
+    let mut app = TestApp::new().await;
+
+    signup_and_login(&app).await;
+
+    let response = app.post_webauthn_register_start(&serde_json::json!({ "password": "wrongpassword" })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_keep_existing_second_factor_when_registering() {
+    let mut app = TestApp::new().await;
+
+    let login_body = signup_and_login(&app).await;
+    let email = Email::parse(login_body["email"].as_str().unwrap().to_owned()).unwrap();
+
+    app.user_store.write().await.set_two_fa_method(&email, TwoFaMethod::Totp).await.unwrap();
+
+    let mut authenticator = register(&app).await;
+
+    let user = app.user_store.read().await.get_user(&email).await.unwrap();
+
+    assert_eq!(user.two_fa_method, TwoFaMethod::Totp);
+
+    // The passkey still logs in on its own
+    let options = login_options(&app, &serde_json::json!({})).await;
+    let credential = authenticator.authenticate(&options, true);
+    let response = app.post_webauthn_login_finish(&credential).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_log_in_without_password_when_user_verified() {
+    let mut app = TestApp::new().await;
+
+    signup_and_login(&app).await;
+    let mut authenticator = register(&app).await;
+
+    let options = login_options(&app, &serde_json::json!({})).await;
+
+    assert!(options.allow_credentials.is_empty());
+    assert_eq!(options.user_verification, "required");
+
+    let credential = authenticator.authenticate(&options, false);
+    let response = app.post_webauthn_login_finish(&credential).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    let options = login_options(&app, &serde_json::json!({})).await;
+    let credential = authenticator.authenticate(&options, true);
+    let response = app.post_webauthn_login_finish(&credential).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(auth_cookie(&response).is_some_and(|token| !token.is_empty()));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reject_cloned_authenticator() {
+    let mut app = TestApp::new().await;
+
+    signup_and_login(&app).await;
+    let mut authenticator = register(&app).await;
+
+    let options = login_options(&app, &serde_json::json!({})).await;
+    let credential = authenticator.authenticate(&options, true);
+    let response = app.post_webauthn_login_finish(&credential).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let stored = app
+        .passkey_store
+        .read()
+        .await
+        .get_passkey(&authenticator.id())
+        .await
+        .expect("Failed to get passkey");
+
+    assert_eq!(stored.sign_count, authenticator.sign_count);
+
+    // A copy of the key that has fallen behind on its counter
+    authenticator.sign_count -= 1;
+
+    let options = login_options(&app, &serde_json::json!({})).await;
+    let credential = authenticator.authenticate(&options, true);
+    let response = app.post_webauthn_login_finish(&credential).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reject_invalid_login_start_requests() {
+    let mut app = TestApp::new().await;
+
+    let login_body = signup_and_login(&app).await;
+    register(&app).await;
+
+    let response = app.post_webauthn_login_start(&serde_json::json!({ "email": login_body["email"] })).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    // Without a password step there is no login attempt to complete
+    let response = app.post_webauthn_login_start(&serde_json::json!({
+        "email": login_body["email"],
+        "loginAttemptId": uuid::Uuid::new_v4().to_string()
+    })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}